@@ -1,11 +1,23 @@
-use crate::fs::{FileInfo, FileSystem, FileSystemError, SaveOptions};
-use crate::pdf::{PdfDocument, PdfError, PdfFormField, PdfMetadata, PdfParser, FormFieldGenerator};
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tauri_plugin_dialog as dialog;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use crate::fs::{self, BackupInfo, BackupManager, BackupPolicy, FileInfo, FileSystem, FileWatcher, FsError};
+use crate::fs::{RecentFile, RecentFileList, RecentFiles, SaveOptions, WriteSource};
+use crate::pdf::{FormFieldTree, MetadataUpdate, PdfDocument, PdfError, PdfFieldValue, PdfFormField, PdfPage, PdfParser};
+use crate::pdf::{self, FormFieldGenerator, FormFiller, FormFlattener};
+use crate::pdf::{FieldRef, FieldUpdate, FormEditor, Fdf, FormCsv, FormJson, Xfdf};
+use crate::pdf::{MergeOptions, PdfMerger, PdfSplitter, RenamedField};
+use crate::session::{DocumentEdit, DocumentSessionInfo, HistoryEntry, SessionError, SessionStore};
+use crate::session::{DocumentDiff, RecoverableSession, RecoveryStore};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Serialize;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tauri::State;
+use tauri_plugin_dialog::DialogExt;
+use tauri_plugin_store::StoreExt;
+
+/// Store file holding the recent files list, in the app data directory
+const RECENT_FILES_STORE: &str = "recent-files.json";
+const RECENT_FILES_KEY: &str = "files";
 
 /// Error response for commands
 #[derive(Debug, Serialize)]
@@ -16,22 +28,62 @@ pub struct CommandError {
 
 impl From<PdfError> for CommandError {
     fn from(err: PdfError) -> Self {
-        CommandError {
-            code: "PDF_ERROR".to_string(),
-            message: err.to_string(),
+        match err {
+            PdfError::Save(err) => err.into(),
+            err => CommandError {
+                code: "PDF_ERROR".to_string(),
+                message: err.to_string(),
+            },
         }
     }
 }
 
-impl From<FileSystemError> for CommandError {
-    fn from(error: FileSystemError) -> Self {
+impl From<FsError> for CommandError {
+    fn from(error: FsError) -> Self {
+        // A save that did not verify left the original file in place, which
+        // the frontend tells the user about differently from other failures
+        let code = match error {
+            FsError::VerificationFailed(_) => "SAVE_VERIFICATION_FAILED",
+            FsError::ModifiedExternally(_) => "MODIFIED_EXTERNALLY",
+            _ => "FS_ERROR",
+        };
         Self {
-            code: "FS_ERROR".to_string(),
+            code: code.to_string(),
             message: error.to_string(),
         }
     }
 }
 
+impl From<SessionError> for CommandError {
+    fn from(err: SessionError) -> Self {
+        match err {
+            SessionError::Pdf(err) => err.into(),
+            SessionError::Fs(err) => err.into(),
+            SessionError::NotFound(_) => CommandError {
+                code: "SESSION_NOT_FOUND".to_string(),
+                message: err.to_string(),
+            },
+            SessionError::RecoveryNotFound(_) => CommandError {
+                code: "RECOVERY_NOT_FOUND".to_string(),
+                message: err.to_string(),
+            },
+            SessionError::Recovery(_) => CommandError {
+                code: "RECOVERY_ERROR".to_string(),
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+impl From<tauri_plugin_store::Error> for CommandError {
+    fn from(err: tauri_plugin_store::Error) -> Self {
+        CommandError {
+            code: "STORE_ERROR".to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
 impl From<std::io::Error> for CommandError {
     fn from(err: std::io::Error) -> Self {
         CommandError {
@@ -41,183 +93,364 @@ impl From<std::io::Error> for CommandError {
     }
 }
 
+/// Open a PDF file using the system dialog
 #[tauri::command]
-pub async fn open_pdf(path: String) -> Result<PdfMetadata, CommandError> {
-    let mut fs = FileSystem::new();
-    let path_buf = PathBuf::from(&path);
-
-    // Create a temporary copy for editing
-    let temp_path = fs.create_temp_file("pdf")?;
-    std::fs::copy(&path_buf, &temp_path).map_err(|e| FileSystemError::OpenError(e.to_string()))?;
+pub async fn open_pdf_dialog(app: tauri::AppHandle) -> Result<Option<String>, CommandError> {
+    let file_path = app
+        .dialog()
+        .file()
+        .add_filter("PDF Files", &["pdf"])
+        .blocking_pick_file();
 
-    let doc = PdfDocument::open(path.clone())?;
-    fs.add_recent_file(path_buf);
-    Ok(doc.get_metadata())
+    Ok(file_path.map(|path| path.to_string()))
 }
 
+/// Parse a PDF file and return its structure
 #[tauri::command]
-pub async fn get_page_info(path: String, page_number: u32) -> Result<PdfPageInfo, CommandError> {
-    let doc = PdfDocument::open(path)?;
-    Ok(doc.get_page_info(page_number)?)
+pub async fn parse_pdf(path: String) -> Result<PdfDocument, CommandError> {
+    let document = PdfParser::open(path)?;
+    Ok(document)
 }
 
+/// Load a PDF file once and keep it open for later queries and edits
 #[tauri::command]
-pub async fn get_total_pages(path: String) -> Result<u32, CommandError> {
-    let doc = PdfDocument::open(path)?;
-    Ok(doc.get_total_pages())
-}
+pub async fn open_document(
+    app: tauri::AppHandle,
+    sessions: State<'_, SessionStore>,
+    recent: State<'_, RecentFileList>,
+    watcher: State<'_, FileWatcher>,
+    path: String,
+) -> Result<DocumentSessionInfo, CommandError> {
+    // Fingerprint the file first so changes made while it loads are noticed
+    let fingerprint = FileSystem::fingerprint(&path)?;
+    let info = sessions.open(&path)?;
+    watcher.watch(&info.session_id, &path, fingerprint);
+    unwatch_evicted(&sessions, &watcher);
+
+    // A recent files list that cannot be saved is no reason to fail the open
+    if let Err(err) = update_recent_files(&app, &recent, |files| {
+        files.record(&path);
+        Ok(())
+    }) {
+        log::warn!("Failed to record {} as a recent file: {}", path, err.message);
+    }
 
-#[tauri::command]
-pub async fn get_form_fields(path: String) -> Result<Vec<PdfFormField>, CommandError> {
-    let doc = PdfDocument::open(path)?;
-    Ok(doc.get_form_fields()?)
+    Ok(info)
 }
 
+/// Close an open document, discarding any unsaved edits
 #[tauri::command]
-pub async fn add_form_field(path: String, field: PdfFormField) -> Result<(), CommandError> {
-    let mut doc = PdfDocument::open(path.clone())?;
-    doc.add_form_field(field)?;
-    doc.save(&path)?;
+pub async fn close_document(
+    sessions: State<'_, SessionStore>,
+    recovery: State<'_, RecoveryStore>,
+    watcher: State<'_, FileWatcher>,
+    session_id: String,
+) -> Result<(), CommandError> {
+    sessions.close(&session_id)?;
+    recovery.remove(&session_id);
+    watcher.unwatch(&session_id);
     Ok(())
 }
 
+/// List the documents with unsaved edits left behind when the app last quit
 #[tauri::command]
-pub async fn get_recent_files() -> Result<Vec<FileInfo>, CommandError> {
-    let fs = FileSystem::new();
-    Ok(fs.get_recent_files())
+pub async fn list_recoverable_sessions(
+    recovery: State<'_, RecoveryStore>,
+) -> Result<Vec<RecoverableSession>, CommandError> {
+    Ok(recovery.list())
 }
 
+/// Reopen a document left with unsaved edits, as it was when last autosaved
 #[tauri::command]
-pub async fn show_open_dialog() -> Result<DialogResult, CommandError> {
-    Ok(crate::dialog::show_open_dialog().await)
+pub async fn recover_session(
+    sessions: State<'_, SessionStore>,
+    recovery: State<'_, RecoveryStore>,
+    watcher: State<'_, FileWatcher>,
+    id: String,
+) -> Result<DocumentSessionInfo, CommandError> {
+    let info = sessions.recover(&recovery, &id)?;
+
+    // The edits were made to whatever version was on disk back then, which
+    // cannot be known now, so changes are watched for from this version on
+    if let Ok(fingerprint) = FileSystem::fingerprint(&info.document.path) {
+        watcher.watch(&info.session_id, &info.document.path, fingerprint);
+    }
+    unwatch_evicted(&sessions, &watcher);
+    Ok(info)
+}
+
+/// Stop watching the files of sessions evicted to free memory, as there is
+/// no one left to tell about changes to them
+pub(crate) fn unwatch_evicted(sessions: &SessionStore, watcher: &FileWatcher) {
+    for session_id in sessions.take_evicted() {
+        watcher.unwatch(&session_id);
+    }
 }
 
+/// Throw away the unsaved edits of a document left behind when the app last quit
 #[tauri::command]
-pub async fn show_save_dialog(default_path: Option<String>) -> Result<DialogResult, CommandError> {
-    let fs = FileSystem::new();
-    Ok(crate::dialog::show_save_dialog(default_path.as_deref()).await)
+pub async fn discard_recoverable_session(
+    recovery: State<'_, RecoveryStore>,
+    id: String,
+) -> Result<(), CommandError> {
+    recovery.discard(&id)?;
+    Ok(())
 }
 
+/// Save an open document, to its own path unless an output path is given
+///
+/// Saving over a file open in any session fails if another program changed
+/// it since that session opened it, unless `force` is set. Without options the file being
+/// replaced is backed up and any other file at the output path is replaced.
+/// A failed backup is logged rather than stopping the save.
 #[tauri::command]
-pub async fn save_pdf(
-    path: String,
-    content: Vec<u8>,
-    options: SaveOptions,
+#[allow(clippy::too_many_arguments)]
+pub async fn save_document(
+    app: tauri::AppHandle,
+    sessions: State<'_, SessionStore>,
+    recent: State<'_, RecentFileList>,
+    backups: State<'_, BackupManager>,
+    recovery: State<'_, RecoveryStore>,
+    watcher: State<'_, FileWatcher>,
+    session_id: String,
+    output_path: Option<String>,
+    options: Option<SaveOptions>,
 ) -> Result<(), CommandError> {
-    let fs = FileSystem::new();
-    let path = PathBuf::from(path);
+    let options = options.unwrap_or(SaveOptions {
+        create_backup: true,
+        overwrite: true,
+        force: false,
+    });
+    let current = sessions.with_session(&session_id, |session| Ok(session.path.clone()))?;
+    let target = output_path.as_ref().map(PathBuf::from).unwrap_or_else(|| current.clone());
+
+    // The same file may be named through different paths
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let same_file = canonical(&target) == canonical(&current);
+
+    if !same_file && target.exists() && !options.overwrite {
+        return Err(FsError::AlreadyExists(target.to_string_lossy().to_string()).into());
+    }
 
-    // Check if file exists and needs confirmation
-    if !options.overwrite && path.exists() {
-        if !crate::dialog::show_overwrite_confirmation(&path.to_string_lossy()).await {
-            return Ok(());
+    // Whichever session has the target open, this one or another, must not
+    // lose changes made to it by another program
+    watcher.guard_write(&target, WriteSource::Session(&session_id), options.force, || {
+        // Keep the file being replaced so the save can be rolled back
+        if options.create_backup && target.is_file() {
+            if let Err(err) = backups.create(&target) {
+                log::warn!("Failed to back up {} before saving: {}", target.display(), err);
+            }
+        }
+        sessions.save(&session_id, Some(target.clone()))
+    })?;
+    recovery.remove(&session_id);
+
+    // Saving under a new name is as good as opening that file
+    if let Some(path) = output_path {
+        if let Err(err) = update_recent_files(&app, &recent, |files| {
+            files.record(&path);
+            Ok(())
+        }) {
+            log::warn!("Failed to record {} as a recent file: {}", path, err.message);
         }
     }
 
-    fs.save_file(&path, &content, &options)?;
-    fs.add_recent_file(path);
     Ok(())
 }
 
+/// List recently opened files, pinned ones first, dropping files that no longer exist
 #[tauri::command]
-pub async fn extract_text(path: String, page_number: u32) -> Result<String, CommandError> {
-    let doc = PdfDocument::open(path)?;
-    Ok(doc.extract_text(page_number)?)
+pub async fn get_recent_files(
+    app: tauri::AppHandle,
+    recent: State<'_, RecentFileList>,
+) -> Result<Vec<RecentFile>, CommandError> {
+    update_recent_files(&app, &recent, |_| Ok(()))
 }
 
+/// Pin a recent file so it stays listed however long ago it was opened
 #[tauri::command]
-pub async fn transform_coordinates(
+pub async fn pin_recent_file(
+    app: tauri::AppHandle,
+    recent: State<'_, RecentFileList>,
     path: String,
-    page_number: u32,
-    x: f64,
-    y: f64,
-) -> Result<(f64, f64), CommandError> {
-    let doc = PdfDocument::open(path)?;
-    Ok(doc.transform_coordinates(page_number, x, y)?)
-}
-
-#[tauri::command]
-pub async fn create_backup(path: String) -> Result<String, CommandError> {
-    let fs = FileSystem::new();
-    let original_path = PathBuf::from(path);
-    let backup_path = fs.create_backup_path(&original_path);
-    fs.write_file(
-        &backup_path,
-        &fs.read_file(&original_path)?,
-        &SaveOptions {
-            create_backup: false,
-            overwrite: true,
-        },
-    )?;
-    Ok(backup_path.to_string_lossy().to_string())
-}
-
-#[tauri::command]
-pub async fn export_form_data(path: String, format: String) -> Result<String, CommandError> {
-    let doc = PdfDocument::open(path)?;
-    let fields = doc.get_form_fields()?;
-
-    match format.as_str() {
-        "json" => serde_json::to_string(&fields).map_err(|e| CommandError {
-            code: "SERIALIZATION_ERROR".to_string(),
-            message: e.to_string(),
-        }),
-        "csv" => {
-            let mut output = String::from("name,type,value,x,y,width,height,page\n");
-            for field in fields {
-                output.push_str(&format!(
-                    "{},{},{},{},{},{},{},{}\n",
-                    field.name,
-                    field.field_type,
-                    field.value.unwrap_or_default(),
-                    field.x,
-                    field.y,
-                    field.width,
-                    field.height,
-                    field.page
-                ));
-            }
-            Ok(output)
-        }
-        _ => Err(CommandError {
-            code: "INVALID_FORMAT_ERROR".to_string(),
-            message: format!("Unsupported export format: {}", format),
-        }),
-    }
+) -> Result<Vec<RecentFile>, CommandError> {
+    update_recent_files(&app, &recent, |files| files.set_pinned(&path, true))
 }
 
-/// Open a PDF file using the system dialog
+/// Unpin a recent file, letting it age out of the list again
 #[tauri::command]
-pub async fn open_pdf_dialog() -> Result<Option<String>, CommandError> {
-    let file_path = dialog::blocking::FileDialogBuilder::new()
-        .add_filter("PDF Files", &["pdf"])
-        .pick_file();
-        
-    Ok(file_path.map(|path| path.to_string_lossy().to_string()))
+pub async fn unpin_recent_file(
+    app: tauri::AppHandle,
+    recent: State<'_, RecentFileList>,
+    path: String,
+) -> Result<Vec<RecentFile>, CommandError> {
+    update_recent_files(&app, &recent, |files| files.set_pinned(&path, false))
 }
 
-/// Parse a PDF file and return its structure
+/// Remember the page last viewed in a recent file
 #[tauri::command]
-pub async fn parse_pdf(path: String) -> Result<PdfDocument, CommandError> {
-    let document = PdfParser::open(path)?;
-    Ok(document)
+pub async fn set_recent_file_page(
+    app: tauri::AppHandle,
+    recent: State<'_, RecentFileList>,
+    path: String,
+    page: u32,
+) -> Result<Vec<RecentFile>, CommandError> {
+    update_recent_files(&app, &recent, |files| files.set_last_page(&path, page))
+}
+
+/// Forget the recent files, keeping the pinned ones unless told otherwise
+#[tauri::command]
+pub async fn clear_recent_files(
+    app: tauri::AppHandle,
+    recent: State<'_, RecentFileList>,
+    keep_pinned: bool,
+) -> Result<Vec<RecentFile>, CommandError> {
+    update_recent_files(&app, &recent, |files| {
+        files.clear(keep_pinned);
+        Ok(())
+    })
+}
+
+/// Change the recent files list and write it back to its store
+///
+/// The list is read from the store on first use and pruned of missing files
+/// on every change. Returns the list as it is afterwards.
+fn update_recent_files(
+    app: &tauri::AppHandle,
+    recent: &RecentFileList,
+    change: impl FnOnce(&mut RecentFiles) -> fs::Result<()>,
+) -> Result<Vec<RecentFile>, CommandError> {
+    let store = app.store(RECENT_FILES_STORE)?;
+    let mut list = recent.lock();
+    let files = list.get_or_insert_with(|| {
+        store
+            .get(RECENT_FILES_KEY)
+            .and_then(|value| {
+                serde_json::from_value(value)
+                    .map_err(|err| log::warn!("Ignoring unreadable recent files list: {}", err))
+                    .ok()
+            })
+            .unwrap_or_default()
+    });
+
+    change(files)?;
+    files.prune();
+
+    let value = serde_json::to_value(&*files).map_err(|err| CommandError {
+        code: "SERIALIZATION_ERROR".to_string(),
+        message: err.to_string(),
+    })?;
+    store.set(RECENT_FILES_KEY, value);
+    store.save()?;
+
+    Ok(files.files().to_vec())
+}
+
+/// Change how much memory open documents may use before idle ones are evicted
+#[tauri::command]
+pub async fn set_session_memory_limit(
+    sessions: State<'_, SessionStore>,
+    watcher: State<'_, FileWatcher>,
+    limit_bytes: usize,
+) -> Result<(), CommandError> {
+    sessions.set_memory_limit(limit_bytes);
+    unwatch_evicted(&sessions, &watcher);
+    Ok(())
+}
+
+/// Get the dimensions and rotation of a page by its zero-based index
+#[tauri::command]
+pub async fn get_page_info(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    page: u32,
+) -> Result<PdfPage, CommandError> {
+    Ok(sessions.with_session(&session_id, |session| session.info.page(page).cloned())?)
+}
+
+/// Get the number of pages in an open document
+#[tauri::command]
+pub async fn get_total_pages(sessions: State<'_, SessionStore>, session_id: String) -> Result<u32, CommandError> {
+    Ok(sessions.with_session(&session_id, |session| Ok(session.info.page_count))?)
+}
+
+/// Get the form field hierarchy of an open document
+#[tauri::command]
+pub async fn get_form_fields(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+) -> Result<FormFieldTree, CommandError> {
+    Ok(sessions.with_session(&session_id, |session| Ok(session.info.form.clone()))?)
+}
+
+/// Extract the text of a page by its zero-based index
+#[tauri::command]
+pub async fn extract_text(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    page: u32,
+) -> Result<String, CommandError> {
+    Ok(sessions.with_session(&session_id, |session| {
+        PdfParser::extract_text_from_document(&session.document, page)
+    })?)
+}
+
+/// Map a point on the displayed page back to unrotated page space
+#[tauri::command]
+pub async fn transform_coordinates(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    page: u32,
+    x: f64,
+    y: f64,
+) -> Result<(f64, f64), CommandError> {
+    Ok(sessions.with_session(&session_id, |session| session.info.transform_coordinates(page, x, y))?)
 }
 
 /// Save a PDF file using the system dialog
 #[tauri::command]
-pub async fn save_pdf_dialog(default_path: Option<String>) -> Result<Option<String>, CommandError> {
-    let file_path = dialog::blocking::FileDialogBuilder::new()
+pub async fn save_pdf_dialog(
+    app: tauri::AppHandle,
+    default_path: Option<String>,
+) -> Result<Option<String>, CommandError> {
+    let file_path = app
+        .dialog()
+        .file()
         .add_filter("PDF Files", &["pdf"])
         .set_file_name(default_path.unwrap_or_else(|| "document.pdf".to_string()))
-        .save_file();
-        
-    Ok(file_path.map(|path| path.to_string_lossy().to_string()))
+        .blocking_save_file();
+
+    Ok(file_path.map(|path| path.to_string()))
 }
 
 /// Save PDF data to a file
+///
+/// Replacing a file open in a session fails if another program changed it since.
 #[tauri::command]
-pub async fn save_pdf_file(path: String, data: Vec<u8>) -> Result<(), CommandError> {
-    FileSystem::write_file(path, &data)?;
+pub async fn save_pdf_file(
+    watcher: State<'_, FileWatcher>,
+    path: String,
+    data: Vec<u8>,
+) -> Result<(), CommandError> {
+    watcher.guard_write(&path, WriteSource::Document, false, || FileSystem::write_pdf(&path, &data))?;
+    Ok(())
+}
+
+/// Save PDF data to a file, optionally keeping a backup of the file it replaces
+///
+/// Like [`save_document`], replacing a file open in a session fails if
+/// another program changed it since, unless `force` is set.
+#[tauri::command]
+pub async fn save_pdf(
+    backups: State<'_, BackupManager>,
+    watcher: State<'_, FileWatcher>,
+    path: String,
+    data: Vec<u8>,
+    options: SaveOptions,
+) -> Result<(), CommandError> {
+    watcher.guard_write(&path, WriteSource::Document, options.force, || {
+        FileSystem::save_file(&path, &data, &options, &backups)
+    })?;
     Ok(())
 }
 
@@ -228,11 +461,64 @@ pub async fn get_file_info(path: String) -> Result<FileInfo, CommandError> {
     Ok(info)
 }
 
-/// Create a backup of a file
+/// Back up a file as it is on disk now
+#[tauri::command]
+pub async fn create_file_backup(
+    backups: State<'_, BackupManager>,
+    path: String,
+) -> Result<BackupInfo, CommandError> {
+    Ok(backups.create(path)?)
+}
+
+/// List the backups of a file, newest first
 #[tauri::command]
-pub async fn create_file_backup(path: String) -> Result<String, CommandError> {
-    let backup_path = FileSystem::create_backup(path)?;
-    Ok(backup_path.to_string_lossy().to_string())
+pub async fn list_backups(
+    backups: State<'_, BackupManager>,
+    path: String,
+) -> Result<Vec<BackupInfo>, CommandError> {
+    Ok(backups.list(path)?)
+}
+
+/// Replace a file with one of its backups, backing up its current content first
+///
+/// Sessions open on the file keep their own copy and are told the file
+/// changed; reopen the file to see the restored version. Fails if another
+/// program changed the file since a session opened it.
+#[tauri::command]
+pub async fn restore_backup(
+    backups: State<'_, BackupManager>,
+    watcher: State<'_, FileWatcher>,
+    path: String,
+    backup_id: String,
+) -> Result<Option<BackupInfo>, CommandError> {
+    Ok(watcher.guard_write(&path, WriteSource::Other, false, || backups.restore(&path, &backup_id))?)
+}
+
+/// Remove one backup of a file
+#[tauri::command]
+pub async fn delete_backup(
+    backups: State<'_, BackupManager>,
+    path: String,
+    backup_id: String,
+) -> Result<(), CommandError> {
+    backups.delete(path, &backup_id)?;
+    Ok(())
+}
+
+/// Get the retention policy for backups
+#[tauri::command]
+pub async fn get_backup_policy(backups: State<'_, BackupManager>) -> Result<BackupPolicy, CommandError> {
+    Ok(backups.policy())
+}
+
+/// Change the retention policy for backups, removing any backups it no longer allows
+#[tauri::command]
+pub async fn set_backup_policy(
+    backups: State<'_, BackupManager>,
+    policy: BackupPolicy,
+) -> Result<(), CommandError> {
+    backups.set_policy(policy)?;
+    Ok(())
 }
 
 /// Check if a file exists
@@ -245,43 +531,469 @@ pub async fn file_exists(path: String) -> Result<bool, CommandError> {
 #[tauri::command]
 pub async fn read_file_base64(path: String) -> Result<String, CommandError> {
     let data = FileSystem::read_file(path)?;
-    let encoded = base64::encode(data);
+    let encoded = BASE64.encode(data);
     Ok(encoded)
 }
 
 /// Write base64 data to a file
+///
+/// Replacing a file open in a session fails if another program changed it since.
 #[tauri::command]
-pub async fn write_file_base64(path: String, data: String) -> Result<(), CommandError> {
-    let decoded = base64::decode(data).map_err(|e| CommandError {
+pub async fn write_file_base64(
+    watcher: State<'_, FileWatcher>,
+    path: String,
+    data: String,
+) -> Result<(), CommandError> {
+    let decoded = BASE64.decode(data).map_err(|e| CommandError {
         code: "DECODE_ERROR".to_string(),
         message: e.to_string(),
     })?;
-    
-    FileSystem::write_file(path, &decoded)?;
+
+    watcher.guard_write(&path, WriteSource::Document, false, || FileSystem::write_file(&path, &decoded))?;
     Ok(())
 }
 
 /// Add form fields to a PDF document
+///
+/// Like every command writing a PDF file to an output path, this fails if
+/// the output is open in a session and another program changed it since.
+/// Sessions with the output open are told it changed.
 #[tauri::command]
 pub async fn add_form_fields_to_pdf(
+    watcher: State<'_, FileWatcher>,
     pdf_path: String,
     fields: Vec<PdfFormField>,
     output_path: String,
 ) -> Result<(), CommandError> {
     // Add form fields to the PDF
-    FormFieldGenerator::add_form_fields_to_pdf(pdf_path, fields, output_path)?;
-    
+    watcher.guard_write(&output_path, WriteSource::Other, false, || {
+        FormFieldGenerator::add_form_fields_to_pdf(&pdf_path, fields, &output_path)
+    })?;
+
     Ok(())
 }
 
+/// Create a new form PDF from a JSON or YAML layout file
+#[tauri::command]
+pub async fn create_form_from_layout(
+    watcher: State<'_, FileWatcher>,
+    layout_path: String,
+    output_path: String,
+) -> Result<(), CommandError> {
+    watcher.guard_write(&output_path, WriteSource::Other, false, || {
+        FormFieldGenerator::create_form_pdf(&layout_path, &output_path)
+    })?;
+
+    Ok(())
+}
+
+/// Fill existing form fields by fully-qualified name
+#[tauri::command]
+pub async fn fill_form(
+    watcher: State<'_, FileWatcher>,
+    pdf_path: String,
+    values: HashMap<String, PdfFieldValue>,
+    output_path: String,
+) -> Result<(), CommandError> {
+    // Fill the fields and regenerate their appearances
+    watcher.guard_write(&output_path, WriteSource::Other, false, || {
+        FormFiller::fill_form(&pdf_path, &values, &output_path)
+    })?;
+
+    Ok(())
+}
+
+/// Flatten form fields into page content, either all of them or only the named ones
+#[tauri::command]
+pub async fn flatten_form(
+    watcher: State<'_, FileWatcher>,
+    pdf_path: String,
+    field_names: Option<Vec<String>>,
+    output_path: String,
+) -> Result<(), CommandError> {
+    watcher.guard_write(&output_path, WriteSource::Other, false, || {
+        FormFlattener::flatten_form(&pdf_path, field_names.as_deref(), &output_path)
+    })?;
+
+    Ok(())
+}
+
+/// Rename, move or reconfigure an existing form field
+#[tauri::command]
+pub async fn update_form_field(
+    watcher: State<'_, FileWatcher>,
+    pdf_path: String,
+    field: FieldRef,
+    update: FieldUpdate,
+    output_path: String,
+) -> Result<(), CommandError> {
+    watcher.guard_write(&output_path, WriteSource::Other, false, || {
+        FormEditor::update_field(&pdf_path, &field, &update, &output_path)
+    })?;
+
+    Ok(())
+}
+
+/// Delete an existing form field along with its kids and widgets
+#[tauri::command]
+pub async fn delete_form_field(
+    watcher: State<'_, FileWatcher>,
+    pdf_path: String,
+    field: FieldRef,
+    output_path: String,
+) -> Result<(), CommandError> {
+    watcher.guard_write(&output_path, WriteSource::Other, false, || {
+        FormEditor::delete_field(&pdf_path, &field, &output_path)
+    })?;
+
+    Ok(())
+}
+
+/// Add form fields to an open document
+#[tauri::command]
+pub async fn add_document_form_fields(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    fields: Vec<PdfFormField>,
+) -> Result<FormFieldTree, CommandError> {
+    Ok(sessions.apply(&session_id, DocumentEdit::AddFields { fields })?.form)
+}
+
+/// Fill form fields of an open document by fully-qualified name
+#[tauri::command]
+pub async fn fill_document_form(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    values: HashMap<String, PdfFieldValue>,
+) -> Result<FormFieldTree, CommandError> {
+    Ok(sessions.apply(&session_id, DocumentEdit::FillForm { values })?.form)
+}
+
+/// Flatten form fields of an open document, either all of them or only the named ones
+#[tauri::command]
+pub async fn flatten_document_form(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    field_names: Option<Vec<String>>,
+) -> Result<FormFieldTree, CommandError> {
+    Ok(sessions.apply(&session_id, DocumentEdit::FlattenForm { field_names })?.form)
+}
+
+/// Rename, move or reconfigure a form field of an open document
+#[tauri::command]
+pub async fn update_document_form_field(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    field: FieldRef,
+    update: FieldUpdate,
+) -> Result<FormFieldTree, CommandError> {
+    Ok(sessions.apply(&session_id, DocumentEdit::UpdateField { field, update })?.form)
+}
+
+/// Delete a form field of an open document along with its kids and widgets
+#[tauri::command]
+pub async fn delete_document_form_field(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    field: FieldRef,
+) -> Result<FormFieldTree, CommandError> {
+    Ok(sessions.apply(&session_id, DocumentEdit::DeleteField { field })?.form)
+}
+
+/// Generate appearance streams for the form fields of an open document
+#[tauri::command]
+pub async fn generate_document_appearance_streams(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+) -> Result<(), CommandError> {
+    sessions.apply(&session_id, DocumentEdit::GenerateAppearances)?;
+    Ok(())
+}
+
+/// Change the title, author and other metadata of an open document
+#[tauri::command]
+pub async fn update_document_metadata(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    metadata: MetadataUpdate,
+) -> Result<PdfDocument, CommandError> {
+    Ok(sessions.apply(&session_id, DocumentEdit::UpdateMetadata { metadata })?)
+}
+
+/// Move pages of an open document, by zero-based index, so they start at position `to`
+#[tauri::command]
+pub async fn move_document_pages(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    pages: Vec<u32>,
+    to: u32,
+) -> Result<PdfDocument, CommandError> {
+    Ok(sessions.apply(&session_id, DocumentEdit::MovePages { pages, to })?)
+}
+
+/// Rotate pages of an open document clockwise by a multiple of 90 degrees
+#[tauri::command]
+pub async fn rotate_document_pages(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    pages: Vec<u32>,
+    degrees: i64,
+) -> Result<PdfDocument, CommandError> {
+    Ok(sessions.apply(&session_id, DocumentEdit::RotatePages { pages, degrees })?)
+}
+
+/// Delete pages of an open document along with their form field widgets
+#[tauri::command]
+pub async fn delete_document_pages(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    pages: Vec<u32>,
+) -> Result<PdfDocument, CommandError> {
+    Ok(sessions.apply(&session_id, DocumentEdit::DeletePages { pages })?)
+}
+
+/// Insert blank pages of the given size in points into an open document
+#[tauri::command]
+pub async fn insert_blank_pages(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    at: u32,
+    count: u32,
+    width: f64,
+    height: f64,
+) -> Result<PdfDocument, CommandError> {
+    Ok(sessions.apply(&session_id, DocumentEdit::InsertBlankPages { at, count, width, height })?)
+}
+
+/// Insert pages copied from another PDF file into an open document, all of
+/// them unless some are listed
+#[tauri::command]
+pub async fn insert_document_pages(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    at: u32,
+    source_path: String,
+    pages: Option<Vec<u32>>,
+) -> Result<PdfDocument, CommandError> {
+    Ok(sessions.apply(&session_id, DocumentEdit::InsertPages { at, source: source_path, pages })?)
+}
+
+/// Revert the most recent edit of an open document
+#[tauri::command]
+pub async fn undo(sessions: State<'_, SessionStore>, session_id: String) -> Result<PdfDocument, CommandError> {
+    Ok(sessions.undo(&session_id)?)
+}
+
+/// Reapply the most recently undone edit of an open document
+#[tauri::command]
+pub async fn redo(sessions: State<'_, SessionStore>, session_id: String) -> Result<PdfDocument, CommandError> {
+    Ok(sessions.redo(&session_id)?)
+}
+
+/// List the edits made to an open document, oldest first
+#[tauri::command]
+pub async fn history(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+) -> Result<Vec<HistoryEntry>, CommandError> {
+    Ok(sessions.history(&session_id)?)
+}
+
+/// Compare an open document with its file as it is on disk now, typically
+/// after another program changed it
+#[tauri::command]
+pub async fn diff_document_with_disk(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+) -> Result<DocumentDiff, CommandError> {
+    Ok(sessions.diff_with_disk(&session_id)?)
+}
+
+/// Replay the unsaved edits of an open document against its file on disk
+#[tauri::command]
+pub async fn replay_journal(
+    sessions: State<'_, SessionStore>,
+    watcher: State<'_, FileWatcher>,
+    session_id: String,
+    output_path: String,
+) -> Result<(), CommandError> {
+    Ok(watcher.guard_write(&output_path, WriteSource::Other, false, || {
+        sessions.replay(&session_id, Path::new(&output_path))
+    })?)
+}
+
+/// Apply the field values of an XFDF file to an open document
+#[tauri::command]
+pub async fn import_xfdf(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    xfdf_path: String,
+) -> Result<FormFieldTree, CommandError> {
+    let xml = std::fs::read_to_string(&xfdf_path)?;
+    let values = Xfdf::parse(&xml)?.into_iter().collect();
+
+    let edit = DocumentEdit::ImportFormData { source: xfdf_path, values };
+    Ok(sessions.apply(&session_id, edit)?.form)
+}
+
+/// Apply the field values of an FDF file to an open document
+#[tauri::command]
+pub async fn import_fdf(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    fdf_path: String,
+) -> Result<FormFieldTree, CommandError> {
+    let data = std::fs::read(&fdf_path)?;
+    let values = Fdf::parse(&data)?.into_iter().collect();
+
+    let edit = DocumentEdit::ImportFormData { source: fdf_path, values };
+    Ok(sessions.apply(&session_id, edit)?.form)
+}
+
+/// Apply the field values of a JSON data file to an open document
+#[tauri::command]
+pub async fn import_json(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    json_path: String,
+) -> Result<FormFieldTree, CommandError> {
+    let text = std::fs::read_to_string(&json_path)?;
+    let values = FormJson::parse(&text)?.into_iter().collect();
+
+    let edit = DocumentEdit::ImportFormData { source: json_path, values };
+    Ok(sessions.apply(&session_id, edit)?.form)
+}
+
+/// Describe the form data of an open document as a JSON Schema
+#[tauri::command]
+pub async fn get_form_schema(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+) -> Result<serde_json::Value, CommandError> {
+    Ok(sessions.with_session(&session_id, |session| Ok(FormJson::schema(&session.document)))?)
+}
+
+/// Export the values of every terminal form field of an open document as
+/// JSON, CSV, XFDF or FDF
+///
+/// JSON is an object keyed by fully-qualified field name, matching the
+/// schema from `get_form_schema`.
+/// FDF is binary and comes back base64 encoded.
+#[tauri::command]
+pub async fn export_form_data(
+    sessions: State<'_, SessionStore>,
+    session_id: String,
+    format: String,
+) -> Result<String, CommandError> {
+    let data = sessions.with_session(&session_id, |session| {
+        let file = session.path.file_name().map(|name| name.to_string_lossy().to_string());
+        match format.as_str() {
+            "json" => Ok(Some(FormJson::export_document(&session.document).to_string())),
+            "csv" => Ok(Some(FormCsv::export_document(&session.document))),
+            "xfdf" => Ok(Some(Xfdf::export_document(&session.document, file.as_deref()))),
+            "fdf" => Ok(Some(BASE64.encode(Fdf::export_document(&session.document, file.as_deref())?))),
+            _ => Ok(None),
+        }
+    })?;
+
+    data.ok_or_else(|| CommandError {
+        code: "INVALID_FORMAT_ERROR".to_string(),
+        message: format!("Unsupported export format: {}", format),
+    })
+}
+
+/// Fill a copy of a template PDF for every record of a CSV file
+///
+/// Returns the paths of the filled PDFs in record order. Replacing a file
+/// open in a session fails if another program changed it since.
+#[tauri::command]
+pub async fn batch_fill_csv(
+    watcher: State<'_, FileWatcher>,
+    template_path: String,
+    csv_path: String,
+    output_dir: String,
+    filename_pattern: String,
+) -> Result<Vec<String>, CommandError> {
+    let written = FormCsv::batch_fill(template_path, csv_path, output_dir, &filename_pattern, |path, document| {
+        watcher.guard_write(path, WriteSource::Other, false, || pdf::write_document(document, path))
+    })?;
+
+    Ok(written.into_iter().map(|path| path.to_string_lossy().to_string()).collect())
+}
+
 /// Generate appearance streams for form fields in a PDF
 #[tauri::command]
 pub async fn generate_appearance_streams(
+    watcher: State<'_, FileWatcher>,
     pdf_path: String,
     output_path: String,
 ) -> Result<(), CommandError> {
     // Generate appearance streams
-    FormFieldGenerator::generate_appearance_streams(pdf_path, output_path)?;
-    
+    watcher.guard_write(&output_path, WriteSource::Other, false, || {
+        FormFieldGenerator::generate_appearance_streams(&pdf_path, &output_path)
+    })?;
+
     Ok(())
 }
+
+/// Merge PDF files in the given order into a new file
+///
+/// Returns the form fields renamed because an earlier file had a field of the same name.
+#[tauri::command]
+pub async fn merge_pdfs(
+    watcher: State<'_, FileWatcher>,
+    paths: Vec<String>,
+    output_path: String,
+    options: Option<MergeOptions>,
+) -> Result<Vec<RenamedField>, CommandError> {
+    Ok(watcher.guard_write(&output_path, WriteSource::Other, false, || {
+        PdfMerger::merge_files(&paths, &output_path, &options.unwrap_or_default())
+    })?)
+}
+
+/// Split a PDF file into one part per page range, such as "1-3,5,8-"
+///
+/// Returns the paths of the parts, which are saved in the output directory.
+/// Replacing a file open in a session fails if another program changed it since.
+#[tauri::command]
+pub async fn split_pdf(
+    watcher: State<'_, FileWatcher>,
+    path: String,
+    ranges: String,
+    output_dir: String,
+) -> Result<Vec<String>, CommandError> {
+    let parts = PdfSplitter::split_by_ranges(&path, &ranges, &output_dir, |part_path, part| {
+        watcher.guard_write(part_path, WriteSource::Other, false, || pdf::write_document(part, part_path))
+    })?;
+    Ok(parts.iter().map(|part| part.to_string_lossy().to_string()).collect())
+}
+
+/// Split a PDF file into parts of a fixed number of pages
+///
+/// Returns the paths of the parts, which are saved in the output directory.
+/// Replacing a file open in a session fails if another program changed it since.
+#[tauri::command]
+pub async fn split_pdf_every(
+    watcher: State<'_, FileWatcher>,
+    path: String,
+    pages_per_part: u32,
+    output_dir: String,
+) -> Result<Vec<String>, CommandError> {
+    let parts = PdfSplitter::split_every(&path, pages_per_part, &output_dir, |part_path, part| {
+        watcher.guard_write(part_path, WriteSource::Other, false, || pdf::write_document(part, part_path))
+    })?;
+    Ok(parts.iter().map(|part| part.to_string_lossy().to_string()).collect())
+}
+
+/// Save selected pages of a PDF file, given as page ranges, as a new file
+#[tauri::command]
+pub async fn extract_pages(
+    watcher: State<'_, FileWatcher>,
+    path: String,
+    pages: String,
+    output_path: String,
+) -> Result<(), CommandError> {
+    Ok(watcher.guard_write(&output_path, WriteSource::Other, false, || {
+        PdfSplitter::extract_pages(&path, &pages, &output_path)
+    })?)
+}
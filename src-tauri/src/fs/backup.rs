@@ -0,0 +1,361 @@
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use super::{fnv1a, FileSystem, FsError, Result};
+
+/// File in the backup directory holding the retention policy
+const POLICY_FILE: &str = "policy.json";
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// How many backups of each document are kept
+///
+/// The newest backup of a document is always kept, whatever the limits say.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BackupPolicy {
+    /// Most backups kept per document
+    pub max_count: usize,
+    /// Days after which a backup is removed, or `None` to keep backups regardless of age
+    pub max_age_days: Option<u64>,
+    /// Most bytes the backups of one document may take up, or `None` for no limit
+    pub max_total_bytes: Option<u64>,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        BackupPolicy {
+            max_count: 20,
+            max_age_days: Some(30),
+            max_total_bytes: None,
+        }
+    }
+}
+
+/// A stored version of a document
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupInfo {
+    /// Identifies the backup among those of its document
+    pub id: String,
+    pub document_path: String,
+    /// Where the backup copy itself is stored
+    pub path: String,
+    /// Milliseconds since the Unix epoch when the backup was made
+    pub created: u64,
+    pub size: u64,
+}
+
+/// Keeps versions of documents in a backup directory outside the documents' own folders
+///
+/// Each document gets a subdirectory named after its path, holding one copy
+/// per backup named by the time it was made. The retention policy is applied
+/// whenever a backup is added. The policy itself is stored alongside the
+/// backups so it outlives the app.
+pub struct BackupManager {
+    root: PathBuf,
+    policy: Mutex<BackupPolicy>,
+}
+
+impl BackupManager {
+    /// Create a manager storing backups under `root`
+    pub fn new<P: Into<PathBuf>>(root: P, policy: BackupPolicy) -> Self {
+        BackupManager {
+            root: root.into(),
+            policy: Mutex::new(policy),
+        }
+    }
+
+    /// Create a manager storing backups under `root`, with the policy saved
+    /// there or the default one if none has been saved yet
+    pub fn load<P: Into<PathBuf>>(root: P) -> Self {
+        let root = root.into();
+        let policy = match fs::read(root.join(POLICY_FILE)) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                log::warn!("Ignoring unreadable backup policy: {}", e);
+                BackupPolicy::default()
+            }),
+            Err(_) => BackupPolicy::default(),
+        };
+        Self::new(root, policy)
+    }
+
+    pub fn policy(&self) -> BackupPolicy {
+        self.policy.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Change and save the retention policy, applying it to every document's backups
+    pub fn set_policy(&self, policy: BackupPolicy) -> Result<()> {
+        fs::create_dir_all(&self.root).map_err(|e| FsError::WriteError(format!("{}: {}", self.root.display(), e)))?;
+        let data = serde_json::to_vec_pretty(&policy).map_err(|e| FsError::WriteError(e.to_string()))?;
+        FileSystem::write_file(self.root.join(POLICY_FILE), &data)?;
+
+        *self.policy.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = policy;
+
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return Ok(());
+        };
+        for entry in entries.flatten() {
+            if entry.path().is_dir() {
+                self.prune_dir(&entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Store a copy of a document as it is on disk now
+    pub fn create<P: AsRef<Path>>(&self, document_path: P) -> Result<BackupInfo> {
+        let document_path = document_path.as_ref();
+        if !document_path.is_file() {
+            return Err(FsError::NotFound(document_path.to_string_lossy().to_string()));
+        }
+        let data = FileSystem::read_file(document_path)?;
+
+        let dir = self.document_dir(document_path);
+        fs::create_dir_all(&dir).map_err(|e| FsError::WriteError(format!("{}: {}", dir.display(), e)))?;
+
+        // Backups made within the same millisecond still get distinct names
+        let mut created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        while dir.join(format!("{}.pdf", created)).exists() {
+            created += 1;
+        }
+        let backup_path = dir.join(format!("{}.pdf", created));
+        FileSystem::write_file(&backup_path, &data)?;
+
+        self.prune_dir(&dir)?;
+
+        Ok(BackupInfo {
+            id: created.to_string(),
+            document_path: Self::key(document_path),
+            path: backup_path.to_string_lossy().to_string(),
+            created,
+            size: data.len() as u64,
+        })
+    }
+
+    /// List the backups of a document, newest first
+    pub fn list<P: AsRef<Path>>(&self, document_path: P) -> Result<Vec<BackupInfo>> {
+        let document_path = Self::key(document_path.as_ref());
+        let dir = self.document_dir(Path::new(&document_path));
+        Ok(Self::read_backups(&dir, &document_path))
+    }
+
+    /// Replace a document with one of its backups
+    ///
+    /// The document as it is now is backed up first, so a restore can itself
+    /// be rolled back. Returns that backup, if there was a document to back up.
+    pub fn restore<P: AsRef<Path>>(&self, document_path: P, backup_id: &str) -> Result<Option<BackupInfo>> {
+        let document_path = document_path.as_ref();
+        let backup = self.find(document_path, backup_id)?;
+        let data = FileSystem::read_file(&backup.path)?;
+
+        let previous = if document_path.is_file() {
+            Some(self.create(document_path)?)
+        } else {
+            None
+        };
+        FileSystem::write_pdf(document_path, &data)?;
+
+        Ok(previous)
+    }
+
+    /// Remove one backup of a document
+    pub fn delete<P: AsRef<Path>>(&self, document_path: P, backup_id: &str) -> Result<()> {
+        let backup = self.find(document_path.as_ref(), backup_id)?;
+        fs::remove_file(&backup.path).map_err(|e| FsError::WriteError(format!("{}: {}", backup.path, e)))
+    }
+
+    fn find(&self, document_path: &Path, backup_id: &str) -> Result<BackupInfo> {
+        self.list(document_path)?
+            .into_iter()
+            .find(|backup| backup.id == backup_id)
+            .ok_or_else(|| {
+                FsError::NotFound(format!("Backup {} of {}", backup_id, document_path.display()))
+            })
+    }
+
+    /// Remove the backups in a document's directory that the policy no longer allows
+    fn prune_dir(&self, dir: &Path) -> Result<()> {
+        let policy = self.policy();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let max_age = policy.max_age_days.map(|days| days * SECONDS_PER_DAY * 1000);
+
+        let mut total = 0;
+        for (index, backup) in Self::read_backups(dir, "").into_iter().enumerate() {
+            total += backup.size;
+            let keep = index == 0
+                || (index < policy.max_count
+                    && max_age.is_none_or(|max_age| now.saturating_sub(backup.created) <= max_age)
+                    && policy.max_total_bytes.is_none_or(|max_total| total <= max_total));
+            if !keep {
+                log::info!("Removing backup {} under the retention policy", backup.path);
+                fs::remove_file(&backup.path)
+                    .map_err(|e| FsError::WriteError(format!("{}: {}", backup.path, e)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The backups stored in a document's directory, newest first
+    fn read_backups(dir: &Path, document_path: &str) -> Vec<BackupInfo> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+
+        let mut backups: Vec<BackupInfo> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension().is_none_or(|extension| extension != "pdf") {
+                    return None;
+                }
+                let created: u64 = path.file_stem()?.to_str()?.parse().ok()?;
+                Some(BackupInfo {
+                    id: created.to_string(),
+                    document_path: document_path.to_string(),
+                    path: path.to_string_lossy().to_string(),
+                    created,
+                    size: entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+                })
+            })
+            .collect();
+        backups.sort_by_key(|backup| std::cmp::Reverse(backup.created));
+        backups
+    }
+
+    /// The directory holding a document's backups, named after the file for
+    /// browsing and a hash of its full path to keep namesakes apart
+    fn document_dir(&self, document_path: &Path) -> PathBuf {
+        let key = Self::key(document_path);
+        let stem: String = Path::new(&key)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+
+        self.root.join(format!("{}-{:016x}", stem, fnv1a(key.as_bytes())))
+    }
+
+    fn key(path: &Path) -> String {
+        path.canonicalize()
+            .unwrap_or_else(|_| path.to_path_buf())
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::testing::blank_document;
+
+    const DAY: u64 = SECONDS_PER_DAY * 1000;
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+    }
+
+    /// Put backups of the given ages in milliseconds and sizes in a document's directory
+    fn plant(manager: &BackupManager, document_path: &Path, backups: &[(u64, usize)]) -> PathBuf {
+        let dir = manager.document_dir(document_path);
+        fs::create_dir_all(&dir).unwrap();
+        let now = now();
+        for (age, size) in backups {
+            fs::write(dir.join(format!("{}.pdf", now - age)), vec![b'x'; *size]).unwrap();
+        }
+        dir
+    }
+
+    /// The ages in days of the backups a policy keeps out of the given ones
+    fn pruned(policy: BackupPolicy, backups: &[(u64, usize)]) -> Vec<u64> {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = BackupManager::new(dir.path().join("backups"), policy);
+        let document_path = dir.path().join("form.pdf");
+        let backup_dir = plant(&manager, &document_path, backups);
+
+        manager.prune_dir(&backup_dir).unwrap();
+        let now = now();
+        manager.list(&document_path).unwrap().iter().map(|backup| (now - backup.created) / DAY).collect()
+    }
+
+    #[test]
+    fn backups_beyond_the_count_are_removed_oldest_first() {
+        let policy = BackupPolicy { max_count: 2, max_age_days: None, max_total_bytes: None };
+        let backups = [(0, 10), (DAY, 10), (2 * DAY, 10), (3 * DAY, 10)];
+
+        assert_eq!(pruned(policy, &backups), vec![0, 1]);
+    }
+
+    #[test]
+    fn backups_older_than_the_age_limit_are_removed() {
+        let policy = BackupPolicy { max_count: 10, max_age_days: Some(2), max_total_bytes: None };
+        let backups = [(0, 10), (DAY, 10), (3 * DAY, 10), (5 * DAY, 10)];
+
+        assert_eq!(pruned(policy, &backups), vec![0, 1]);
+    }
+
+    #[test]
+    fn backups_beyond_the_size_limit_are_removed_oldest_first() {
+        let policy = BackupPolicy { max_count: 10, max_age_days: None, max_total_bytes: Some(250) };
+        let backups = [(0, 100), (DAY, 100), (2 * DAY, 100), (3 * DAY, 10)];
+
+        assert_eq!(pruned(policy, &backups), vec![0, 1]);
+    }
+
+    #[test]
+    fn the_newest_backup_is_kept_whatever_the_limits() {
+        let policy = BackupPolicy { max_count: 0, max_age_days: Some(1), max_total_bytes: Some(10) };
+        let backups = [(3 * DAY, 100), (4 * DAY, 100)];
+
+        assert_eq!(pruned(policy, &backups), vec![3]);
+    }
+
+    #[test]
+    fn restoring_backs_up_the_current_version_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = BackupManager::new(dir.path().join("backups"), BackupPolicy::default());
+        let document_path = dir.path().join("form.pdf");
+        let pdf = |pages| {
+            let mut data = Vec::new();
+            blank_document(pages).save_to(&mut data).unwrap();
+            data
+        };
+
+        fs::write(&document_path, pdf(1)).unwrap();
+        let original = manager.create(&document_path).unwrap();
+        fs::write(&document_path, pdf(2)).unwrap();
+
+        let previous = manager.restore(&document_path, &original.id).unwrap().unwrap();
+        assert_eq!(fs::read(&document_path).unwrap(), pdf(1));
+        assert_eq!(fs::read(&previous.path).unwrap(), pdf(2));
+
+        let ids: Vec<String> = manager.list(&document_path).unwrap().into_iter().map(|backup| backup.id).collect();
+        assert_eq!(ids, vec![previous.id, original.id]);
+        assert!(matches!(manager.restore(&document_path, "0"), Err(FsError::NotFound(_))));
+    }
+
+    #[test]
+    fn policy_is_loaded_after_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = BackupPolicy {
+            max_count: 3,
+            max_age_days: None,
+            max_total_bytes: Some(1024),
+        };
+        BackupManager::load(dir.path()).set_policy(policy).unwrap();
+
+        let reloaded = BackupManager::load(dir.path()).policy();
+        assert_eq!(reloaded.max_count, 3);
+        assert_eq!(reloaded.max_age_days, None);
+        assert_eq!(reloaded.max_total_bytes, Some(1024));
+    }
+}
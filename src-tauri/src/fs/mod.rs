@@ -1,10 +1,17 @@
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::fs;
+use std::io::Write;
+use std::time::UNIX_EPOCH;
 use serde::{Serialize, Deserialize};
-use tauri::api::dialog;
-use tempfile::NamedTempFile;
 use thiserror::Error;
 
+mod backup;
+mod recent;
+mod watch;
+pub use backup::{BackupInfo, BackupManager, BackupPolicy};
+pub use recent::{RecentFile, RecentFileList, RecentFiles, MAX_RECENT_FILES};
+pub use watch::{FileChange, FileFingerprint, FileWatcher, WriteSource, DOCUMENT_CHANGED_EVENT, WATCH_INTERVAL};
+
 /// Error types for file system operations
 #[derive(Error, Debug)]
 pub enum FsError {
@@ -20,8 +27,17 @@ pub enum FsError {
     #[error("Permission denied: {0}")]
     PermissionDenied(String),
     
+    #[error("File already exists: {0}")]
+    AlreadyExists(String),
+    
     #[error("Operation cancelled by user")]
     Cancelled,
+
+    #[error("Saved file failed verification: {0}")]
+    VerificationFailed(String),
+
+    #[error("File was changed by another program since it was opened: {0}")]
+    ModifiedExternally(String),
 }
 
 /// Result type for file system operations
@@ -34,6 +50,20 @@ pub struct FileInfo {
     pub name: String,
     pub size: u64,
     pub last_modified: Option<String>,
+    /// Hash of the file's content, to tell whether it has changed
+    pub content_hash: String,
+}
+
+/// Options controlling how a file is saved
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SaveOptions {
+    /// Back up the existing file before replacing it
+    pub create_backup: bool,
+    /// Replace an existing file instead of failing
+    pub overwrite: bool,
+    /// Save even if the file was changed by another program since it was opened
+    #[serde(default)]
+    pub force: bool,
 }
 
 /// File system operations implementation
@@ -45,18 +75,103 @@ impl FileSystem {
         fs::read(path).map_err(FsError::ReadError)
     }
     
-    /// Write bytes to a file
+    /// Write bytes to a file, replacing it atomically
+    ///
+    /// The file either keeps its old content or gets all of the new content,
+    /// however the write is interrupted.
     pub fn write_file<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<()> {
-        fs::write(path, data).map_err(|e| FsError::WriteError(e.to_string()))
+        Self::replace_atomically(path.as_ref(), data, |_| Ok(()))
+    }
+    
+    /// Write PDF data to a file, replacing it atomically once the written
+    /// data has been read back as a PDF
+    pub fn write_pdf<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<()> {
+        Self::replace_atomically(path.as_ref(), data, |written| {
+            lopdf::Document::load(written)
+                .map(|_| ())
+                .map_err(|e| FsError::VerificationFailed(format!(
+                    "{} does not reparse as a PDF: {}",
+                    path.as_ref().display(),
+                    e
+                )))
+        })
     }
     
-    /// Create a temporary file
-    pub fn create_temp_file() -> Result<PathBuf> {
-        let temp_file = NamedTempFile::new().map_err(FsError::ReadError)?;
-        let path = temp_file.path().to_path_buf();
-        // Keep the file on disk by leaking the tempfile
-        std::mem::forget(temp_file);
-        Ok(path)
+    /// Save PDF data to a file, honouring the overwrite and backup options
+    pub fn save_file<P: AsRef<Path>>(
+        path: P,
+        data: &[u8],
+        options: &SaveOptions,
+        backups: &BackupManager,
+    ) -> Result<()> {
+        let path_ref = path.as_ref();
+        if path_ref.exists() {
+            if !options.overwrite {
+                return Err(FsError::AlreadyExists(path_ref.to_string_lossy().to_string()));
+            }
+            if options.create_backup {
+                backups.create(path_ref)?;
+            }
+        }
+        
+        Self::write_pdf(path_ref, data)
+    }
+    
+    /// Write data to a sibling temp file, flush it to disk, check it, and
+    /// rename it over the target
+    ///
+    /// The target's permissions carry over to the new file. A failed check
+    /// leaves the target untouched and removes the temp file.
+    fn replace_atomically(path: &Path, data: &[u8], verify: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+        let write_error = |e: std::io::Error| FsError::WriteError(format!("{}: {}", path.display(), e));
+        
+        // The temp file must be on the same file system for the rename to be atomic
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or_else(|| FsError::WriteError(format!("{} is not a file path", path.display())))?;
+        let permissions = fs::metadata(path).ok().map(|metadata| metadata.permissions());
+        
+        let mut temp_file = tempfile::Builder::new()
+            .prefix(&format!(".{}.", file_name))
+            .suffix(".tmp")
+            .tempfile_in(parent)
+            .map_err(write_error)?;
+        temp_file.write_all(data).map_err(write_error)?;
+        temp_file.as_file().sync_all().map_err(write_error)?;
+        
+        verify(temp_file.path())?;
+        
+        match permissions {
+            Some(permissions) => fs::set_permissions(temp_file.path(), permissions).map_err(write_error)?,
+            None => Self::set_new_file_permissions(temp_file.path()).map_err(write_error)?,
+        }
+        
+        temp_file.persist(path).map_err(|e| write_error(e.error))?;
+        
+        // Make the rename itself durable; not every platform can sync a directory
+        if let Ok(dir) = fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
+        
+        Ok(())
+    }
+    
+    /// Temp files are private to the user, so give new files the usual
+    /// readable permissions instead
+    #[cfg(unix)]
+    fn set_new_file_permissions(path: &Path) -> std::io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o644))
+    }
+    
+    #[cfg(not(unix))]
+    fn set_new_file_permissions(_path: &Path) -> std::io::Result<()> {
+        Ok(())
     }
     
     /// Get file information
@@ -74,7 +189,7 @@ impl FileSystem {
             .modified()
             .ok()
             .map(|time| {
-                time.duration_since(std::time::UNIX_EPOCH)
+                time.duration_since(UNIX_EPOCH)
                     .map(|d| d.as_secs().to_string())
                     .unwrap_or_else(|_| "Unknown".to_string())
             });
@@ -84,6 +199,20 @@ impl FileSystem {
             name,
             size: metadata.len(),
             last_modified,
+            content_hash: Self::fingerprint(path_ref)?.content_hash,
+        })
+    }
+    
+    /// Identify the current version of a file by its modification time, size and content
+    pub fn fingerprint<P: AsRef<Path>>(path: P) -> Result<FileFingerprint> {
+        let path_ref = path.as_ref();
+        let modified = FileFingerprint::modified(path_ref)?;
+        let data = Self::read_file(path_ref)?;
+        
+        Ok(FileFingerprint {
+            modified,
+            size: data.len() as u64,
+            content_hash: format!("{:016x}", fnv1a(&data)),
         })
     }
     
@@ -91,26 +220,79 @@ impl FileSystem {
     pub fn file_exists<P: AsRef<Path>>(path: P) -> bool {
         path.as_ref().exists()
     }
-    
-    /// Create a backup of a file
-    pub fn create_backup<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
-        let path_ref = path.as_ref();
-        if !path_ref.exists() {
-            return Err(FsError::NotFound(path_ref.to_string_lossy().to_string()));
-        }
-        
-        let file_name = path_ref
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown");
-            
-        let parent = path_ref.parent().unwrap_or(Path::new("."));
-        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
-        let backup_name = format!("{}.{}.bak", file_name, timestamp);
-        let backup_path = parent.join(backup_name);
-        
-        fs::copy(path_ref, &backup_path).map_err(FsError::ReadError)?;
-        
-        Ok(backup_path)
+}
+
+/// 64-bit FNV-1a, a hash that stays the same across builds and platforms
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandError;
+    use crate::pdf::testing::blank_document;
+
+    fn pdf_bytes() -> Vec<u8> {
+        let mut data = Vec::new();
+        blank_document(1).save_to(&mut data).unwrap();
+        data
     }
-} 
\ No newline at end of file
+
+    fn dir_entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn unverified_writes_leave_the_original_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("form.pdf");
+        let original = pdf_bytes();
+        fs::write(&path, &original).unwrap();
+
+        let error = FileSystem::write_pdf(&path, b"not a pdf").unwrap_err();
+        assert!(matches!(error, FsError::VerificationFailed(_)));
+        assert_eq!(CommandError::from(error).code, "SAVE_VERIFICATION_FAILED");
+        assert_eq!(fs::read(&path).unwrap(), original);
+        assert_eq!(dir_entries(dir.path()), vec!["form.pdf"]);
+    }
+
+    #[test]
+    fn replaced_files_leave_no_temp_file_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("form.pdf");
+        fs::write(&path, b"old").unwrap();
+
+        let data = pdf_bytes();
+        FileSystem::write_pdf(&path, &data).unwrap();
+        FileSystem::write_file(dir.path().join("notes.txt"), b"new file").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), data);
+        assert_eq!(dir_entries(dir.path()), vec!["form.pdf", "notes.txt"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replaced_files_keep_their_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("form.pdf");
+        fs::write(&path, b"old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        FileSystem::write_file(&path, b"new").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+
+        let created = dir.path().join("created.pdf");
+        FileSystem::write_file(&created, b"new").unwrap();
+        assert_eq!(fs::metadata(&created).unwrap().permissions().mode() & 0o777, 0o644);
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use super::{FsError, Result};
+
+/// Number of unpinned files remembered; pinned files are always kept
+pub const MAX_RECENT_FILES: usize = 20;
+
+/// A recently opened file as remembered between runs
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecentFile {
+    pub path: String,
+    pub name: String,
+    /// Seconds since the Unix epoch when the file was last opened
+    pub last_opened: u64,
+    /// Zero-based index of the page last viewed
+    #[serde(default)]
+    pub last_page: Option<u32>,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// The recent files list, pinned files first and then most recent first
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(transparent)]
+pub struct RecentFiles {
+    files: Vec<RecentFile>,
+}
+
+impl RecentFiles {
+    /// The listed files in display order
+    pub fn files(&self) -> &[RecentFile] {
+        &self.files
+    }
+
+    /// Move a file to the top of the list, adding it if it is new
+    pub fn record<P: AsRef<Path>>(&mut self, path: P) {
+        let path = Self::key(path.as_ref());
+        let last_opened = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let file = match self.files.iter().position(|file| file.path == path) {
+            Some(index) => RecentFile {
+                last_opened,
+                ..self.files.remove(index)
+            },
+            None => RecentFile {
+                name: Path::new(&path)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| path.clone()),
+                path,
+                last_opened,
+                last_page: None,
+                pinned: false,
+            },
+        };
+
+        // Files opened within the same second keep the latest on top
+        self.files.insert(0, file);
+        self.arrange();
+    }
+
+    /// Remember the page last viewed in a file
+    pub fn set_last_page<P: AsRef<Path>>(&mut self, path: P, page: u32) -> Result<()> {
+        self.find_mut(path.as_ref())?.last_page = Some(page);
+        Ok(())
+    }
+
+    /// Pin or unpin a file, which keeps it listed however old it gets
+    pub fn set_pinned<P: AsRef<Path>>(&mut self, path: P, pinned: bool) -> Result<()> {
+        self.find_mut(path.as_ref())?.pinned = pinned;
+        self.arrange();
+        Ok(())
+    }
+
+    /// Forget the listed files, except for pinned ones if asked to keep them
+    pub fn clear(&mut self, keep_pinned: bool) {
+        self.files.retain(|file| keep_pinned && file.pinned);
+    }
+
+    /// Drop unpinned files that no longer exist, returning whether any were dropped
+    ///
+    /// Pinned files stay listed, as they may live on a drive that is only
+    /// unavailable for now.
+    pub fn prune(&mut self) -> bool {
+        let count = self.files.len();
+        self.files.retain(|file| file.pinned || Path::new(&file.path).is_file());
+        self.files.len() != count
+    }
+
+    fn find_mut(&mut self, path: &Path) -> Result<&mut RecentFile> {
+        let key = Self::key(path);
+        self.files
+            .iter_mut()
+            .find(|file| file.path == key)
+            .ok_or_else(|| FsError::NotFound(format!("{} is not a recent file", key)))
+    }
+
+    /// Sort the list and trim the oldest unpinned files beyond the limit
+    fn arrange(&mut self) {
+        self.files.sort_by(|a, b| b.pinned.cmp(&a.pinned).then(b.last_opened.cmp(&a.last_opened)));
+
+        let mut unpinned = 0;
+        self.files.retain(|file| {
+            if file.pinned {
+                return true;
+            }
+            unpinned += 1;
+            unpinned <= MAX_RECENT_FILES
+        });
+    }
+
+    /// The same file may be opened through different paths, so list it by
+    /// its canonical one where possible
+    fn key(path: &Path) -> String {
+        path.canonicalize()
+            .unwrap_or_else(|_| path.to_path_buf())
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// The recent files list shared between commands, managed as Tauri state
+///
+/// The list is loaded from the store on first use and kept in memory after
+/// that, so concurrent commands never overwrite each other's changes.
+#[derive(Default)]
+pub struct RecentFileList {
+    files: Mutex<Option<RecentFiles>>,
+}
+
+impl RecentFileList {
+    /// Lock the list, which is `None` until it has been loaded
+    pub fn lock(&self) -> MutexGuard<'_, Option<RecentFiles>> {
+        self.files.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pruning_keeps_pinned_files_that_are_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("existing.pdf");
+        std::fs::write(&existing, b"%PDF-1.5").unwrap();
+
+        let mut recent = RecentFiles::default();
+        for name in ["pinned.pdf", "unpinned.pdf"] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, b"%PDF-1.5").unwrap();
+            recent.record(&path);
+        }
+        recent.record(&existing);
+        recent.set_pinned(dir.path().join("pinned.pdf"), true).unwrap();
+        for name in ["pinned.pdf", "unpinned.pdf"] {
+            std::fs::remove_file(dir.path().join(name)).unwrap();
+        }
+
+        assert!(recent.prune());
+        let names: Vec<&str> = recent.files().iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, ["pinned.pdf", "existing.pdf"]);
+    }
+}
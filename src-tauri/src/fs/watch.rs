@@ -0,0 +1,388 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, UNIX_EPOCH};
+use super::{FileSystem, FsError, Result};
+
+/// How often open documents are checked for changes on disk
+///
+/// Watching is done by polling, as change notifications are unreliable on
+/// network shares, which is where documents edited by others tend to live.
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Event emitted with a [`FileChange`] when an open document changes on disk
+pub const DOCUMENT_CHANGED_EVENT: &str = "document-changed";
+
+/// Identifies a version of a file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FileFingerprint {
+    /// Milliseconds since the Unix epoch when the file was last modified
+    pub modified: Option<u64>,
+    pub size: u64,
+    pub content_hash: String,
+}
+
+impl FileFingerprint {
+    /// Whether a file's modification time and size still match, which is
+    /// checked before going to the trouble of hashing it
+    fn matches_metadata(&self, metadata: &fs::Metadata) -> bool {
+        self.size == metadata.len() && self.modified == Self::modified_time(metadata)
+    }
+
+    pub(super) fn modified(path: &Path) -> Result<Option<u64>> {
+        Ok(Self::modified_time(&fs::metadata(path)?))
+    }
+
+    fn modified_time(metadata: &fs::Metadata) -> Option<u64> {
+        metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64)
+    }
+}
+
+/// Where the data the app is about to write to a file comes from, which
+/// decides what the sessions with that file open know of it afterwards
+#[derive(Debug, Clone, Copy)]
+pub enum WriteSource<'a> {
+    /// A session saving its document, to its own file or to a new one
+    Session(&'a str),
+    /// Document data from the frontend, standing for what every session
+    /// with the file open shows
+    Document,
+    /// Anything else, such as a filled copy or a restored backup, which the
+    /// sessions with the file open are told about as a change
+    Other,
+}
+
+/// An open document that was changed on disk by another program
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileChange {
+    pub session_id: String,
+    pub path: String,
+    /// The version now on disk, or `None` if the file was removed
+    pub fingerprint: Option<FileFingerprint>,
+}
+
+struct WatchedFile {
+    path: PathBuf,
+    /// The version the session was loaded from or last saved as
+    known: FileFingerprint,
+    /// The version last seen on disk, `None` once the file is gone
+    on_disk: Option<FileFingerprint>,
+    /// Set while the app itself writes the file, so the write is not reported
+    suspended: bool,
+}
+
+/// Tracks the files behind open documents to notice when they change on disk
+///
+/// Each session remembers the version of its file it was loaded from or last
+/// saved as. A change is reported once per new version, and only when the
+/// content differs from that version, so touching a file is not a change.
+#[derive(Default)]
+pub struct FileWatcher {
+    files: Mutex<HashMap<String, WatchedFile>>,
+}
+
+impl FileWatcher {
+    /// Start watching the file of a session, or move the session to another file
+    ///
+    /// The fingerprint should be taken before the file is read, so changes
+    /// made while reading it are not missed.
+    pub fn watch<P: AsRef<Path>>(&self, session_id: &str, path: P, fingerprint: FileFingerprint) {
+        self.lock().insert(
+            session_id.to_string(),
+            WatchedFile {
+                path: path.as_ref().to_path_buf(),
+                known: fingerprint.clone(),
+                on_disk: Some(fingerprint),
+                suspended: false,
+            },
+        );
+    }
+
+    /// Stop reporting changes to the file of a session until it is watched
+    /// again or resumed, for the duration of a save
+    pub fn suspend(&self, session_id: &str) {
+        if let Some(file) = self.lock().get_mut(session_id) {
+            file.suspended = true;
+        }
+    }
+
+    /// Report changes to the file of a session again after a failed save
+    pub fn resume(&self, session_id: &str) {
+        if let Some(file) = self.lock().get_mut(session_id) {
+            file.suspended = false;
+        }
+    }
+
+    /// The sessions whose file is at a path, however the path is spelled
+    pub fn sessions_watching<P: AsRef<Path>>(&self, path: P) -> Vec<String> {
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let target = canonical(path.as_ref());
+        let mut sessions: Vec<String> = self
+            .lock()
+            .iter()
+            .filter(|(_, file)| canonical(&file.path) == target)
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+        sessions.sort();
+        sessions
+    }
+
+    /// Write a file without losing changes another program made to it
+    ///
+    /// Every session with the file open must still know the version on disk,
+    /// unless `force` is set. The sessions are not told about the write
+    /// itself; afterwards those holding the written data watch the new
+    /// version, and the others see it as a change on their next check.
+    pub fn guard_write<P, T, E>(
+        &self,
+        path: P,
+        source: WriteSource,
+        force: bool,
+        write: impl FnOnce() -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E>
+    where
+        P: AsRef<Path>,
+        E: From<FsError>,
+    {
+        let path = path.as_ref();
+        let owners = self.sessions_watching(path);
+        if !force {
+            for owner in &owners {
+                self.ensure_unchanged(owner)?;
+            }
+        }
+
+        let saving = match source {
+            WriteSource::Session(session_id) => Some(session_id),
+            _ => None,
+        };
+        let suspended: Vec<&str> = owners.iter().map(String::as_str).chain(saving).collect();
+        for session_id in &suspended {
+            self.suspend(session_id);
+        }
+
+        let result = write();
+        let fingerprint = match &result {
+            Ok(_) => FileSystem::fingerprint(path)
+                .map_err(|err| log::warn!("Failed to fingerprint {} after writing it: {}", path.display(), err))
+                .ok(),
+            Err(_) => None,
+        };
+
+        for session_id in suspended {
+            let holds_written = match source {
+                WriteSource::Session(saving) => session_id == saving,
+                WriteSource::Document => true,
+                WriteSource::Other => false,
+            };
+            match &fingerprint {
+                Some(fingerprint) if saving == Some(session_id) => self.watch(session_id, path, fingerprint.clone()),
+                Some(fingerprint) if holds_written => self.refresh(session_id, fingerprint.clone()),
+                _ => self.resume(session_id),
+            }
+        }
+        result
+    }
+
+    /// Take a version of the file of a session as the one it knows
+    fn refresh(&self, session_id: &str, fingerprint: FileFingerprint) {
+        if let Some(file) = self.lock().get_mut(session_id) {
+            file.known = fingerprint.clone();
+            file.on_disk = Some(fingerprint);
+            file.suspended = false;
+        }
+    }
+
+    pub fn unwatch(&self, session_id: &str) {
+        self.lock().remove(session_id);
+    }
+
+    /// Fail if the file of a session no longer holds the version the session knows
+    ///
+    /// A removed file is no reason to fail, as saving simply writes it again.
+    pub fn ensure_unchanged(&self, session_id: &str) -> Result<()> {
+        let Some((path, known)) = self
+            .lock()
+            .get(session_id)
+            .map(|file| (file.path.clone(), file.known.clone()))
+        else {
+            return Ok(());
+        };
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let current = FileSystem::fingerprint(&path)?;
+        if current.content_hash != known.content_hash {
+            return Err(FsError::ModifiedExternally(path.to_string_lossy().to_string()));
+        }
+        Ok(())
+    }
+
+    /// Check every watched file, returning the changes not reported before
+    pub fn check(&self) -> Vec<FileChange> {
+        // Files are read without holding the lock, as they may be on a slow share
+        let watched: Vec<(String, PathBuf, Option<FileFingerprint>)> = self
+            .lock()
+            .iter()
+            .filter(|(_, file)| !file.suspended)
+            .map(|(session_id, file)| (session_id.clone(), file.path.clone(), file.on_disk.clone()))
+            .collect();
+
+        let mut changes = Vec::new();
+        for (session_id, path, on_disk) in watched {
+            let current = match fs::metadata(&path) {
+                Ok(metadata) => {
+                    if on_disk.as_ref().is_some_and(|on_disk| on_disk.matches_metadata(&metadata)) {
+                        continue;
+                    }
+                    match FileSystem::fingerprint(&path) {
+                        Ok(fingerprint) => Some(fingerprint),
+                        Err(err) => {
+                            log::warn!("Failed to check {} for changes: {}", path.display(), err);
+                            continue;
+                        }
+                    }
+                }
+                Err(_) if on_disk.is_none() => continue,
+                Err(_) => None,
+            };
+
+            let mut files = self.lock();
+            // The session may have been closed or saved while the file was read
+            let Some(file) = files
+                .get_mut(&session_id)
+                .filter(|file| !file.suspended && file.on_disk == on_disk)
+            else {
+                continue;
+            };
+            let previous = std::mem::replace(&mut file.on_disk, current.clone());
+
+            let previous_content = previous.as_ref().map(|fingerprint| &fingerprint.content_hash);
+            let current_content = current.as_ref().map(|fingerprint| &fingerprint.content_hash);
+            if current_content != previous_content && current_content != Some(&file.known.content_hash) {
+                changes.push(FileChange {
+                    session_id,
+                    path: path.to_string_lossy().to_string(),
+                    fingerprint: current,
+                });
+            }
+        }
+        changes
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, WatchedFile>> {
+        self.files.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_made_while_suspended_are_not_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("form.pdf");
+        fs::write(&path, b"first").unwrap();
+
+        let watcher = FileWatcher::default();
+        watcher.watch("session", &path, FileSystem::fingerprint(&path).unwrap());
+
+        watcher.suspend("session");
+        fs::write(&path, b"saved by us").unwrap();
+        assert!(watcher.check().is_empty());
+        watcher.watch("session", &path, FileSystem::fingerprint(&path).unwrap());
+        assert!(watcher.check().is_empty());
+
+        fs::write(&path, b"changed by someone else").unwrap();
+        let changes = watcher.check();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].session_id, "session");
+    }
+
+    #[test]
+    fn guarded_writes_refuse_to_replace_changed_files_unless_forced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("form.pdf");
+        fs::write(&path, b"first").unwrap();
+
+        let watcher = FileWatcher::default();
+        watcher.watch("session", &path, FileSystem::fingerprint(&path).unwrap());
+        fs::write(&path, b"changed by someone else").unwrap();
+
+        let write = |data: &'static [u8]| {
+            let path = path.clone();
+            move || fs::write(&path, data).map_err(FsError::from)
+        };
+        let result = watcher.guard_write(&path, WriteSource::Document, false, write(b"ours"));
+        assert!(matches!(result, Err(FsError::ModifiedExternally(_))));
+        assert_eq!(fs::read(&path).unwrap(), b"changed by someone else");
+
+        watcher.guard_write(&path, WriteSource::Document, true, write(b"ours")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"ours");
+        assert!(watcher.check().is_empty());
+        watcher.ensure_unchanged("session").unwrap();
+    }
+
+    #[test]
+    fn guarded_writes_of_other_data_are_reported_to_the_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("form.pdf");
+        fs::write(&path, b"first").unwrap();
+        let other = dir.path().join("other.pdf");
+
+        let watcher = FileWatcher::default();
+        watcher.watch("first", &path, FileSystem::fingerprint(&path).unwrap());
+        watcher.watch("second", &path, FileSystem::fingerprint(&path).unwrap());
+
+        // Saving one session over the file leaves the other one behind. The
+        // data differs in size, as the write may land within the same
+        // modification time
+        watcher
+            .guard_write(&path, WriteSource::Session("first"), false, || fs::write(&path, b"saved by first").map_err(FsError::from))
+            .unwrap();
+        let changes = watcher.check();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].session_id, "second");
+
+        // Saving to a new file moves the session there
+        watcher
+            .guard_write(&other, WriteSource::Session("first"), false, || fs::write(&other, b"saved").map_err(FsError::from))
+            .unwrap();
+        assert_eq!(watcher.sessions_watching(&other), ["first"]);
+
+        watcher
+            .guard_write(&other, WriteSource::Other, false, || fs::write(&other, b"filled").map_err(FsError::from))
+            .unwrap();
+        let changes = watcher.check();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].session_id, "first");
+        assert!(matches!(watcher.ensure_unchanged("first"), Err(FsError::ModifiedExternally(_))));
+    }
+
+    #[test]
+    fn sessions_are_found_by_any_spelling_of_their_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("forms")).unwrap();
+        let path = dir.path().join("forms").join("form.pdf");
+        fs::write(&path, b"first").unwrap();
+        let other = dir.path().join("other.pdf");
+        fs::write(&other, b"other").unwrap();
+
+        let watcher = FileWatcher::default();
+        watcher.watch("first", &path, FileSystem::fingerprint(&path).unwrap());
+        watcher.watch("second", &path, FileSystem::fingerprint(&path).unwrap());
+        watcher.watch("other", &other, FileSystem::fingerprint(&other).unwrap());
+
+        let spelled = dir.path().join("forms").join("..").join("forms").join("form.pdf");
+        assert_eq!(watcher.sessions_watching(&spelled), ["first", "second"]);
+        assert!(watcher.sessions_watching(dir.path().join("missing.pdf")).is_empty());
+    }
+}
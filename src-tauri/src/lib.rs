@@ -1,23 +1,128 @@
-mod commands;
-mod fs;
-mod pdf;
+pub mod commands;
+pub mod fs;
+pub mod pdf;
+pub mod session;
 
 use commands::*;
+use fs::{BackupManager, FileWatcher, RecentFileList, DOCUMENT_CHANGED_EVENT, WATCH_INTERVAL};
+use session::{RecoveryStore, SessionStore, AUTOSAVE_INTERVAL};
+use tauri::{Emitter, Manager};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Initialize logger
     env_logger::init();
 
     tauri::Builder::default()
-        .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
-        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_fs::init())
+        .plugin(tauri_plugin_shell::init())
+        .plugin(tauri_plugin_store::Builder::new().build())
+        .manage(SessionStore::default())
+        .manage(RecentFileList::default())
+        .manage(FileWatcher::default())
+        .setup(|app| {
+            let app_data_dir = app.path().app_data_dir()?;
+            app.manage(BackupManager::load(app_data_dir.join("backups")));
+
+            let recovery = RecoveryStore::new(app_data_dir.join("recovery"));
+            let unfinished = recovery.list().len();
+            if unfinished > 0 {
+                log::info!("Found {} document(s) with unsaved edits from an earlier run", unfinished);
+            }
+            app.manage(recovery);
+
+            // Keep working copies of edited documents in case the app is killed,
+            // on a thread of its own as serializing and writing them blocks
+            let handle = app.handle().clone();
+            std::thread::Builder::new().name("autosave".to_string()).spawn(move || loop {
+                std::thread::sleep(AUTOSAVE_INTERVAL);
+                handle.state::<SessionStore>().autosave(&handle.state::<RecoveryStore>());
+            })?;
+
+            // Tell the frontend when another program changes an open document,
+            // on a thread of its own as reading the files blocks
+            let handle = app.handle().clone();
+            std::thread::Builder::new().name("file-watcher".to_string()).spawn(move || loop {
+                std::thread::sleep(WATCH_INTERVAL);
+                // Edits may have evicted sessions since the last check
+                let watcher = handle.state::<FileWatcher>();
+                unwatch_evicted(&handle.state::<SessionStore>(), &watcher);
+                for change in watcher.check() {
+                    if let Err(err) = handle.emit(DOCUMENT_CHANGED_EVENT, &change) {
+                        log::warn!("Failed to report a change to {}: {}", change.path, err);
+                    }
+                }
+            })?;
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
-            open_pdf,
+            open_pdf_dialog,
+            parse_pdf,
+            open_document,
+            close_document,
+            list_recoverable_sessions,
+            recover_session,
+            discard_recoverable_session,
+            save_document,
+            set_session_memory_limit,
+            get_recent_files,
+            pin_recent_file,
+            unpin_recent_file,
+            set_recent_file_page,
+            clear_recent_files,
             get_page_info,
             get_total_pages,
-            get_recent_files,
-            save_pdf
+            get_form_fields,
+            extract_text,
+            transform_coordinates,
+            save_pdf_dialog,
+            save_pdf_file,
+            save_pdf,
+            get_file_info,
+            create_file_backup,
+            list_backups,
+            restore_backup,
+            delete_backup,
+            get_backup_policy,
+            set_backup_policy,
+            file_exists,
+            read_file_base64,
+            write_file_base64,
+            add_form_fields_to_pdf,
+            create_form_from_layout,
+            fill_form,
+            flatten_form,
+            update_form_field,
+            delete_form_field,
+            add_document_form_fields,
+            fill_document_form,
+            flatten_document_form,
+            update_document_form_field,
+            delete_document_form_field,
+            generate_document_appearance_streams,
+            update_document_metadata,
+            move_document_pages,
+            rotate_document_pages,
+            delete_document_pages,
+            insert_blank_pages,
+            insert_document_pages,
+            undo,
+            redo,
+            history,
+            diff_document_with_disk,
+            replay_journal,
+            export_form_data,
+            import_xfdf,
+            import_fdf,
+            import_json,
+            get_form_schema,
+            batch_fill_csv,
+            generate_appearance_streams,
+            merge_pdfs,
+            split_pdf,
+            split_pdf_every,
+            extract_pages,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
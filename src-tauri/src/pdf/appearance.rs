@@ -0,0 +1,257 @@
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Object, Stream, StringFormat};
+use serde::{Deserialize, Serialize};
+use crate::pdf::font::{self, StandardFont};
+use crate::pdf::layout::TextLayout;
+use crate::pdf::Result;
+
+/// Default appearance used when neither a field nor its form define one
+pub const DEFAULT_APPEARANCE: &str = "/Helv 0 Tf 0 g";
+
+/// Space kept between a widget's edge and its text, in points
+const TEXT_PADDING: f64 = 2.0;
+
+/// Parsed default appearance (`/DA`) string of a variable text field
+#[derive(Debug, Clone)]
+pub struct DefaultAppearance {
+    /// Font resource name in the `/DR /Font` dictionary
+    pub font_name: Vec<u8>,
+    /// Font size in points, where 0 means auto-size
+    pub font_size: f64,
+    /// Remaining operators, usually the text colour
+    pub operations: Vec<Operation>,
+}
+
+impl DefaultAppearance {
+    /// Parse a `/DA` string such as `/Helv 12 Tf 0 g`
+    pub fn parse(da: &[u8]) -> Self {
+        let mut font_name = b"Helv".to_vec();
+        let mut font_size = 0.0;
+        let mut operations = Vec::new();
+
+        if let Ok(content) = Content::decode(da) {
+            for operation in content.operations {
+                if operation.operator == "Tf" && operation.operands.len() == 2 {
+                    if let Ok(name) = operation.operands[0].as_name() {
+                        font_name = name.to_vec();
+                    }
+                    font_size = operation.operands[1].as_float().unwrap_or(0.0) as f64;
+                } else {
+                    operations.push(operation);
+                }
+            }
+        }
+
+        Self {
+            font_name,
+            font_size,
+            operations,
+        }
+    }
+}
+
+/// Horizontal text alignment, from the `/Q` (quadding) entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextAlignment {
+    Left,
+    Center,
+    Right,
+}
+
+impl TextAlignment {
+    /// Map a `/Q` value to an alignment, defaulting to left
+    pub fn from_quadding(quadding: i64) -> Self {
+        match quadding {
+            1 => TextAlignment::Center,
+            2 => TextAlignment::Right,
+            _ => TextAlignment::Left,
+        }
+    }
+}
+
+/// Everything needed to draw the normal appearance of a text field widget
+#[derive(Debug, Clone)]
+pub struct TextAppearance<'a> {
+    pub value: &'a str,
+    pub width: f64,
+    pub height: f64,
+    pub appearance: &'a DefaultAppearance,
+    pub font: StandardFont,
+    pub alignment: TextAlignment,
+    pub multiline: bool,
+    pub password: bool,
+    /// Background colour from the widget's `/MK /BG`
+    pub background: Option<Vec<f64>>,
+    /// Border colour from the widget's `/MK /BC`
+    pub border: Option<Vec<f64>>,
+}
+
+impl TextAppearance<'_> {
+    /// Build the appearance as a Form XObject using the given font resource
+    pub fn to_stream(&self, font_resource: Object) -> Result<Stream> {
+        let mut font_dict = Dictionary::new();
+        font_dict.set(self.appearance.font_name.clone(), font_resource);
+
+        let mut resources = Dictionary::new();
+        resources.set("Font", Object::Dictionary(font_dict));
+
+        let mut stream = Stream::new(Dictionary::new(), self.content()?);
+        stream.dict.set("Type", Object::Name("XObject".as_bytes().to_vec()));
+        stream.dict.set("Subtype", Object::Name("Form".as_bytes().to_vec()));
+        stream.dict.set("FormType", Object::Integer(1));
+        stream.dict.set("BBox", Object::Array(vec![
+            Object::Real(0.0),
+            Object::Real(0.0),
+            Object::Real(self.width as f32),
+            Object::Real(self.height as f32),
+        ]));
+        stream.dict.set("Resources", Object::Dictionary(resources));
+
+        Ok(stream)
+    }
+
+    /// Encode the content stream of the appearance
+    pub fn content(&self) -> Result<Vec<u8>> {
+        let mut operations = Vec::new();
+        let (width, height) = (self.width, self.height);
+
+        // Widget background and border sit outside the marked text content
+        if let Some(op) = self.background.as_deref().and_then(|c| color_operation(c, false)) {
+            operations.push(op);
+            operations.push(Operation::new("re", reals(&[0.0, 0.0, width, height])));
+            operations.push(Operation::new("f", vec![]));
+        }
+        if let Some(op) = self.border.as_deref().and_then(|c| color_operation(c, true)) {
+            operations.push(op);
+            operations.push(Operation::new("w", reals(&[1.0])));
+            operations.push(Operation::new("re", reals(&[0.5, 0.5, width - 1.0, height - 1.0])));
+            operations.push(Operation::new("S", vec![]));
+        }
+
+        operations.push(Operation::new("BMC", vec![Object::Name(b"Tx".to_vec())]));
+        operations.push(Operation::new("q", vec![]));
+
+        // Clip to the widget so long values never spill onto the page
+        operations.push(Operation::new("re", reals(&[1.0, 1.0, width - 2.0, height - 2.0])));
+        operations.push(Operation::new("W", vec![]));
+        operations.push(Operation::new("n", vec![]));
+
+        let text = self.display_text();
+        if !text.is_empty() {
+            let layout = TextLayout::new(
+                &text,
+                self.font,
+                width - 2.0 * TEXT_PADDING,
+                height - 2.0 * TEXT_PADDING,
+                self.appearance.font_size,
+                self.multiline,
+            );
+            let font_size = layout.font_size;
+
+            // Multiline text hangs from the top; a single line is centred vertically
+            let first_baseline = if self.multiline {
+                height - TEXT_PADDING - self.font.ascent() * font_size / 1000.0
+            } else {
+                let line_height = (self.font.ascent() - self.font.descent()) * font_size / 1000.0;
+                (height - line_height) / 2.0 - self.font.descent() * font_size / 1000.0
+            };
+
+            operations.push(Operation::new("BT", vec![]));
+            operations.extend(self.appearance.operations.iter().cloned());
+            operations.push(Operation::new("Tf", vec![
+                Object::Name(self.appearance.font_name.clone()),
+                Object::Real(font_size as f32),
+            ]));
+
+            // Td is relative to the start of the previous line
+            let (mut previous_x, mut previous_y) = (0.0, 0.0);
+            for (index, line) in layout.lines.into_iter().enumerate() {
+                let x = match self.alignment {
+                    TextAlignment::Left => TEXT_PADDING,
+                    TextAlignment::Center => (width - line.width) / 2.0,
+                    TextAlignment::Right => width - TEXT_PADDING - line.width,
+                };
+                let y = first_baseline - layout.leading * index as f64;
+
+                operations.push(Operation::new("Td", reals(&[x - previous_x, y - previous_y])));
+                operations.push(Operation::new("Tj", vec![Object::String(line.text, StringFormat::Literal)]));
+                (previous_x, previous_y) = (x, y);
+            }
+            operations.push(Operation::new("ET", vec![]));
+        }
+
+        operations.push(Operation::new("Q", vec![]));
+        operations.push(Operation::new("EMC", vec![]));
+
+        Ok(Content { operations }.encode()?)
+    }
+
+    /// The WinAnsi-encoded text to draw
+    fn display_text(&self) -> Vec<u8> {
+        if self.password {
+            return vec![b'*'; self.value.chars().count()];
+        }
+        font::encode_win_ansi(self.value)
+    }
+}
+
+/// Build the colour operator for a `/MK` colour array
+pub(crate) fn color_operation(components: &[f64], stroke: bool) -> Option<Operation> {
+    let operator = match (components.len(), stroke) {
+        (1, false) => "g",
+        (1, true) => "G",
+        (3, false) => "rg",
+        (3, true) => "RG",
+        (4, false) => "k",
+        (4, true) => "K",
+        // An empty array means transparent
+        _ => return None,
+    };
+    Some(Operation::new(operator, reals(components)))
+}
+
+/// Convert numbers to PDF real operands
+pub(crate) fn reals(values: &[f64]) -> Vec<Object> {
+    values.iter().map(|v| Object::Real(*v as f32)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The strings drawn by the `Tj` operators of an appearance
+    fn drawn_lines(value: &str, multiline: bool) -> Vec<Vec<u8>> {
+        let appearance = DefaultAppearance::parse(b"/Helv 10 Tf 0 g");
+        let text = TextAppearance {
+            value,
+            width: 200.0,
+            height: 60.0,
+            appearance: &appearance,
+            font: StandardFont::Helvetica,
+            alignment: TextAlignment::Left,
+            multiline,
+            password: false,
+            background: None,
+            border: None,
+        };
+        let content = Content::decode(&text.content().unwrap()).unwrap();
+        content
+            .operations
+            .into_iter()
+            .filter(|operation| operation.operator == "Tj")
+            .map(|operation| operation.operands[0].as_str().unwrap().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn line_breaks_in_multiline_values_start_new_lines() {
+        assert_eq!(drawn_lines("one\ntwo", true), [b"one".to_vec(), b"two".to_vec()]);
+        assert_eq!(drawn_lines("one\r\ntwo", true), [b"one".to_vec(), b"two".to_vec()]);
+    }
+
+    #[test]
+    fn line_breaks_in_single_line_values_become_spaces() {
+        assert_eq!(drawn_lines("one\ntwo", false), [b"one two".to_vec()]);
+    }
+}
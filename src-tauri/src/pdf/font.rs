@@ -0,0 +1,98 @@
+/// Glyph widths of Helvetica for WinAnsiEncoding codes 32 to 255, in 1/1000 em
+const HELVETICA_WIDTHS: [u16; 224] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584, 350,
+    556, 350, 222, 556, 333, 1000, 556, 556, 333, 1000, 667, 333, 1000, 350, 611, 350,
+    350, 222, 222, 333, 333, 350, 556, 1000, 333, 1000, 500, 333, 944, 350, 500, 667,
+    278, 333, 556, 556, 556, 556, 260, 556, 333, 737, 370, 556, 584, 333, 737, 333,
+    400, 584, 333, 333, 333, 556, 537, 278, 333, 333, 365, 556, 834, 834, 834, 611,
+    667, 667, 667, 667, 667, 667, 1000, 722, 667, 667, 667, 667, 278, 278, 278, 278,
+    722, 722, 778, 778, 778, 778, 778, 584, 778, 722, 722, 722, 722, 667, 667, 611,
+    556, 556, 556, 556, 556, 556, 889, 500, 556, 556, 556, 556, 278, 278, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 584, 611, 556, 556, 556, 556, 500, 556, 500,
+];
+
+/// Unicode code points of WinAnsiEncoding codes 128 to 159
+const WIN_ANSI_HIGH: [(u8, char); 27] = [
+    (0x80, '\u{20AC}'), (0x82, '\u{201A}'), (0x83, '\u{0192}'), (0x84, '\u{201E}'),
+    (0x85, '\u{2026}'), (0x86, '\u{2020}'), (0x87, '\u{2021}'), (0x88, '\u{02C6}'),
+    (0x89, '\u{2030}'), (0x8A, '\u{0160}'), (0x8B, '\u{2039}'), (0x8C, '\u{0152}'),
+    (0x8E, '\u{017D}'), (0x91, '\u{2018}'), (0x92, '\u{2019}'), (0x93, '\u{201C}'),
+    (0x94, '\u{201D}'), (0x95, '\u{2022}'), (0x96, '\u{2013}'), (0x97, '\u{2014}'),
+    (0x98, '\u{02DC}'), (0x99, '\u{2122}'), (0x9A, '\u{0161}'), (0x9B, '\u{203A}'),
+    (0x9C, '\u{0153}'), (0x9E, '\u{017E}'), (0x9F, '\u{0178}'),
+];
+
+/// A standard 14 font whose metrics are known without embedding
+///
+/// Only the families commonly referenced from `/DA` strings are measured
+/// exactly; any other font falls back to Helvetica metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StandardFont {
+    Helvetica,
+    Courier,
+}
+
+impl StandardFont {
+    /// Pick the metrics for a font's `/BaseFont` name
+    pub fn from_base_font(base_font: &[u8]) -> Self {
+        if base_font.starts_with(b"Courier") {
+            StandardFont::Courier
+        } else {
+            StandardFont::Helvetica
+        }
+    }
+
+    /// Width of a WinAnsi-encoded glyph in 1/1000 em
+    pub fn glyph_width(&self, code: u8) -> u16 {
+        match self {
+            StandardFont::Courier => 600,
+            StandardFont::Helvetica => match code {
+                32..=255 => HELVETICA_WIDTHS[(code - 32) as usize],
+                _ => 0,
+            },
+        }
+    }
+
+    /// Width of WinAnsi-encoded text at the given font size, in points
+    pub fn text_width(&self, text: &[u8], font_size: f64) -> f64 {
+        let units: u32 = text.iter().map(|code| self.glyph_width(*code) as u32).sum();
+        units as f64 * font_size / 1000.0
+    }
+
+    /// Ascender height in 1/1000 em
+    pub fn ascent(&self) -> f64 {
+        match self {
+            StandardFont::Helvetica => 718.0,
+            StandardFont::Courier => 629.0,
+        }
+    }
+
+    /// Descender depth in 1/1000 em (negative)
+    pub fn descent(&self) -> f64 {
+        match self {
+            StandardFont::Helvetica => -207.0,
+            StandardFont::Courier => -157.0,
+        }
+    }
+}
+
+/// Encode text as WinAnsiEncoding, replacing unsupported characters with `?`
+///
+/// Line breaks are kept so that text layout can act on them.
+pub fn encode_win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x0A | 0x0D | 0x20..=0x7E | 0xA0..=0xFF => c as u8,
+            _ => WIN_ANSI_HIGH
+                .iter()
+                .find(|(_, mapped)| *mapped == c)
+                .map(|(code, _)| *code)
+                .unwrap_or(b'?'),
+        })
+        .collect()
+}
@@ -1,20 +1,75 @@
 use lopdf::{Document, Object, Dictionary, Stream, ObjectId, StringFormat};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use serde::{Serialize, Deserialize};
 use std::path::Path;
 use std::fs;
-use crate::pdf::{PdfError, Result};
+use crate::pdf::{self, PdfError, Result};
+use crate::pdf::appearance::{DefaultAppearance, TextAlignment, TextAppearance, DEFAULT_APPEARANCE};
+use crate::pdf::font::StandardFont;
+use crate::pdf::objects::prune_unreachable_among;
+
+mod csv;
+mod edit;
+mod fdf;
+mod fill;
+mod flatten;
+mod json;
+mod kind;
+mod template;
+mod tree;
+mod xfdf;
+pub use csv::FormCsv;
+pub use edit::{FieldRef, FieldUpdate, FormEditor};
+pub use fdf::Fdf;
+pub use fill::FormFiller;
+pub use flatten::FormFlattener;
+pub use json::FormJson;
+pub use kind::{ChoiceOption, FieldFlags, FieldKind};
+pub use template::{FormLayout, LayoutElement, LayoutField, LayoutPage, LayoutWidget, PageSize};
+pub use tree::{FormFieldTree, PdfFieldNode, PdfFieldValue, PdfFieldWidget};
+pub use xfdf::Xfdf;
+
+// Annotation flag bits (PDF 32000-1:2008, section 12.5.3)
+const ANNOT_PRINT: i64 = 1 << 2;
 
 /// Represents a PDF form field with its properties
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PdfFormField {
     pub id: String,
     pub name: String,
-    pub field_type: String,
+    pub field_type: FieldKind,
     pub value: Option<String>,
     pub rect: [f64; 4],
     pub page: u32,
+    #[serde(default = "FieldFlags::for_new_field")]
+    pub flags: FieldFlags,
+    /// Options of choice fields and radio groups
+    #[serde(default)]
+    pub options: Vec<ChoiceOption>,
+    /// Appearance settings such as `fontSize`, `alignment` and `maxLength`
+    #[serde(default)]
     pub properties: HashMap<String, String>,
+    /// Per-option widgets for fields that have several, such as radio groups
+    #[serde(default)]
+    pub widgets: Vec<PdfFormWidget>,
+}
+
+/// A single widget annotation belonging to a form field
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PdfFormWidget {
+    pub rect: [f64; 4],
+    pub page: u32,
+    pub export_value: Option<String>,
+}
+
+/// Inheritable text field attributes resolved for a single widget
+#[derive(Debug, Clone, Default)]
+struct TextFieldAttributes {
+    field_type: Vec<u8>,
+    value: String,
+    default_appearance: Option<Vec<u8>>,
+    quadding: i64,
+    flags: i64,
 }
 
 /// Form field generator for PDF documents
@@ -30,16 +85,25 @@ impl FormFieldGenerator {
         // Load the PDF document
         let mut document = Document::load(pdf_path.as_ref())?;
         
+        Self::add_fields_to_document(&mut document, fields)?;
+        
+        // Save the modified document
+        pdf::write_document(&mut document, output_path)?;
+        
+        Ok(())
+    }
+    
+    /// Add form fields to a loaded document
+    pub fn add_fields_to_document(document: &mut Document, fields: Vec<PdfFormField>) -> Result<()> {
         // Create or get the AcroForm dictionary
-        let acro_form_id = Self::ensure_acro_form(&mut document)?;
+        let acro_form_id = Self::ensure_acro_form(document)?;
         
         // Add each field to the document
+        let mut replaced = HashSet::new();
         for field in fields {
-            Self::add_field_to_document(&mut document, field, acro_form_id)?;
+            Self::add_field_to_document(document, field, acro_form_id, &mut replaced)?;
         }
-        
-        // Save the modified document
-        document.save(output_path.as_ref())?;
+        prune_unreachable_among(document, replaced);
         
         Ok(())
     }
@@ -49,12 +113,10 @@ impl FormFieldGenerator {
         // Check if the document already has an AcroForm
         let root_id = document.trailer.get(b"Root")
             .and_then(|obj| obj.as_reference())
-            .ok_or_else(|| PdfError::MalformedPdf("Missing Root dictionary".to_string()))?;
+            .map_err(|_| PdfError::MalformedPdf("Missing Root dictionary".to_string()))?;
             
-        let root_dict = document.get_dictionary(root_id)?;
-        
         // If AcroForm exists, return its ID
-        if let Some(acro_form) = root_dict.get(b"AcroForm").and_then(|obj| obj.as_reference()) {
+        if let Some(acro_form) = ensure_acro_form_id(document) {
             return Ok(acro_form);
         }
         
@@ -69,13 +131,7 @@ impl FormFieldGenerator {
         let mut font_dict = Dictionary::new();
         
         // Add Helvetica font
-        let mut helvetica_dict = Dictionary::new();
-        helvetica_dict.set("Type", Object::Name("Font".as_bytes().to_vec()));
-        helvetica_dict.set("Subtype", Object::Name("Type1".as_bytes().to_vec()));
-        helvetica_dict.set("BaseFont", Object::Name("Helvetica".as_bytes().to_vec()));
-        helvetica_dict.set("Encoding", Object::Name("WinAnsiEncoding".as_bytes().to_vec()));
-        
-        let helvetica_id = document.add_object(Object::Dictionary(helvetica_dict));
+        let helvetica_id = document.add_object(Object::Dictionary(Self::helvetica_font()));
         font_dict.set("Helv", Object::Reference(helvetica_id));
         
         // Add ZapfDingbats font for checkboxes
@@ -94,7 +150,7 @@ impl FormFieldGenerator {
         let acro_form_id = document.add_object(Object::Dictionary(acro_form_dict));
         
         // Update the root dictionary
-        let mut root = document.get_dictionary_mut(root_id)?;
+        let root = document.get_dictionary_mut(root_id)?;
         root.set("AcroForm", Object::Reference(acro_form_id));
         
         Ok(acro_form_id)
@@ -104,52 +160,58 @@ impl FormFieldGenerator {
     fn add_field_to_document(
         document: &mut Document, 
         field: PdfFormField, 
-        acro_form_id: ObjectId
+        acro_form_id: ObjectId,
+        replaced: &mut HashSet<ObjectId>,
     ) -> Result<ObjectId> {
         // Create the field dictionary
         let mut field_dict = Dictionary::new();
         
         // Set common properties
-        field_dict.set("T", Object::String(field.name.as_bytes().to_vec(), StringFormat::Literal));
+        field_dict.set("T", pdf::encode_text_string(&field.name));
         field_dict.set("Type", Object::Name("Annot".as_bytes().to_vec()));
         field_dict.set("Subtype", Object::Name("Widget".as_bytes().to_vec()));
         
-        // Set the field type
-        match field.field_type.as_str() {
-            "text" => {
-                field_dict.set("FT", Object::Name("Tx".as_bytes().to_vec()));
-                
+        // Set the field type and flags
+        field_dict.set("FT", Object::Name(field.field_type.field_type().as_bytes().to_vec()));
+        let mut flags = field.flags;
+        if field.field_type == FieldKind::Text && field.properties.get("multiline").is_some_and(|m| m == "true") {
+            flags.multiline = true;
+        }
+        field_dict.set("Ff", Object::Integer(flags.bits(field.field_type)));
+        
+        match field.field_type {
+            // Radio groups are split into a parent field and one widget per option
+            FieldKind::Radio => return Self::add_radio_group_to_document(document, field, acro_form_id),
+            FieldKind::Text => {
                 // Set text field properties
-                if let Some(value) = field.value {
-                    field_dict.set("V", Object::String(value.as_bytes().to_vec(), StringFormat::Literal));
+                if let Some(value) = &field.value {
+                    field_dict.set("V", pdf::encode_text_string(value));
                 }
                 
+                // Set the default appearance used to draw the value
+                let font_size = field.properties
+                    .get("fontSize")
+                    .and_then(|size| size.parse::<f64>().ok())
+                    .unwrap_or(0.0);
+                field_dict.set("DA", Object::String(format!("/Helv {} Tf 0 g", font_size).into_bytes(), StringFormat::Literal));
+                
+                let quadding = match field.properties.get("alignment").map(String::as_str) {
+                    Some("center") => 1,
+                    Some("right") => 2,
+                    _ => 0,
+                };
+                field_dict.set("Q", Object::Integer(quadding));
+                
                 // Set additional properties
                 if let Some(max_length) = field.properties.get("maxLength") {
                     if let Ok(max_len) = max_length.parse::<i64>() {
                         field_dict.set("MaxLen", Object::Integer(max_len));
                     }
                 }
-                
-                // Set multiline flag if specified
-                if let Some(multiline) = field.properties.get("multiline") {
-                    if multiline == "true" {
-                        let mut flags = 0;
-                        flags |= 1 << 12; // Multiline flag
-                        field_dict.set("Ff", Object::Integer(flags));
-                    }
-                }
             },
-            "checkbox" => {
-                field_dict.set("FT", Object::Name("Btn".as_bytes().to_vec()));
-                
-                // Set button field flags (checkbox)
-                let mut flags = 0;
-                flags |= 1 << 15; // Radio button flag
-                field_dict.set("Ff", Object::Integer(flags));
-                
+            FieldKind::Checkbox => {
                 // Set checkbox states
-                let is_checked = field.value.unwrap_or_default() == "true";
+                let is_checked = field.value.as_deref() == Some("true");
                 
                 // Define the appearance states
                 let mut ap_dict = Dictionary::new();
@@ -177,34 +239,35 @@ impl FormFieldGenerator {
                     field_dict.set("AS", Object::Name("Off".as_bytes().to_vec()));
                 }
             },
-            "dropdown" => {
-                field_dict.set("FT", Object::Name("Ch".as_bytes().to_vec()));
-                
-                // Set choice field flags (dropdown)
-                let mut flags = 0;
-                flags |= 1 << 17; // Combo box flag
-                field_dict.set("Ff", Object::Integer(flags));
+            FieldKind::Combo | FieldKind::List => {
+                // Set options as export/display pairs
+                if !field.options.is_empty() {
+                    field_dict.set("Opt", ChoiceOption::to_opt_array(&field.options));
+                }
                 
-                // Set options
-                if let Some(options_str) = field.properties.get("options") {
-                    let options: Vec<&str> = options_str.split(',').collect();
-                    let mut opt_array = vec![];
-                    
-                    for option in options {
-                        opt_array.push(Object::String(option.trim().as_bytes().to_vec(), StringFormat::Literal));
+                // Set selected value, matching either the export or the display value
+                if let Some(value) = &field.value {
+                    let selected = field.options
+                        .iter()
+                        .position(|option| option.export_value == *value || option.display_value == *value);
+                    match selected {
+                        Some(index) => {
+                            field_dict.set("V", pdf::encode_text_string(&field.options[index].export_value));
+                            field_dict.set("I", Object::Array(vec![Object::Integer(index as i64)]));
+                        }
+                        None => field_dict.set("V", pdf::encode_text_string(value)),
                     }
-                    
-                    field_dict.set("Opt", Object::Array(opt_array));
                 }
-                
-                // Set selected value
-                if let Some(value) = field.value {
-                    field_dict.set("V", Object::String(value.as_bytes().to_vec(), StringFormat::Literal));
+            },
+            FieldKind::PushButton => {
+                // Push buttons hold no value; the value is used as the caption
+                if let Some(caption) = &field.value {
+                    let mut mk_dict = Dictionary::new();
+                    mk_dict.set("CA", pdf::encode_text_string(caption));
+                    field_dict.set("MK", Object::Dictionary(mk_dict));
                 }
             },
-            "signature" => {
-                field_dict.set("FT", Object::Name("Sig".as_bytes().to_vec()));
-                
+            FieldKind::Signature => {
                 // Create empty signature dictionary
                 let mut sig_dict = Dictionary::new();
                 sig_dict.set("Type", Object::Name("Sig".as_bytes().to_vec()));
@@ -213,39 +276,186 @@ impl FormFieldGenerator {
                 
                 field_dict.set("V", Object::Dictionary(sig_dict));
             },
-            _ => return Err(PdfError::UnsupportedOperation(format!("Unsupported field type: {}", field.field_type))),
         }
         
         // Set the field's rectangle (position and size)
-        let rect_array = vec![
-            Object::Real(field.rect[0]),
-            Object::Real(field.rect[1]),
-            Object::Real(field.rect[2]),
-            Object::Real(field.rect[3]),
-        ];
-        field_dict.set("Rect", Object::Array(rect_array));
-        
-        // Set the page reference
-        let pages_id = document.get_pages_id()?;
-        let page_id = document.get_object_id(pages_id, field.page as usize + 1)
-            .ok_or_else(|| PdfError::InvalidPage(field.page))?;
+        field_dict.set("Rect", Self::rect_object(&field.rect));
+        
+        // Set the page reference and make the widget print with the page
+        let page_id = Self::page_object_id(document, field.page)?;
         field_dict.set("P", Object::Reference(page_id));
+        field_dict.set("F", Object::Integer(ANNOT_PRINT));
         
         // Add the field to the document
         let field_id = document.add_object(Object::Dictionary(field_dict));
         
-        // Add the field to the AcroForm's Fields array
-        let acro_form = document.get_dictionary_mut(acro_form_id)?;
-        if let Some(Object::Array(fields)) = acro_form.get_mut(b"Fields") {
-            fields.push(Object::Reference(field_id));
+        // Add the field to the AcroForm's Fields array and its widget to the page
+        Self::register_field(document, acro_form_id, field_id)?;
+        add_annotation(document, page_id, field_id)?;
+        
+        // Text fields get a real appearance so the value shows in every viewer
+        if field.field_type == FieldKind::Text {
+            let attributes = Self::text_field_attributes(document.get_dictionary(field_id)?, TextFieldAttributes::default());
+            Self::write_text_appearance(document, field_id, &attributes, acro_form_id, replaced)?;
         }
         
         Ok(field_id)
     }
     
+    /// Add a radio button group to the document
+    ///
+    /// The group is a single parent field holding the value and the `/Opt`
+    /// export values, with one widget annotation per option in its `/Kids`.
+    fn add_radio_group_to_document(
+        document: &mut Document,
+        field: PdfFormField,
+        acro_form_id: ObjectId
+    ) -> Result<ObjectId> {
+        let widgets = Self::radio_widgets(&field);
+        if widgets.is_empty() {
+            return Err(PdfError::UnsupportedOperation(format!("Radio group '{}' has no options", field.name)));
+        }
+        
+        // Appearance states are named after the index of the option's export
+        // value, so values that are not valid PDF names still round-trip and
+        // widgets sharing an export value switch on together in unison mode
+        let export_values: Vec<String> = widgets
+            .iter()
+            .map(|widget| widget.export_value.clone().unwrap_or_default())
+            .collect();
+        let state_names: Vec<String> = export_values
+            .iter()
+            .map(|value| export_values.iter().position(|v| v == value).unwrap_or(0).to_string())
+            .collect();
+        
+        let selected_state = field.value
+            .as_ref()
+            .and_then(|value| export_values.iter().position(|v| v == value))
+            .map(|index| state_names[index].clone())
+            .unwrap_or_else(|| "Off".to_string());
+        
+        // Create the parent field dictionary
+        let mut parent_dict = Dictionary::new();
+        parent_dict.set("FT", Object::Name("Btn".as_bytes().to_vec()));
+        parent_dict.set("T", pdf::encode_text_string(&field.name));
+        parent_dict.set("Ff", Object::Integer(field.flags.bits(FieldKind::Radio)));
+        parent_dict.set("V", Object::Name(selected_state.as_bytes().to_vec()));
+        parent_dict.set("Opt", ChoiceOption::to_opt_array(
+            &export_values.iter().map(ChoiceOption::new).collect::<Vec<_>>(),
+        ));
+        
+        let parent_id = document.add_object(Object::Dictionary(parent_dict));
+        
+        // Create one widget per option
+        let mut kids = Vec::with_capacity(widgets.len());
+        for (widget, state_name) in widgets.iter().zip(&state_names) {
+            let width = (widget.rect[2] - widget.rect[0]).abs();
+            let height = (widget.rect[3] - widget.rect[1]).abs();
+            
+            let on_id = document.add_object(Object::Stream(Self::create_radio_appearance(true, width, height)));
+            let off_id = document.add_object(Object::Stream(Self::create_radio_appearance(false, width, height)));
+            
+            let mut n_dict = Dictionary::new();
+            n_dict.set(state_name.as_bytes().to_vec(), Object::Reference(on_id));
+            n_dict.set("Off", Object::Reference(off_id));
+            
+            let mut ap_dict = Dictionary::new();
+            ap_dict.set("N", Object::Dictionary(n_dict));
+            
+            // Appearance characteristics let viewers rebuild the widget consistently
+            let mut mk_dict = Dictionary::new();
+            mk_dict.set("BC", Object::Array(vec![Object::Real(0.0)]));
+            mk_dict.set("BG", Object::Array(vec![Object::Real(1.0)]));
+            mk_dict.set("CA", Object::String(b"l".to_vec(), StringFormat::Literal));
+            
+            let is_selected = *state_name == selected_state;
+            let state = if is_selected { state_name.as_str() } else { "Off" };
+            
+            let page_id = Self::page_object_id(document, widget.page)?;
+            
+            let mut widget_dict = Dictionary::new();
+            widget_dict.set("Type", Object::Name("Annot".as_bytes().to_vec()));
+            widget_dict.set("Subtype", Object::Name("Widget".as_bytes().to_vec()));
+            widget_dict.set("Parent", Object::Reference(parent_id));
+            widget_dict.set("Rect", Self::rect_object(&widget.rect));
+            widget_dict.set("P", Object::Reference(page_id));
+            widget_dict.set("F", Object::Integer(ANNOT_PRINT));
+            widget_dict.set("AP", Object::Dictionary(ap_dict));
+            widget_dict.set("AS", Object::Name(state.as_bytes().to_vec()));
+            widget_dict.set("MK", Object::Dictionary(mk_dict));
+            
+            let widget_id = document.add_object(Object::Dictionary(widget_dict));
+            add_annotation(document, page_id, widget_id)?;
+            kids.push(Object::Reference(widget_id));
+        }
+        
+        document.get_dictionary_mut(parent_id)?.set("Kids", Object::Array(kids));
+        
+        // Only the parent goes into the AcroForm's Fields array
+        Self::register_field(document, acro_form_id, parent_id)?;
+        
+        Ok(parent_id)
+    }
+    
+    /// Resolve the widgets of a radio group
+    ///
+    /// Explicit widgets are used as given. Otherwise one widget per option is
+    /// laid out left to right inside the field's rect.
+    fn radio_widgets(field: &PdfFormField) -> Vec<PdfFormWidget> {
+        if !field.widgets.is_empty() {
+            return field.widgets.clone();
+        }
+        
+        let options = &field.options;
+        if options.is_empty() {
+            return Vec::new();
+        }
+        
+        let [x1, y1, x2, y2] = field.rect;
+        let slot_width = (x2 - x1) / options.len().max(1) as f64;
+        let size = slot_width.min(y2 - y1);
+        
+        options
+            .iter()
+            .enumerate()
+            .map(|(i, option)| {
+                let x = x1 + slot_width * i as f64;
+                PdfFormWidget {
+                    rect: [x, y1, x + size, y1 + size],
+                    page: field.page,
+                    export_value: Some(option.export_value.clone()),
+                }
+            })
+            .collect()
+    }
+    
+    /// Look up the object id of a page by its zero-based index
+    fn page_object_id(document: &Document, page: u32) -> Result<ObjectId> {
+        document
+            .get_pages()
+            .get(&(page + 1))
+            .copied()
+            .ok_or(PdfError::InvalidPage(page))
+    }
+    
+    /// Append a field reference to the AcroForm's Fields array
+    fn register_field(document: &mut Document, acro_form_id: ObjectId, field_id: ObjectId) -> Result<()> {
+        let acro_form = document.get_dictionary_mut(acro_form_id)?;
+        match acro_form.get_mut(b"Fields") {
+            Ok(Object::Array(fields)) => fields.push(Object::Reference(field_id)),
+            _ => acro_form.set("Fields", Object::Array(vec![Object::Reference(field_id)])),
+        }
+        Ok(())
+    }
+    
+    /// Convert a rectangle to a PDF array
+    fn rect_object(rect: &[f64; 4]) -> Object {
+        Object::Array(rect.iter().map(|v| Object::Real(*v as f32)).collect())
+    }
+    
     /// Create a checkbox appearance stream
     fn create_checkbox_appearance(checked: bool) -> Stream {
-        let content = if checked {
+        let content: &[u8] = if checked {
             // ZapfDingbats checkmark
             b"/ZaDb 12 Tf 0 0 0 rg 0.3 0.3 0.4 0.4 re f 0.2 0.2 0.6 0.6 re W n BT /ZaDb 12 Tf 0 0 Td (4) Tj ET"
         } else {
@@ -267,7 +477,56 @@ impl FormFieldGenerator {
         stream
     }
     
+    /// Create a radio button appearance stream sized to its widget
+    fn create_radio_appearance(selected: bool, width: f64, height: f64) -> Stream {
+        let cx = width / 2.0;
+        let cy = height / 2.0;
+        let radius = (width.min(height) / 2.0 - 0.5).max(0.0);
+        
+        // White background with a black circular border
+        let mut content = format!(
+            "1 g {} f 0 G 1 w {} s",
+            Self::circle_path(cx, cy, radius),
+            Self::circle_path(cx, cy, radius),
+        );
+        
+        // Filled dot for the selected state
+        if selected {
+            content.push_str(&format!(" 0 g {} f", Self::circle_path(cx, cy, radius * 0.5)));
+        }
+        
+        let mut stream = Stream::new(Dictionary::new(), content.into_bytes());
+        stream.dict.set("Type", Object::Name("XObject".as_bytes().to_vec()));
+        stream.dict.set("Subtype", Object::Name("Form".as_bytes().to_vec()));
+        stream.dict.set("FormType", Object::Integer(1));
+        stream.dict.set("BBox", Self::rect_object(&[0.0, 0.0, width, height]));
+        
+        stream
+    }
+    
+    /// Build a closed circle path from four Bezier curves
+    fn circle_path(cx: f64, cy: f64, r: f64) -> String {
+        // Control point distance for a quarter-circle approximation
+        let k = r * 0.552_284_75;
+        format!(
+            "{:.3} {:.3} m \
+             {:.3} {:.3} {:.3} {:.3} {:.3} {:.3} c \
+             {:.3} {:.3} {:.3} {:.3} {:.3} {:.3} c \
+             {:.3} {:.3} {:.3} {:.3} {:.3} {:.3} c \
+             {:.3} {:.3} {:.3} {:.3} {:.3} {:.3} c h",
+            cx + r, cy,
+            cx + r, cy + k, cx + k, cy + r, cx, cy + r,
+            cx - k, cy + r, cx - r, cy + k, cx - r, cy,
+            cx - r, cy - k, cx - k, cy - r, cx, cy - r,
+            cx + k, cy - r, cx + r, cy - k, cx + r, cy,
+        )
+    }
+    
     /// Generate appearance streams for form fields
+    ///
+    /// Text field widgets get a normal appearance built from their value and
+    /// `/DA`. `NeedAppearances` stays set so viewers still refresh the field
+    /// types that are not generated here.
     pub fn generate_appearance_streams<P: AsRef<Path>>(
         pdf_path: P,
         output_path: P
@@ -275,23 +534,776 @@ impl FormFieldGenerator {
         // Load the PDF document
         let mut document = Document::load(pdf_path.as_ref())?;
         
-        // Set the NeedAppearances flag to true
-        if let Some(acro_form) = document.trailer
-            .get(b"Root")
-            .and_then(|obj| obj.as_reference())
-            .and_then(|id| document.get_object(id).ok())
-            .and_then(|obj| obj.as_dict().ok())
-            .and_then(|root| root.get(b"AcroForm"))
-            .and_then(|obj| obj.as_reference()) {
-                
-            if let Ok(mut acro_form_dict) = document.get_dictionary_mut(acro_form) {
+        Self::generate_appearances_in_document(&mut document)?;
+        
+        // Save the modified document
+        pdf::write_document(&mut document, output_path)?;
+        
+        Ok(())
+    }
+    
+    /// Generate appearance streams for the form fields of a loaded document
+    pub fn generate_appearances_in_document(document: &mut Document) -> Result<()> {
+        if let Some(acro_form) = ensure_acro_form_id(document) {
+            
+            // Collect every text widget along with its inherited attributes
+            let mut widgets = Vec::new();
+            let field_ids: Vec<ObjectId> = document.get_dictionary(acro_form)?
+                .get(b"Fields")
+                .and_then(|obj| obj.as_array())
+                .map(|fields| fields.iter().filter_map(|f| f.as_reference().ok()).collect())
+                .unwrap_or_default();
+            for field_id in field_ids {
+                Self::collect_text_widgets(document, field_id, TextFieldAttributes::default(), &mut widgets, 0);
+            }
+            
+            let mut replaced = HashSet::new();
+            for (widget_id, attributes) in widgets {
+                Self::write_text_appearance(document, widget_id, &attributes, acro_form, &mut replaced)?;
+            }
+            prune_unreachable_among(document, replaced);
+            
+            // Set the NeedAppearances flag to true
+            if let Ok(acro_form_dict) = document.get_dictionary_mut(acro_form) {
                 acro_form_dict.set("NeedAppearances", Object::Boolean(true));
             }
         }
         
-        // Save the modified document
-        document.save(output_path.as_ref())?;
+        Ok(())
+    }
+    
+    /// Walk a field and its kids, collecting text widgets with inherited attributes
+    fn collect_text_widgets(
+        document: &Document,
+        field_id: ObjectId,
+        inherited: TextFieldAttributes,
+        widgets: &mut Vec<(ObjectId, TextFieldAttributes)>,
+        depth: usize,
+    ) {
+        // Guard against reference cycles in malformed field trees
+        if depth > 32 {
+            return;
+        }
+        let Ok(field_dict) = document.get_dictionary(field_id) else {
+            return;
+        };
+        
+        let attributes = Self::text_field_attributes(field_dict, inherited.clone());
+        
+        let kids: Vec<ObjectId> = field_dict
+            .get(b"Kids")
+            .and_then(|obj| obj.as_array())
+            .map(|kids| kids.iter().filter_map(|k| k.as_reference().ok()).collect())
+            .unwrap_or_default();
+        
+        if kids.is_empty() {
+            // A terminal node is a widget; keep it only if it is a text field
+            if attributes.field_type == b"Tx" {
+                widgets.push((field_id, attributes));
+            }
+            return;
+        }
+        
+        for kid_id in kids {
+            Self::collect_text_widgets(document, kid_id, attributes.clone(), widgets, depth + 1);
+        }
+    }
+    
+    /// Overlay a node's own text attributes on those inherited from its parent
+    fn text_field_attributes(dict: &Dictionary, inherited: TextFieldAttributes) -> TextFieldAttributes {
+        let mut attributes = inherited;
+        if let Ok(field_type) = dict.get(b"FT").and_then(|obj| obj.as_name()) {
+            attributes.field_type = field_type.to_vec();
+        }
+        if let Ok(value) = dict.get(b"V").and_then(|obj| obj.as_str()) {
+            attributes.value = pdf::decode_text_string(value);
+        }
+        if let Ok(da) = dict.get(b"DA").and_then(|obj| obj.as_str()) {
+            attributes.default_appearance = Some(da.to_vec());
+        }
+        if let Ok(quadding) = dict.get(b"Q").and_then(|obj| obj.as_i64()) {
+            attributes.quadding = quadding;
+        }
+        if let Ok(flags) = dict.get(b"Ff").and_then(|obj| obj.as_i64()) {
+            attributes.flags = flags;
+        }
+        attributes
+    }
+    
+    /// Resolve the text attributes of a widget by folding in every ancestor
+    fn resolved_text_attributes(document: &Document, widget_id: ObjectId) -> Result<TextFieldAttributes> {
+        let mut chain = vec![document.get_dictionary(widget_id)?];
+        while let Ok(parent_id) = chain[chain.len() - 1].get(b"Parent").and_then(|obj| obj.as_reference()) {
+            // Guard against reference cycles in malformed field trees
+            if chain.len() > 32 {
+                break;
+            }
+            chain.push(document.get_dictionary(parent_id)?);
+        }
+        
+        Ok(chain
+            .into_iter()
+            .rev()
+            .fold(TextFieldAttributes::default(), |inherited, dict| Self::text_field_attributes(dict, inherited)))
+    }
+    
+    /// Build and attach the normal appearance stream of a text widget
+    ///
+    /// The objects the new appearance replaces are added to `replaced`, for
+    /// the caller to prune once it has drawn every widget it means to.
+    fn write_text_appearance(
+        document: &mut Document,
+        widget_id: ObjectId,
+        attributes: &TextFieldAttributes,
+        acro_form_id: ObjectId,
+        replaced: &mut HashSet<ObjectId>,
+    ) -> Result<()> {
+        let widget = document.get_dictionary(widget_id)?;
+        
+        let rect: Vec<f64> = widget
+            .get(b"Rect")
+            .and_then(|obj| obj.as_array())
+            .map(|rect| rect.iter().map(|v| v.as_float().unwrap_or(0.0) as f64).collect())
+            .unwrap_or_default();
+        if rect.len() != 4 {
+            return Err(PdfError::MalformedPdf("Widget has no valid Rect".to_string()));
+        }
+        
+        // Widget appearance characteristics supply background and border colours
+        let mk = widget.get(b"MK").ok().and_then(|obj| Self::resolve_dict(document, obj));
+        let mk_color = |key: &[u8]| {
+            mk.and_then(|mk| mk.get(key).and_then(|obj| obj.as_array()).ok())
+                .map(|c| c.iter().map(|v| v.as_float().unwrap_or(0.0) as f64).collect::<Vec<f64>>())
+        };
+        let background = mk_color(b"BG");
+        let border = mk_color(b"BC");
+        
+        // The widget's own /DA wins, then the field's, then the form default
+        let da = widget
+            .get(b"DA")
+            .and_then(|obj| obj.as_str())
+            .map(|da| da.to_vec())
+            .ok()
+            .or_else(|| attributes.default_appearance.clone())
+            .or_else(|| {
+                document.get_dictionary(acro_form_id).ok()
+                    .and_then(|form| form.get(b"DA").and_then(|obj| obj.as_str()).ok())
+                    .map(|da| da.to_vec())
+            })
+            .unwrap_or_else(|| DEFAULT_APPEARANCE.as_bytes().to_vec());
+        let mut appearance = DefaultAppearance::parse(&da);
+        
+        let (font_resource, font) = match Self::form_font(document, acro_form_id, &appearance.font_name) {
+            Some(found) => found,
+            None => {
+                // Fall back to Helvetica when the /DA font is not in /DR
+                appearance.font_name = b"Helv".to_vec();
+                (Self::ensure_helvetica(document, acro_form_id)?, StandardFont::Helvetica)
+            }
+        };
+        
+        let flags = FieldFlags::from_bits(FieldKind::Text, attributes.flags);
+        let text_appearance = TextAppearance {
+            value: &attributes.value,
+            width: (rect[2] - rect[0]).abs(),
+            height: (rect[3] - rect[1]).abs(),
+            appearance: &appearance,
+            font,
+            alignment: TextAlignment::from_quadding(attributes.quadding),
+            multiline: flags.multiline,
+            password: flags.password,
+            background,
+            border,
+        };
+        let stream = text_appearance.to_stream(font_resource)?;
+        
+        // The old normal appearance may be shared with other widgets, so the
+        // new one is a stream of its own and the old one may go only once
+        // nothing refers to it. The widget gets its own copy of /AP as well;
+        // only /N changes, so down (/D) and rollover (/R) appearances stay
+        let stream_id = document.add_object(Object::Stream(stream));
+        let (ap_id, mut ap_dict) = match document.get_dictionary(widget_id)?.get(b"AP") {
+            Ok(ap) => match document.dereference(ap) {
+                Ok((id, Object::Dictionary(ap_dict))) => (id, ap_dict.clone()),
+                _ => (None, Dictionary::new()),
+            },
+            Err(_) => (None, Dictionary::new()),
+        };
+        let old_stream_id = ap_dict.get(b"N").and_then(|obj| obj.as_reference()).ok();
+        ap_dict.set("N", Object::Reference(stream_id));
+        document.get_dictionary_mut(widget_id)?.set("AP", Object::Dictionary(ap_dict));
+        replaced.extend(ap_id.into_iter().chain(old_stream_id));
         
         Ok(())
     }
-} 
\ No newline at end of file
+    
+    /// Look up a font in the AcroForm's `/DR` resources
+    fn form_font(document: &Document, acro_form_id: ObjectId, name: &[u8]) -> Option<(Object, StandardFont)> {
+        let acro_form = document.get_dictionary(acro_form_id).ok()?;
+        let dr = Self::resolve_dict(document, acro_form.get(b"DR").ok()?)?;
+        let fonts = Self::resolve_dict(document, dr.get(b"Font").ok()?)?;
+        let font_obj = fonts.get(name).ok()?;
+        let font_dict = Self::resolve_dict(document, font_obj)?;
+        let base_font = font_dict.get(b"BaseFont").and_then(|obj| obj.as_name()).unwrap_or(b"Helvetica");
+        Some((font_obj.clone(), StandardFont::from_base_font(base_font)))
+    }
+    
+    /// Add Helvetica to the AcroForm's `/DR` fonts as `/Helv`
+    fn ensure_helvetica(document: &mut Document, acro_form_id: ObjectId) -> Result<Object> {
+        let helvetica = Object::Reference(document.add_object(Object::Dictionary(Self::helvetica_font())));
+        
+        // /DR and its /Font entry may each be direct or indirect
+        let acro_form = document.get_dictionary(acro_form_id)?;
+        let dr_id = acro_form.get(b"DR").and_then(|obj| obj.as_reference()).ok();
+        let fonts_id = match dr_id {
+            Some(id) => document.get_dictionary(id).ok(),
+            None => acro_form.get(b"DR").and_then(|obj| obj.as_dict()).ok(),
+        }
+        .and_then(|dr| dr.get(b"Font").and_then(|obj| obj.as_reference()).ok());
+        
+        if let Some(fonts_id) = fonts_id {
+            document.get_dictionary_mut(fonts_id)?.set("Helv", helvetica.clone());
+            return Ok(helvetica);
+        }
+        
+        let dr = match dr_id {
+            Some(id) => document.get_dictionary_mut(id)?,
+            None => {
+                let acro_form = document.get_dictionary_mut(acro_form_id)?;
+                if !matches!(acro_form.get(b"DR"), Ok(Object::Dictionary(_))) {
+                    acro_form.set("DR", Object::Dictionary(Dictionary::new()));
+                }
+                acro_form.get_mut(b"DR")?.as_dict_mut()?
+            }
+        };
+        match dr.get_mut(b"Font") {
+            Ok(Object::Dictionary(fonts)) => fonts.set("Helv", helvetica.clone()),
+            _ => {
+                let mut fonts = Dictionary::new();
+                fonts.set("Helv", helvetica.clone());
+                dr.set("Font", Object::Dictionary(fonts));
+            }
+        }
+        
+        Ok(helvetica)
+    }
+    
+    /// Dictionary for the standard Helvetica font
+    fn helvetica_font() -> Dictionary {
+        let mut helvetica_dict = Dictionary::new();
+        helvetica_dict.set("Type", Object::Name("Font".as_bytes().to_vec()));
+        helvetica_dict.set("Subtype", Object::Name("Type1".as_bytes().to_vec()));
+        helvetica_dict.set("BaseFont", Object::Name("Helvetica".as_bytes().to_vec()));
+        helvetica_dict.set("Encoding", Object::Name("WinAnsiEncoding".as_bytes().to_vec()));
+        helvetica_dict
+    }
+    
+    /// Resolve an object that is either a dictionary or a reference to one
+    fn resolve_dict<'a>(document: &'a Document, obj: &'a Object) -> Option<&'a Dictionary> {
+        match obj {
+            Object::Reference(id) => document.get_dictionary(*id).ok(),
+            Object::Dictionary(dict) => Some(dict),
+            _ => None,
+        }
+    }
+}
+
+/// Find the AcroForm of a document by object id, if it is held in an object of its own
+fn acro_form_id(document: &Document) -> Option<ObjectId> {
+    document
+        .catalog()
+        .and_then(|catalog| catalog.get(b"AcroForm"))
+        .and_then(|obj| obj.as_reference())
+        .ok()
+}
+
+/// Find the AcroForm of a document by object id, moving a form held
+/// directly in the catalog into an object of its own
+fn ensure_acro_form_id(document: &mut Document) -> Option<ObjectId> {
+    if let Some(id) = acro_form_id(document) {
+        return Some(id);
+    }
+    let acro_form = document.catalog().and_then(|catalog| catalog.get(b"AcroForm")).ok()?;
+    let acro_form = acro_form.as_dict().ok()?.clone();
+    let id = document.add_object(acro_form);
+    document.catalog_mut().ok()?.set("AcroForm", id);
+    Some(id)
+}
+
+/// Map fully-qualified names to the fields that hold values
+fn terminal_fields(document: &Document, acro_form_id: ObjectId) -> HashMap<String, ObjectId> {
+    FormFieldTree::from_acro_form(document, acro_form_id)
+        .terminals()
+        .into_iter()
+        .map(|field| (field.full_name.clone(), field.object_id))
+        .collect()
+}
+
+/// Widget annotations of a terminal field, which may be the field itself
+fn field_widgets(document: &Document, field_id: ObjectId) -> Vec<ObjectId> {
+    let widgets: Vec<ObjectId> = field_kids(document, field_id)
+        .into_iter()
+        .filter(|kid| document.get_dictionary(*kid).is_ok_and(|dict| !dict.has(b"T")))
+        .collect();
+
+    if widgets.is_empty() {
+        vec![field_id]
+    } else {
+        widgets
+    }
+}
+
+fn field_kids(document: &Document, field_id: ObjectId) -> Vec<ObjectId> {
+    document
+        .get_dictionary(field_id)
+        .and_then(|dict| dict.get(b"Kids"))
+        .and_then(|obj| obj.as_array())
+        .map(|kids| kids.iter().filter_map(|k| k.as_reference().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Look up an inheritable attribute on a field or its nearest ancestor
+fn inherited_attribute(document: &Document, field_id: ObjectId, key: &[u8]) -> Option<Object> {
+    let mut current = Some(field_id);
+    let mut depth = 0;
+    while let Some(id) = current {
+        let dict = document.get_dictionary(id).ok()?;
+        if let Ok(value) = dict.get(key) {
+            return document.dereference(value).ok().map(|(_, obj)| obj.clone());
+        }
+        depth += 1;
+        if depth > 32 {
+            return None;
+        }
+        current = dict.get(b"Parent").and_then(|obj| obj.as_reference()).ok();
+    }
+    None
+}
+
+/// Map a radio group's state name to the export value in `/Opt` of the
+/// widget showing that state, keeping the name if there is none
+fn radio_export_value(document: &Document, field: &PdfFieldNode, state: &str) -> String {
+    field
+        .widgets
+        .iter()
+        .position(|widget| FormFiller::on_state(document, widget.object_id).is_some_and(|on| on == state.as_bytes()))
+        .and_then(|index| field.options.get(index))
+        .map(|option| option.export_value.clone())
+        .unwrap_or_else(|| state.to_string())
+}
+
+/// Mutable access to a page's `/Annots` array, creating it when missing and
+/// following it when it is an indirect object
+fn page_annotations_mut(document: &mut Document, page_id: ObjectId) -> Result<&mut Vec<Object>> {
+    let annots_id = match document.get_dictionary(page_id)?.get(b"Annots") {
+        Ok(Object::Reference(id)) => Some(*id),
+        _ => None,
+    };
+    if let Some(annots_id) = annots_id {
+        return Ok(document.get_object_mut(annots_id)?.as_array_mut()?);
+    }
+
+    let page = document.get_dictionary_mut(page_id)?;
+    if !matches!(page.get(b"Annots"), Ok(Object::Array(_))) {
+        page.set("Annots", Object::Array(vec![]));
+    }
+    Ok(page.get_mut(b"Annots")?.as_array_mut()?)
+}
+
+/// List an annotation in a page's `/Annots` unless it is already there
+fn add_annotation(document: &mut Document, page_id: ObjectId, annot_id: ObjectId) -> Result<()> {
+    let annots = page_annotations_mut(document, page_id)?;
+    if !annots.iter().any(|annot| annot.as_reference().is_ok_and(|id| id == annot_id)) {
+        annots.push(Object::Reference(annot_id));
+    }
+    Ok(())
+}
+
+/// Drop annotations from every page's `/Annots`
+fn remove_annotations(document: &mut Document, annots: &HashSet<ObjectId>) -> Result<()> {
+    let page_ids: Vec<ObjectId> = document.get_pages().into_values().collect();
+    for page_id in page_ids {
+        let annots_id = match document.get_dictionary(page_id)?.get(b"Annots") {
+            Ok(Object::Reference(id)) => Some(*id),
+            _ => None,
+        };
+        let array = match annots_id {
+            Some(id) => document.get_object_mut(id).and_then(|obj| obj.as_array_mut()),
+            None => document.get_dictionary_mut(page_id)?.get_mut(b"Annots").and_then(|obj| obj.as_array_mut()),
+        };
+        if let Ok(array) = array {
+            array.retain(|annot| annot.as_reference().map_or(true, |id| !annots.contains(&id)));
+        }
+    }
+    Ok(())
+}
+
+/// Unlink a field from its parent's `/Kids` or the AcroForm's `/Fields`,
+/// pruning parents that are left without kids
+///
+/// The field's widgets must already be gone from the page `/Annots`.
+fn detach_field(document: &mut Document, acro_form_id: ObjectId, field_id: ObjectId) -> Result<()> {
+    let parent_id = document
+        .get_dictionary(field_id)
+        .and_then(|field| field.get(b"Parent"))
+        .and_then(|obj| obj.as_reference())
+        .ok();
+
+    let (owner_id, key): (ObjectId, &[u8]) = match parent_id {
+        Some(parent_id) => (parent_id, b"Kids"),
+        None => (acro_form_id, b"Fields"),
+    };
+    if let Ok(Object::Array(entries)) = document.get_dictionary_mut(owner_id)?.get_mut(key) {
+        entries.retain(|entry| entry.as_reference().map_or(true, |id| id != field_id));
+    }
+
+    document.objects.remove(&field_id);
+
+    if let Some(parent_id) = parent_id {
+        if field_kids(document, parent_id).is_empty() {
+            detach_field(document, acro_form_id, parent_id)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::testing::{blank_document, field};
+    use lopdf::dictionary;
+
+    /// Save a document with empty pages, optionally giving the first page an
+    /// indirect `/Annots` array holding one unrelated annotation
+    fn blank_pdf(path: &Path, pages: usize, indirect_annots: bool) {
+        let mut document = blank_document(pages);
+        if indirect_annots {
+            let link_id = document.add_object(dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "Link",
+                "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+            });
+            let annots_id = document.add_object(Object::Array(vec![Object::Reference(link_id)]));
+            let first_page = document.get_pages()[&1];
+            document.get_dictionary_mut(first_page).unwrap().set("Annots", annots_id);
+        }
+        document.save(path).unwrap();
+    }
+
+    /// Add fields to a fresh document and reopen the saved output
+    fn round_trip(pages: usize, indirect_annots: bool, fields: Vec<PdfFormField>) -> Document {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.pdf");
+        let output = dir.path().join("output.pdf");
+        blank_pdf(&input, pages, indirect_annots);
+
+        FormFieldGenerator::add_form_fields_to_pdf(input.as_path(), fields, output.as_path()).unwrap();
+
+        Document::load(&output).unwrap()
+    }
+
+    fn page_annots(document: &Document, page: u32) -> Vec<ObjectId> {
+        let page_id = document.get_pages()[&(page + 1)];
+        document
+            .get_dictionary(page_id)
+            .unwrap()
+            .get(b"Annots")
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_array())
+            .map(|annots| annots.iter().filter_map(|annot| annot.as_reference().ok()).collect())
+            .unwrap_or_default()
+    }
+
+    fn widget_ids(document: &Document, name: &str) -> Vec<ObjectId> {
+        FormFieldTree::from_document(document)
+            .find(name)
+            .unwrap()
+            .widgets
+            .iter()
+            .map(|widget| widget.object_id)
+            .collect()
+    }
+
+    #[test]
+    fn text_widget_is_listed_on_its_page() {
+        let document = round_trip(2, false, vec![field("name", FieldKind::Text, 1, &[])]);
+
+        let widgets = widget_ids(&document, "name");
+        assert_eq!(widgets.len(), 1);
+        assert_eq!(page_annots(&document, 1), widgets);
+        assert!(page_annots(&document, 0).is_empty());
+
+        let widget = document.get_dictionary(widgets[0]).unwrap();
+        assert_eq!(widget.get(b"F").unwrap().as_i64().unwrap(), ANNOT_PRINT);
+        assert_eq!(widget.get(b"P").unwrap().as_reference().unwrap(), document.get_pages()[&2]);
+    }
+
+    #[test]
+    fn every_radio_widget_is_listed_on_its_page() {
+        let document = round_trip(1, false, vec![field("choice", FieldKind::Radio, 0, &["Yes", "No", "Maybe"])]);
+
+        let widgets = widget_ids(&document, "choice");
+        assert_eq!(widgets.len(), 3);
+        assert_eq!(page_annots(&document, 0), widgets);
+        for widget_id in widgets {
+            let widget = document.get_dictionary(widget_id).unwrap();
+            assert_eq!(widget.get(b"F").unwrap().as_i64().unwrap(), ANNOT_PRINT);
+        }
+    }
+
+    #[test]
+    fn indirect_annots_array_is_extended_in_place() {
+        let document = round_trip(1, true, vec![field("name", FieldKind::Text, 0, &[])]);
+
+        let page = document.get_dictionary(document.get_pages()[&1]).unwrap();
+        assert!(page.get(b"Annots").unwrap().as_reference().is_ok());
+
+        let annots = page_annots(&document, 0);
+        assert_eq!(annots.len(), 2);
+        assert_eq!(annots[1], widget_ids(&document, "name")[0]);
+    }
+
+    #[test]
+    fn deleting_a_field_removes_its_widgets_from_the_page() {
+        let mut document = round_trip(1, true, vec![
+            field("name", FieldKind::Text, 0, &[]),
+            field("choice", FieldKind::Radio, 0, &["Yes", "No"]),
+        ]);
+        let link_id = page_annots(&document, 0)[0];
+        let name_widget = widget_ids(&document, "name")[0];
+
+        FormEditor::delete_from_document(&mut document, &FieldRef::Name("choice".to_string())).unwrap();
+
+        let mut output = Vec::new();
+        document.save_to(&mut output).unwrap();
+        let document = Document::load_mem(&output).unwrap();
+
+        assert_eq!(page_annots(&document, 0), vec![link_id, name_widget]);
+        assert!(FormFieldTree::from_document(&document).find("choice").is_none());
+    }
+
+    #[test]
+    fn regenerating_leaves_shared_appearance_streams_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("form.pdf");
+        blank_document(1).save(&path).unwrap();
+        let fields = vec![field("first", FieldKind::Text, 0, &[]), field("second", FieldKind::Text, 0, &[])];
+        FormFieldGenerator::add_form_fields_to_pdf(&path, fields, &path).unwrap();
+
+        // Let both fields use the appearance of the first, which also has a
+        // down appearance
+        let mut document = Document::load(&path).unwrap();
+        let first = widget_ids(&document, "first")[0];
+        let second = widget_ids(&document, "second")[0];
+        let down_id = document.add_object(Stream::new(Dictionary::new(), Vec::new()));
+        let mut shared_ap = document.get_dictionary(first).unwrap().get(b"AP").unwrap().as_dict().unwrap().clone();
+        shared_ap.set("D", down_id);
+        let shared_ap_id = document.add_object(shared_ap);
+        let shared = document.get_dictionary(shared_ap_id).unwrap().get(b"N").unwrap().as_reference().unwrap();
+        for (id, value) in [(first, "Ada"), (second, "Grace")] {
+            let widget = document.get_dictionary_mut(id).unwrap();
+            widget.set("AP", shared_ap_id);
+            widget.set("V", Object::string_literal(value));
+        }
+        document.save(&path).unwrap();
+
+        FormFieldGenerator::generate_appearance_streams(&path, &path).unwrap();
+
+        let document = Document::load(&path).unwrap();
+        let normal_appearance = |id: ObjectId| {
+            let ap = document.get_dictionary(id).unwrap().get(b"AP").unwrap().as_dict().unwrap();
+            assert_eq!(ap.get(b"D").unwrap().as_reference().unwrap(), down_id);
+            let stream_id = ap.get(b"N").unwrap().as_reference().unwrap();
+            String::from_utf8_lossy(&document.get_object(stream_id).unwrap().as_stream().unwrap().content).to_string()
+        };
+        assert!(normal_appearance(first).contains("(Ada)"));
+        assert!(normal_appearance(second).contains("(Grace)"));
+        assert!(document.get_object(shared).is_err());
+        assert!(document.get_object(shared_ap_id).is_err());
+    }
+
+    #[test]
+    fn checkboxes_fill_from_true_and_false() {
+        let mut checkbox = field("agree", FieldKind::Checkbox, 0, &[]);
+        checkbox.value = Some("true".to_string());
+        let mut document = round_trip(1, false, vec![checkbox]);
+        let widget_id = widget_ids(&document, "agree")[0];
+        let appearance_state = |document: &Document| {
+            document.get_dictionary(widget_id).unwrap().get(b"AS").unwrap().as_name().unwrap().to_vec()
+        };
+        assert_ne!(appearance_state(&document), b"Off");
+
+        for (value, checked) in [("false", false), ("true", true)] {
+            let values = HashMap::from([("agree".to_string(), PdfFieldValue::Single(value.to_string()))]);
+            FormFiller::fill_document(&mut document, &values).unwrap();
+            assert_eq!(appearance_state(&document) != b"Off", checked);
+        }
+    }
+
+    #[test]
+    fn acro_form_held_directly_in_the_catalog_is_used() {
+        let mut document = round_trip(1, false, vec![field("name", FieldKind::Text, 0, &[])]);
+        let acro_form_id = document.catalog().unwrap().get(b"AcroForm").unwrap().as_reference().unwrap();
+        let acro_form = document.objects.remove(&acro_form_id).unwrap();
+        document.catalog_mut().unwrap().set("AcroForm", acro_form);
+
+        assert!(FormFieldTree::from_document(&document).find("name").is_some());
+
+        let values = HashMap::from([("name".to_string(), PdfFieldValue::Single("Ada".to_string()))]);
+        FormFiller::fill_document(&mut document, &values).unwrap();
+        FormFieldGenerator::add_fields_to_document(&mut document, vec![field("other", FieldKind::Text, 0, &[])])
+            .unwrap();
+
+        let tree = FormFieldTree::from_document(&document);
+        assert_eq!(tree.find("name").unwrap().value, Some(PdfFieldValue::Single("Ada".to_string())));
+        assert!(tree.find("other").is_some());
+        assert!(document.catalog().unwrap().get(b"AcroForm").unwrap().as_reference().is_ok());
+    }
+
+    #[test]
+    fn failed_edits_leave_a_direct_form_in_the_catalog() {
+        let mut document = round_trip(1, false, vec![field("name", FieldKind::Text, 0, &[])]);
+        let acro_form_id = document.catalog().unwrap().get(b"AcroForm").unwrap().as_reference().unwrap();
+        let acro_form = document.objects.remove(&acro_form_id).unwrap();
+        document.catalog_mut().unwrap().set("AcroForm", acro_form);
+
+        let missing = FieldRef::Name("missing".to_string());
+        assert!(FormEditor::update_in_document(&mut document, &missing, &FieldUpdate::default()).is_err());
+        assert!(FormEditor::delete_from_document(&mut document, &missing).is_err());
+
+        assert!(document.catalog().unwrap().get(b"AcroForm").unwrap().as_dict().is_ok());
+    }
+
+    #[test]
+    fn generated_flags_keep_their_defaults() {
+        let mut notes = field("notes", FieldKind::Text, 0, &[]);
+        notes.properties.insert("multiline".to_string(), "true".to_string());
+        let document = round_trip(1, false, vec![notes, field("choice", FieldKind::Radio, 0, &["Yes", "No"])]);
+
+        let tree = FormFieldTree::from_document(&document);
+        assert!(tree.find("notes").unwrap().flags.multiline);
+        let choice = tree.find("choice").unwrap();
+        assert!(choice.flags.no_toggle_to_off);
+        assert!(choice.flags.radios_in_unison);
+
+        let written = document.get_dictionary(choice.object_id).unwrap().get(b"Ff").unwrap().as_i64().unwrap();
+        assert_ne!(written & (1 << 14), 0, "NoToggleToOff");
+        assert_ne!(written & (1 << 15), 0, "Radio");
+        assert_ne!(written & (1 << 25), 0, "RadiosInUnison");
+    }
+
+    #[test]
+    fn radio_flags_turned_off_stay_off() {
+        let mut choice = field("choice", FieldKind::Radio, 0, &["Yes", "No"]);
+        choice.flags = FieldFlags { no_toggle_to_off: false, radios_in_unison: false, ..FieldFlags::for_new_field() };
+        let document = round_trip(1, false, vec![choice]);
+
+        let flags = FormFieldTree::from_document(&document).find("choice").unwrap().flags;
+        assert!(!flags.no_toggle_to_off);
+        assert!(!flags.radios_in_unison);
+    }
+
+    #[test]
+    fn deserialized_radio_flags_default_only_when_left_out() {
+        let flags: FieldFlags = serde_json::from_str(r#"{"read_only": true, "radios_in_unison": false}"#).unwrap();
+        assert!(flags.read_only);
+        assert!(flags.no_toggle_to_off);
+        assert!(!flags.radios_in_unison);
+    }
+
+    #[test]
+    fn updated_flags_are_the_ones_asked_for() {
+        let mut document = round_trip(1, false, vec![field("choice", FieldKind::Radio, 0, &["Yes", "No"])]);
+        let update = FieldUpdate {
+            flags: Some(FieldFlags { read_only: true, ..Default::default() }),
+            ..Default::default()
+        };
+
+        FormEditor::update_in_document(&mut document, &FieldRef::Name("choice".to_string()), &update).unwrap();
+
+        let flags = FormFieldTree::from_document(&document).find("choice").unwrap().flags;
+        assert_eq!(flags, FieldFlags { read_only: true, ..Default::default() });
+    }
+
+    #[test]
+    fn alignments_outside_of_q_are_refused() {
+        let mut document = round_trip(1, false, vec![field("name", FieldKind::Text, 0, &[])]);
+        let name = FieldRef::Name("name".to_string());
+
+        let update = FieldUpdate { quadding: Some(3), ..Default::default() };
+        assert!(matches!(
+            FormEditor::update_in_document(&mut document, &name, &update),
+            Err(PdfError::InvalidFieldValue(_))
+        ));
+        assert_eq!(FormFieldTree::from_document(&document).find("name").unwrap().quadding, 0);
+
+        let update = FieldUpdate { quadding: Some(2), ..Default::default() };
+        FormEditor::update_in_document(&mut document, &name, &update).unwrap();
+        assert_eq!(FormFieldTree::from_document(&document).find("name").unwrap().quadding, 2);
+    }
+
+    #[test]
+    fn deleting_a_field_keeps_appearance_streams_shared_with_others() {
+        let mut document = round_trip(1, false, vec![
+            field("first", FieldKind::Checkbox, 0, &[]),
+            field("second", FieldKind::Checkbox, 0, &[]),
+        ]);
+        // Let the second checkbox use the appearances of the first
+        let first_ap = document.get_dictionary(widget_ids(&document, "first")[0]).unwrap().get(b"AP").unwrap().clone();
+        let second_widget = widget_ids(&document, "second")[0];
+        document.get_dictionary_mut(second_widget).unwrap().set("AP", first_ap.clone());
+
+        FormEditor::delete_from_document(&mut document, &FieldRef::Name("first".to_string())).unwrap();
+
+        let states = first_ap.as_dict().unwrap().get(b"N").unwrap().as_dict().unwrap();
+        for (_, stream) in states.iter() {
+            assert!(document.get_object(stream.as_reference().unwrap()).is_ok());
+        }
+        assert!(document.get_object(widget_ids(&document, "second")[0]).is_ok());
+    }
+
+    #[test]
+    fn deleting_a_field_removes_only_what_it_left_behind() {
+        let mut document = round_trip(1, false, vec![field("name", FieldKind::Text, 0, &[])]);
+        let widget_id = widget_ids(&document, "name")[0];
+        let appearance = document.get_dictionary(widget_id).unwrap().get(b"AP").unwrap().as_dict().unwrap()
+            .get(b"N").unwrap().as_reference().unwrap();
+        let unrelated = document.add_object(Stream::new(Dictionary::new(), b"unrelated".to_vec()));
+
+        FormEditor::delete_from_document(&mut document, &FieldRef::Name("name".to_string())).unwrap();
+
+        assert!(document.get_object(appearance).is_err());
+        assert!(document.get_object(unrelated).is_ok());
+    }
+
+    #[test]
+    fn filling_a_field_leaves_shared_appearance_streams_alone() {
+        let mut document = round_trip(1, false, vec![
+            field("first", FieldKind::Text, 0, &[]),
+            field("second", FieldKind::Text, 0, &[]),
+            field("third", FieldKind::Text, 0, &[]),
+        ]);
+        let normal_appearance = |document: &Document, name: &str| {
+            let widget = document.get_dictionary(widget_ids(document, name)[0]).unwrap();
+            widget.get(b"AP").unwrap().as_dict().unwrap().get(b"N").unwrap().as_reference().unwrap()
+        };
+        // Let the second field use the appearance of the first
+        let first_ap = document.get_dictionary(widget_ids(&document, "first")[0]).unwrap().get(b"AP").unwrap().clone();
+        let second_widget = widget_ids(&document, "second")[0];
+        document.get_dictionary_mut(second_widget).unwrap().set("AP", first_ap);
+        let shared = normal_appearance(&document, "first");
+        let shared_content = document.get_object(shared).unwrap().as_stream().unwrap().content.clone();
+        let unshared = normal_appearance(&document, "third");
+
+        let values = HashMap::from([
+            ("first".to_string(), PdfFieldValue::Single("Ada".to_string())),
+            ("third".to_string(), PdfFieldValue::Single("Grace".to_string())),
+        ]);
+        FormFiller::fill_document(&mut document, &values).unwrap();
+
+        assert_ne!(normal_appearance(&document, "first"), shared);
+        assert_eq!(normal_appearance(&document, "second"), shared);
+        assert_eq!(document.get_object(shared).unwrap().as_stream().unwrap().content, shared_content);
+        assert!(document.get_object(unshared).is_err());
+    }
+}
@@ -0,0 +1,413 @@
+use lopdf::Document;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::pdf::{PdfError, Result};
+use super::{radio_export_value, FieldKind, FormFieldTree, FormFiller, PdfFieldNode, PdfFieldValue};
+
+/// Joins the selected options of a multi-select list box within one cell
+const MULTI_VALUE_SEPARATOR: char = ';';
+
+/// Reads and writes form data as RFC 4180 CSV with one column per field
+pub struct FormCsv;
+
+impl FormCsv {
+    /// Export the field values of a loaded document as a header row of
+    /// fully-qualified names followed by one row of values
+    ///
+    /// Push buttons, signatures and fields flagged NoExport are left out.
+    /// Radio groups give the export value of the chosen button, and the
+    /// selections of a multi-select list box are joined with `;`.
+    pub fn export_document(document: &Document) -> String {
+        let tree = FormFieldTree::from_document(document);
+        let fields: Vec<&PdfFieldNode> = tree.terminals().into_iter().filter(|field| field.is_exported()).collect();
+
+        let header: Vec<&str> = fields.iter().map(|field| field.full_name.as_str()).collect();
+        let values: Vec<String> = fields
+            .iter()
+            .map(|field| match &field.value {
+                Some(PdfFieldValue::Single(state)) if field.kind == Some(FieldKind::Radio) => {
+                    radio_export_value(document, field, state)
+                }
+                Some(PdfFieldValue::Single(value)) => value.clone(),
+                Some(PdfFieldValue::Multiple(values)) => values.join(&MULTI_VALUE_SEPARATOR.to_string()),
+                None => String::new(),
+            })
+            .collect();
+
+        let mut csv = Self::write_record(&header);
+        csv.push_str(&Self::write_record(&values));
+        csv
+    }
+
+    /// Write one CSV record terminated by CRLF, quoting cells where needed
+    pub fn write_record<S: AsRef<str>>(cells: &[S]) -> String {
+        let mut record = cells
+            .iter()
+            .map(|cell| {
+                let cell = cell.as_ref();
+                if cell.contains([',', '"', '\r', '\n']) {
+                    format!("\"{}\"", cell.replace('"', "\"\""))
+                } else {
+                    cell.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        record.push_str("\r\n");
+        record
+    }
+
+    /// Parse CSV text into records of cells
+    ///
+    /// Quoted cells may contain commas, doubled quotes and line breaks.
+    /// Records may end with CRLF or LF, and a leading byte order mark is
+    /// ignored.
+    pub fn parse(text: &str) -> Result<Vec<Vec<String>>> {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        let mut records = Vec::new();
+        let mut record = Vec::new();
+        let mut cell = String::new();
+        let mut chars = text.chars().peekable();
+        let mut line = 1;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if cell.is_empty() => {
+                    // Quoted cell, which runs until a quote that is not doubled
+                    let start = line;
+                    loop {
+                        match chars.next() {
+                            Some('"') if chars.peek() == Some(&'"') => {
+                                chars.next();
+                                cell.push('"');
+                            }
+                            Some('"') => break,
+                            Some(c) => {
+                                if c == '\n' {
+                                    line += 1;
+                                }
+                                cell.push(c);
+                            }
+                            None => {
+                                return Err(PdfError::InvalidFormData(format!(
+                                    "Unterminated quoted CSV cell starting on line {}",
+                                    start
+                                )));
+                            }
+                        }
+                    }
+                    match chars.peek() {
+                        None | Some(',' | '\r' | '\n') => {}
+                        Some(_) => {
+                            return Err(PdfError::InvalidFormData(format!(
+                                "Unexpected text after a quoted CSV cell on line {}",
+                                line
+                            )));
+                        }
+                    }
+                }
+                ',' => record.push(std::mem::take(&mut cell)),
+                '\r' if chars.peek() == Some(&'\n') => {}
+                '\n' => {
+                    line += 1;
+                    record.push(std::mem::take(&mut cell));
+                    records.push(std::mem::take(&mut record));
+                }
+                c => cell.push(c),
+            }
+        }
+        if !cell.is_empty() || !record.is_empty() {
+            record.push(cell);
+            records.push(record);
+        }
+
+        Ok(records)
+    }
+
+    /// Fill a copy of a template PDF for every record of a CSV file
+    ///
+    /// The CSV header names the fields by fully-qualified name. Output files
+    /// are written to `output_dir`, named by `filename_pattern` where `{row}`
+    /// is the one-based record number and `{field name}` the record's value
+    /// for that column. A `.pdf` extension is added if the pattern leaves it
+    /// out. Empty cells leave the template's value alone. Each
+    /// filled copy is handed to `write` along with its path to be saved.
+    /// Returns the paths written, in record order.
+    pub fn batch_fill<P: AsRef<Path>>(
+        template_path: P,
+        csv_path: P,
+        output_dir: P,
+        filename_pattern: &str,
+        mut write: impl FnMut(&Path, &mut Document) -> Result<()>,
+    ) -> Result<Vec<PathBuf>> {
+        let template = Document::load(template_path.as_ref())?;
+        let text = fs::read_to_string(csv_path.as_ref())?;
+        let mut records = Self::parse(&text)?.into_iter();
+        let header = records
+            .next()
+            .ok_or_else(|| PdfError::InvalidFormData("CSV file is empty".to_string()))?;
+
+        // Check every column up front rather than failing part way through
+        let tree = FormFieldTree::from_document(&template);
+        let mut columns = Vec::with_capacity(header.len());
+        for name in &header {
+            let field = tree
+                .find(name)
+                .filter(|field| field.is_terminal())
+                .ok_or_else(|| PdfError::FieldNotFound(name.clone()))?;
+            columns.push((name.as_str(), field.kind == Some(FieldKind::List) && field.flags.multi_select));
+        }
+
+        // Name every output before writing any of them
+        let mut outputs: Vec<(usize, PathBuf, HashMap<String, PdfFieldValue>)> = Vec::new();
+        let mut seen: HashMap<PathBuf, usize> = HashMap::new();
+        for (index, record) in records.enumerate() {
+            let row = index + 1;
+            if record.iter().all(|cell| cell.is_empty()) {
+                continue;
+            }
+            if record.len() != header.len() {
+                return Err(PdfError::InvalidFormData(format!(
+                    "Record {} has {} cells but the header has {}",
+                    row,
+                    record.len(),
+                    header.len()
+                )));
+            }
+
+            let mut name = Self::file_name(filename_pattern, row, &header, &record);
+            // Only a plain name is its own last component; "", "." and ".."
+            // have none, and a name with separators has another
+            if Path::new(&name).file_name() != Some(name.as_ref()) {
+                return Err(PdfError::InvalidFormData(format!(
+                    "Record {} is named \"{}\", which is not a file name in the output directory",
+                    row, name
+                )));
+            }
+            if !name.to_ascii_lowercase().ends_with(".pdf") {
+                name.push_str(".pdf");
+            }
+            let path = output_dir.as_ref().join(name);
+            if let Some(previous) = seen.insert(path.clone(), row) {
+                return Err(PdfError::InvalidFormData(format!(
+                    "Records {} and {} would both be written to {}",
+                    previous,
+                    row,
+                    path.display()
+                )));
+            }
+
+            let values = columns
+                .iter()
+                .zip(&record)
+                .filter(|(_, cell)| !cell.is_empty())
+                .map(|((name, multiple), cell)| {
+                    let value = if *multiple {
+                        PdfFieldValue::Multiple(cell.split(MULTI_VALUE_SEPARATOR).map(|s| s.trim().to_string()).collect())
+                    } else {
+                        PdfFieldValue::Single(cell.clone())
+                    };
+                    (name.to_string(), value)
+                })
+                .collect();
+            outputs.push((row, path, values));
+        }
+
+        fs::create_dir_all(output_dir.as_ref())?;
+        let mut written = Vec::with_capacity(outputs.len());
+        for (row, path, values) in outputs {
+            let mut document = template.clone();
+            FormFiller::fill_document(&mut document, &values)
+                .map_err(|e| PdfError::InvalidFormData(format!("Record {}: {}", row, e)))?;
+            write(&path, &mut document)?;
+            written.push(path);
+        }
+
+        Ok(written)
+    }
+
+    /// Expand a file name pattern for one record
+    fn file_name(pattern: &str, row: usize, header: &[String], record: &[String]) -> String {
+        let mut name = pattern.replace("{row}", &row.to_string());
+        for (column, cell) in header.iter().zip(record) {
+            name = name.replace(&format!("{{{}}}", column), &Self::sanitize(cell));
+        }
+        name
+    }
+
+    /// Replace characters that cannot appear in a file name
+    fn sanitize(value: &str) -> String {
+        value
+            .trim()
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf;
+    use crate::pdf::testing::{field, form_document};
+
+    fn records(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn quoted_cells_keep_embedded_commas() {
+        assert_eq!(
+            FormCsv::parse("name,address\r\nAda,\"12 Main St, Springfield\"\r\n").unwrap(),
+            records(&[&["name", "address"], &["Ada", "12 Main St, Springfield"]])
+        );
+    }
+
+    #[test]
+    fn doubled_quotes_become_one_quote() {
+        assert_eq!(
+            FormCsv::parse("\"She said \"\"hi\"\"\",\"\"\"\"\n").unwrap(),
+            records(&[&["She said \"hi\"", "\""]])
+        );
+    }
+
+    #[test]
+    fn quoted_cells_keep_line_breaks() {
+        assert_eq!(
+            FormCsv::parse("note,other\r\n\"first\r\nsecond\nthird\",x\r\n").unwrap(),
+            records(&[&["note", "other"], &["first\r\nsecond\nthird", "x"]])
+        );
+    }
+
+    #[test]
+    fn records_may_end_with_lf_or_crlf() {
+        assert_eq!(
+            FormCsv::parse("a,b\nc,d\r\ne,f").unwrap(),
+            records(&[&["a", "b"], &["c", "d"], &["e", "f"]])
+        );
+    }
+
+    #[test]
+    fn leading_byte_order_mark_is_ignored() {
+        assert_eq!(
+            FormCsv::parse("\u{feff}name,age\r\nAda,36\r\n").unwrap(),
+            records(&[&["name", "age"], &["Ada", "36"]])
+        );
+    }
+
+    #[test]
+    fn trailing_empty_cells_are_kept() {
+        assert_eq!(
+            FormCsv::parse("a,b,c\r\n1,,\r\n2,,").unwrap(),
+            records(&[&["a", "b", "c"], &["1", "", ""], &["2", "", ""]])
+        );
+    }
+
+    #[test]
+    fn malformed_quoting_is_rejected() {
+        assert!(FormCsv::parse("a,\"unterminated\r\n").is_err());
+        assert!(FormCsv::parse("\"quoted\"tail,b\r\n").is_err());
+    }
+
+    #[test]
+    fn written_records_parse_back_unchanged() {
+        let rows = records(&[
+            &["name", "note", "empty", "quote"],
+            &["Ada, Countess", "line one\r\nline two\nline three", "", "\"as is\""],
+            &["plain", "", "", ""],
+        ]);
+
+        let text: String = rows.iter().map(|row| FormCsv::write_record(row)).collect();
+        assert!(text.starts_with("name,note,empty,quote\r\n\"Ada, Countess\","));
+        assert_eq!(FormCsv::parse(&text).unwrap(), rows);
+    }
+
+    #[test]
+    fn radio_groups_export_the_value_of_the_chosen_button() {
+        let mut size = field("size", FieldKind::Radio, 0, &["Small", "Medium", "Large"]);
+        size.value = Some("Large".to_string());
+        let document = form_document(1, vec![size]);
+        // The group's value is the name of the chosen button's state
+        let state = FormFieldTree::from_document(&document).find("size").unwrap().value.clone();
+        assert_ne!(state, Some(PdfFieldValue::Single("Large".to_string())));
+
+        assert_eq!(FormCsv::export_document(&document), "size\r\nLarge\r\n");
+    }
+
+    /// Write a template with a text field per name and a CSV file, then
+    /// batch fill them into an output directory
+    fn batch_fill(fields: &[&str], csv: &str, pattern: &str) -> (tempfile::TempDir, Result<Vec<PathBuf>>) {
+        let dir = tempfile::tempdir().unwrap();
+        let template_path = dir.path().join("template.pdf");
+        let fields = fields.iter().map(|name| field(name, FieldKind::Text, 0, &[])).collect();
+        form_document(1, fields).save(&template_path).unwrap();
+        let csv_path = dir.path().join("records.csv");
+        fs::write(&csv_path, csv).unwrap();
+
+        let output_dir = dir.path().join("out");
+        let written = FormCsv::batch_fill(&template_path, &csv_path, &output_dir, pattern, |path, document| {
+            pdf::write_document(document, path)
+        });
+        (dir, written)
+    }
+
+    fn value(path: &Path, name: &str) -> Option<PdfFieldValue> {
+        let document = Document::load(path).unwrap();
+        FormFieldTree::from_document(&document).find(name).unwrap().value.clone()
+    }
+
+    #[test]
+    fn every_record_gets_a_filled_copy() {
+        let (dir, written) = batch_fill(&["name", "city"], "name,city\r\nAda,London\r\nGrace,\r\n", "{row}-{name}");
+
+        let out = dir.path().join("out");
+        assert_eq!(written.unwrap(), [out.join("1-Ada.pdf"), out.join("2-Grace.pdf")]);
+        assert_eq!(value(&out.join("1-Ada.pdf"), "city"), Some(PdfFieldValue::Single("London".to_string())));
+        assert_eq!(value(&out.join("2-Grace.pdf"), "name"), Some(PdfFieldValue::Single("Grace".to_string())));
+        assert_eq!(value(&out.join("2-Grace.pdf"), "city"), None);
+    }
+
+    #[test]
+    fn records_sharing_a_file_name_are_rejected_before_writing() {
+        let (dir, written) = batch_fill(&["name"], "name\r\nAda\r\nAda\r\n", "{name}.pdf");
+
+        assert!(matches!(written, Err(PdfError::InvalidFormData(message)) if message.starts_with("Records 1 and 2")));
+        assert!(!dir.path().join("out").exists());
+    }
+
+    #[test]
+    fn unknown_columns_are_rejected() {
+        let (_dir, written) = batch_fill(&["name"], "name,missing\r\nAda,x\r\n", "{row}");
+
+        assert!(matches!(written, Err(PdfError::FieldNotFound(name)) if name == "missing"));
+    }
+
+    #[test]
+    fn names_outside_the_output_directory_are_rejected() {
+        for (csv, pattern) in [
+            ("name,city\r\n,London\r\n", "{name}"),
+            ("name,city\r\n.,London\r\n", "{name}"),
+            ("name,city\r\n..,London\r\n", "{name}"),
+            ("name,city\r\nAda,London\r\n", "sub/{name}"),
+            ("name,city\r\nAda,London\r\n", "../{name}"),
+        ] {
+            let (dir, written) = batch_fill(&["name", "city"], csv, pattern);
+
+            assert!(
+                matches!(&written, Err(PdfError::InvalidFormData(message)) if message.starts_with("Record 1 is named")),
+                "{:?} with {:?}: {:?}",
+                csv,
+                pattern,
+                written
+            );
+            assert!(!dir.path().join("out").exists());
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use lopdf::{Document, Object, ObjectId, StringFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use crate::pdf::{self, PdfError, Result};
+use crate::pdf::objects::{collect_references, prune_unreachable_among};
+use super::{add_annotation, ensure_acro_form_id, detach_field, remove_annotations};
+use super::{FieldFlags, FieldKind, FormFieldGenerator, FormFieldTree, PdfFieldNode};
+
+/// Identifies an existing field by fully-qualified name or object id
+///
+/// An object id may also be that of a widget, which limits rect and page
+/// changes to that single widget.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldRef {
+    Name(String),
+    ObjectId(ObjectId),
+}
+
+/// Changes to apply to an existing field; unset entries are left alone
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct FieldUpdate {
+    /// New partial name (`/T`), which must not contain a period
+    pub name: Option<String>,
+    pub rect: Option<[f64; 4]>,
+    /// Zero-based index of the page to move the widget to
+    pub page: Option<u32>,
+    pub flags: Option<FieldFlags>,
+    pub default_value: Option<String>,
+    pub default_appearance: Option<String>,
+    pub quadding: Option<i64>,
+}
+
+/// Modifies and removes fields of an existing AcroForm
+pub struct FormEditor;
+
+impl FormEditor {
+    /// Update a field and save the result
+    pub fn update_field<P: AsRef<Path>>(
+        pdf_path: P,
+        field: &FieldRef,
+        update: &FieldUpdate,
+        output_path: P
+    ) -> Result<()> {
+        let mut document = Document::load(pdf_path.as_ref())?;
+
+        Self::update_in_document(&mut document, field, update)?;
+
+        pdf::write_document(&mut document, output_path)?;
+
+        Ok(())
+    }
+
+    /// Delete a field, its kids and its widgets, and save the result
+    pub fn delete_field<P: AsRef<Path>>(
+        pdf_path: P,
+        field: &FieldRef,
+        output_path: P
+    ) -> Result<()> {
+        let mut document = Document::load(pdf_path.as_ref())?;
+
+        Self::delete_from_document(&mut document, field)?;
+
+        pdf::write_document(&mut document, output_path)?;
+
+        Ok(())
+    }
+
+    /// Update a field of a loaded document
+    ///
+    /// Text widgets get regenerated appearances so a new size, font or
+    /// alignment shows straight away.
+    pub fn update_in_document(document: &mut Document, field: &FieldRef, update: &FieldUpdate) -> Result<()> {
+        // Left, centred and right are the only alignments /Q knows
+        if let Some(quadding) = update.quadding.filter(|quadding| !(0..=2).contains(quadding)) {
+            return Err(PdfError::InvalidFieldValue(format!(
+                "{} is not a valid alignment (expected 0, 1 or 2)",
+                quadding
+            )));
+        }
+
+        let tree = Self::form_tree(document)?;
+        let (node, widget_ids) = Self::resolve(&tree, field)?;
+
+        if let Some(name) = &update.name {
+            Self::rename(document, &tree, node, name)?;
+        }
+
+        if update.rect.is_some() || update.page.is_some() {
+            // A rect or page only makes sense for a single widget
+            let [widget_id] = widget_ids.as_slice() else {
+                return Err(PdfError::UnsupportedOperation(format!(
+                    "'{}' has {} widgets; address one of them by object id",
+                    node.full_name,
+                    widget_ids.len()
+                )));
+            };
+            if let Some(page) = update.page {
+                Self::move_to_page(document, *widget_id, page)?;
+            }
+            if let Some(rect) = &update.rect {
+                document
+                    .get_dictionary_mut(*widget_id)?
+                    .set("Rect", FormFieldGenerator::rect_object(rect));
+            }
+        }
+
+        let field_id = node.object_id;
+        if let Some(flags) = &update.flags {
+            let kind = node.kind.ok_or_else(|| {
+                PdfError::UnsupportedOperation(format!("'{}' has no field type to apply flags to", node.full_name))
+            })?;
+            document.get_dictionary_mut(field_id)?.set("Ff", Object::Integer(flags.bits(kind)));
+        }
+        if let Some(default_value) = &update.default_value {
+            // Buttons store their default state as a name, other fields as text
+            let value = match node.kind {
+                Some(FieldKind::Checkbox | FieldKind::Radio) => Object::Name(default_value.as_bytes().to_vec()),
+                _ => pdf::encode_text_string(default_value),
+            };
+            document.get_dictionary_mut(field_id)?.set("DV", value);
+        }
+        if let Some(da) = &update.default_appearance {
+            document
+                .get_dictionary_mut(field_id)?
+                .set("DA", Object::String(da.as_bytes().to_vec(), StringFormat::Literal));
+        }
+        if let Some(quadding) = update.quadding {
+            document.get_dictionary_mut(field_id)?.set("Q", Object::Integer(quadding));
+        }
+
+        // Attributes set on a parent change how every text widget below it is drawn
+        let acro_form_id = Self::writable_acro_form_id(document)?;
+        let mut replaced = HashSet::new();
+        for widget_id in Self::subtree_widgets(node) {
+            let attributes = FormFieldGenerator::resolved_text_attributes(document, widget_id)?;
+            if attributes.field_type == b"Tx" {
+                FormFieldGenerator::write_text_appearance(document, widget_id, &attributes, acro_form_id, &mut replaced)?;
+            }
+        }
+        prune_unreachable_among(document, replaced);
+
+        Ok(())
+    }
+
+    /// Delete a field of a loaded document along with its kids and widgets
+    ///
+    /// The widgets leave their pages' `/Annots` and the field leaves its
+    /// parent's `/Kids` or the AcroForm's `/Fields`. Parents left without
+    /// kids are removed as well, and so are objects such as appearance
+    /// streams once nothing else refers to them.
+    pub fn delete_from_document(document: &mut Document, field: &FieldRef) -> Result<()> {
+        let tree = Self::form_tree(document)?;
+        let (node, _) = Self::resolve(&tree, field)?;
+        let acro_form_id = Self::writable_acro_form_id(document)?;
+
+        let widgets: HashSet<ObjectId> = Self::subtree_widgets(node).into_iter().collect();
+        let removed = Self::subtree_fields(node);
+        let referenced = Self::referenced_objects(document, removed.iter().chain(&widgets).copied());
+        remove_annotations(document, &widgets)?;
+
+        detach_field(document, acro_form_id, node.object_id)?;
+
+        for id in removed.into_iter().chain(widgets) {
+            document.objects.remove(&id);
+        }
+        // Appearance streams and the like may be shared with other widgets,
+        // so only those nothing refers to any more go
+        prune_unreachable_among(document, referenced);
+
+        Ok(())
+    }
+
+    /// Read the field hierarchy of a document that must have a form
+    fn form_tree(document: &Document) -> Result<FormFieldTree> {
+        let has_form = document.catalog().is_ok_and(|catalog| catalog.has(b"AcroForm"));
+        if !has_form {
+            return Err(PdfError::MalformedPdf("Document has no AcroForm".to_string()));
+        }
+        Ok(FormFieldTree::from_document(document))
+    }
+
+    /// The id of the AcroForm, which is about to be written to
+    fn writable_acro_form_id(document: &mut Document) -> Result<ObjectId> {
+        ensure_acro_form_id(document).ok_or_else(|| PdfError::MalformedPdf("Document has no AcroForm".to_string()))
+    }
+
+    /// Find the field a reference points to and the widgets it selects
+    fn resolve<'a>(tree: &'a FormFieldTree, field: &FieldRef) -> Result<(&'a PdfFieldNode, Vec<ObjectId>)> {
+        match field {
+            FieldRef::Name(name) => {
+                let node = tree.find(name).ok_or_else(|| PdfError::FieldNotFound(name.clone()))?;
+                Ok((node, node.widgets.iter().map(|widget| widget.object_id).collect()))
+            }
+            FieldRef::ObjectId(id) => {
+                let node = tree
+                    .find_by_id(*id)
+                    .ok_or_else(|| PdfError::FieldNotFound(format!("{} {} R", id.0, id.1)))?;
+                let widgets = if node.object_id == *id {
+                    node.widgets.iter().map(|widget| widget.object_id).collect()
+                } else {
+                    vec![*id]
+                };
+                Ok((node, widgets))
+            }
+        }
+    }
+
+    /// Change a field's partial name, refusing names that clash with another field
+    fn rename(document: &mut Document, tree: &FormFieldTree, node: &PdfFieldNode, name: &str) -> Result<()> {
+        if name.is_empty() || name.contains('.') {
+            return Err(PdfError::InvalidFieldValue(format!(
+                "'{}' is not a valid field name (it must be non-empty and contain no periods)",
+                name
+            )));
+        }
+
+        let full_name = match node.full_name.rfind('.') {
+            Some(index) => format!("{}.{}", &node.full_name[..index], name),
+            None => name.to_string(),
+        };
+        if full_name != node.full_name && tree.find(&full_name).is_some() {
+            return Err(PdfError::InvalidFieldValue(format!("A field named '{}' already exists", full_name)));
+        }
+
+        document.get_dictionary_mut(node.object_id)?.set("T", pdf::encode_text_string(name));
+        Ok(())
+    }
+
+    /// Move a widget to another page's `/Annots`
+    fn move_to_page(document: &mut Document, widget_id: ObjectId, page: u32) -> Result<()> {
+        let page_id = FormFieldGenerator::page_object_id(document, page)?;
+
+        remove_annotations(document, &HashSet::from([widget_id]))?;
+        add_annotation(document, page_id, widget_id)?;
+        document.get_dictionary_mut(widget_id)?.set("P", Object::Reference(page_id));
+
+        Ok(())
+    }
+
+    /// Every widget of a field and of its descendants
+    fn subtree_widgets(node: &PdfFieldNode) -> Vec<ObjectId> {
+        let mut widgets: Vec<ObjectId> = node.widgets.iter().map(|widget| widget.object_id).collect();
+        for child in &node.children {
+            widgets.extend(Self::subtree_widgets(child));
+        }
+        widgets
+    }
+
+    /// Objects that fields and widgets refer to, directly or through one
+    /// another, short of their pages and parent fields
+    fn referenced_objects(document: &Document, ids: impl Iterator<Item = ObjectId>) -> HashSet<ObjectId> {
+        let pages: HashSet<ObjectId> = document.get_pages().into_values().collect();
+        let mut pending: Vec<ObjectId> = Vec::new();
+        for id in ids {
+            if let Ok(dict) = document.get_dictionary(id) {
+                for (key, value) in dict.iter() {
+                    if key != b"P" && key != b"Parent" {
+                        collect_references(value, &mut pending);
+                    }
+                }
+            }
+        }
+
+        let mut referenced = HashSet::new();
+        while let Some(id) = pending.pop() {
+            if pages.contains(&id) || !referenced.insert(id) {
+                continue;
+            }
+            if let Some(object) = document.objects.get(&id) {
+                collect_references(object, &mut pending);
+            }
+        }
+        referenced
+    }
+
+    /// A field and all of its descendant fields
+    fn subtree_fields(node: &PdfFieldNode) -> Vec<ObjectId> {
+        let mut fields = vec![node.object_id];
+        for child in &node.children {
+            fields.extend(Self::subtree_fields(child));
+        }
+        fields
+    }
+}
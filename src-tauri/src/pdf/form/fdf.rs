@@ -0,0 +1,309 @@
+use lopdf::xref::XrefType;
+use lopdf::{Dictionary, Document, Object};
+use std::collections::HashMap;
+use crate::pdf::{self, PdfError, Result};
+use super::{FieldKind, FormFieldTree, FormFiller, PdfFieldNode, PdfFieldValue};
+
+/// Deepest `/Kids` nesting followed before giving up on a malformed file
+const MAX_FIELD_DEPTH: usize = 32;
+
+/// Reads and writes form data as FDF (Forms Data Format)
+///
+/// FDF uses PDF syntax with an `%FDF-` header, so files are written and read
+/// through lopdf with the header swapped.
+pub struct Fdf;
+
+impl Fdf {
+    /// Export the field values of a loaded document as FDF
+    ///
+    /// Fields keep their hierarchy through `/Kids`. Push buttons, signatures
+    /// and fields flagged NoExport are left out. `file` becomes the `/F`
+    /// entry pointing at the source PDF.
+    pub fn export_document(document: &Document, file: Option<&str>) -> Result<Vec<u8>> {
+        let tree = FormFieldTree::from_document(document);
+        let fields: Vec<Object> = tree
+            .fields
+            .iter()
+            .filter_map(Self::field_dictionary)
+            .map(Object::Dictionary)
+            .collect();
+
+        let mut fdf = Dictionary::new();
+        if let Some(file) = file {
+            fdf.set("F", pdf::encode_text_string(file));
+        }
+        if let Ok(ids) = document.trailer.get(b"ID") {
+            fdf.set("ID", ids.clone());
+        }
+        fdf.set("Fields", Object::Array(fields));
+
+        let mut catalog = Dictionary::new();
+        catalog.set("FDF", Object::Dictionary(fdf));
+
+        // FDF predates cross-reference streams, so write a classic xref table
+        let mut output = Document::with_version("1.2");
+        output.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
+        let catalog_id = output.add_object(Object::Dictionary(catalog));
+        output.trailer.set("Root", Object::Reference(catalog_id));
+
+        let mut data = Vec::new();
+        output.save_to(&mut data)?;
+        data[..5].copy_from_slice(b"%FDF-");
+
+        Ok(data)
+    }
+
+    /// Parse the field values of an FDF file by fully-qualified name
+    ///
+    /// Names are built from `/T` through `/Kids`; fields without `/V` are
+    /// skipped.
+    pub fn parse(data: &[u8]) -> Result<Vec<(String, PdfFieldValue)>> {
+        if !data.starts_with(b"%FDF-") {
+            return Err(PdfError::InvalidFormData("Missing %FDF- header".to_string()));
+        }
+        let mut data = data.to_vec();
+        data[..5].copy_from_slice(b"%PDF-");
+
+        let document = Document::load_mem(&data)
+            .map_err(|e| PdfError::InvalidFormData(format!("Unreadable FDF file: {}", e)))?;
+        let fields = document
+            .catalog()
+            .and_then(|catalog| catalog.get(b"FDF"))
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_dict())
+            .and_then(|fdf| fdf.get(b"Fields"))
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_array())
+            .map_err(|_| PdfError::InvalidFormData("FDF file has no /FDF /Fields array".to_string()))?;
+
+        let mut values = Vec::new();
+        for field in fields {
+            Self::read_field(&document, field, "", &mut values, 0)?;
+        }
+
+        Ok(values)
+    }
+
+    /// Apply FDF field values to a loaded document
+    pub fn import_into_document(document: &mut Document, data: &[u8]) -> Result<()> {
+        let values: HashMap<String, PdfFieldValue> = Self::parse(data)?.into_iter().collect();
+
+        FormFiller::fill_document(document, &values)
+    }
+
+    /// Build the FDF dictionary of a field, or nothing if no value below it is exported
+    fn field_dictionary(node: &PdfFieldNode) -> Option<Dictionary> {
+        let mut field = Dictionary::new();
+        field.set("T", pdf::encode_text_string(&node.name));
+
+        if !node.is_terminal() {
+            let kids: Vec<Object> = node
+                .children
+                .iter()
+                .filter_map(Self::field_dictionary)
+                .map(Object::Dictionary)
+                .collect();
+            if kids.is_empty() {
+                return None;
+            }
+            field.set("Kids", Object::Array(kids));
+            return Some(field);
+        }
+
+        if !node.is_exported() {
+            return None;
+        }
+
+        // Buttons hold state names, the other fields text strings
+        let encode = |value: &str| match node.kind {
+            Some(FieldKind::Checkbox | FieldKind::Radio) => Object::Name(value.as_bytes().to_vec()),
+            _ => pdf::encode_text_string(value),
+        };
+        match &node.value {
+            Some(PdfFieldValue::Single(value)) => field.set("V", encode(value)),
+            Some(PdfFieldValue::Multiple(values)) => {
+                field.set("V", Object::Array(values.iter().map(|value| encode(value)).collect()));
+            }
+            None => {}
+        }
+
+        Some(field)
+    }
+
+    fn read_field(
+        document: &Document,
+        field: &Object,
+        parent_name: &str,
+        values: &mut Vec<(String, PdfFieldValue)>,
+        depth: usize,
+    ) -> Result<()> {
+        if depth > MAX_FIELD_DEPTH {
+            return Err(PdfError::InvalidFormData("FDF fields are nested too deeply".to_string()));
+        }
+        let field = document
+            .dereference(field)
+            .and_then(|(_, obj)| obj.as_dict())
+            .map_err(|_| PdfError::InvalidFormData("FDF field is not a dictionary".to_string()))?;
+
+        let name = field
+            .get(b"T")
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_str())
+            .map(pdf::decode_text_string)
+            .unwrap_or_default();
+        let full_name = match (parent_name.is_empty(), name.is_empty()) {
+            (true, _) => name,
+            (false, true) => parent_name.to_string(),
+            (false, false) => format!("{}.{}", parent_name, name),
+        };
+
+        if let Ok((_, value)) = field.get(b"V").and_then(|obj| document.dereference(obj)) {
+            if let Some(value) = Self::field_value(document, value) {
+                values.push((full_name.clone(), value));
+            }
+        }
+
+        if let Ok((_, Object::Array(kids))) = field.get(b"Kids").and_then(|obj| document.dereference(obj)) {
+            for kid in kids {
+                Self::read_field(document, kid, &full_name, values, depth + 1)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decode a `/V` entry, which is a text string, a name or an array of strings
+    fn field_value(document: &Document, value: &Object) -> Option<PdfFieldValue> {
+        let text = |obj: &Object| match obj {
+            Object::String(bytes, _) => Some(pdf::decode_text_string(bytes)),
+            Object::Name(name) => Some(String::from_utf8_lossy(name).to_string()),
+            _ => None,
+        };
+
+        match value {
+            Object::Array(items) => Some(PdfFieldValue::Multiple(
+                items
+                    .iter()
+                    .filter_map(|item| document.dereference(item).ok())
+                    .filter_map(|(_, item)| text(item))
+                    .collect(),
+            )),
+            value => text(value).map(PdfFieldValue::Single),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::form::PdfFormField;
+    use crate::pdf::testing::{field, form_document, nest_field};
+    use lopdf::dictionary;
+
+    fn value(text: &str) -> PdfFieldValue {
+        PdfFieldValue::Single(text.to_string())
+    }
+
+    fn fields() -> Vec<PdfFormField> {
+        let mut colors = field("colors", FieldKind::List, 0, &["Red", "Green", "Blue"]);
+        colors.flags.multi_select = true;
+        vec![field("name", FieldKind::Text, 0, &[]), field("agree", FieldKind::Checkbox, 0, &[]), colors]
+    }
+
+    fn document() -> Document {
+        let mut document = form_document(1, fields());
+        nest_field(&mut document, "person", "name");
+        document
+    }
+
+    #[test]
+    fn exported_values_import_into_a_fresh_copy() {
+        let mut source = document();
+        let values = HashMap::from([
+            ("person.name".to_string(), value("Ada Lovelace")),
+            ("agree".to_string(), value("true")),
+            ("colors".to_string(), PdfFieldValue::Multiple(vec!["Red".to_string(), "Blue".to_string()])),
+        ]);
+        FormFiller::fill_document(&mut source, &values).unwrap();
+
+        let data = Fdf::export_document(&source, Some("form.pdf")).unwrap();
+        assert!(data.starts_with(b"%FDF-"));
+
+        let expected = FormFieldTree::from_document(&source);
+        let parsed: HashMap<String, PdfFieldValue> = Fdf::parse(&data).unwrap().into_iter().collect();
+        assert_eq!(parsed.len(), 3);
+        for name in ["person.name", "agree", "colors"] {
+            assert_eq!(Some(&parsed[name]), expected.find(name).unwrap().value.as_ref(), "{}", name);
+        }
+
+        let mut copy = document();
+        Fdf::import_into_document(&mut copy, &data).unwrap();
+        let imported = FormFieldTree::from_document(&copy);
+        for name in ["person.name", "agree", "colors"] {
+            assert_eq!(imported.find(name).unwrap().value, expected.find(name).unwrap().value, "{}", name);
+        }
+    }
+
+    #[test]
+    fn buttons_are_written_as_names_inside_their_parents() {
+        let mut source = document();
+        let values = HashMap::from([("person.name".to_string(), value("Ada")), ("agree".to_string(), value("true"))]);
+        FormFiller::fill_document(&mut source, &values).unwrap();
+
+        let mut data = Fdf::export_document(&source, None).unwrap();
+        data[..5].copy_from_slice(b"%PDF-");
+        let fdf = Document::load_mem(&data).unwrap();
+        let fields = fdf.catalog().unwrap().get(b"FDF").unwrap().as_dict().unwrap().get(b"Fields").unwrap().as_array().unwrap();
+        let by_name = |name: &str| {
+            fields
+                .iter()
+                .map(|field| field.as_dict().unwrap())
+                .find(|field| field.get(b"T").unwrap().as_str().unwrap() == name.as_bytes())
+                .unwrap()
+        };
+
+        assert!(matches!(by_name("agree").get(b"V").unwrap(), Object::Name(_)));
+        let person = by_name("person");
+        assert!(person.get(b"V").is_err());
+        let kids = person.get(b"Kids").unwrap().as_array().unwrap();
+        assert_eq!(kids.len(), 1);
+        assert!(matches!(kids[0].as_dict().unwrap().get(b"V").unwrap(), Object::String(..)));
+    }
+
+    #[test]
+    fn names_and_strings_both_read_as_values() {
+        let mut data = Fdf::export_document(&form_document(1, Vec::new()), None).unwrap();
+        data[..5].copy_from_slice(b"%PDF-");
+        let mut fdf = Document::load_mem(&data).unwrap();
+        let fields = vec![Object::Dictionary(dictionary! {
+            "T" => Object::string_literal("group"),
+            "Kids" => vec![
+                Object::Dictionary(dictionary! { "T" => Object::string_literal("on"), "V" => Object::Name(b"Yes".to_vec()) }),
+                Object::Dictionary(dictionary! { "T" => Object::string_literal("text"), "V" => Object::string_literal("Hi") }),
+                Object::Dictionary(dictionary! { "T" => Object::string_literal("unset") }),
+            ],
+        })];
+        let catalog_id = fdf.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        let catalog = fdf.get_dictionary_mut(catalog_id).unwrap();
+        catalog.get_mut(b"FDF").unwrap().as_dict_mut().unwrap().set("Fields", fields);
+
+        let mut data = Vec::new();
+        fdf.save_to(&mut data).unwrap();
+        data[..5].copy_from_slice(b"%FDF-");
+        assert_eq!(
+            Fdf::parse(&data).unwrap(),
+            vec![("group.on".to_string(), value("Yes")), ("group.text".to_string(), value("Hi"))]
+        );
+    }
+
+    #[test]
+    fn files_without_the_fdf_header_are_refused() {
+        let mut data = Fdf::export_document(&form_document(1, fields()), None).unwrap();
+        data[..5].copy_from_slice(b"%PDF-");
+
+        assert!(matches!(Fdf::parse(&data), Err(PdfError::InvalidFormData(_))));
+        assert!(matches!(Fdf::parse(b""), Err(PdfError::InvalidFormData(_))));
+        let mut document = form_document(1, fields());
+        assert!(Fdf::import_into_document(&mut document, &data).is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use lopdf::{Document, Object, ObjectId};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use crate::pdf::{self, PdfError, Result};
+use crate::pdf::objects::prune_unreachable_among;
+use super::{ensure_acro_form_id, field_widgets, inherited_attribute, terminal_fields};
+use super::{ChoiceOption, FieldFlags, FieldKind, FormFieldGenerator, PdfFieldValue};
+
+/// Fills the values of fields that already exist in a PDF's AcroForm
+pub struct FormFiller;
+
+impl FormFiller {
+    /// Fill fields by fully-qualified name and save the result
+    pub fn fill_form<P: AsRef<Path>>(
+        pdf_path: P,
+        values: &HashMap<String, PdfFieldValue>,
+        output_path: P
+    ) -> Result<()> {
+        let mut document = Document::load(pdf_path.as_ref())?;
+
+        Self::fill_document(&mut document, values)?;
+
+        pdf::write_document(&mut document, output_path)?;
+
+        Ok(())
+    }
+
+    /// Fill fields of a loaded document by fully-qualified name
+    ///
+    /// Text and combo box widgets get regenerated appearances. List boxes
+    /// are left to the viewer by setting `NeedAppearances`. Several values
+    /// may only be given for multi-select list boxes.
+    pub fn fill_document(document: &mut Document, values: &HashMap<String, PdfFieldValue>) -> Result<()> {
+        let acro_form_id = ensure_acro_form_id(document)
+            .ok_or_else(|| PdfError::MalformedPdf("Document has no AcroForm".to_string()))?;
+        let fields = terminal_fields(document, acro_form_id);
+
+        let mut need_appearances = false;
+        let mut replaced = HashSet::new();
+        for (name, value) in values {
+            let field_id = *fields
+                .get(name)
+                .ok_or_else(|| PdfError::FieldNotFound(name.clone()))?;
+
+            let field_type = inherited_attribute(document, field_id, b"FT")
+                .and_then(|obj| obj.as_name().ok().map(|ft| ft.to_vec()))
+                .unwrap_or_default();
+            let flags = inherited_attribute(document, field_id, b"Ff")
+                .and_then(|obj| obj.as_i64().ok())
+                .unwrap_or(0);
+            let widgets = field_widgets(document, field_id);
+            let kind = FieldKind::from_pdf(&field_type, flags);
+
+            let value = match value {
+                PdfFieldValue::Single(value) => value.as_str(),
+                PdfFieldValue::Multiple(selection) if kind == Some(FieldKind::List) => {
+                    Self::fill_list_selection(document, field_id, name, selection, flags)?;
+                    need_appearances = true;
+                    continue;
+                }
+                PdfFieldValue::Multiple(selection) if selection.len() == 1 => selection[0].as_str(),
+                PdfFieldValue::Multiple(_) => {
+                    return Err(PdfError::InvalidFieldValue(format!("'{}' takes a single value", name)));
+                }
+            };
+
+            match kind {
+                Some(FieldKind::Text) => {
+                    Self::fill_text(document, field_id, &widgets, name, value, acro_form_id, &mut replaced)?
+                }
+                Some(FieldKind::PushButton) => {
+                    return Err(PdfError::InvalidFieldValue(format!("'{}' is a push button and has no value", name)));
+                }
+                Some(kind @ (FieldKind::Checkbox | FieldKind::Radio)) => {
+                    Self::fill_button(document, field_id, &widgets, name, value, kind)?
+                }
+                Some(kind @ (FieldKind::Combo | FieldKind::List)) => {
+                    Self::fill_choice(document, field_id, &widgets, name, value, kind, acro_form_id, &mut replaced)?;
+                    need_appearances |= kind == FieldKind::List;
+                }
+                _ => {
+                    return Err(PdfError::UnsupportedOperation(format!("Cannot fill field '{}' of this type", name)));
+                }
+            }
+        }
+
+        if need_appearances {
+            document.get_dictionary_mut(acro_form_id)?.set("NeedAppearances", Object::Boolean(true));
+        }
+        prune_unreachable_among(document, replaced);
+
+        Ok(())
+    }
+
+    /// Set a text field's value, enforcing `/MaxLen`
+    fn fill_text(
+        document: &mut Document,
+        field_id: ObjectId,
+        widgets: &[ObjectId],
+        name: &str,
+        value: &str,
+        acro_form_id: ObjectId,
+        replaced: &mut HashSet<ObjectId>,
+    ) -> Result<()> {
+        if let Some(max_len) = inherited_attribute(document, field_id, b"MaxLen").and_then(|obj| obj.as_i64().ok()) {
+            if value.chars().count() as i64 > max_len {
+                return Err(PdfError::InvalidFieldValue(format!(
+                    "'{}' accepts at most {} characters",
+                    name, max_len
+                )));
+            }
+        }
+
+        document.get_dictionary_mut(field_id)?.set("V", pdf::encode_text_string(value));
+
+        for widget_id in widgets {
+            let attributes = FormFieldGenerator::resolved_text_attributes(document, *widget_id)?;
+            FormFieldGenerator::write_text_appearance(document, *widget_id, &attributes, acro_form_id, replaced)?;
+        }
+
+        Ok(())
+    }
+
+    /// Switch a checkbox or radio group to one of its appearance states
+    ///
+    /// The value may be a state name, an export value from `/Opt`, or `Off`.
+    /// Checkboxes also take `true` and `false`, as the generator creates them
+    /// from those values.
+    fn fill_button(
+        document: &mut Document,
+        field_id: ObjectId,
+        widgets: &[ObjectId],
+        name: &str,
+        value: &str,
+        kind: FieldKind,
+    ) -> Result<()> {
+        let on_states: Vec<Option<Vec<u8>>> = widgets
+            .iter()
+            .map(|widget_id| Self::on_state(document, *widget_id))
+            .collect();
+
+        let state = if value.is_empty() || value == "Off" {
+            b"Off".to_vec()
+        } else if on_states.iter().flatten().any(|state| state == value.as_bytes()) {
+            value.as_bytes().to_vec()
+        } else if kind == FieldKind::Checkbox && value.eq_ignore_ascii_case("false") {
+            b"Off".to_vec()
+        } else if let Some(on_state) = on_states
+            .iter()
+            .flatten()
+            .next()
+            .filter(|_| kind == FieldKind::Checkbox && value.eq_ignore_ascii_case("true"))
+        {
+            on_state.clone()
+        } else {
+            // Export values in /Opt map onto the widget at the same index
+            let export_values: Vec<String> = inherited_attribute(document, field_id, b"Opt")
+                .map(|opt| ChoiceOption::read_options(document, &opt))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|option| option.export_value)
+                .collect();
+            let export_index = export_values.iter().position(|export| export == value);
+
+            match export_index.and_then(|index| on_states.get(index).cloned().flatten()) {
+                Some(state) => state,
+                None => {
+                    let expected: Vec<String> = if export_values.is_empty() {
+                        on_states
+                            .iter()
+                            .flatten()
+                            .map(|state| String::from_utf8_lossy(state).to_string())
+                            .collect()
+                    } else {
+                        export_values
+                    };
+                    return Err(PdfError::InvalidFieldValue(format!(
+                        "'{}' is not a state of '{}' (expected Off or one of: {})",
+                        value, name, expected.join(", ")
+                    )));
+                }
+            }
+        };
+
+        document.get_dictionary_mut(field_id)?.set("V", Object::Name(state.clone()));
+
+        for (widget_id, on_state) in widgets.iter().zip(&on_states) {
+            let appearance_state = match on_state {
+                Some(on_state) if *on_state == state => state.clone(),
+                _ => b"Off".to_vec(),
+            };
+            document.get_dictionary_mut(*widget_id)?.set("AS", Object::Name(appearance_state));
+        }
+
+        Ok(())
+    }
+
+    /// Select a choice field option by export or display value
+    #[allow(clippy::too_many_arguments)]
+    fn fill_choice(
+        document: &mut Document,
+        field_id: ObjectId,
+        widgets: &[ObjectId],
+        name: &str,
+        value: &str,
+        kind: FieldKind,
+        acro_form_id: ObjectId,
+        replaced: &mut HashSet<ObjectId>,
+    ) -> Result<()> {
+        let options = inherited_attribute(document, field_id, b"Opt")
+            .map(|opt| ChoiceOption::read_options(document, &opt))
+            .unwrap_or_default();
+
+        let selected = options
+            .iter()
+            .position(|option| option.export_value == value || option.display_value == value);
+
+        // Editable combo boxes accept free text
+        let flags = inherited_attribute(document, field_id, b"Ff")
+            .and_then(|obj| obj.as_i64().ok())
+            .unwrap_or(0);
+        let editable = kind == FieldKind::Combo && FieldFlags::from_bits(kind, flags).edit;
+        let option = match selected {
+            Some(index) => options[index].clone(),
+            None if editable || value.is_empty() => ChoiceOption::new(value),
+            None => {
+                let expected: Vec<&str> = options.iter().map(|option| option.export_value.as_str()).collect();
+                return Err(PdfError::InvalidFieldValue(format!(
+                    "'{}' is not an option of '{}' (expected one of: {})",
+                    value, name, expected.join(", ")
+                )));
+            }
+        };
+
+        let field = document.get_dictionary_mut(field_id)?;
+        field.set("V", pdf::encode_text_string(&option.export_value));
+        match selected {
+            Some(index) => field.set("I", Object::Array(vec![Object::Integer(index as i64)])),
+            None => {
+                field.remove(b"I");
+            }
+        }
+
+        // Combo boxes draw like a single line text field showing the display value
+        if kind == FieldKind::Combo {
+            for widget_id in widgets {
+                let mut attributes = FormFieldGenerator::resolved_text_attributes(document, *widget_id)?;
+                attributes.value = option.display_value.clone();
+                FormFieldGenerator::write_text_appearance(document, *widget_id, &attributes, acro_form_id, replaced)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Select any number of options of a list box by export or display value
+    fn fill_list_selection(
+        document: &mut Document,
+        field_id: ObjectId,
+        name: &str,
+        selection: &[String],
+        flags: i64,
+    ) -> Result<()> {
+        if selection.len() > 1 && !FieldFlags::from_bits(FieldKind::List, flags).multi_select {
+            return Err(PdfError::InvalidFieldValue(format!("'{}' does not allow multiple selections", name)));
+        }
+
+        let options = inherited_attribute(document, field_id, b"Opt")
+            .map(|opt| ChoiceOption::read_options(document, &opt))
+            .unwrap_or_default();
+
+        let mut indices = Vec::with_capacity(selection.len());
+        for value in selection {
+            let index = options
+                .iter()
+                .position(|option| option.export_value == *value || option.display_value == *value)
+                .ok_or_else(|| {
+                    let expected: Vec<&str> = options.iter().map(|option| option.export_value.as_str()).collect();
+                    PdfError::InvalidFieldValue(format!(
+                        "'{}' is not an option of '{}' (expected one of: {})",
+                        value, name, expected.join(", ")
+                    ))
+                })?;
+            indices.push(index);
+        }
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mut exported: Vec<Object> = indices
+            .iter()
+            .map(|index| pdf::encode_text_string(&options[*index].export_value))
+            .collect();
+        let field = document.get_dictionary_mut(field_id)?;
+        match exported.len() {
+            0 => {
+                field.remove(b"V");
+            }
+            1 => field.set("V", exported.remove(0)),
+            _ => field.set("V", Object::Array(exported)),
+        }
+        field.set("I", Object::Array(indices.into_iter().map(|index| Object::Integer(index as i64)).collect()));
+
+        Ok(())
+    }
+
+    /// The non-Off appearance state of a button widget
+    pub(super) fn on_state(document: &Document, widget_id: ObjectId) -> Option<Vec<u8>> {
+        let widget = document.get_dictionary(widget_id).ok()?;
+        let ap = FormFieldGenerator::resolve_dict(document, widget.get(b"AP").ok()?)?;
+        let normal = FormFieldGenerator::resolve_dict(document, ap.get(b"N").ok()?)?;
+        normal
+            .iter()
+            .map(|(state, _)| state)
+            .find(|state| state.as_slice() != b"Off")
+            .cloned()
+    }
+}
@@ -0,0 +1,585 @@
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use crate::pdf::{self, PdfError, Result};
+use crate::pdf::objects::prune_unreachable_among;
+use super::{detach_field, ensure_acro_form_id, field_widgets, remove_annotations};
+use super::{FieldKind, FormFieldGenerator, FormFieldTree, PdfFieldNode, PdfFieldValue};
+
+// Annotation flag bits (PDF 32000-1:2008, section 12.5.3)
+const ANNOT_HIDDEN: i64 = 1 << 1;
+const ANNOT_NO_ROTATE: i64 = 1 << 4;
+
+/// A 2D affine transform `[a b c d e f]` as used by the `cm` operator
+type Matrix = [f64; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Burns form field appearances into page content so the form is no longer editable
+pub struct FormFlattener;
+
+impl FormFlattener {
+    /// Flatten all fields, or only the named ones, and save the result
+    pub fn flatten_form<P: AsRef<Path>>(
+        pdf_path: P,
+        field_names: Option<&[String]>,
+        output_path: P
+    ) -> Result<()> {
+        let mut document = Document::load(pdf_path.as_ref())?;
+
+        Self::flatten_document(&mut document, field_names)?;
+
+        pdf::write_document(&mut document, output_path)?;
+
+        Ok(())
+    }
+
+    /// Flatten fields of a loaded document
+    ///
+    /// With no names every field is flattened and the `/AcroForm` is removed
+    /// along with every field object. Otherwise only the named fields go and
+    /// the rest of the form stays editable.
+    ///
+    /// Text and choice widgets that rely on `NeedAppearances` are drawn
+    /// first so their values survive. Checkboxes and radio buttons without an
+    /// appearance cannot be drawn here, so flattening them is refused.
+    pub fn flatten_document(document: &mut Document, field_names: Option<&[String]>) -> Result<()> {
+        if document.catalog().map_or(true, |catalog| !catalog.has(b"AcroForm")) {
+            // Nothing to flatten
+            return Ok(());
+        }
+        // Read the form as it is; a form held in the catalog is only moved
+        // into an object of its own once flattening goes ahead
+        let tree = FormFieldTree::from_document(document);
+        let terminals = tree.terminals();
+
+        let selected: Vec<&PdfFieldNode> = match field_names {
+            None => terminals,
+            Some(names) => names
+                .iter()
+                .map(|name| {
+                    terminals
+                        .iter()
+                        .find(|node| node.full_name == *name)
+                        .copied()
+                        .ok_or_else(|| PdfError::FieldNotFound(name.clone()))
+                })
+                .collect::<Result<_>>()?,
+        };
+
+        // Check every widget before anything changes so a refusal leaves the document as it was
+        let mut undrawn = Vec::new();
+        for node in &selected {
+            for widget_id in field_widgets(document, node.object_id) {
+                let has_appearance = document
+                    .get_dictionary(widget_id)
+                    .is_ok_and(|widget| widget.has(b"AP"));
+                if has_appearance {
+                    continue;
+                }
+                if matches!(node.kind, Some(FieldKind::Checkbox | FieldKind::Radio)) {
+                    return Err(PdfError::UnsupportedOperation(format!(
+                        "'{}' has no appearance to flatten; regenerate it in a viewer first",
+                        node.full_name
+                    )));
+                }
+                undrawn.push((*node, widget_id));
+            }
+        }
+
+        let Some(acro_form_id) = ensure_acro_form_id(document) else {
+            return Ok(());
+        };
+
+        let mut replaced = HashSet::new();
+        for (node, widget_id) in undrawn {
+            let mut attributes = FormFieldGenerator::resolved_text_attributes(document, widget_id)?;
+            match node.kind {
+                Some(FieldKind::Text) => {}
+                // Choice fields draw like a single line text field showing the display values
+                Some(FieldKind::Combo | FieldKind::List) => attributes.value = Self::display_value(node),
+                // Push buttons and unsigned signatures have no value to keep
+                _ => continue,
+            }
+            FormFieldGenerator::write_text_appearance(document, widget_id, &attributes, acro_form_id, &mut replaced)?;
+        }
+
+        let widget_pages = Self::widget_pages(document);
+        let rotations = Self::page_rotations(document);
+
+        // Group the XObjects to draw by page so each page is rewritten once
+        let mut draws: BTreeMap<ObjectId, Vec<(Vec<u8>, Matrix)>> = BTreeMap::new();
+        let mut removed_widgets = HashSet::new();
+
+        for node in &selected {
+            for widget_id in field_widgets(document, node.object_id) {
+                let page_id = widget_pages.get(&widget_id).copied().or_else(|| {
+                    document
+                        .get_dictionary(widget_id)
+                        .and_then(|widget| widget.get(b"P"))
+                        .and_then(|obj| obj.as_reference())
+                        .ok()
+                });
+
+                let placed = page_id.and_then(|page_id| {
+                    let rotation = rotations.get(&page_id).copied().unwrap_or(0);
+                    Self::placed_appearance(document, widget_id, rotation).map(|placed| (page_id, placed))
+                });
+                if let Some((page_id, (stream_id, matrix))) = placed {
+                    let name = format!("FlatFm{}_{}", widget_id.0, widget_id.1).into_bytes();
+                    Self::own_resources(document, page_id)?;
+                    document.add_xobject(page_id, name.clone(), stream_id)?;
+                    draws.entry(page_id).or_default().push((name, matrix));
+                }
+
+                removed_widgets.insert(widget_id);
+            }
+        }
+
+        for (page_id, page_draws) in draws {
+            Self::append_to_page(document, page_id, &page_draws)?;
+        }
+
+        remove_annotations(document, &removed_widgets)?;
+
+        match field_names {
+            None => {
+                // Everything is flattened, so the form itself goes away along
+                // with the fields that only it led to
+                document.catalog_mut()?.remove(b"AcroForm");
+                replaced.insert(acro_form_id);
+                Self::collect_field_objects(&tree.fields, &mut replaced);
+            }
+            Some(_) => {
+                for node in &selected {
+                    detach_field(document, acro_form_id, node.object_id)?;
+                }
+            }
+        }
+
+        for widget_id in removed_widgets {
+            document.objects.remove(&widget_id);
+        }
+        prune_unreachable_among(document, replaced);
+
+        Ok(())
+    }
+
+    /// The display values of a choice field's selected options, joined for a single line
+    fn display_value(node: &PdfFieldNode) -> String {
+        let selected: Vec<&str> = match &node.value {
+            Some(PdfFieldValue::Single(value)) => vec![value.as_str()],
+            Some(PdfFieldValue::Multiple(values)) => values.iter().map(String::as_str).collect(),
+            None => Vec::new(),
+        };
+        selected
+            .into_iter()
+            .map(|value| {
+                node.options
+                    .iter()
+                    .find(|option| option.export_value == value)
+                    .map_or(value, |option| option.display_value.as_str())
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Every field and widget object of a field hierarchy
+    fn collect_field_objects(nodes: &[PdfFieldNode], objects: &mut HashSet<ObjectId>) {
+        for node in nodes {
+            objects.insert(node.object_id);
+            objects.extend(node.widgets.iter().map(|widget| widget.object_id));
+            Self::collect_field_objects(&node.children, objects);
+        }
+    }
+
+    /// Locate the normal appearance of a widget and the matrix placing it on the page
+    fn placed_appearance(document: &mut Document, widget_id: ObjectId, page_rotation: i64) -> Option<(ObjectId, Matrix)> {
+        let widget = document.get_dictionary(widget_id).ok()?;
+
+        let flags = widget.get(b"F").and_then(|obj| obj.as_i64()).unwrap_or(0);
+        if flags & ANNOT_HIDDEN != 0 {
+            return None;
+        }
+
+        let rect = Self::numbers(widget.get(b"Rect").ok()?)?;
+        if rect.len() != 4 {
+            return None;
+        }
+        let (x0, x1) = (rect[0].min(rect[2]), rect[0].max(rect[2]));
+        let (y0, y1) = (rect[1].min(rect[3]), rect[1].max(rect[3]));
+
+        // Buttons keep one appearance per state in /N, chosen by /AS
+        let ap = FormFieldGenerator::resolve_dict(document, widget.get(b"AP").ok()?)?;
+        let stream_id = match ap.get(b"N").ok()? {
+            Object::Reference(id) => match document.get_object(*id).ok()? {
+                Object::Stream(_) => *id,
+                Object::Dictionary(states) => states
+                    .get(widget.get(b"AS").and_then(|obj| obj.as_name()).ok()?)
+                    .and_then(|obj| obj.as_reference())
+                    .ok()?,
+                _ => return None,
+            },
+            Object::Dictionary(states) => states
+                .get(widget.get(b"AS").and_then(|obj| obj.as_name()).ok()?)
+                .and_then(|obj| obj.as_reference())
+                .ok()?,
+            _ => return None,
+        };
+
+        let stream = document.get_object(stream_id).and_then(|obj| obj.as_stream()).ok()?;
+        let bbox = Self::numbers(stream.dict.get(b"BBox").ok()?)?;
+        if bbox.len() != 4 {
+            return None;
+        }
+        let form_matrix: Matrix = stream
+            .dict
+            .get(b"Matrix")
+            .ok()
+            .and_then(Self::numbers)
+            .and_then(|m| m.try_into().ok())
+            .unwrap_or(IDENTITY);
+
+        // Map the transformed bounding box onto the widget rectangle (section 12.5.5)
+        let corners = [(bbox[0], bbox[1]), (bbox[2], bbox[1]), (bbox[0], bbox[3]), (bbox[2], bbox[3])]
+            .map(|(x, y)| Self::apply(&form_matrix, x, y));
+        let bx0 = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+        let bx1 = corners.iter().map(|c| c.0).fold(f64::NEG_INFINITY, f64::max);
+        let by0 = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+        let by1 = corners.iter().map(|c| c.1).fold(f64::NEG_INFINITY, f64::max);
+        if bx1 - bx0 <= 0.0 || by1 - by0 <= 0.0 {
+            return None;
+        }
+
+        let sx = (x1 - x0) / (bx1 - bx0);
+        let sy = (y1 - y0) / (by1 - by0);
+        let mut matrix = [sx, 0.0, 0.0, sy, x0 - sx * bx0, y0 - sy * by0];
+
+        // NoRotate widgets stay upright on rotated pages, pivoting on their top-left corner
+        if flags & ANNOT_NO_ROTATE != 0 && page_rotation.rem_euclid(360) != 0 {
+            let angle = (page_rotation.rem_euclid(360) as f64).to_radians();
+            let (sin, cos) = (angle.sin(), angle.cos());
+            let rotation = [cos, sin, -sin, cos, 0.0, 0.0];
+            matrix = Self::multiply(&matrix, &[1.0, 0.0, 0.0, 1.0, -x0, -y1]);
+            matrix = Self::multiply(&matrix, &rotation);
+            matrix = Self::multiply(&matrix, &[1.0, 0.0, 0.0, 1.0, x0, y1]);
+        }
+
+        // The stream is drawn with Do, which needs a complete Form XObject
+        if let Ok(Object::Stream(stream)) = document.get_object_mut(stream_id) {
+            stream.dict.set("Type", Object::Name(b"XObject".to_vec()));
+            stream.dict.set("Subtype", Object::Name(b"Form".to_vec()));
+        }
+
+        Some((stream_id, matrix))
+    }
+
+    /// Wrap a page's existing content in q/Q and draw the flattened appearances after it
+    fn append_to_page(document: &mut Document, page_id: ObjectId, draws: &[(Vec<u8>, Matrix)]) -> Result<()> {
+        // Leading newline keeps Q from running into the last token of the previous stream
+        let mut content = b"\nQ\n".to_vec();
+        for (name, m) in draws {
+            content.extend_from_slice(
+                format!("q {:.4} {:.4} {:.4} {:.4} {:.4} {:.4} cm /", m[0], m[1], m[2], m[3], m[4], m[5]).as_bytes(),
+            );
+            content.extend_from_slice(name);
+            content.extend_from_slice(b" Do Q\n");
+        }
+
+        let save_id = document.add_object(Object::Stream(Stream::new(Dictionary::new(), b"q\n".to_vec())));
+        let draw_id = document.add_object(Object::Stream(Stream::new(Dictionary::new(), content)));
+
+        let mut contents = vec![Object::Reference(save_id)];
+        contents.extend(document.get_page_contents(page_id).into_iter().map(Object::Reference));
+        contents.push(Object::Reference(draw_id));
+
+        document.get_dictionary_mut(page_id)?.set("Contents", Object::Array(contents));
+        Ok(())
+    }
+
+    /// Give a page its own `/Resources` entry, taking over any inherited one
+    fn own_resources(document: &mut Document, page_id: ObjectId) -> Result<()> {
+        if document.get_dictionary(page_id)?.has(b"Resources") {
+            return Ok(());
+        }
+
+        let mut resources = Object::Dictionary(Dictionary::new());
+        let mut current = document
+            .get_dictionary(page_id)?
+            .get(b"Parent")
+            .and_then(|obj| obj.as_reference())
+            .ok();
+        while let Some(id) = current {
+            let node = document.get_dictionary(id)?;
+            if let Ok(inherited) = node.get(b"Resources") {
+                resources = inherited.clone();
+                break;
+            }
+            current = node.get(b"Parent").and_then(|obj| obj.as_reference()).ok();
+        }
+
+        document.get_dictionary_mut(page_id)?.set("Resources", resources);
+        Ok(())
+    }
+
+    /// Map each annotation on a page to that page
+    fn widget_pages(document: &Document) -> BTreeMap<ObjectId, ObjectId> {
+        let mut pages = BTreeMap::new();
+        for page_id in document.get_pages().into_values() {
+            let annots = document
+                .get_dictionary(page_id)
+                .and_then(|page| page.get(b"Annots"))
+                .and_then(|obj| document.dereference(obj))
+                .and_then(|(_, obj)| obj.as_array());
+            if let Ok(annots) = annots {
+                for annot_id in annots.iter().filter_map(|annot| annot.as_reference().ok()) {
+                    pages.insert(annot_id, page_id);
+                }
+            }
+        }
+        pages
+    }
+
+    /// `/Rotate` of every page, following inheritance from the page tree
+    fn page_rotations(document: &Document) -> BTreeMap<ObjectId, i64> {
+        document
+            .get_pages()
+            .into_values()
+            .map(|page_id| {
+                let mut current = Some(page_id);
+                let mut rotation = 0;
+                while let Some(id) = current {
+                    let Ok(node) = document.get_dictionary(id) else {
+                        break;
+                    };
+                    if let Ok(rotate) = node.get(b"Rotate").and_then(|obj| obj.as_i64()) {
+                        rotation = rotate;
+                        break;
+                    }
+                    current = node.get(b"Parent").and_then(|obj| obj.as_reference()).ok();
+                }
+                (page_id, rotation)
+            })
+            .collect()
+    }
+
+    fn numbers(obj: &Object) -> Option<Vec<f64>> {
+        obj.as_array()
+            .ok()
+            .map(|values| values.iter().map(|v| v.as_float().unwrap_or(0.0) as f64).collect())
+    }
+
+    fn apply(m: &Matrix, x: f64, y: f64) -> (f64, f64) {
+        (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+    }
+
+    /// Concatenate two matrices so that `a` is applied before `b`
+    fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+        [
+            a[0] * b[0] + a[1] * b[2],
+            a[0] * b[1] + a[1] * b[3],
+            a[2] * b[0] + a[3] * b[2],
+            a[2] * b[1] + a[3] * b[3],
+            a[4] * b[0] + a[5] * b[2] + b[4],
+            a[4] * b[1] + a[5] * b[3] + b[5],
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::form::PdfFormField;
+    use crate::pdf::testing::{field, form_document, nest_field};
+    use lopdf::dictionary;
+
+    fn widget_id(document: &Document, name: &str) -> ObjectId {
+        FormFieldTree::from_document(document).find(name).unwrap().widgets[0].object_id
+    }
+
+    /// The `cm` matrices the flattened appearances of the first page are drawn with
+    fn drawn_matrices(document: &Document) -> Vec<Matrix> {
+        let page_id = document.get_pages()[&1];
+        let contents = document.get_page_contents(page_id);
+        let draw = document.get_object(*contents.last().unwrap()).unwrap().as_stream().unwrap();
+        String::from_utf8_lossy(&draw.content)
+            .lines()
+            .filter_map(|line| line.strip_prefix("q "))
+            .map(|line| {
+                let numbers: Vec<f64> = line.split_whitespace().take(6).map(|n| n.parse().unwrap()).collect();
+                numbers.try_into().unwrap()
+            })
+            .collect()
+    }
+
+    fn set_appearance(document: &mut Document, widget_id: ObjectId, bbox: [i64; 4], matrix: Option<[i64; 6]>) {
+        let mut stream = Stream::new(
+            dictionary! { "BBox" => bbox.iter().map(|v| Object::Integer(*v)).collect::<Vec<_>>() },
+            b"0 g 0 0 10 10 re f".to_vec(),
+        );
+        if let Some(matrix) = matrix {
+            stream.dict.set("Matrix", matrix.iter().map(|v| Object::Integer(*v)).collect::<Vec<_>>());
+        }
+        let stream_id = document.add_object(stream);
+        document
+            .get_dictionary_mut(widget_id)
+            .unwrap()
+            .set("AP", dictionary! { "N" => stream_id });
+    }
+
+    fn with_value(mut field: PdfFormField, value: &str) -> PdfFormField {
+        field.value = Some(value.to_string());
+        field
+    }
+
+    #[test]
+    fn rotated_appearances_are_mapped_onto_the_widget_rect() {
+        let mut document = form_document(1, vec![field("name", FieldKind::Text, 0, &[])]);
+        let widget = widget_id(&document, "name");
+        // A tall box turned a quarter turn anticlockwise to fill the wide widget
+        set_appearance(&mut document, widget, [0, 0, 20, 200], Some([0, 1, -1, 0, 0, 0]));
+
+        FormFlattener::flatten_document(&mut document, None).unwrap();
+
+        let [matrix] = drawn_matrices(&document)[..] else { panic!("expected one draw") };
+        let form_matrix = [0.0, 1.0, -1.0, 0.0, 0.0, 0.0];
+        let placed = |x, y| {
+            let (x, y) = FormFlattener::apply(&form_matrix, x, y);
+            FormFlattener::apply(&matrix, x, y)
+        };
+        assert_eq!(placed(0.0, 0.0), (300.0, 600.0));
+        assert_eq!(placed(20.0, 200.0), (100.0, 620.0));
+    }
+
+    #[test]
+    fn no_rotate_widgets_stay_upright_on_rotated_pages() {
+        let mut document = form_document(1, vec![field("name", FieldKind::Text, 0, &[])]);
+        let widget = widget_id(&document, "name");
+        set_appearance(&mut document, widget, [0, 0, 200, 20], None);
+        document.get_dictionary_mut(widget).unwrap().set("F", Object::Integer(ANNOT_NO_ROTATE));
+        // The rotation is inherited from the page tree
+        let pages_id = document.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        document.get_dictionary_mut(pages_id).unwrap().set("Rotate", Object::Integer(90));
+
+        FormFlattener::flatten_document(&mut document, None).unwrap();
+
+        let [matrix] = drawn_matrices(&document)[..] else { panic!("expected one draw") };
+        let round = |(x, y): (f64, f64)| ((x * 1000.0).round() / 1000.0, (y * 1000.0).round() / 1000.0);
+        // The top-left corner stays put while the rest turns with the page
+        assert_eq!(round(FormFlattener::apply(&matrix, 0.0, 20.0)), (100.0, 620.0));
+        assert_eq!(round(FormFlattener::apply(&matrix, 200.0, 20.0)), (100.0, 820.0));
+    }
+
+    #[test]
+    fn flattening_some_fields_keeps_the_rest_editable() {
+        let mut document = form_document(1, vec![
+            with_value(field("first", FieldKind::Text, 0, &[]), "Ada"),
+            with_value(field("second", FieldKind::Text, 0, &[]), "Grace"),
+        ]);
+        let first_widget = widget_id(&document, "first");
+        let second_widget = widget_id(&document, "second");
+
+        FormFlattener::flatten_document(&mut document, Some(&["first".to_string()])).unwrap();
+
+        let tree = FormFieldTree::from_document(&document);
+        assert!(tree.find("first").is_none());
+        assert!(tree.find("second").is_some());
+        assert!(document.get_object(first_widget).is_err());
+
+        let page_id = document.get_pages()[&1];
+        let annots: Vec<ObjectId> = document.get_dictionary(page_id).unwrap().get(b"Annots").unwrap().as_array().unwrap()
+            .iter()
+            .map(|annot| annot.as_reference().unwrap())
+            .collect();
+        assert_eq!(annots, vec![second_widget]);
+        assert_eq!(drawn_matrices(&document).len(), 1);
+    }
+
+    #[test]
+    fn flattening_everything_removes_parent_fields() {
+        let mut document = form_document(1, vec![with_value(field("name", FieldKind::Text, 0, &[]), "Ada")]);
+        let field_id = widget_id(&document, "name");
+        let parent_id = nest_field(&mut document, "person", "name");
+        let acro_form_id = document.catalog().unwrap().get(b"AcroForm").unwrap().as_reference().unwrap();
+        assert!(FormFieldTree::from_document(&document).find("person.name").is_some());
+
+        FormFlattener::flatten_document(&mut document, None).unwrap();
+
+        assert!(!document.catalog().unwrap().has(b"AcroForm"));
+        assert!(document.get_object(field_id).is_err());
+        assert!(document.get_object(parent_id).is_err());
+        assert!(document.get_object(acro_form_id).is_err());
+        assert_eq!(drawn_matrices(&document).len(), 1);
+    }
+
+    #[test]
+    fn values_without_appearances_are_drawn_before_flattening() {
+        let mut document = form_document(1, vec![
+            with_value(field("name", FieldKind::Text, 0, &[]), "Ada"),
+            with_value(field("colour", FieldKind::Combo, 0, &["Red", "Green"]), "Green"),
+        ]);
+        for name in ["name", "colour"] {
+            let widget = widget_id(&document, name);
+            document.get_dictionary_mut(widget).unwrap().remove(b"AP");
+        }
+
+        FormFlattener::flatten_document(&mut document, None).unwrap();
+
+        assert_eq!(drawn_matrices(&document).len(), 2);
+        let page_id = document.get_pages()[&1];
+        let drawn: Vec<Vec<u8>> = document
+            .get_page_resources(page_id)
+            .0
+            .unwrap()
+            .get(b"XObject")
+            .unwrap()
+            .as_dict()
+            .unwrap()
+            .iter()
+            .map(|(_, stream)| {
+                let stream = document.get_object(stream.as_reference().unwrap()).unwrap().as_stream().unwrap();
+                stream.decompressed_content().unwrap_or_else(|_| stream.content.clone())
+            })
+            .collect();
+        let shows = |text: &str| drawn.iter().any(|content| String::from_utf8_lossy(content).contains(text));
+        assert!(shows("(Ada)"));
+        assert!(shows("(Green)"));
+    }
+
+    #[test]
+    fn checkboxes_without_appearances_are_refused() {
+        let mut document = form_document(1, vec![
+            with_value(field("name", FieldKind::Text, 0, &[]), "Ada"),
+            with_value(field("agree", FieldKind::Checkbox, 0, &[]), "true"),
+        ]);
+        let checkbox = widget_id(&document, "agree");
+        document.get_dictionary_mut(checkbox).unwrap().remove(b"AP");
+        let before = document.objects.len();
+
+        assert!(matches!(
+            FormFlattener::flatten_document(&mut document, None),
+            Err(PdfError::UnsupportedOperation(_))
+        ));
+        assert_eq!(document.objects.len(), before);
+        assert!(document.catalog().unwrap().has(b"AcroForm"));
+    }
+
+    #[test]
+    fn refused_flattens_leave_a_form_held_in_the_catalog_untouched() {
+        let mut document = form_document(1, vec![with_value(field("agree", FieldKind::Checkbox, 0, &[]), "true")]);
+        let checkbox = widget_id(&document, "agree");
+        document.get_dictionary_mut(checkbox).unwrap().remove(b"AP");
+
+        // Hold the AcroForm directly in the catalog
+        let acro_form_id = document.catalog().unwrap().get(b"AcroForm").unwrap().as_reference().unwrap();
+        let acro_form = document.objects.remove(&acro_form_id).unwrap();
+        document.catalog_mut().unwrap().set("AcroForm", acro_form);
+        let before = format!("{:?}", document.objects);
+
+        assert!(matches!(
+            FormFlattener::flatten_document(&mut document, None),
+            Err(PdfError::UnsupportedOperation(_))
+        ));
+        assert_eq!(format!("{:?}", document.objects), before);
+    }
+}
@@ -0,0 +1,485 @@
+use lopdf::{Document, Object, ObjectId};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use crate::pdf::{self, PdfError, Result};
+use super::{inherited_attribute, radio_export_value, FieldKind, FormFieldTree, FormFiller, PdfFieldNode, PdfFieldValue};
+
+const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Deepest object nesting followed when flattening imported data
+const MAX_NESTING_DEPTH: usize = 32;
+
+/// Reads and writes form data as JSON keyed by fully-qualified field name,
+/// and describes that data with a JSON Schema
+pub struct FormJson;
+
+impl FormJson {
+    /// Describe the form data of a loaded document as a JSON Schema
+    ///
+    /// Each exported field becomes a property named by its fully-qualified
+    /// name. Choice options and button states become enums, `/MaxLen` becomes
+    /// `maxLength`, the Required flag lists the field under `required`, and
+    /// the number, date and special formats of Acrobat's keystroke and format
+    /// scripts become patterns.
+    pub fn schema(document: &Document) -> Value {
+        let tree = FormFieldTree::from_document(document);
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+
+        for field in tree.terminals() {
+            if !field.is_exported() {
+                continue;
+            }
+            if field.flags.required {
+                required.push(Value::String(field.full_name.clone()));
+            }
+            properties.insert(field.full_name.clone(), Self::field_schema(document, field));
+        }
+
+        json!({
+            "$schema": JSON_SCHEMA_DIALECT,
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": false,
+        })
+    }
+
+    /// Export the field values of a loaded document as a flat JSON object
+    ///
+    /// Multi-select list boxes hold an array of their selections and radio
+    /// groups with `/Opt` the export value of the selected option. Fields
+    /// without a value are left out, as are push buttons, signatures and
+    /// fields flagged NoExport.
+    pub fn export_document(document: &Document) -> Value {
+        let tree = FormFieldTree::from_document(document);
+        let values: Map<String, Value> = tree
+            .terminals()
+            .into_iter()
+            .filter(|field| field.is_exported())
+            .filter_map(|field| {
+                let value = Self::json_value(document, field, field.value.as_ref()?);
+                Some((field.full_name.clone(), value))
+            })
+            .collect();
+
+        Value::Object(values)
+    }
+
+    /// Parse JSON field values by fully-qualified name
+    ///
+    /// Keys may be fully-qualified names, nested objects of partial names, or
+    /// a mix of both. Strings and numbers set a single value, arrays of them
+    /// a multiple selection, and nulls are skipped.
+    pub fn parse(text: &str) -> Result<Vec<(String, PdfFieldValue)>> {
+        let data: Value = serde_json::from_str(text)
+            .map_err(|e| PdfError::InvalidFormData(format!("Invalid JSON: {}", e)))?;
+        let Value::Object(data) = data else {
+            return Err(PdfError::InvalidFormData("JSON form data must be an object".to_string()));
+        };
+
+        let mut values = Vec::new();
+        Self::read_object(&data, "", &mut values, 0)?;
+
+        Ok(values)
+    }
+
+    /// Apply JSON field values to a loaded document
+    pub fn import_into_document(document: &mut Document, text: &str) -> Result<()> {
+        let values: HashMap<String, PdfFieldValue> = Self::parse(text)?.into_iter().collect();
+
+        FormFiller::fill_document(document, &values)
+    }
+
+    fn field_schema(document: &Document, field: &PdfFieldNode) -> Value {
+        let mut schema = Map::new();
+        let options: Vec<&str> = field.options.iter().map(|option| option.export_value.as_str()).collect();
+
+        match field.kind {
+            Some(FieldKind::Checkbox | FieldKind::Radio) => {
+                // Radio groups with /Opt are filled by export value, the rest by state name
+                let mut states = vec!["Off".to_string()];
+                if field.kind == Some(FieldKind::Radio) && !options.is_empty() {
+                    states.extend(options.iter().map(|option| option.to_string()));
+                } else {
+                    states.extend(
+                        field
+                            .widgets
+                            .iter()
+                            .filter_map(|widget| FormFiller::on_state(document, widget.object_id))
+                            .map(|state| String::from_utf8_lossy(&state).to_string()),
+                    );
+                }
+                states.dedup();
+                schema.insert("type".to_string(), json!("string"));
+                schema.insert("enum".to_string(), json!(states));
+            }
+            Some(FieldKind::List) if field.flags.multi_select => {
+                schema.insert("type".to_string(), json!("array"));
+                schema.insert("items".to_string(), json!({ "type": "string", "enum": options }));
+                schema.insert("uniqueItems".to_string(), json!(true));
+            }
+            Some(FieldKind::Combo) if field.flags.edit => {
+                // Editable combo boxes accept text beyond their options
+                schema.insert("type".to_string(), json!("string"));
+                schema.insert("examples".to_string(), json!(options));
+            }
+            Some(FieldKind::Combo | FieldKind::List) => {
+                schema.insert("type".to_string(), json!("string"));
+                schema.insert("enum".to_string(), json!(options));
+            }
+            _ => {
+                schema.insert("type".to_string(), json!("string"));
+                let max_len = inherited_attribute(document, field.object_id, b"MaxLen")
+                    .and_then(|obj| obj.as_i64().ok())
+                    .filter(|max_len| *max_len > 0);
+                if let Some(max_len) = max_len {
+                    schema.insert("maxLength".to_string(), json!(max_len));
+                }
+                if let Some(pattern) = Self::validation_pattern(document, field) {
+                    schema.insert("pattern".to_string(), json!(pattern));
+                }
+            }
+        }
+
+        let description = inherited_attribute(document, field.object_id, b"TU")
+            .and_then(|obj| obj.as_str().map(pdf::decode_text_string).ok());
+        if let Some(description) = description {
+            schema.insert("description".to_string(), json!(description));
+        }
+        if let Some(default_value) = &field.default_value {
+            schema.insert("default".to_string(), Self::json_value(document, field, default_value));
+        }
+        if field.flags.read_only {
+            schema.insert("readOnly".to_string(), json!(true));
+        }
+
+        Value::Object(schema)
+    }
+
+    /// The JSON form of a field value as described by the schema
+    fn json_value(document: &Document, field: &PdfFieldNode, value: &PdfFieldValue) -> Value {
+        match value {
+            PdfFieldValue::Single(value) if field.kind == Some(FieldKind::List) && field.flags.multi_select => {
+                json!([value])
+            }
+            PdfFieldValue::Single(state) if field.kind == Some(FieldKind::Radio) => {
+                json!(radio_export_value(document, field, state))
+            }
+            PdfFieldValue::Single(value) => json!(value),
+            PdfFieldValue::Multiple(values) => json!(values),
+        }
+    }
+
+    /// Derive a pattern from the keystroke or format script of a text field
+    ///
+    /// Only the formatting functions of Acrobat's own script library are
+    /// recognised; custom scripts yield no pattern.
+    fn validation_pattern(document: &Document, field: &PdfFieldNode) -> Option<String> {
+        let owners = std::iter::once(field.object_id).chain(field.widgets.iter().map(|widget| widget.object_id));
+        for owner in owners {
+            for trigger in [&b"K"[..], b"F"] {
+                let Some(script) = Self::action_script(document, owner, trigger) else {
+                    continue;
+                };
+                if let Some(pattern) = Self::script_pattern(&script) {
+                    return Some(pattern);
+                }
+            }
+        }
+        None
+    }
+
+    /// The JavaScript run by one of a field's additional actions (`/AA`)
+    fn action_script(document: &Document, owner: ObjectId, trigger: &[u8]) -> Option<String> {
+        let resolve = |obj: &Object| document.dereference(obj).ok().map(|(_, obj)| obj.clone());
+
+        let actions = resolve(document.get_dictionary(owner).ok()?.get(b"AA").ok()?)?;
+        let action = resolve(actions.as_dict().ok()?.get(trigger).ok()?)?;
+        match resolve(action.as_dict().ok()?.get(b"JS").ok()?)? {
+            Object::String(bytes, _) => Some(pdf::decode_text_string(&bytes)),
+            Object::Stream(stream) => {
+                let content = stream.decompressed_content().unwrap_or(stream.content);
+                Some(pdf::decode_text_string(&content))
+            }
+            _ => None,
+        }
+    }
+
+    fn script_pattern(script: &str) -> Option<String> {
+        let call = |names: &[&str]| names.iter().find_map(|name| Self::script_call(script, name));
+
+        if let Some(args) = call(&["AFNumber_Keystroke", "AFNumber_Format", "AFPercent_Keystroke", "AFPercent_Format"]) {
+            // The separator style picks the grouping and decimal marks
+            let pattern = match args.get(1).map(String::as_str).unwrap_or("0") {
+                "1" => r"^-?\d*(\.\d*)?$",
+                "2" => r"^-?(\d{1,3}(\.\d{3})*|\d*)(,\d*)?$",
+                "3" => r"^-?\d*(,\d*)?$",
+                _ => r"^-?(\d{1,3}(,\d{3})*|\d*)(\.\d*)?$",
+            };
+            return Some(pattern.to_string());
+        }
+        if let Some(args) = call(&["AFSpecial_KeystrokeEx", "AFSpecial_FormatEx"]) {
+            return args.first().map(|mask| Self::mask_pattern(mask));
+        }
+        if let Some(args) = call(&["AFSpecial_Keystroke", "AFSpecial_Format"]) {
+            // Zip code, Zip+4, phone number and social security number
+            let pattern = match args.first().map(String::as_str) {
+                Some("0") => r"^\d{5}$",
+                Some("1") => r"^\d{5}[- ]?\d{4}$",
+                Some("2") => r"^(\(?\d{3}\)?[- .]?)?\d{3}[- .]?\d{4}$",
+                Some("3") => r"^\d{3}[- ]?\d{2}[- ]?\d{4}$",
+                _ => return None,
+            };
+            return Some(pattern.to_string());
+        }
+        if let Some(args) = call(&["AFDate_KeystrokeEx", "AFDate_FormatEx", "AFTime_FormatEx"]) {
+            return args.first().map(|format| Self::date_pattern(format));
+        }
+        None
+    }
+
+    /// The arguments of the first call to a function in a script, with
+    /// string literals unquoted
+    fn script_call(script: &str, name: &str) -> Option<Vec<String>> {
+        let start = script.find(&format!("{}(", name))? + name.len() + 1;
+        let mut args = Vec::new();
+        let mut current = String::new();
+        let mut quote: Option<char> = None;
+        let mut chars = script[start..].chars();
+
+        while let Some(c) = chars.next() {
+            match (quote, c) {
+                (Some(_), '\\') => current.extend(chars.next()),
+                (Some(open), c) if c == open => quote = None,
+                (Some(_), c) => current.push(c),
+                (None, '"' | '\'') => quote = Some(c),
+                (None, ',') => args.push(std::mem::take(&mut current).trim().to_string()),
+                (None, ')') => {
+                    args.push(current.trim().to_string());
+                    return Some(args);
+                }
+                (None, c) => current.push(c),
+            }
+        }
+        None
+    }
+
+    /// Translate an `AFSpecial_KeystrokeEx` mask, where `9` is a digit, `A` a
+    /// letter, `O` a letter or digit and `X` any character
+    fn mask_pattern(mask: &str) -> String {
+        let mut pattern = String::from("^");
+        for c in mask.chars() {
+            match c {
+                '9' => pattern.push_str(r"\d"),
+                'A' => pattern.push_str("[A-Za-z]"),
+                'O' => pattern.push_str("[A-Za-z0-9]"),
+                'X' => pattern.push('.'),
+                c => pattern.push_str(&Self::escape_regex(c)),
+            }
+        }
+        pattern.push('$');
+        pattern
+    }
+
+    /// Translate an Acrobat date or time format such as `mm/dd/yyyy`
+    fn date_pattern(format: &str) -> String {
+        let mut pattern = String::from("^");
+        let chars: Vec<char> = format.chars().collect();
+        let mut index = 0;
+
+        while index < chars.len() {
+            let c = chars[index];
+            let run = chars[index..].iter().take_while(|other| **other == c).count();
+            let part = match (c, run) {
+                ('y', 4) => Some(r"\d{4}".to_string()),
+                ('y', _) => Some(r"\d{2}".to_string()),
+                ('m', 3..) => Some("[A-Za-z]+".to_string()),
+                ('d' | 'm' | 'H' | 'h' | 'M' | 's', 1) => Some(r"\d{1,2}".to_string()),
+                ('d' | 'm' | 'H' | 'h' | 'M' | 's', 2) => Some(r"\d{2}".to_string()),
+                ('t', _) => Some("[AaPp][Mm]?".to_string()),
+                _ => None,
+            };
+            match part {
+                Some(part) => {
+                    pattern.push_str(&part);
+                    index += run;
+                }
+                None => {
+                    pattern.push_str(&Self::escape_regex(c));
+                    index += 1;
+                }
+            }
+        }
+        pattern.push('$');
+        pattern
+    }
+
+    fn escape_regex(c: char) -> String {
+        if r"\^$.|?*+()[]{}/".contains(c) {
+            format!("\\{}", c)
+        } else {
+            c.to_string()
+        }
+    }
+
+    fn read_object(
+        object: &Map<String, Value>,
+        parent_name: &str,
+        values: &mut Vec<(String, PdfFieldValue)>,
+        depth: usize,
+    ) -> Result<()> {
+        if depth > MAX_NESTING_DEPTH {
+            return Err(PdfError::InvalidFormData("JSON form data is nested too deeply".to_string()));
+        }
+
+        for (name, value) in object {
+            let full_name = if parent_name.is_empty() {
+                name.clone()
+            } else {
+                format!("{}.{}", parent_name, name)
+            };
+            let scalar = |value: &Value| match value {
+                Value::String(value) => Ok(value.clone()),
+                Value::Number(value) => Ok(value.to_string()),
+                _ => Err(PdfError::InvalidFormData(format!(
+                    "Value of '{}' must be a string, a number or an array of them",
+                    full_name
+                ))),
+            };
+
+            match value {
+                Value::Null => {}
+                Value::Object(children) => Self::read_object(children, &full_name, values, depth + 1)?,
+                Value::Array(items) => {
+                    let items = items.iter().map(scalar).collect::<Result<Vec<_>>>()?;
+                    values.push((full_name.clone(), PdfFieldValue::Multiple(items)));
+                }
+                value => {
+                    let value = scalar(value)?;
+                    values.push((full_name.clone(), PdfFieldValue::Single(value)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::testing::{field, form_document, nest_field};
+    use lopdf::dictionary;
+
+    /// A document with one text field per script, each run on keystrokes
+    fn scripted_document(scripts: &[(&str, &str)]) -> Document {
+        let fields = scripts.iter().map(|(name, _)| field(name, FieldKind::Text, 0, &[])).collect();
+        let mut document = form_document(1, fields);
+        let tree = FormFieldTree::from_document(&document);
+        for (name, script) in scripts {
+            let field_id = tree.find(name).unwrap().object_id;
+            let action = dictionary! { "S" => "JavaScript", "JS" => Object::string_literal(*script) };
+            document.get_dictionary_mut(field_id).unwrap().set("AA", dictionary! { "K" => action });
+        }
+        document
+    }
+
+    #[test]
+    fn acrobat_format_scripts_become_patterns() {
+        let document = scripted_document(&[
+            ("amount", "AFNumber_Keystroke(2, 0, 0, 0, \"\", true);"),
+            ("european", "AFNumber_Keystroke(2, 2, 0, 0, \"\", true);"),
+            ("code", "AFSpecial_KeystrokeEx(\"AA-9999\");"),
+            ("zip", "AFSpecial_Keystroke(0);"),
+            ("born", "AFDate_KeystrokeEx(\"mm/dd/yyyy\");"),
+            ("custom", "event.rc = event.change != 'x';"),
+        ]);
+        let schema = FormJson::schema(&document);
+        let pattern = |name: &str| schema["properties"][name]["pattern"].clone();
+
+        assert_eq!(pattern("amount"), json!(r"^-?(\d{1,3}(,\d{3})*|\d*)(\.\d*)?$"));
+        assert_eq!(pattern("european"), json!(r"^-?(\d{1,3}(\.\d{3})*|\d*)(,\d*)?$"));
+        assert_eq!(pattern("code"), json!(r"^[A-Za-z][A-Za-z]-\d\d\d\d$"));
+        assert_eq!(pattern("zip"), json!(r"^\d{5}$"));
+        assert_eq!(pattern("born"), json!(r"^\d{2}\/\d{2}\/\d{4}$"));
+        assert_eq!(pattern("custom"), Value::Null);
+    }
+
+    #[test]
+    fn field_settings_become_schema_keywords() {
+        let mut name = field("name", FieldKind::Text, 0, &[]);
+        name.properties.insert("maxLength".to_string(), "20".to_string());
+        name.flags.required = true;
+        let mut colors = field("colors", FieldKind::List, 0, &["Red", "Green", "Blue"]);
+        colors.flags.multi_select = true;
+        let fields = vec![name, field("size", FieldKind::Combo, 0, &["S", "M", "L"]), colors];
+        let schema = FormJson::schema(&form_document(1, fields));
+
+        assert_eq!(schema["properties"]["name"]["maxLength"], json!(20));
+        assert_eq!(schema["required"], json!(["name"]));
+        assert_eq!(schema["properties"]["size"]["enum"], json!(["S", "M", "L"]));
+        assert_eq!(schema["properties"]["colors"]["type"], json!("array"));
+        assert_eq!(schema["properties"]["colors"]["items"]["enum"], json!(["Red", "Green", "Blue"]));
+        assert_eq!(schema["properties"]["size"].get("maxLength"), None);
+    }
+
+    #[test]
+    fn radio_groups_round_trip_by_export_value() {
+        let fields = || {
+            let mut size = field("size", FieldKind::Radio, 0, &["Small", "Medium", "Large"]);
+            size.value = Some("Large".to_string());
+            vec![size, field("name", FieldKind::Text, 0, &[])]
+        };
+        let mut source = form_document(1, fields());
+        nest_field(&mut source, "customer", "name");
+        let values = HashMap::from([("customer.name".to_string(), PdfFieldValue::Single("Ada".to_string()))]);
+        FormFiller::fill_document(&mut source, &values).unwrap();
+
+        let exported = FormJson::export_document(&source);
+        assert_eq!(exported, json!({ "size": "Large", "customer.name": "Ada" }));
+        let schema = FormJson::schema(&source);
+        assert_eq!(schema["properties"]["size"]["enum"], json!(["Off", "Small", "Medium", "Large"]));
+
+        let mut copy = form_document(1, fields());
+        nest_field(&mut copy, "customer", "name");
+        let tree = FormFieldTree::from_document(&copy);
+        let size_id = tree.find("size").unwrap().object_id;
+        copy.get_dictionary_mut(size_id).unwrap().set("V", "Off");
+        FormJson::import_into_document(&mut copy, &exported.to_string()).unwrap();
+
+        let expected = FormFieldTree::from_document(&source);
+        let imported = FormFieldTree::from_document(&copy);
+        for name in ["size", "customer.name"] {
+            assert_eq!(imported.find(name).unwrap().value, expected.find(name).unwrap().value, "{}", name);
+        }
+    }
+
+    #[test]
+    fn nested_objects_give_fully_qualified_names() {
+        let text = r#"{
+            "customer": { "name": "Ada", "address": { "city": "London", "zip": 12345 } },
+            "customer.email": "ada@example.com",
+            "colors": ["Red", "Blue"],
+            "unset": null
+        }"#;
+
+        let values: HashMap<String, PdfFieldValue> = FormJson::parse(text).unwrap().into_iter().collect();
+        let single = |text: &str| PdfFieldValue::Single(text.to_string());
+        assert_eq!(
+            values,
+            HashMap::from([
+                ("customer.name".to_string(), single("Ada")),
+                ("customer.address.city".to_string(), single("London")),
+                ("customer.address.zip".to_string(), single("12345")),
+                ("customer.email".to_string(), single("ada@example.com")),
+                ("colors".to_string(), PdfFieldValue::Multiple(vec!["Red".to_string(), "Blue".to_string()])),
+            ])
+        );
+
+        assert!(matches!(FormJson::parse(r#"{ "flag": true }"#), Err(PdfError::InvalidFormData(_))));
+        assert!(matches!(FormJson::parse("[1, 2]"), Err(PdfError::InvalidFormData(_))));
+        let deep = format!("{}\"a\"{}", "{\"a\":".repeat(MAX_NESTING_DEPTH + 2), "}".repeat(MAX_NESTING_DEPTH + 2));
+        assert!(matches!(FormJson::parse(&deep), Err(PdfError::InvalidFormData(_))));
+    }
+}
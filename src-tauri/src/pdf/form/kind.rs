@@ -0,0 +1,297 @@
+use lopdf::{Document, Object};
+use serde::{Deserialize, Serialize};
+use crate::pdf;
+
+// Field flag bits common to all fields (PDF 32000-1:2008, table 221)
+const FLAG_READ_ONLY: i64 = 1 << 0;
+const FLAG_REQUIRED: i64 = 1 << 1;
+const FLAG_NO_EXPORT: i64 = 1 << 2;
+
+// Text field flag bits (table 228)
+const FLAG_MULTILINE: i64 = 1 << 12;
+const FLAG_PASSWORD: i64 = 1 << 13;
+const FLAG_FILE_SELECT: i64 = 1 << 20;
+const FLAG_DO_NOT_SPELL_CHECK: i64 = 1 << 22;
+const FLAG_DO_NOT_SCROLL: i64 = 1 << 23;
+const FLAG_COMB: i64 = 1 << 24;
+const FLAG_RICH_TEXT: i64 = 1 << 25;
+
+// Button field flag bits (table 226)
+const FLAG_NO_TOGGLE_TO_OFF: i64 = 1 << 14;
+const FLAG_RADIO: i64 = 1 << 15;
+const FLAG_PUSHBUTTON: i64 = 1 << 16;
+const FLAG_RADIOS_IN_UNISON: i64 = 1 << 25;
+
+// Choice field flag bits (table 230)
+const FLAG_COMBO: i64 = 1 << 17;
+const FLAG_EDIT: i64 = 1 << 18;
+const FLAG_SORT: i64 = 1 << 19;
+const FLAG_MULTI_SELECT: i64 = 1 << 21;
+const FLAG_COMMIT_ON_SEL_CHANGE: i64 = 1 << 26;
+
+/// The kind of a form field, combining its `/FT` type with the flags that
+/// tell buttons and choice fields apart
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldKind {
+    Text,
+    Checkbox,
+    Radio,
+    #[serde(alias = "dropdown")]
+    Combo,
+    List,
+    PushButton,
+    Signature,
+}
+
+impl FieldKind {
+    /// Determine the kind from a field's `/FT` and `/Ff` entries
+    pub fn from_pdf(field_type: &[u8], flags: i64) -> Option<Self> {
+        match field_type {
+            b"Tx" => Some(FieldKind::Text),
+            b"Btn" if flags & FLAG_PUSHBUTTON != 0 => Some(FieldKind::PushButton),
+            b"Btn" if flags & FLAG_RADIO != 0 => Some(FieldKind::Radio),
+            b"Btn" => Some(FieldKind::Checkbox),
+            b"Ch" if flags & FLAG_COMBO != 0 => Some(FieldKind::Combo),
+            b"Ch" => Some(FieldKind::List),
+            b"Sig" => Some(FieldKind::Signature),
+            _ => None,
+        }
+    }
+
+    /// The name of the kind as it appears in serialized data
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FieldKind::Text => "text",
+            FieldKind::Checkbox => "checkbox",
+            FieldKind::Radio => "radio",
+            FieldKind::Combo => "combo",
+            FieldKind::List => "list",
+            FieldKind::PushButton => "push_button",
+            FieldKind::Signature => "signature",
+        }
+    }
+
+    /// The `/FT` name of fields of this kind
+    pub fn field_type(self) -> &'static str {
+        match self {
+            FieldKind::Text => "Tx",
+            FieldKind::Checkbox | FieldKind::Radio | FieldKind::PushButton => "Btn",
+            FieldKind::Combo | FieldKind::List => "Ch",
+            FieldKind::Signature => "Sig",
+        }
+    }
+
+    /// The `/Ff` bits that select this kind within its field type
+    fn type_bits(self) -> i64 {
+        match self {
+            FieldKind::Radio => FLAG_RADIO,
+            FieldKind::PushButton => FLAG_PUSHBUTTON,
+            FieldKind::Combo => FLAG_COMBO,
+            _ => 0,
+        }
+    }
+}
+
+/// Decoded `/Ff` field flags
+///
+/// Only the flags that apply to a field's kind are read or written; the
+/// radio, push button and combo bits are implied by [`FieldKind`].
+///
+/// The default has no flags set. Flags left out when deserializing come from
+/// [`FieldFlags::for_new_field`] instead.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(default = "FieldFlags::for_new_field")]
+pub struct FieldFlags {
+    pub read_only: bool,
+    pub required: bool,
+    pub no_export: bool,
+    pub multiline: bool,
+    pub password: bool,
+    pub file_select: bool,
+    pub do_not_spell_check: bool,
+    pub do_not_scroll: bool,
+    pub comb: bool,
+    pub rich_text: bool,
+    pub no_toggle_to_off: bool,
+    pub radios_in_unison: bool,
+    pub edit: bool,
+    pub sort: bool,
+    pub multi_select: bool,
+    pub commit_on_sel_change: bool,
+}
+
+impl FieldFlags {
+    /// The flags of a field created without any: radio groups cannot be
+    /// toggled off and their options sharing an export value switch on
+    /// together, as users expect. Other kinds ignore both flags.
+    pub fn for_new_field() -> Self {
+        FieldFlags { no_toggle_to_off: true, radios_in_unison: true, ..Default::default() }
+    }
+
+    /// Decode the `/Ff` bits of a field of the given kind
+    pub fn from_bits(kind: FieldKind, bits: i64) -> Self {
+        let has = |bit: i64| bits & bit != 0;
+        let mut flags = FieldFlags {
+            read_only: has(FLAG_READ_ONLY),
+            required: has(FLAG_REQUIRED),
+            no_export: has(FLAG_NO_EXPORT),
+            ..Default::default()
+        };
+
+        match kind {
+            FieldKind::Text => {
+                flags.multiline = has(FLAG_MULTILINE);
+                flags.password = has(FLAG_PASSWORD);
+                flags.file_select = has(FLAG_FILE_SELECT);
+                flags.do_not_spell_check = has(FLAG_DO_NOT_SPELL_CHECK);
+                flags.do_not_scroll = has(FLAG_DO_NOT_SCROLL);
+                flags.comb = has(FLAG_COMB);
+                flags.rich_text = has(FLAG_RICH_TEXT);
+            }
+            FieldKind::Radio => {
+                flags.no_toggle_to_off = has(FLAG_NO_TOGGLE_TO_OFF);
+                flags.radios_in_unison = has(FLAG_RADIOS_IN_UNISON);
+            }
+            FieldKind::Combo | FieldKind::List => {
+                flags.edit = has(FLAG_EDIT);
+                flags.sort = has(FLAG_SORT);
+                flags.multi_select = has(FLAG_MULTI_SELECT);
+                flags.do_not_spell_check = has(FLAG_DO_NOT_SPELL_CHECK);
+                flags.commit_on_sel_change = has(FLAG_COMMIT_ON_SEL_CHANGE);
+            }
+            FieldKind::Checkbox | FieldKind::PushButton | FieldKind::Signature => {}
+        }
+
+        flags
+    }
+
+    /// Encode the flags as the `/Ff` value of a field of the given kind
+    pub fn bits(&self, kind: FieldKind) -> i64 {
+        let bit = |on: bool, bit: i64| if on { bit } else { 0 };
+        let mut bits = kind.type_bits()
+            | bit(self.read_only, FLAG_READ_ONLY)
+            | bit(self.required, FLAG_REQUIRED)
+            | bit(self.no_export, FLAG_NO_EXPORT);
+
+        match kind {
+            FieldKind::Text => {
+                bits |= bit(self.multiline, FLAG_MULTILINE)
+                    | bit(self.password, FLAG_PASSWORD)
+                    | bit(self.file_select, FLAG_FILE_SELECT)
+                    | bit(self.do_not_spell_check, FLAG_DO_NOT_SPELL_CHECK)
+                    | bit(self.do_not_scroll, FLAG_DO_NOT_SCROLL)
+                    | bit(self.comb, FLAG_COMB)
+                    | bit(self.rich_text, FLAG_RICH_TEXT);
+            }
+            FieldKind::Radio => {
+                bits |= bit(self.no_toggle_to_off, FLAG_NO_TOGGLE_TO_OFF)
+                    | bit(self.radios_in_unison, FLAG_RADIOS_IN_UNISON);
+            }
+            FieldKind::Combo | FieldKind::List => {
+                bits |= bit(self.edit && kind == FieldKind::Combo, FLAG_EDIT)
+                    | bit(self.sort, FLAG_SORT)
+                    | bit(self.multi_select, FLAG_MULTI_SELECT)
+                    | bit(self.do_not_spell_check, FLAG_DO_NOT_SPELL_CHECK)
+                    | bit(self.commit_on_sel_change, FLAG_COMMIT_ON_SEL_CHANGE);
+            }
+            FieldKind::Checkbox | FieldKind::PushButton | FieldKind::Signature => {}
+        }
+
+        bits
+    }
+}
+
+/// An entry of a choice field's or radio group's `/Opt` array
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(from = "ChoiceOptionInput")]
+pub struct ChoiceOption {
+    /// Value stored in `/V` when the option is selected
+    pub export_value: String,
+    /// Text shown to the user, which is usually the export value
+    pub display_value: String,
+}
+
+/// Options may be sent as a plain string or as an export/display pair
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ChoiceOptionInput {
+    Plain(String),
+    Pair {
+        export_value: String,
+        #[serde(default)]
+        display_value: Option<String>,
+    },
+}
+
+impl From<ChoiceOptionInput> for ChoiceOption {
+    fn from(input: ChoiceOptionInput) -> Self {
+        match input {
+            ChoiceOptionInput::Plain(value) => ChoiceOption::new(value),
+            ChoiceOptionInput::Pair { export_value, display_value } => ChoiceOption {
+                display_value: display_value.unwrap_or_else(|| export_value.clone()),
+                export_value,
+            },
+        }
+    }
+}
+
+impl ChoiceOption {
+    /// An option whose export and display values are the same
+    pub fn new(value: impl Into<String>) -> Self {
+        let value = value.into();
+        ChoiceOption {
+            export_value: value.clone(),
+            display_value: value,
+        }
+    }
+
+    /// Read an `/Opt` array, where each entry is a text string or an
+    /// `[export display]` pair
+    pub fn read_options(document: &Document, opt: &Object) -> Vec<ChoiceOption> {
+        let text = |obj: &Object| {
+            document
+                .dereference(obj)
+                .and_then(|(_, obj)| obj.as_str())
+                .ok()
+                .map(pdf::decode_text_string)
+        };
+
+        document
+            .dereference(opt)
+            .and_then(|(_, obj)| obj.as_array())
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter_map(|entry| match document.dereference(entry).map(|(_, obj)| obj) {
+                        Ok(Object::Array(pair)) if pair.len() == 2 => Some(ChoiceOption {
+                            export_value: text(&pair[0])?,
+                            display_value: text(&pair[1])?,
+                        }),
+                        Ok(entry) => text(entry).map(ChoiceOption::new),
+                        Err(_) => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Build an `/Opt` array, writing pairs only where the display value differs
+    pub fn to_opt_array(options: &[ChoiceOption]) -> Object {
+        Object::Array(
+            options
+                .iter()
+                .map(|option| {
+                    if option.export_value == option.display_value {
+                        pdf::encode_text_string(&option.export_value)
+                    } else {
+                        Object::Array(vec![
+                            pdf::encode_text_string(&option.export_value),
+                            pdf::encode_text_string(&option.display_value),
+                        ])
+                    }
+                })
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,448 @@
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream, StringFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use crate::pdf::appearance::{color_operation, reals, TextAlignment};
+use crate::pdf::font::{self, StandardFont};
+use crate::pdf::{self, MetadataUpdate, PdfError, PdfParser, Result};
+use super::{ChoiceOption, FieldFlags, FieldKind, FormFieldGenerator, PdfFormField, PdfFormWidget};
+
+/// Base fonts every PDF viewer provides without embedding
+///
+/// Symbol and ZapfDingbats are left out: labels are encoded as WinAnsi,
+/// which those fonts do not use.
+const STANDARD_FONTS: [&str; 12] = [
+    "Helvetica", "Helvetica-Bold", "Helvetica-Oblique", "Helvetica-BoldOblique",
+    "Times-Roman", "Times-Bold", "Times-Italic", "Times-BoldItalic",
+    "Courier", "Courier-Bold", "Courier-Oblique", "Courier-BoldOblique",
+];
+
+/// Line spacing of multi-line labels as a multiple of the font size
+const LABEL_LEADING: f64 = 1.2;
+
+/// A declarative description of a form document, read from JSON or YAML
+///
+/// Positions are in points from the bottom-left corner of the page, as
+/// everywhere else in PDF.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FormLayout {
+    #[serde(default)]
+    pub title: Option<String>,
+    pub pages: Vec<LayoutPage>,
+}
+
+/// One page of a form layout with the elements drawn on it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LayoutPage {
+    #[serde(default)]
+    pub size: PageSize,
+    #[serde(default)]
+    pub landscape: bool,
+    #[serde(default)]
+    pub elements: Vec<LayoutElement>,
+}
+
+/// A page size, either a paper name such as `letter` or `a4`, or `[width, height]`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum PageSize {
+    Named(String),
+    Custom([f64; 2]),
+}
+
+impl Default for PageSize {
+    fn default() -> Self {
+        PageSize::Named("letter".to_string())
+    }
+}
+
+impl PageSize {
+    /// Width and height in points, in portrait orientation
+    pub fn dimensions(&self) -> Result<[f64; 2]> {
+        let dimensions = match self {
+            PageSize::Custom([width, height]) => [*width, *height],
+            PageSize::Named(name) => match name.to_ascii_lowercase().as_str() {
+                "letter" => [612.0, 792.0],
+                "legal" => [612.0, 1008.0],
+                "tabloid" => [792.0, 1224.0],
+                "a3" => [842.0, 1191.0],
+                "a4" => [595.0, 842.0],
+                "a5" => [420.0, 595.0],
+                _ => return Err(PdfError::InvalidLayout(format!("Unknown page size '{}'", name))),
+            },
+        };
+
+        if dimensions.iter().any(|side| !side.is_finite() || *side <= 0.0) {
+            return Err(PdfError::InvalidLayout(format!("Invalid page size {:?}", dimensions)));
+        }
+        Ok(dimensions)
+    }
+}
+
+/// Something drawn on a layout page
+///
+/// Colours are arrays of one (gray), three (RGB) or four (CMYK) components
+/// from 0 to 1, and an empty array leaves the text or outline undrawn.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LayoutElement {
+    /// A static label whose baseline starts at, is centred on or ends at `x`
+    Text {
+        text: String,
+        x: f64,
+        y: f64,
+        #[serde(default = "default_font_size")]
+        font_size: f64,
+        #[serde(default = "default_font")]
+        font: String,
+        #[serde(default)]
+        align: Option<TextAlignment>,
+        #[serde(default = "default_color")]
+        color: Vec<f64>,
+    },
+    Line {
+        from: [f64; 2],
+        to: [f64; 2],
+        #[serde(default = "default_line_width")]
+        width: f64,
+        #[serde(default = "default_color")]
+        color: Vec<f64>,
+    },
+    /// A rectangle given as `[x1, y1, x2, y2]`, outlined and optionally filled
+    Box {
+        rect: [f64; 4],
+        #[serde(default = "default_line_width")]
+        width: f64,
+        #[serde(default = "default_color")]
+        color: Vec<f64>,
+        #[serde(default)]
+        fill: Option<Vec<f64>>,
+    },
+    Field(LayoutField),
+}
+
+/// A form field placed on a layout page
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LayoutField {
+    pub name: String,
+    pub field_type: FieldKind,
+    #[serde(default)]
+    pub value: Option<String>,
+    pub rect: [f64; 4],
+    #[serde(default = "FieldFlags::for_new_field")]
+    pub flags: FieldFlags,
+    #[serde(default)]
+    pub options: Vec<ChoiceOption>,
+    /// Appearance settings such as `fontSize`, `alignment` and `maxLength`,
+    /// which may be written as numbers
+    #[serde(default)]
+    pub properties: HashMap<String, serde_json::Value>,
+    /// Per-option widgets of a radio group on the same page
+    #[serde(default)]
+    pub widgets: Vec<LayoutWidget>,
+}
+
+/// A widget of a layout field with several, such as a radio button
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LayoutWidget {
+    pub rect: [f64; 4],
+    #[serde(default)]
+    pub export_value: Option<String>,
+}
+
+fn default_font_size() -> f64 {
+    12.0
+}
+
+fn default_font() -> String {
+    "Helvetica".to_string()
+}
+
+fn default_color() -> Vec<f64> {
+    vec![0.0]
+}
+
+fn default_line_width() -> f64 {
+    1.0
+}
+
+impl FormLayout {
+    /// Read a layout file, as YAML for `.yaml` and `.yml` files and JSON otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let text = fs::read_to_string(path.as_ref())?;
+        let extension = path
+            .as_ref()
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("yaml" | "yml") => Self::from_yaml(&text),
+            _ => Self::from_json(&text),
+        }
+    }
+
+    /// Parse a layout written as JSON
+    pub fn from_json(text: &str) -> Result<Self> {
+        serde_json::from_str(text).map_err(|e| PdfError::InvalidLayout(e.to_string()))
+    }
+
+    /// Parse a layout written as YAML
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        serde_norway::from_str(text).map_err(|e| PdfError::InvalidLayout(e.to_string()))
+    }
+}
+
+impl LayoutField {
+    /// The field as the generator takes it, placed on the given page
+    fn to_form_field(&self, page: u32) -> PdfFormField {
+        let properties = self
+            .properties
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    serde_json::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                (key.clone(), value)
+            })
+            .collect();
+
+        PdfFormField {
+            id: self.name.clone(),
+            name: self.name.clone(),
+            field_type: self.field_type,
+            value: self.value.clone(),
+            rect: self.rect,
+            page,
+            flags: self.flags,
+            options: self.options.clone(),
+            properties,
+            widgets: self
+                .widgets
+                .iter()
+                .map(|widget| PdfFormWidget {
+                    rect: widget.rect,
+                    page,
+                    export_value: widget.export_value.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl FormFieldGenerator {
+    /// Create a new form PDF from a JSON or YAML layout file
+    pub fn create_form_pdf<P: AsRef<Path>>(layout_path: P, output_path: P) -> Result<()> {
+        let layout = FormLayout::load(layout_path)?;
+        let mut document = Self::create_form_document(&layout)?;
+
+        pdf::write_document(&mut document, output_path)?;
+
+        Ok(())
+    }
+
+    /// Build a new document from a layout
+    ///
+    /// Static elements are drawn into each page's content stream, then the
+    /// fields are added the same way as to an existing PDF.
+    pub fn create_form_document(layout: &FormLayout) -> Result<Document> {
+        if layout.pages.is_empty() {
+            return Err(PdfError::InvalidLayout("A layout needs at least one page".to_string()));
+        }
+
+        let mut document = Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+        let mut kids = Vec::with_capacity(layout.pages.len());
+        let mut fields = Vec::new();
+
+        for (index, page) in layout.pages.iter().enumerate() {
+            let page_id = Self::add_layout_page(&mut document, pages_id, page)
+                .map_err(|e| match e {
+                    PdfError::InvalidLayout(message) => {
+                        PdfError::InvalidLayout(format!("Page {}: {}", index + 1, message))
+                    }
+                    e => e,
+                })?;
+            kids.push(Object::Reference(page_id));
+
+            fields.extend(page.elements.iter().filter_map(|element| match element {
+                LayoutElement::Field(field) => Some(field.to_form_field(index as u32)),
+                _ => None,
+            }));
+        }
+
+        let mut pages = Dictionary::new();
+        pages.set("Type", Object::Name(b"Pages".to_vec()));
+        pages.set("Count", Object::Integer(kids.len() as i64));
+        pages.set("Kids", Object::Array(kids));
+        document.objects.insert(pages_id, Object::Dictionary(pages));
+
+        let mut catalog = Dictionary::new();
+        catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+        catalog.set("Pages", Object::Reference(pages_id));
+        let catalog_id = document.add_object(Object::Dictionary(catalog));
+        document.trailer.set("Root", Object::Reference(catalog_id));
+
+        if let Some(title) = &layout.title {
+            let metadata = MetadataUpdate {
+                title: Some(title.clone()),
+                ..Default::default()
+            };
+            PdfParser::update_metadata(&mut document, &metadata)?;
+        }
+
+        Self::ensure_acro_form(&mut document)?;
+        Self::add_fields_to_document(&mut document, fields)?;
+        Self::generate_appearances_in_document(&mut document)?;
+
+        Ok(document)
+    }
+
+    /// Add a page with its static elements drawn, returning its object id
+    fn add_layout_page(document: &mut Document, pages_id: ObjectId, page: &LayoutPage) -> Result<ObjectId> {
+        let [width, height] = page.size.dimensions()?;
+        let (width, height) = if page.landscape { (height, width) } else { (width, height) };
+
+        // Each base font used on the page gets its own resource name
+        let mut font_names: Vec<&str> = Vec::new();
+        let mut operations = Vec::new();
+        for element in &page.elements {
+            match element {
+                LayoutElement::Text { text, x, y, font_size, font, align, color } => {
+                    if !STANDARD_FONTS.contains(&font.as_str()) {
+                        return Err(PdfError::InvalidLayout(format!(
+                            "'{}' is not a standard text font (expected one of: {})",
+                            font,
+                            STANDARD_FONTS.join(", ")
+                        )));
+                    }
+                    let Some(color) = color_operation(color, false) else {
+                        continue;
+                    };
+                    let index = match font_names.iter().position(|name| name == font) {
+                        Some(index) => index,
+                        None => {
+                            font_names.push(font);
+                            font_names.len() - 1
+                        }
+                    };
+
+                    let metrics = StandardFont::from_base_font(font.as_bytes());
+                    operations.push(Operation::new("BT", vec![]));
+                    operations.push(color);
+                    operations.push(Operation::new("Tf", vec![
+                        Object::Name(format!("F{}", index + 1).into_bytes()),
+                        Object::Real(*font_size as f32),
+                    ]));
+
+                    // Td is relative to the start of the previous line
+                    let (mut previous_x, mut previous_y) = (0.0, 0.0);
+                    for (line_index, line) in text.lines().enumerate() {
+                        let encoded = font::encode_win_ansi(line);
+                        let line_width = metrics.text_width(&encoded, *font_size);
+                        let line_x = match align.unwrap_or(TextAlignment::Left) {
+                            TextAlignment::Left => *x,
+                            TextAlignment::Center => x - line_width / 2.0,
+                            TextAlignment::Right => x - line_width,
+                        };
+                        let line_y = y - font_size * LABEL_LEADING * line_index as f64;
+
+                        operations.push(Operation::new("Td", reals(&[line_x - previous_x, line_y - previous_y])));
+                        operations.push(Operation::new("Tj", vec![Object::String(encoded, StringFormat::Literal)]));
+                        (previous_x, previous_y) = (line_x, line_y);
+                    }
+                    operations.push(Operation::new("ET", vec![]));
+                }
+                LayoutElement::Line { from, to, width, color } => {
+                    let Some(color) = color_operation(color, true) else {
+                        continue;
+                    };
+                    operations.push(color);
+                    operations.push(Operation::new("w", reals(&[*width])));
+                    operations.push(Operation::new("m", reals(from)));
+                    operations.push(Operation::new("l", reals(to)));
+                    operations.push(Operation::new("S", vec![]));
+                }
+                LayoutElement::Box { rect, width, color, fill } => {
+                    let [x1, y1, x2, y2] = *rect;
+                    let bounds = reals(&[x1.min(x2), y1.min(y2), (x2 - x1).abs(), (y2 - y1).abs()]);
+                    let fill = fill.as_deref().and_then(|fill| color_operation(fill, false));
+                    let stroke = color_operation(color, true);
+
+                    let paint = match (&fill, &stroke) {
+                        (Some(_), Some(_)) => "B",
+                        (Some(_), None) => "f",
+                        (None, Some(_)) => "S",
+                        (None, None) => continue,
+                    };
+
+                    // Colours cannot change between building a path and painting it
+                    operations.extend(fill.into_iter().chain(stroke));
+                    operations.push(Operation::new("w", reals(&[*width])));
+                    operations.push(Operation::new("re", bounds));
+                    operations.push(Operation::new(paint, vec![]));
+                }
+                LayoutElement::Field(_) => {}
+            }
+        }
+
+        let mut fonts = Dictionary::new();
+        for (index, base_font) in font_names.iter().enumerate() {
+            let mut font = Dictionary::new();
+            font.set("Type", Object::Name(b"Font".to_vec()));
+            font.set("Subtype", Object::Name(b"Type1".to_vec()));
+            font.set("BaseFont", Object::Name(base_font.as_bytes().to_vec()));
+            font.set("Encoding", Object::Name(b"WinAnsiEncoding".to_vec()));
+            let font_id = document.add_object(Object::Dictionary(font));
+            fonts.set(format!("F{}", index + 1), Object::Reference(font_id));
+        }
+        let mut resources = Dictionary::new();
+        resources.set("Font", Object::Dictionary(fonts));
+
+        let content = Content { operations }.encode()?;
+        let content_id = document.add_object(Object::Stream(Stream::new(Dictionary::new(), content)));
+
+        let mut page_dict = Dictionary::new();
+        page_dict.set("Type", Object::Name(b"Page".to_vec()));
+        page_dict.set("Parent", Object::Reference(pages_id));
+        page_dict.set("MediaBox", Object::Array(reals(&[0.0, 0.0, width, height])));
+        page_dict.set("Resources", Object::Dictionary(resources));
+        page_dict.set("Contents", Object::Reference(content_id));
+
+        Ok(document.add_object(Object::Dictionary(page_dict)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label_layout(font: &str) -> String {
+        format!(
+            "title: Sign-up\npages:\n  - size: a4\n    elements:\n      - type: text\n        text: Name\n        x: 72\n        y: 700\n        font: {}\n",
+            font
+        )
+    }
+
+    #[test]
+    fn yaml_layouts_are_read() {
+        let layout = FormLayout::from_yaml(&label_layout("Times-Bold")).unwrap();
+        assert_eq!(layout.title.as_deref(), Some("Sign-up"));
+
+        let document = FormFieldGenerator::create_form_document(&layout).unwrap();
+        assert_eq!(document.get_pages().len(), 1);
+    }
+
+    #[test]
+    fn symbolic_fonts_are_rejected_for_labels() {
+        for font in ["Symbol", "ZapfDingbats"] {
+            let layout = FormLayout::from_yaml(&label_layout(font)).unwrap();
+            assert!(matches!(
+                FormFieldGenerator::create_form_document(&layout),
+                Err(PdfError::InvalidLayout(message)) if message.contains(font)
+            ));
+        }
+    }
+}
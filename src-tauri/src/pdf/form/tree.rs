@@ -0,0 +1,538 @@
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::pdf;
+use super::{ChoiceOption, FieldFlags, FieldKind};
+
+/// Deepest field hierarchy walked before giving up on a malformed tree
+const MAX_FIELD_DEPTH: usize = 32;
+
+/// The value of a field's `/V` or `/DV` entry
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum PdfFieldValue {
+    /// A text string or a button state name
+    Single(String),
+    /// The selected options of a multi-select list box
+    Multiple(Vec<String>),
+}
+
+/// A widget annotation through which a field is displayed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PdfFieldWidget {
+    pub object_id: ObjectId,
+    /// Zero-based index of the page showing the widget, if it could be found
+    pub page: Option<u32>,
+    pub rect: [f64; 4],
+    /// Current appearance state (`/AS`) of checkbox and radio widgets
+    pub appearance_state: Option<String>,
+}
+
+/// A node of the AcroForm field hierarchy with its inherited attributes resolved
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PdfFieldNode {
+    pub object_id: ObjectId,
+    /// Partial name from `/T`
+    pub name: String,
+    /// Fully-qualified name, the partial names of all ancestors joined with `.`
+    pub full_name: String,
+    /// Kind of the field, unknown for pure grouping nodes without `/FT`
+    pub kind: Option<FieldKind>,
+    pub flags: FieldFlags,
+    pub value: Option<PdfFieldValue>,
+    pub default_value: Option<PdfFieldValue>,
+    pub default_appearance: Option<String>,
+    pub quadding: i64,
+    /// Options of choice fields and radio groups from `/Opt`
+    pub options: Vec<ChoiceOption>,
+    pub widgets: Vec<PdfFieldWidget>,
+    pub children: Vec<PdfFieldNode>,
+}
+
+impl PdfFieldNode {
+    /// Whether the node holds a value rather than grouping child fields
+    pub fn is_terminal(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Whether the field's value belongs in exported form data, which
+    /// leaves out push buttons, signatures and fields flagged NoExport
+    pub fn is_exported(&self) -> bool {
+        let has_value = matches!(
+            self.kind,
+            Some(FieldKind::Text | FieldKind::Checkbox | FieldKind::Radio | FieldKind::Combo | FieldKind::List)
+        );
+        has_value && !self.flags.no_export
+    }
+}
+
+/// The complete field hierarchy of a document's interactive form
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FormFieldTree {
+    pub fields: Vec<PdfFieldNode>,
+}
+
+/// Inheritable attributes passed down from a field to its kids
+#[derive(Debug, Clone, Default)]
+struct InheritedAttributes {
+    field_type: Option<Vec<u8>>,
+    flags: i64,
+    value: Option<PdfFieldValue>,
+    default_value: Option<PdfFieldValue>,
+    default_appearance: Option<String>,
+    quadding: i64,
+}
+
+/// Zero-based page indexes looked up while reading widgets
+#[derive(Debug, Default)]
+struct PageIndex {
+    /// Pages by object id, for widgets found only through their `/P` entry
+    pages: HashMap<ObjectId, u32>,
+    /// The page listing each annotation in its `/Annots`
+    annotations: HashMap<ObjectId, u32>,
+}
+
+impl FormFieldTree {
+    /// Read the field hierarchy of a document, which is empty without an AcroForm
+    pub fn from_document(document: &Document) -> Self {
+        // The catalog may hold the AcroForm directly
+        match document
+            .catalog()
+            .and_then(|catalog| catalog.get(b"AcroForm"))
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_dict())
+        {
+            Ok(acro_form) => Self::read_acro_form(document, acro_form),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Read the field hierarchy rooted at an AcroForm's `/Fields`
+    pub fn from_acro_form(document: &Document, acro_form_id: ObjectId) -> Self {
+        match document.get_dictionary(acro_form_id) {
+            Ok(acro_form) => Self::read_acro_form(document, acro_form),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn read_acro_form(document: &Document, acro_form: &Dictionary) -> Self {
+
+        // Fields without their own /DA or /Q fall back to the form-wide values
+        let inherited = InheritedAttributes {
+            default_appearance: acro_form.get(b"DA").and_then(|obj| obj.as_str()).ok().map(text),
+            quadding: acro_form.get(b"Q").and_then(|obj| obj.as_i64()).unwrap_or(0),
+            ..Default::default()
+        };
+
+        let pages = Self::page_index(document);
+        let fields = acro_form
+            .get(b"Fields")
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_array())
+            .map(|fields| fields.iter().filter_map(|f| f.as_reference().ok()).collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|field_id| Self::read_node(document, field_id, "", &inherited, &pages, 0))
+            .collect();
+
+        Self { fields }
+    }
+
+    /// Every field that holds a value, in document order
+    pub fn terminals(&self) -> Vec<&PdfFieldNode> {
+        fn collect<'a>(nodes: &'a [PdfFieldNode], terminals: &mut Vec<&'a PdfFieldNode>) {
+            for node in nodes {
+                if node.is_terminal() {
+                    terminals.push(node);
+                } else {
+                    collect(&node.children, terminals);
+                }
+            }
+        }
+
+        let mut terminals = Vec::new();
+        collect(&self.fields, &mut terminals);
+        terminals
+    }
+
+    /// Look up a field, terminal or not, by its fully-qualified name
+    pub fn find(&self, full_name: &str) -> Option<&PdfFieldNode> {
+        fn search<'a>(nodes: &'a [PdfFieldNode], full_name: &str) -> Option<&'a PdfFieldNode> {
+            nodes.iter().find_map(|node| {
+                if node.full_name == full_name {
+                    Some(node)
+                } else {
+                    search(&node.children, full_name)
+                }
+            })
+        }
+
+        search(&self.fields, full_name)
+    }
+
+    /// Look up the field owning an object, which is either the field itself
+    /// or one of its widgets
+    pub fn find_by_id(&self, object_id: ObjectId) -> Option<&PdfFieldNode> {
+        fn search(nodes: &[PdfFieldNode], object_id: ObjectId) -> Option<&PdfFieldNode> {
+            nodes.iter().find_map(|node| {
+                let owns = node.object_id == object_id
+                    || node.widgets.iter().any(|widget| widget.object_id == object_id);
+                if owns {
+                    Some(node)
+                } else {
+                    search(&node.children, object_id)
+                }
+            })
+        }
+
+        search(&self.fields, object_id)
+    }
+
+    fn read_node(
+        document: &Document,
+        field_id: ObjectId,
+        parent_name: &str,
+        inherited: &InheritedAttributes,
+        pages: &PageIndex,
+        depth: usize,
+    ) -> Option<PdfFieldNode> {
+        // Guard against reference cycles in malformed field trees
+        if depth > MAX_FIELD_DEPTH {
+            return None;
+        }
+        let field = document.get_dictionary(field_id).ok()?;
+
+        let name = field
+            .get(b"T")
+            .and_then(|obj| obj.as_str())
+            .map(pdf::decode_text_string)
+            .unwrap_or_default();
+        let full_name = match (parent_name.is_empty(), name.is_empty()) {
+            (true, _) => name.clone(),
+            (false, true) => parent_name.to_string(),
+            (false, false) => format!("{}.{}", parent_name, name),
+        };
+
+        let attributes = Self::inherit(document, field, inherited);
+
+        // Kids with their own /T are child fields; the rest are widgets
+        let mut children = Vec::new();
+        let mut widgets = Vec::new();
+        let kids: Vec<ObjectId> = field
+            .get(b"Kids")
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_array())
+            .map(|kids| kids.iter().filter_map(|k| k.as_reference().ok()).collect())
+            .unwrap_or_default();
+        for kid_id in kids {
+            let Ok(kid) = document.get_dictionary(kid_id) else {
+                continue;
+            };
+            if kid.has(b"T") {
+                children.extend(Self::read_node(document, kid_id, &full_name, &attributes, pages, depth + 1));
+            } else {
+                widgets.push(Self::read_widget(document, kid_id, kid, pages));
+            }
+        }
+
+        // A field without kids is merged with its only widget
+        if !field.has(b"Kids") && (field.has(b"Rect") || field.has(b"Subtype")) {
+            widgets.push(Self::read_widget(document, field_id, field, pages));
+        }
+
+        let kind = attributes
+            .field_type
+            .as_deref()
+            .and_then(|field_type| FieldKind::from_pdf(field_type, attributes.flags));
+        let options = field
+            .get(b"Opt")
+            .map(|opt| ChoiceOption::read_options(document, opt))
+            .unwrap_or_default();
+
+        Some(PdfFieldNode {
+            object_id: field_id,
+            name,
+            full_name,
+            kind,
+            flags: kind.map(|kind| FieldFlags::from_bits(kind, attributes.flags)).unwrap_or_default(),
+            value: attributes.value,
+            default_value: attributes.default_value,
+            default_appearance: attributes.default_appearance,
+            quadding: attributes.quadding,
+            options,
+            widgets,
+            children,
+        })
+    }
+
+    /// Overlay a field's own inheritable attributes on those of its parent
+    fn inherit(document: &Document, field: &Dictionary, inherited: &InheritedAttributes) -> InheritedAttributes {
+        let mut attributes = inherited.clone();
+        let get = |key: &[u8]| field.get(key).and_then(|obj| document.dereference(obj)).map(|(_, obj)| obj).ok();
+
+        if let Some(field_type) = get(b"FT").and_then(|obj| obj.as_name().ok()) {
+            attributes.field_type = Some(field_type.to_vec());
+        }
+        if let Some(flags) = get(b"Ff").and_then(|obj| obj.as_i64().ok()) {
+            attributes.flags = flags;
+        }
+        if let Some(value) = get(b"V") {
+            attributes.value = field_value(document, value);
+        }
+        if let Some(default_value) = get(b"DV") {
+            attributes.default_value = field_value(document, default_value);
+        }
+        if let Some(da) = get(b"DA").and_then(|obj| obj.as_str().ok()) {
+            attributes.default_appearance = Some(text(da));
+        }
+        if let Some(quadding) = get(b"Q").and_then(|obj| obj.as_i64().ok()) {
+            attributes.quadding = quadding;
+        }
+        attributes
+    }
+
+    fn read_widget(
+        document: &Document,
+        widget_id: ObjectId,
+        widget: &Dictionary,
+        pages: &PageIndex,
+    ) -> PdfFieldWidget {
+        let mut rect = [0.0; 4];
+        if let Ok(values) = widget
+            .get(b"Rect")
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_array())
+        {
+            for (slot, value) in rect.iter_mut().zip(values) {
+                *slot = value.as_float().unwrap_or(0.0) as f64;
+            }
+        }
+
+        // Prefer the page listing the widget in /Annots over the optional /P entry
+        let page = pages.annotations.get(&widget_id).copied().or_else(|| {
+            let page_id = widget.get(b"P").and_then(|obj| obj.as_reference()).ok()?;
+            pages.pages.get(&page_id).copied()
+        });
+
+        PdfFieldWidget {
+            object_id: widget_id,
+            page,
+            rect,
+            appearance_state: widget
+                .get(b"AS")
+                .and_then(|obj| obj.as_name())
+                .ok()
+                .map(|state| String::from_utf8_lossy(state).to_string()),
+        }
+    }
+
+    /// Index every page and every annotation listed by a page in a single walk
+    fn page_index(document: &Document) -> PageIndex {
+        let mut index = PageIndex::default();
+        for (number, page_id) in document.get_pages() {
+            index.pages.insert(page_id, number - 1);
+            let annots = document
+                .get_dictionary(page_id)
+                .and_then(|page| page.get(b"Annots"))
+                .and_then(|obj| document.dereference(obj))
+                .and_then(|(_, obj)| obj.as_array());
+            if let Ok(annots) = annots {
+                for annot_id in annots.iter().filter_map(|annot| annot.as_reference().ok()) {
+                    index.annotations.entry(annot_id).or_insert(number - 1);
+                }
+            }
+        }
+        index
+    }
+}
+
+/// Decode a `/V` or `/DV` entry, which is a text string, a name or an array of strings
+fn field_value(document: &Document, obj: &Object) -> Option<PdfFieldValue> {
+    match obj {
+        Object::String(bytes, _) => Some(PdfFieldValue::Single(pdf::decode_text_string(bytes))),
+        Object::Name(name) => Some(PdfFieldValue::Single(String::from_utf8_lossy(name).to_string())),
+        Object::Array(items) => Some(PdfFieldValue::Multiple(
+            items
+                .iter()
+                .filter_map(|item| document.dereference(item).ok())
+                .filter_map(|(_, item)| item.as_str().ok())
+                .map(pdf::decode_text_string)
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::encode_text_string;
+    use crate::pdf::testing::blank_document;
+    use lopdf::dictionary;
+
+    /// Make the given fields the document's top-level `/Fields`
+    fn with_fields(document: &mut Document, fields: &[ObjectId]) {
+        let fields: Vec<Object> = fields.iter().map(|id| Object::Reference(*id)).collect();
+        let acro_form_id = document.add_object(dictionary! { "Fields" => fields });
+        let catalog_id = document.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        document.get_dictionary_mut(catalog_id).unwrap().set("AcroForm", acro_form_id);
+    }
+
+    fn kids(ids: &[ObjectId]) -> Object {
+        Object::Array(ids.iter().map(|id| Object::Reference(*id)).collect())
+    }
+
+    /// A widget annotation without a field of its own
+    fn widget(document: &mut Document, rect: [i64; 4]) -> ObjectId {
+        let rect: Vec<Object> = rect.iter().map(|value| Object::Integer(*value)).collect();
+        document.add_object(dictionary! { "Subtype" => "Widget", "Rect" => rect })
+    }
+
+    #[test]
+    fn nested_fields_get_fully_qualified_names() {
+        let mut document = blank_document(1);
+        let street = document.add_object(dictionary! {
+            "T" => encode_text_string("street"),
+            "FT" => "Tx",
+            "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+        });
+        let address = document.add_object(dictionary! {
+            "T" => encode_text_string("address"),
+            "Kids" => kids(&[street]),
+        });
+        let form = document.add_object(dictionary! { "T" => encode_text_string("form"), "Kids" => kids(&[address]) });
+        with_fields(&mut document, &[form]);
+
+        let tree = FormFieldTree::from_document(&document);
+        let node = tree.find("form.address.street").unwrap();
+        assert_eq!(node.object_id, street);
+        assert_eq!(node.name, "street");
+        assert!(!tree.find("form.address").unwrap().is_terminal());
+        let terminals: Vec<&str> = tree.terminals().iter().map(|node| node.full_name.as_str()).collect();
+        assert_eq!(terminals, ["form.address.street"]);
+    }
+
+    #[test]
+    fn kids_inherit_attributes_they_do_not_override() {
+        let mut document = blank_document(1);
+        let inheriting = document.add_object(dictionary! { "T" => encode_text_string("inheriting") });
+        let overriding = document.add_object(dictionary! {
+            "T" => encode_text_string("overriding"),
+            "Ff" => 0,
+            "V" => Object::string_literal("own"),
+            "DA" => Object::string_literal("/Cour 12 Tf 0 g"),
+            "Q" => 2,
+        });
+        let parent = document.add_object(dictionary! {
+            "T" => encode_text_string("parent"),
+            "FT" => "Tx",
+            "Ff" => 1 << 12,
+            "V" => Object::string_literal("inherited"),
+            "DA" => Object::string_literal("/Helv 10 Tf 0 g"),
+            "Q" => 1,
+            "Kids" => kids(&[inheriting, overriding]),
+        });
+        with_fields(&mut document, &[parent]);
+
+        let tree = FormFieldTree::from_document(&document);
+        let inheriting = tree.find("parent.inheriting").unwrap();
+        assert_eq!(inheriting.kind, Some(FieldKind::Text));
+        assert!(inheriting.flags.multiline);
+        assert_eq!(inheriting.value, Some(PdfFieldValue::Single("inherited".to_string())));
+        assert_eq!(inheriting.default_appearance.as_deref(), Some("/Helv 10 Tf 0 g"));
+        assert_eq!(inheriting.quadding, 1);
+
+        let overriding = tree.find("parent.overriding").unwrap();
+        assert_eq!(overriding.kind, Some(FieldKind::Text));
+        assert!(!overriding.flags.multiline);
+        assert_eq!(overriding.value, Some(PdfFieldValue::Single("own".to_string())));
+        assert_eq!(overriding.default_appearance.as_deref(), Some("/Cour 12 Tf 0 g"));
+        assert_eq!(overriding.quadding, 2);
+    }
+
+    #[test]
+    fn widgets_are_merged_into_their_field_or_listed_as_kids() {
+        let mut document = blank_document(1);
+        let merged = document.add_object(dictionary! {
+            "T" => encode_text_string("merged"),
+            "FT" => "Btn",
+            "Subtype" => "Widget",
+            "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+            "AS" => "Off",
+        });
+        let first = widget(&mut document, [0, 0, 10, 10]);
+        let second = widget(&mut document, [20, 0, 30, 10]);
+        let separate = document.add_object(dictionary! {
+            "T" => encode_text_string("separate"),
+            "FT" => "Tx",
+            "Kids" => kids(&[first, second]),
+        });
+        with_fields(&mut document, &[merged, separate]);
+
+        let tree = FormFieldTree::from_document(&document);
+        let merged_node = tree.find("merged").unwrap();
+        assert_eq!(merged_node.widgets.len(), 1);
+        assert_eq!(merged_node.widgets[0].object_id, merged);
+        assert_eq!(merged_node.widgets[0].appearance_state.as_deref(), Some("Off"));
+
+        let separate_node = tree.find("separate").unwrap();
+        assert!(separate_node.is_terminal());
+        let widget_ids: Vec<ObjectId> = separate_node.widgets.iter().map(|widget| widget.object_id).collect();
+        assert_eq!(widget_ids, [first, second]);
+        assert_eq!(tree.find_by_id(second).unwrap().object_id, separate);
+    }
+
+    #[test]
+    fn widgets_know_their_page_and_rect() {
+        let mut document = blank_document(2);
+        let pages: Vec<ObjectId> = document.get_pages().into_values().collect();
+        let listed = document.add_object(dictionary! {
+            "Subtype" => "Widget",
+            "Rect" => vec![10.into(), 20.5.into(), 110.into(), 40.into()],
+            // A stale /P loses to the page listing the widget
+            "P" => pages[0],
+        });
+        let unlisted = widget(&mut document, [0, 0, 10, 10]);
+        document.get_dictionary_mut(unlisted).unwrap().set("P", pages[1]);
+        let orphan = widget(&mut document, [0, 0, 10, 10]);
+        document.get_dictionary_mut(pages[1]).unwrap().set("Annots", vec![Object::Reference(listed)]);
+        let field_id = document.add_object(dictionary! {
+            "T" => encode_text_string("name"),
+            "FT" => "Tx",
+            "Kids" => kids(&[listed, unlisted, orphan]),
+        });
+        with_fields(&mut document, &[field_id]);
+
+        let tree = FormFieldTree::from_document(&document);
+        let widgets = &tree.find("name").unwrap().widgets;
+        assert_eq!(widgets[0].page, Some(1));
+        assert_eq!(widgets[0].rect, [10.0, 20.5, 110.0, 40.0]);
+        assert_eq!(widgets[1].page, Some(1));
+        assert_eq!(widgets[2].page, None);
+    }
+
+    #[test]
+    fn cyclic_field_trees_stop_at_the_depth_limit() {
+        let mut document = blank_document(1);
+        let looping = document.new_object_id();
+        document.objects.insert(looping, Object::Dictionary(dictionary! {
+            "T" => encode_text_string("loop"),
+            "Kids" => kids(&[looping]),
+        }));
+        with_fields(&mut document, &[looping]);
+
+        let tree = FormFieldTree::from_document(&document);
+        let mut depth = 0;
+        let mut node = &tree.fields[0];
+        while let Some(child) = node.children.first() {
+            node = child;
+            depth += 1;
+        }
+        assert_eq!(depth, MAX_FIELD_DEPTH);
+        assert_eq!(node.full_name, vec!["loop"; MAX_FIELD_DEPTH + 1].join("."));
+    }
+}
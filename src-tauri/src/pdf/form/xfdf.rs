@@ -0,0 +1,269 @@
+use lopdf::{Document, Object};
+use quick_xml::escape::{escape, unescape};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use crate::pdf::{PdfError, Result};
+use super::{FormFieldTree, FormFiller, PdfFieldValue};
+
+const XFDF_NAMESPACE: &str = "http://ns.adobe.com/xfdf/";
+
+/// Reads and writes form data as XFDF (XML Forms Data Format)
+pub struct Xfdf;
+
+impl Xfdf {
+    /// Export the field values of a loaded document as XFDF
+    ///
+    /// Every terminal field is written flat under its fully-qualified name.
+    /// Push buttons, signatures and fields flagged NoExport are left out.
+    /// `href` names the source PDF in the `<f>` element.
+    pub fn export_document(document: &Document, href: Option<&str>) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!("<xfdf xmlns=\"{}\" xml:space=\"preserve\">\n", XFDF_NAMESPACE));
+
+        if let Some(href) = href {
+            xml.push_str(&format!("<f href=\"{}\"/>\n", escape(href)));
+        }
+        if let Some((original, modified)) = Self::document_ids(document) {
+            xml.push_str(&format!("<ids original=\"{}\" modified=\"{}\"/>\n", original, modified));
+        }
+
+        xml.push_str("<fields>\n");
+        let tree = FormFieldTree::from_document(document);
+        for field in tree.terminals() {
+            if !field.is_exported() {
+                continue;
+            }
+
+            xml.push_str(&format!("<field name=\"{}\">", escape(field.full_name.as_str())));
+            match &field.value {
+                Some(PdfFieldValue::Single(value)) => {
+                    xml.push_str(&format!("<value>{}</value>", escape(value.as_str())));
+                }
+                Some(PdfFieldValue::Multiple(values)) => {
+                    for value in values {
+                        xml.push_str(&format!("<value>{}</value>", escape(value.as_str())));
+                    }
+                }
+                None => xml.push_str("<value/>"),
+            }
+            xml.push_str("</field>\n");
+        }
+        xml.push_str("</fields>\n</xfdf>\n");
+
+        xml
+    }
+
+    /// Parse the field values of an XFDF document by fully-qualified name
+    ///
+    /// Fields may be nested `<field>` elements, flat elements with dotted
+    /// names, or a mix of both. A field with several `<value>` elements
+    /// yields a multiple selection.
+    pub fn parse(xml: &str) -> Result<Vec<(String, PdfFieldValue)>> {
+        let mut reader = Reader::from_str(xml);
+        let mut values = Vec::new();
+
+        // Names and collected values of the open <field> elements
+        let mut names: Vec<String> = Vec::new();
+        let mut field_values: Vec<Vec<String>> = Vec::new();
+        let mut text: Option<String> = None;
+
+        loop {
+            let event = reader.read_event().map_err(|e| Self::invalid(&reader, e))?;
+            match event {
+                Event::Start(element) => match element.local_name().as_ref() {
+                    b"field" => {
+                        names.push(Self::field_name(&element)?);
+                        field_values.push(Vec::new());
+                    }
+                    b"value" if !names.is_empty() => text = Some(String::new()),
+                    _ => {}
+                },
+                Event::Empty(element) => match element.local_name().as_ref() {
+                    b"field" => {
+                        // A field without values says nothing about its value
+                        Self::field_name(&element)?;
+                    }
+                    b"value" if !names.is_empty() => {
+                        if let Some(current) = field_values.last_mut() {
+                            current.push(String::new());
+                        }
+                    }
+                    _ => {}
+                },
+                Event::Text(content) => {
+                    if let Some(text) = text.as_mut() {
+                        text.push_str(&content.unescape().map_err(|e| Self::invalid(&reader, e))?);
+                    }
+                }
+                Event::CData(content) => {
+                    if let Some(text) = text.as_mut() {
+                        text.push_str(&String::from_utf8_lossy(&content));
+                    }
+                }
+                Event::End(element) => match element.local_name().as_ref() {
+                    b"value" => {
+                        if let (Some(value), Some(current)) = (text.take(), field_values.last_mut()) {
+                            current.push(value);
+                        }
+                    }
+                    b"field" => {
+                        let full_name = names.join(".");
+                        names.pop();
+                        let mut collected = field_values.pop().unwrap_or_default();
+                        match collected.len() {
+                            0 => {}
+                            1 => values.push((full_name, PdfFieldValue::Single(collected.remove(0)))),
+                            _ => values.push((full_name, PdfFieldValue::Multiple(collected))),
+                        }
+                    }
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        if !names.is_empty() {
+            return Err(PdfError::InvalidFormData("XFDF ends inside a <field> element".to_string()));
+        }
+
+        Ok(values)
+    }
+
+    /// Apply XFDF field values to a loaded document
+    pub fn import_into_document(document: &mut Document, xml: &str) -> Result<()> {
+        let values: HashMap<String, PdfFieldValue> = Self::parse(xml)?.into_iter().collect();
+
+        FormFiller::fill_document(document, &values)
+    }
+
+    fn field_name(element: &BytesStart) -> Result<String> {
+        let name = element
+            .try_get_attribute("name")
+            .map_err(|e| PdfError::InvalidFormData(e.to_string()))?
+            .ok_or_else(|| PdfError::InvalidFormData("<field> element without a name".to_string()))?;
+
+        // Attribute::unescape_value rejects even the predefined entities here
+        unescape(&String::from_utf8_lossy(&name.value))
+            .map(|name| name.to_string())
+            .map_err(|e| PdfError::InvalidFormData(e.to_string()))
+    }
+
+    /// The two halves of the trailer's `/ID`, hex encoded as XFDF expects
+    fn document_ids(document: &Document) -> Option<(String, String)> {
+        let ids = document.trailer.get(b"ID").and_then(|obj| obj.as_array()).ok()?;
+        let hex = |obj: &Object| -> Option<String> {
+            let bytes = obj.as_str().ok()?;
+            Some(bytes.iter().map(|byte| format!("{:02X}", byte)).collect())
+        };
+
+        Some((hex(ids.first()?)?, hex(ids.get(1)?)?))
+    }
+
+    fn invalid(reader: &Reader<&[u8]>, error: quick_xml::Error) -> PdfError {
+        PdfError::InvalidFormData(format!("XFDF error at byte {}: {}", reader.buffer_position(), error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::form::FieldKind;
+    use crate::pdf::testing::{field, form_document};
+
+    fn value(text: &str) -> PdfFieldValue {
+        PdfFieldValue::Single(text.to_string())
+    }
+
+    #[test]
+    fn nested_and_dotted_names_give_the_same_full_name() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xfdf xmlns="http://ns.adobe.com/xfdf/">
+  <fields>
+    <field name="address">
+      <field name="city"><value>Paris</value></field>
+      <field name="street">
+        <field name="number"><value>12</value></field>
+      </field>
+    </field>
+    <field name="address.zip"><value>75001</value></field>
+  </fields>
+</xfdf>"#;
+
+        assert_eq!(
+            Xfdf::parse(xml).unwrap(),
+            vec![
+                ("address.city".to_string(), value("Paris")),
+                ("address.street.number".to_string(), value("12")),
+                ("address.zip".to_string(), value("75001")),
+            ]
+        );
+    }
+
+    #[test]
+    fn several_values_make_a_multiple_selection() {
+        let xml = r#"<xfdf><fields>
+            <field name="colors"><value>Red</value><value>Blue</value></field>
+            <field name="empty"><value/></field>
+            <field name="unset"/>
+        </fields></xfdf>"#;
+
+        assert_eq!(
+            Xfdf::parse(xml).unwrap(),
+            vec![
+                ("colors".to_string(), PdfFieldValue::Multiple(vec!["Red".to_string(), "Blue".to_string()])),
+                ("empty".to_string(), value("")),
+            ]
+        );
+    }
+
+    #[test]
+    fn entities_are_escaped_and_unescaped() {
+        let xml = r#"<xfdf><fields>
+            <field name="a&amp;b"><value>&lt;Fish &amp; Chips&gt; &quot;to go&quot;</value></field>
+            <field name="raw"><value><![CDATA[<kept & as is>]]></value></field>
+        </fields></xfdf>"#;
+        assert_eq!(
+            Xfdf::parse(xml).unwrap(),
+            vec![
+                ("a&b".to_string(), value("<Fish & Chips> \"to go\"")),
+                ("raw".to_string(), value("<kept & as is>")),
+            ]
+        );
+
+        let mut document = form_document(1, vec![field("dish", FieldKind::Text, 0, &[])]);
+        let values = HashMap::from([("dish".to_string(), value("<Fish & Chips>"))]);
+        FormFiller::fill_document(&mut document, &values).unwrap();
+
+        let exported = Xfdf::export_document(&document, Some("menu & more.pdf"));
+        assert!(exported.contains("<f href=\"menu &amp; more.pdf\"/>"));
+        assert!(exported.contains("<value>&lt;Fish &amp; Chips&gt;</value>"));
+    }
+
+    #[test]
+    fn exported_values_import_into_a_fresh_copy() {
+        let fields = || {
+            let mut colors = field("colors", FieldKind::List, 0, &["Red", "Green", "Blue"]);
+            colors.flags.multi_select = true;
+            vec![field("name", FieldKind::Text, 0, &[]), field("agree", FieldKind::Checkbox, 0, &[]), colors]
+        };
+        let mut source = form_document(1, fields());
+        let values = HashMap::from([
+            ("name".to_string(), value("Ada Lovelace")),
+            ("agree".to_string(), value("true")),
+            ("colors".to_string(), PdfFieldValue::Multiple(vec!["Red".to_string(), "Blue".to_string()])),
+        ]);
+        FormFiller::fill_document(&mut source, &values).unwrap();
+
+        let xml = Xfdf::export_document(&source, None);
+        let mut copy = form_document(1, fields());
+        Xfdf::import_into_document(&mut copy, &xml).unwrap();
+
+        let expected = FormFieldTree::from_document(&source);
+        let imported = FormFieldTree::from_document(&copy);
+        for name in ["name", "agree", "colors"] {
+            assert_eq!(imported.find(name).unwrap().value, expected.find(name).unwrap().value, "{}", name);
+        }
+    }
+}
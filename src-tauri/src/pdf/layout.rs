@@ -0,0 +1,258 @@
+use crate::pdf::font::StandardFont;
+
+/// Largest font size picked when a field asks for auto-sizing
+pub const MAX_AUTO_FONT_SIZE: f64 = 12.0;
+
+/// Smallest font size auto-sizing will shrink to
+pub const MIN_AUTO_FONT_SIZE: f64 = 4.0;
+
+/// Step used when shrinking multiline text to fit
+const AUTO_FONT_SIZE_STEP: f64 = 0.5;
+
+/// Distance between baselines as a multiple of the font size
+const LINE_SPACING: f64 = 1.15;
+
+/// A single laid out line of WinAnsi-encoded text
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutLine {
+    pub text: Vec<u8>,
+    pub width: f64,
+}
+
+/// Text laid out to fit an area of a widget
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    pub font_size: f64,
+    /// Distance between consecutive baselines
+    pub leading: f64,
+    pub lines: Vec<LayoutLine>,
+}
+
+impl TextLayout {
+    /// Lay out text inside an area of the given size
+    ///
+    /// Multiline text is word-wrapped and keeps explicit line breaks; single
+    /// line text has line breaks folded into spaces. A font size of 0 picks
+    /// the largest size up to [`MAX_AUTO_FONT_SIZE`] at which the text fits.
+    pub fn new(
+        text: &[u8],
+        font: StandardFont,
+        width: f64,
+        height: f64,
+        font_size: f64,
+        multiline: bool,
+    ) -> Self {
+        if !multiline {
+            return Self::single_line(text, font, width, height, font_size);
+        }
+
+        if font_size > 0.0 {
+            return Self::wrapped(text, font, width, font_size);
+        }
+
+        // Shrink until every wrapped line fits vertically
+        let mut size = MAX_AUTO_FONT_SIZE;
+        loop {
+            let layout = Self::wrapped(text, font, width, size);
+            if layout.height() <= height || size <= MIN_AUTO_FONT_SIZE {
+                return layout;
+            }
+            size = (size - AUTO_FONT_SIZE_STEP).max(MIN_AUTO_FONT_SIZE);
+        }
+    }
+
+    /// Total height taken by all lines, from the top of the first to the
+    /// bottom of the last
+    pub fn height(&self) -> f64 {
+        match self.lines.len() {
+            0 => 0.0,
+            n => self.font_size + self.leading * (n - 1) as f64,
+        }
+    }
+
+    /// Lay out text on one line, fitting it to the area when auto-sizing
+    fn single_line(text: &[u8], font: StandardFont, width: f64, height: f64, font_size: f64) -> Self {
+        let text: Vec<u8> = text
+            .iter()
+            .map(|byte| if *byte == b'\r' || *byte == b'\n' { b' ' } else { *byte })
+            .collect();
+
+        let size = if font_size > 0.0 {
+            font_size
+        } else {
+            let mut size = (height * 1000.0 / (font.ascent() - font.descent())).min(MAX_AUTO_FONT_SIZE);
+            let text_width = font.text_width(&text, size);
+            if text_width > width && text_width > 0.0 {
+                size *= width / text_width;
+            }
+            size.max(MIN_AUTO_FONT_SIZE)
+        };
+
+        let width = font.text_width(&text, size);
+        Self {
+            font_size: size,
+            leading: size * LINE_SPACING,
+            lines: vec![LayoutLine { text, width }],
+        }
+    }
+
+    /// Word-wrap text at a fixed font size
+    fn wrapped(text: &[u8], font: StandardFont, width: f64, font_size: f64) -> Self {
+        let mut lines = Vec::new();
+
+        // Normalise CR LF and lone CR into LF before splitting paragraphs
+        let mut normalized = Vec::with_capacity(text.len());
+        let mut bytes = text.iter().peekable();
+        while let Some(byte) = bytes.next() {
+            if *byte == b'\r' {
+                if bytes.peek() == Some(&&b'\n') {
+                    bytes.next();
+                }
+                normalized.push(b'\n');
+            } else {
+                normalized.push(*byte);
+            }
+        }
+
+        for paragraph in normalized.split(|byte| *byte == b'\n') {
+            Self::wrap_paragraph(paragraph, font, width, font_size, &mut lines);
+        }
+
+        Self {
+            font_size,
+            leading: font_size * LINE_SPACING,
+            lines,
+        }
+    }
+
+    /// Greedily fill lines with whole words, breaking words that are wider
+    /// than a full line
+    fn wrap_paragraph(
+        paragraph: &[u8],
+        font: StandardFont,
+        width: f64,
+        font_size: f64,
+        lines: &mut Vec<LayoutLine>,
+    ) {
+        let mut current: Vec<u8> = Vec::new();
+
+        for word in paragraph.split(|byte| *byte == b' ').filter(|word| !word.is_empty()) {
+            let mut candidate = current.clone();
+            if !candidate.is_empty() {
+                candidate.push(b' ');
+            }
+            candidate.extend_from_slice(word);
+
+            if font.text_width(&candidate, font_size) <= width {
+                current = candidate;
+                continue;
+            }
+
+            if !current.is_empty() {
+                Self::push_line(lines, std::mem::take(&mut current), font, font_size);
+            }
+
+            // A word that does not fit on a line of its own is split by character
+            let mut piece: Vec<u8> = Vec::new();
+            for byte in word {
+                piece.push(*byte);
+                if piece.len() > 1 && font.text_width(&piece, font_size) > width {
+                    let last = piece.pop().unwrap_or(b' ');
+                    Self::push_line(lines, std::mem::take(&mut piece), font, font_size);
+                    piece.push(last);
+                }
+            }
+            current = piece;
+        }
+
+        // Empty paragraphs still take up a line
+        Self::push_line(lines, current, font, font_size);
+    }
+
+    fn push_line(lines: &mut Vec<LayoutLine>, text: Vec<u8>, font: StandardFont, font_size: f64) {
+        let width = font.text_width(&text, font_size);
+        lines.push(LayoutLine { text, width });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Courier glyphs are all 600/1000 em wide, so widths are easy to predict
+    const FONT: StandardFont = StandardFont::Courier;
+
+    fn line_texts(layout: &TextLayout) -> Vec<&[u8]> {
+        layout.lines.iter().map(|line| line.text.as_slice()).collect()
+    }
+
+    #[test]
+    fn multiline_text_wraps_at_word_boundaries() {
+        // Seven characters at 10pt take 42pt
+        let layout = TextLayout::new(b"aaa bbb ccc", FONT, 45.0, 100.0, 10.0, true);
+
+        assert_eq!(line_texts(&layout), vec![b"aaa bbb".as_slice(), b"ccc"]);
+        assert!(layout.lines.iter().all(|line| line.width <= 45.0));
+    }
+
+    #[test]
+    fn explicit_line_breaks_are_kept() {
+        let layout = TextLayout::new(b"one\r\ntwo\rthree\n\nfour", FONT, 500.0, 100.0, 10.0, true);
+
+        assert_eq!(line_texts(&layout), vec![b"one".as_slice(), b"two", b"three", b"", b"four"]);
+    }
+
+    #[test]
+    fn words_wider_than_a_line_are_split() {
+        let layout = TextLayout::new(b"abcdefghij", FONT, 30.0, 100.0, 10.0, true);
+
+        assert_eq!(line_texts(&layout), vec![b"abcde".as_slice(), b"fghij"]);
+    }
+
+    #[test]
+    fn auto_size_shrinks_multiline_text_to_fit() {
+        let text = b"the quick brown fox jumps over the lazy dog ".repeat(4);
+        let layout = TextLayout::new(&text, FONT, 100.0, 40.0, 0.0, true);
+
+        assert!(layout.font_size < MAX_AUTO_FONT_SIZE);
+        assert!(layout.font_size >= MIN_AUTO_FONT_SIZE);
+        assert!(layout.height() <= 40.0);
+    }
+
+    #[test]
+    fn auto_size_keeps_the_largest_size_when_text_fits() {
+        let layout = TextLayout::new(b"short", FONT, 200.0, 200.0, 0.0, true);
+
+        assert_eq!(layout.font_size, MAX_AUTO_FONT_SIZE);
+        assert_eq!(layout.lines.len(), 1);
+    }
+
+    #[test]
+    fn auto_size_fits_single_line_text_to_the_width() {
+        // Ten characters at 12pt would take 72pt
+        let layout = TextLayout::new(b"aaaaaaaaaa", FONT, 36.0, 100.0, 0.0, false);
+
+        assert!((layout.font_size - 6.0).abs() < 1e-9);
+        assert!(layout.lines[0].width <= 36.0 + 1e-9);
+    }
+
+    #[test]
+    fn single_line_text_folds_line_breaks() {
+        let layout = TextLayout::new(b"one\ntwo", FONT, 500.0, 20.0, 10.0, false);
+
+        assert_eq!(line_texts(&layout), vec![b"one two".as_slice()]);
+    }
+
+    #[test]
+    fn empty_text_lays_out_one_empty_line() {
+        for multiline in [false, true] {
+            for font_size in [0.0, 10.0] {
+                let layout = TextLayout::new(b"", FONT, 100.0, 20.0, font_size, multiline);
+
+                assert_eq!(line_texts(&layout), vec![b"".as_slice()]);
+                assert_eq!(layout.lines[0].width, 0.0);
+                assert!(layout.font_size > 0.0);
+            }
+        }
+    }
+}
@@ -1,12 +1,22 @@
-use lopdf::{Document, Object, Dictionary, Stream};
+use lopdf::{Document, Object, ObjectId, Dictionary};
 use serde::{Serialize, Deserialize};
 use std::path::Path;
-use std::collections::HashMap;
 use thiserror::Error;
+use crate::fs::{FileSystem, FsError};
 
 // Include the form module
 pub mod form;
-pub use form::{PdfFormField, FormFieldGenerator};
+pub mod appearance;
+pub mod font;
+pub mod layout;
+pub(crate) mod objects;
+pub mod pages;
+#[cfg(test)]
+pub(crate) mod testing;
+pub use form::{PdfFormField, FormFieldGenerator, FormFiller, FormFlattener, FormLayout};
+pub use form::{FormFieldTree, PdfFieldNode, PdfFieldValue, PdfFieldWidget};
+pub use form::{FieldRef, FieldUpdate, FormEditor, Fdf, FormCsv, FormJson, Xfdf};
+pub use pages::{MergeOptions, PageEditor, PdfMerger, PdfSplitter, RenamedField};
 
 /// Error types for PDF operations
 #[derive(Error, Debug)]
@@ -19,19 +29,74 @@ pub enum PdfError {
     
     #[error("Invalid page number: {0}")]
     InvalidPage(u32),
+
+    #[error("Invalid page range: {0}")]
+    InvalidPageRange(String),
     
     #[error("Malformed PDF structure: {0}")]
     MalformedPdf(String),
     
     #[error("Unsupported operation: {0}")]
     UnsupportedOperation(String),
+    
+    #[error("Form field not found: {0}")]
+    FieldNotFound(String),
+    
+    #[error("Invalid form field value: {0}")]
+    InvalidFieldValue(String),
+    
+    #[error("Invalid form data: {0}")]
+    InvalidFormData(String),
+
+    #[error("Invalid form layout: {0}")]
+    InvalidLayout(String),
+
+    #[error(transparent)]
+    Save(#[from] FsError),
 }
 
 /// Result type for PDF operations
 pub type Result<T> = std::result::Result<T, PdfError>;
 
+/// Save a document through an atomic, verified write
+///
+/// The document is serialized in memory first so a failure part way through
+/// never touches the file on disk.
+pub fn write_document<P: AsRef<Path>>(document: &mut Document, path: P) -> Result<()> {
+    let mut data = Vec::new();
+    document.save_to(&mut data)?;
+    FileSystem::write_pdf(path, &data)?;
+    Ok(())
+}
+
+/// Decode a PDF text string, which is either UTF-16BE with a byte order mark
+/// or (close enough to) Latin-1 PDFDocEncoding
+pub fn decode_text_string(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        bytes.iter().map(|b| *b as char).collect()
+    }
+}
+
+/// Encode a PDF text string, using UTF-16BE only when the text is not ASCII
+pub fn encode_text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::String(text.as_bytes().to_vec(), lopdf::StringFormat::Literal);
+    }
+    let mut bytes = vec![0xFE, 0xFF];
+    for unit in text.encode_utf16() {
+        bytes.extend_from_slice(&unit.to_be_bytes());
+    }
+    Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+}
+
 /// Represents a PDF page with its dimensions and content
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PdfPage {
     pub index: u32,
     pub width: f64,
@@ -40,16 +105,43 @@ pub struct PdfPage {
 }
 
 /// Represents a PDF document with its metadata and pages
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PdfDocument {
     pub path: String,
     pub page_count: u32,
     pub pages: Vec<PdfPage>,
     pub metadata: PdfMetadata,
+    /// Interactive form fields, empty when the document has no AcroForm
+    #[serde(default)]
+    pub form: FormFieldTree,
+}
+
+impl PdfDocument {
+    /// Look up a page by its zero-based index
+    pub fn page(&self, index: u32) -> Result<&PdfPage> {
+        self.pages
+            .get(index as usize)
+            .ok_or(PdfError::InvalidPage(index))
+    }
+    
+    /// Map a point on the displayed (rotated) page back to unrotated page space
+    pub fn transform_coordinates(&self, page: u32, x: f64, y: f64) -> Result<(f64, f64)> {
+        let page = self.page(page)?;
+        
+        // Apply rotation transformation
+        let transformed = match page.rotation.rem_euclid(360) {
+            90 => (y, page.width - x),
+            180 => (page.width - x, page.height - y),
+            270 => (page.height - y, x),
+            _ => (x, y),
+        };
+        
+        Ok(transformed)
+    }
 }
 
 /// Metadata for a PDF document
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PdfMetadata {
     pub title: Option<String>,
     pub author: Option<String>,
@@ -61,6 +153,19 @@ pub struct PdfMetadata {
     pub modification_date: Option<String>,
 }
 
+/// Changes to the document information dictionary; unset entries are left
+/// alone and empty ones are removed
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MetadataUpdate {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    pub creator: Option<String>,
+    pub producer: Option<String>,
+}
+
 /// PDF parser implementation
 pub struct PdfParser;
 
@@ -70,49 +175,57 @@ impl PdfParser {
         let path_str = path.as_ref().to_string_lossy().to_string();
         let document = Document::load(path.as_ref())?;
         
+        Self::parse_document(path_str, &document)
+    }
+    
+    /// Describe an already loaded document
+    pub fn parse_document(path: String, document: &Document) -> Result<PdfDocument> {
         // Extract metadata
-        let metadata = Self::extract_metadata(&document);
+        let metadata = Self::extract_metadata(document);
         
         // Extract pages
         let page_count = document.get_pages().len() as u32;
         let mut pages = Vec::with_capacity(page_count as usize);
         
-        for (i, (page_id, _)) in document.get_pages().iter().enumerate() {
-            let page_dict = document.get_dictionary(*page_id)?;
-            let page = Self::extract_page_info(page_dict, i as u32)?;
+        for (i, page_id) in document.get_pages().into_values().enumerate() {
+            let page = Self::extract_page_info(document, page_id, i as u32)?;
             pages.push(page);
         }
         
+        // Extract the form field hierarchy
+        let form = Self::extract_form_fields(document);
+        
         Ok(PdfDocument {
-            path: path_str,
+            path,
             page_count,
             pages,
             metadata,
+            form,
         })
     }
     
-    /// Extract page information from a page dictionary
-    fn extract_page_info(page_dict: &Dictionary, index: u32) -> Result<PdfPage> {
+    /// Extract page information from a page, following inheritance from the page tree
+    fn extract_page_info(document: &Document, page_id: ObjectId, index: u32) -> Result<PdfPage> {
         // Get page dimensions
-        let media_box = page_dict.get(b"MediaBox")
-            .and_then(|obj| obj.as_array())
+        let media_box = Self::inherited_page_attribute(document, page_id, b"MediaBox")
+            .and_then(|obj| obj.as_array().ok())
             .ok_or_else(|| PdfError::MalformedPdf("Missing MediaBox".to_string()))?;
         
         if media_box.len() != 4 {
             return Err(PdfError::MalformedPdf("Invalid MediaBox format".to_string()));
         }
         
-        let x1 = media_box[0].as_f64().unwrap_or(0.0);
-        let y1 = media_box[1].as_f64().unwrap_or(0.0);
-        let x2 = media_box[2].as_f64().unwrap_or(0.0);
-        let y2 = media_box[3].as_f64().unwrap_or(0.0);
+        let x1 = media_box[0].as_float().unwrap_or(0.0) as f64;
+        let y1 = media_box[1].as_float().unwrap_or(0.0) as f64;
+        let x2 = media_box[2].as_float().unwrap_or(0.0) as f64;
+        let y2 = media_box[3].as_float().unwrap_or(0.0) as f64;
         
         let width = x2 - x1;
         let height = y2 - y1;
         
         // Get page rotation
-        let rotation = page_dict.get(b"Rotate")
-            .and_then(|obj| obj.as_i64())
+        let rotation = Self::inherited_page_attribute(document, page_id, b"Rotate")
+            .and_then(|obj| obj.as_i64().ok())
             .unwrap_or(0);
         
         Ok(PdfPage {
@@ -123,18 +236,32 @@ impl PdfParser {
         })
     }
     
+    /// Look up a page attribute on the page or its nearest ancestor in the page tree
+    fn inherited_page_attribute<'a>(document: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+        let mut current = Some(page_id);
+        while let Some(id) = current {
+            let node = document.get_dictionary(id).ok()?;
+            if let Ok(value) = node.get(key) {
+                return document.dereference(value).ok().map(|(_, obj)| obj);
+            }
+            current = node.get(b"Parent").and_then(|obj| obj.as_reference()).ok();
+        }
+        None
+    }
+    
     /// Extract metadata from a PDF document
     fn extract_metadata(document: &Document) -> PdfMetadata {
+        let empty = Dictionary::new();
         let info = document.trailer.get(b"Info")
-            .and_then(|obj| obj.as_reference())
-            .and_then(|id| document.get_object(id).ok())
-            .and_then(|obj| obj.as_dict().ok())
-            .unwrap_or(&Dictionary::new());
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_dict())
+            .unwrap_or(&empty);
         
         let extract_text = |key: &[u8]| {
             info.get(key)
-                .and_then(|obj| obj.as_str().ok())
-                .map(|s| s.to_string())
+                .and_then(|obj| obj.as_str())
+                .ok()
+                .map(decode_text_string)
         };
         
         PdfMetadata {
@@ -149,51 +276,65 @@ impl PdfParser {
         }
     }
     
-    /// Extract existing form fields from a PDF document
-    pub fn extract_form_fields(document: &Document) -> HashMap<String, String> {
-        let mut fields = HashMap::new();
-        
-        // Try to get the form root
-        if let Some(acro_form) = document.trailer
-            .get(b"Root")
-            .and_then(|obj| obj.as_reference())
-            .and_then(|id| document.get_object(id).ok())
-            .and_then(|obj| obj.as_dict().ok())
-            .and_then(|root| root.get(b"AcroForm"))
-            .and_then(|obj| obj.as_reference())
-            .and_then(|id| document.get_object(id).ok())
-            .and_then(|obj| obj.as_dict().ok()) {
-                
-            // Extract fields from the AcroForm dictionary
-            if let Some(fields_array) = acro_form.get(b"Fields")
-                .and_then(|obj| obj.as_array().ok()) {
-                
-                for field_ref in fields_array {
-                    if let Some(field_id) = field_ref.as_reference() {
-                        if let Ok(field_obj) = document.get_object(field_id) {
-                            if let Ok(field_dict) = field_obj.as_dict() {
-                                // Extract field name
-                                if let Some(name) = field_dict.get(b"T")
-                                    .and_then(|obj| obj.as_str().ok()) {
-                                    
-                                    // Extract field value
-                                    let value = field_dict.get(b"V")
-                                        .and_then(|obj| match obj {
-                                            Object::String(bytes, _) => Some(String::from_utf8_lossy(bytes).to_string()),
-                                            Object::Name(name) => Some(String::from_utf8_lossy(name).to_string()),
-                                            _ => None,
-                                        })
-                                        .unwrap_or_default();
-                                    
-                                    fields.insert(name.to_string(), value);
-                                }
-                            }
-                        }
-                    }
+    /// Apply changes to a loaded document's information dictionary, creating it if needed
+    pub fn update_metadata(document: &mut Document, update: &MetadataUpdate) -> Result<()> {
+        let info_id = match document.trailer.get(b"Info").and_then(|obj| obj.as_reference()) {
+            Ok(id) => id,
+            Err(_) => {
+                // Move a direct (or missing) dictionary into its own object
+                let info = document.trailer.get(b"Info")
+                    .and_then(|obj| obj.as_dict())
+                    .cloned()
+                    .unwrap_or_default();
+                let id = document.add_object(Object::Dictionary(info));
+                document.trailer.set("Info", Object::Reference(id));
+                id
+            }
+        };
+        
+        let info = document.get_dictionary_mut(info_id)?;
+        let entries = [
+            ("Title", &update.title),
+            ("Author", &update.author),
+            ("Subject", &update.subject),
+            ("Keywords", &update.keywords),
+            ("Creator", &update.creator),
+            ("Producer", &update.producer),
+        ];
+        for (key, value) in entries {
+            match value.as_deref() {
+                Some("") => {
+                    info.remove(key.as_bytes());
                 }
+                Some(value) => info.set(key, encode_text_string(value)),
+                None => {}
             }
         }
         
-        fields
+        Ok(())
     }
-} 
\ No newline at end of file
+    
+    /// Extract the text of a page by its zero-based index
+    pub fn extract_text<P: AsRef<Path>>(path: P, page: u32) -> Result<String> {
+        let document = Document::load(path.as_ref())?;
+        
+        Self::extract_text_from_document(&document, page)
+    }
+    
+    /// Extract the text of a page of a loaded document by its zero-based index
+    pub fn extract_text_from_document(document: &Document, page: u32) -> Result<String> {
+        if !document.get_pages().contains_key(&(page + 1)) {
+            return Err(PdfError::InvalidPage(page));
+        }
+        
+        Ok(document.extract_text(&[page + 1])?)
+    }
+    
+    /// Extract the form field hierarchy of a PDF document
+    ///
+    /// Fields are walked through `/Kids`, so nested fields get fully-qualified
+    /// names and inherit attributes such as `FT` and `DA` from their ancestors.
+    pub fn extract_form_fields(document: &Document) -> FormFieldTree {
+        FormFieldTree::from_document(document)
+    }
+}
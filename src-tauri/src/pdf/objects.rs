@@ -0,0 +1,50 @@
+use lopdf::{Document, Object, ObjectId};
+use std::collections::HashSet;
+
+/// Drop the objects that can no longer be reached from the trailer
+pub(crate) fn prune_unreachable(document: &mut Document) {
+    let reachable = reachable_objects(document);
+    document.objects.retain(|id, _| reachable.contains(id));
+}
+
+/// Drop those of the given objects that can no longer be reached from the trailer
+pub(crate) fn prune_unreachable_among(document: &mut Document, candidates: HashSet<ObjectId>) {
+    if candidates.is_empty() {
+        return;
+    }
+    let reachable = reachable_objects(document);
+    for id in candidates.difference(&reachable) {
+        document.objects.remove(id);
+    }
+}
+
+/// Every object that can be reached from the trailer
+pub(crate) fn reachable_objects(document: &Document) -> HashSet<ObjectId> {
+    let mut pending = Vec::new();
+    for (_, value) in document.trailer.iter() {
+        collect_references(value, &mut pending);
+    }
+
+    let mut reachable = HashSet::new();
+    while let Some(id) = pending.pop() {
+        if reachable.insert(id) {
+            if let Some(object) = document.objects.get(&id) {
+                collect_references(object, &mut pending);
+            }
+        }
+    }
+    reachable
+}
+
+pub(crate) fn collect_references(object: &Object, references: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => references.push(*id),
+        Object::Array(items) => items.iter().for_each(|item| collect_references(item, references)),
+        Object::Dictionary(dict) => dict.iter().for_each(|(_, value)| collect_references(value, references)),
+        Object::Stream(stream) => stream
+            .dict
+            .iter()
+            .for_each(|(_, value)| collect_references(value, references)),
+        _ => {}
+    }
+}
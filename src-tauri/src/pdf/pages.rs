@@ -0,0 +1,532 @@
+use lopdf::{Document, Object, ObjectId};
+use std::collections::{HashMap, HashSet};
+use crate::pdf::objects::prune_unreachable;
+use super::{PdfError, Result};
+use outline::{action_page, destination_page, named_destinations, prune_outline_items, retain_named_destinations, target_page};
+use structure::{prune_structure_tree, prune_threads, renumber_page_labels};
+
+mod edit;
+mod merge;
+mod outline;
+mod split;
+mod structure;
+pub use edit::PageEditor;
+pub use merge::{MergeOptions, PdfMerger, RenamedField};
+pub use split::PdfSplitter;
+
+/// Page attributes a page may inherit from its ancestors in the page tree
+const INHERITABLE_PAGE_ATTRIBUTES: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// Deepest page tree walked before giving up on a malformed document
+const MAX_PAGE_TREE_DEPTH: usize = 64;
+
+/// Deepest field hierarchy walked before giving up on a malformed document
+const MAX_FIELD_DEPTH: usize = 32;
+
+/// What becomes of the parts of a document that describe its pages when some pages are dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DroppedPages {
+    /// The pages kept make up a new document, so page labels, the structure
+    /// tree and article threads, which describe the old one, go
+    Strip,
+    /// The document loses the pages, so only their entries go
+    Prune,
+}
+
+/// The root node of a document's page tree
+fn page_tree_root(document: &Document) -> Result<ObjectId> {
+    Ok(document.catalog()?.get(b"Pages")?.as_reference()?)
+}
+
+/// The object ids of pages selected by zero-based index, in the order given
+fn selected_page_ids(document: &Document, pages: &[u32]) -> Result<Vec<ObjectId>> {
+    if pages.is_empty() {
+        return Err(PdfError::InvalidPageRange("No pages selected".to_string()));
+    }
+    let page_ids = document.get_pages();
+    let mut selected = HashSet::new();
+    pages
+        .iter()
+        .map(|page| {
+            let page_id = page_ids.get(&(page + 1)).ok_or(PdfError::InvalidPage(*page))?;
+            if !selected.insert(*page) {
+                return Err(PdfError::InvalidPageRange(format!("Page {} is selected more than once", page + 1)));
+            }
+            Ok(*page_id)
+        })
+        .collect()
+}
+
+/// Make the given pages, in the given order, the only pages of a document
+///
+/// The page tree is flattened into a single node. Anything that needs a
+/// dropped page goes with it: form fields whose widgets were all on dropped
+/// pages, and outline items, named destinations and links leading to them.
+/// Page labels, the structure tree and article threads lose the dropped
+/// pages' entries or go altogether, as `dropped_pages` says. Objects nothing
+/// refers to any more are pruned.
+fn retain_pages(document: &mut Document, page_ids: &[ObjectId], dropped_pages: DroppedPages) -> Result<()> {
+    let root_id = page_tree_root(document)?;
+    let kept: HashSet<ObjectId> = page_ids.iter().copied().collect();
+    let old_order: Vec<ObjectId> = document.get_pages().into_values().collect();
+    let dropped: HashSet<ObjectId> = old_order
+        .iter()
+        .copied()
+        .filter(|page_id| !kept.contains(page_id))
+        .collect();
+
+    for page_id in page_ids {
+        inherit_page_attributes(document, *page_id)?;
+        document.get_dictionary_mut(*page_id)?.set("Parent", root_id);
+    }
+    let root = document.get_dictionary_mut(root_id)?;
+    root.set("Kids", page_ids.iter().copied().map(Object::Reference).collect::<Vec<_>>());
+    root.set("Count", page_ids.len() as i64);
+    // Every page now has its own copy, and pages added later must not inherit
+    for key in INHERITABLE_PAGE_ATTRIBUTES {
+        root.remove(key);
+    }
+
+    if !dropped.is_empty() {
+        drop_page_references(document, page_ids, &dropped)?;
+        match dropped_pages {
+            DroppedPages::Strip => {
+                let catalog = document.catalog_mut()?;
+                for key in [b"PageLabels".as_slice(), b"StructTreeRoot", b"MarkInfo", b"Threads"] {
+                    catalog.remove(key);
+                }
+            }
+            DroppedPages::Prune => {
+                renumber_page_labels(document, &old_order, page_ids)?;
+                prune_structure_tree(document, &dropped)?;
+                prune_threads(document, &dropped)?;
+            }
+        }
+    }
+    prune_unreachable(document);
+    Ok(())
+}
+
+/// Remove what refers to dropped pages from the rest of a document
+fn drop_page_references(document: &mut Document, page_ids: &[ObjectId], dropped: &HashSet<ObjectId>) -> Result<()> {
+    let destinations = named_destinations(document, document.catalog()?);
+    let leads_to_dropped = |page: Option<ObjectId>| page.is_some_and(|page| dropped.contains(&page));
+
+    // Links to dropped pages go, and the widgets left decide which fields stay
+    let mut widgets = HashSet::new();
+    for page_id in page_ids {
+        let Ok(annotations) = document
+            .get_dictionary(*page_id)?
+            .get(b"Annots")
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_array())
+        else {
+            continue;
+        };
+        let remaining: Vec<Object> = annotations
+            .iter()
+            .filter(|annotation| {
+                let Ok(annotation) = document.dereference(annotation).and_then(|(_, obj)| obj.as_dict()) else {
+                    return true;
+                };
+                let is_link = annotation.get(b"Subtype").and_then(|obj| obj.as_name()).is_ok_and(|s| s == b"Link");
+                !(is_link && leads_to_dropped(target_page(document, annotation, &destinations)))
+            })
+            .cloned()
+            .collect();
+        let changed = remaining.len() != annotations.len();
+        widgets.extend(remaining.iter().filter_map(|annotation| annotation.as_reference().ok()));
+        if changed {
+            document.get_dictionary_mut(*page_id)?.set("Annots", remaining);
+        }
+    }
+    retain_fields(document, &widgets)?;
+
+    let outlines_id = document
+        .catalog()?
+        .get(b"Outlines")
+        .and_then(|obj| obj.as_reference())
+        .ok();
+    if let Some(outlines_id) = outlines_id {
+        prune_outline_items(document, outlines_id, dropped, &destinations, 0);
+    }
+    retain_named_destinations(document, dropped, &destinations)?;
+
+    let opens_dropped_page = document
+        .catalog()?
+        .get(b"OpenAction")
+        .and_then(|obj| document.dereference(obj))
+        .is_ok_and(|(_, open_action)| match open_action {
+            Object::Dictionary(_) => leads_to_dropped(action_page(document, open_action, &destinations)),
+            _ => leads_to_dropped(destination_page(document, open_action, &destinations)),
+        });
+    if opens_dropped_page {
+        document.catalog_mut()?.remove(b"OpenAction");
+    }
+    Ok(())
+}
+
+/// Drop the form fields that have widgets, none of which is among the given ones
+fn retain_fields(document: &mut Document, widgets: &HashSet<ObjectId>) -> Result<()> {
+    let Some((acro_form_id, mut acro_form)) = document
+        .catalog()?
+        .get(b"AcroForm")
+        .and_then(|obj| document.dereference(obj))
+        .ok()
+        .and_then(|(id, obj)| Some((id, obj.as_dict().ok()?.clone())))
+    else {
+        return Ok(());
+    };
+    let field_ids: Vec<ObjectId> = acro_form
+        .get(b"Fields")
+        .and_then(|obj| document.dereference(obj))
+        .and_then(|(_, obj)| obj.as_array())
+        .map(|fields| fields.iter().filter_map(|f| f.as_reference().ok()).collect())
+        .unwrap_or_default();
+
+    let mut kept = HashSet::new();
+    let fields: Vec<Object> = field_ids
+        .into_iter()
+        .filter(|field_id| retain_field(document, *field_id, widgets, &mut kept, 0))
+        .map(Object::Reference)
+        .collect();
+    if fields.is_empty() {
+        document.catalog_mut()?.remove(b"AcroForm");
+        return Ok(());
+    }
+
+    acro_form.set("Fields", fields);
+    if let Ok(order) = acro_form.get(b"CO").and_then(|obj| obj.as_array()) {
+        let order: Vec<Object> = order
+            .iter()
+            .filter(|field| field.as_reference().is_ok_and(|id| kept.contains(&id)))
+            .cloned()
+            .collect();
+        acro_form.set("CO", order);
+    }
+    match acro_form_id {
+        Some(acro_form_id) => {
+            document.objects.insert(acro_form_id, Object::Dictionary(acro_form));
+        }
+        None => document.catalog_mut()?.set("AcroForm", acro_form),
+    }
+    Ok(())
+}
+
+/// Whether a field keeps a widget, pruning the kids that do not
+///
+/// Fields without any widget are kept, as nothing ties them to a page.
+fn retain_field(
+    document: &mut Document,
+    field_id: ObjectId,
+    widgets: &HashSet<ObjectId>,
+    kept: &mut HashSet<ObjectId>,
+    depth: usize,
+) -> bool {
+    let Ok(field) = document.get_dictionary(field_id) else {
+        return false;
+    };
+    let kids: Option<Vec<ObjectId>> = field
+        .get(b"Kids")
+        .and_then(|obj| obj.as_array())
+        .ok()
+        .map(|kids| kids.iter().filter_map(|kid| kid.as_reference().ok()).collect());
+    let is_widget = field.get(b"Subtype").and_then(|obj| obj.as_name()).is_ok_and(|s| s == b"Widget");
+
+    let keep = match kids {
+        Some(kids) if !kids.is_empty() && depth < MAX_FIELD_DEPTH => {
+            let remaining: Vec<Object> = kids
+                .into_iter()
+                .filter(|kid| retain_field(document, *kid, widgets, kept, depth + 1))
+                .map(Object::Reference)
+                .collect();
+            let keep = !remaining.is_empty();
+            if keep {
+                if let Ok(field) = document.get_dictionary_mut(field_id) {
+                    field.set("Kids", remaining);
+                }
+            }
+            keep
+        }
+        _ => !is_widget || widgets.contains(&field_id),
+    };
+    if keep {
+        kept.insert(field_id);
+    }
+    keep
+}
+
+/// Copy the attributes a page inherits from the page tree onto the page
+/// itself, so it keeps them when moved to another parent
+fn inherit_page_attributes(document: &mut Document, page_id: ObjectId) -> Result<()> {
+    let mut inherited: Vec<(&[u8], Object)> = Vec::new();
+    let mut parent = document
+        .get_dictionary(page_id)?
+        .get(b"Parent")
+        .and_then(|obj| obj.as_reference())
+        .ok();
+    let mut depth = 0;
+
+    while let Some(node_id) = parent {
+        let Ok(node) = document.get_dictionary(node_id) else {
+            break;
+        };
+        for key in INHERITABLE_PAGE_ATTRIBUTES {
+            if !inherited.iter().any(|(found, _)| *found == key) {
+                if let Ok(value) = node.get(key) {
+                    inherited.push((key, value.clone()));
+                }
+            }
+        }
+        depth += 1;
+        if depth > MAX_PAGE_TREE_DEPTH {
+            break;
+        }
+        parent = node.get(b"Parent").and_then(|obj| obj.as_reference()).ok();
+    }
+
+    let page = document.get_dictionary_mut(page_id)?;
+    for (key, value) in inherited {
+        if !page.has(key) {
+            page.set(key, value);
+        }
+    }
+    Ok(())
+}
+
+/// Give every object of a document a new id, counting up from `first_id`
+/// in the order of the old ids, and rewrite the references to match
+///
+/// References to objects that do not exist become null, so they cannot end
+/// up pointing at an unrelated object once documents are combined.
+fn renumber_objects(document: &mut Document, first_id: u32) {
+    let ids: HashMap<ObjectId, ObjectId> = document
+        .objects
+        .keys()
+        .enumerate()
+        .map(|(index, id)| (*id, (first_id + index as u32, 0)))
+        .collect();
+
+    let objects = std::mem::take(&mut document.objects);
+    document.objects = objects
+        .into_iter()
+        .map(|(id, mut object)| {
+            rewrite_references(&mut object, &ids);
+            (ids[&id], object)
+        })
+        .collect();
+    for (_, value) in document.trailer.iter_mut() {
+        rewrite_references(value, &ids);
+    }
+    document.max_id = first_id + ids.len() as u32 - 1;
+}
+
+fn rewrite_references(object: &mut Object, ids: &HashMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => match ids.get(id) {
+            Some(new_id) => *id = *new_id,
+            None => *object = Object::Null,
+        },
+        Object::Array(items) => items.iter_mut().for_each(|item| rewrite_references(item, ids)),
+        Object::Dictionary(dict) => dict.iter_mut().for_each(|(_, value)| rewrite_references(value, ids)),
+        Object::Stream(stream) => stream
+            .dict
+            .iter_mut()
+            .for_each(|(_, value)| rewrite_references(value, ids)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::form::{FieldKind, FormFieldTree};
+    use crate::pdf::testing::{field, form_document};
+    use lopdf::dictionary;
+
+    /// A document with one text field on each of its pages, named `page1`,
+    /// `page2` and so on
+    fn page_form_document(pages: u32) -> Document {
+        let fields = (0..pages)
+            .map(|page| field(&format!("page{}", page + 1), FieldKind::Text, page, &[]))
+            .collect();
+        form_document(pages as usize, fields)
+    }
+
+    fn field_names(document: &Document) -> Vec<String> {
+        let tree = FormFieldTree::from_document(document);
+        let mut names: Vec<String> = tree.terminals().iter().map(|field| field.full_name.clone()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn fields_whose_widgets_are_all_dropped_leave_the_form() {
+        let mut document = page_form_document(3);
+        let page_ids = selected_page_ids(&document, &[2, 0]).unwrap();
+
+        retain_pages(&mut document, &page_ids, DroppedPages::Prune).unwrap();
+
+        assert_eq!(field_names(&document), ["page1", "page3"]);
+        let acro_form = document
+            .catalog()
+            .and_then(|catalog| catalog.get(b"AcroForm"))
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_dict())
+            .unwrap();
+        assert_eq!(acro_form.get(b"Fields").and_then(|obj| obj.as_array()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn deleting_pages_keeps_the_structure_tree_of_the_rest() {
+        let mut document = page_form_document(3);
+        let pages: Vec<ObjectId> = document.get_pages().into_values().collect();
+        let root_id = document.new_object_id();
+        let mut elements = Vec::new();
+        let mut parent_tree = Vec::new();
+        for (index, page_id) in pages.iter().enumerate() {
+            let element_id = document.add_object(dictionary! {
+                "Type" => "StructElem",
+                "S" => "P",
+                "P" => root_id,
+                "Pg" => *page_id,
+                "K" => 0,
+            });
+            document.get_dictionary_mut(*page_id).unwrap().set("StructParents", index as i64);
+            elements.push(Object::Reference(element_id));
+            parent_tree.push(Object::Integer(index as i64));
+            parent_tree.push(Object::Array(vec![Object::Reference(element_id)]));
+        }
+        let removed = elements[1].clone();
+        document.objects.insert(root_id, Object::Dictionary(dictionary! {
+            "Type" => "StructTreeRoot",
+            "K" => dictionary! { "Type" => "StructElem", "S" => "Document", "K" => elements.clone() },
+            "ParentTree" => dictionary! { "Nums" => parent_tree },
+        }));
+        let catalog = document.catalog_mut().unwrap();
+        catalog.set("StructTreeRoot", root_id);
+        catalog.set("MarkInfo", dictionary! { "Marked" => true });
+
+        PageEditor::delete_pages(&mut document, &[1]).unwrap();
+
+        let catalog = document.catalog().unwrap();
+        assert!(catalog.has(b"MarkInfo"));
+        let root = document.get_dictionary(root_id).unwrap();
+        let kids = root
+            .get(b"K")
+            .and_then(|obj| obj.as_dict())
+            .and_then(|element| element.get(b"K"))
+            .and_then(|obj| obj.as_array())
+            .unwrap();
+        let kids: Vec<ObjectId> = kids.iter().filter_map(|kid| kid.as_reference().ok()).collect();
+        let kept: Vec<ObjectId> = [&elements[0], &elements[2]].iter().filter_map(|kid| kid.as_reference().ok()).collect();
+        assert_eq!(kids, kept);
+        let nums = root
+            .get(b"ParentTree")
+            .and_then(|obj| obj.as_dict())
+            .and_then(|tree| tree.get(b"Nums"))
+            .and_then(|obj| obj.as_array())
+            .unwrap();
+        let keys: Vec<i64> = nums.chunks(2).filter_map(|pair| pair[0].as_i64().ok()).collect();
+        assert_eq!(keys, [0, 2]);
+        assert!(!document.objects.contains_key(&removed.as_reference().unwrap()));
+    }
+
+    #[test]
+    fn pages_keep_their_labels_when_others_are_deleted() {
+        let mut document = page_form_document(5);
+        document.catalog_mut().unwrap().set("PageLabels", dictionary! {
+            "Nums" => vec![
+                0.into(),
+                Object::Dictionary(dictionary! { "S" => "r" }),
+                2.into(),
+                Object::Dictionary(dictionary! { "S" => "D", "St" => 1 }),
+            ],
+        });
+
+        PageEditor::delete_pages(&mut document, &[1, 2]).unwrap();
+
+        // Pages i, 2 and 3 are left
+        let labels = document
+            .catalog()
+            .and_then(|catalog| catalog.get(b"PageLabels"))
+            .and_then(|obj| obj.as_dict())
+            .and_then(|labels| labels.get(b"Nums"))
+            .and_then(|obj| obj.as_array())
+            .unwrap();
+        let labels: Vec<(i64, String, Option<i64>)> = labels
+            .chunks(2)
+            .map(|pair| {
+                let range = pair[1].as_dict().unwrap();
+                let style = String::from_utf8_lossy(range.get(b"S").and_then(|obj| obj.as_name()).unwrap()).into_owned();
+                (pair[0].as_i64().unwrap(), style, range.get(b"St").and_then(|obj| obj.as_i64()).ok())
+            })
+            .collect();
+        assert_eq!(labels, [(0, "r".to_string(), None), (1, "D".to_string(), Some(2))]);
+    }
+
+    #[test]
+    fn deleting_pages_drops_the_outline_items_and_destinations_leading_to_them() {
+        let mut document = page_form_document(3);
+        let pages: Vec<ObjectId> = document.get_pages().into_values().collect();
+        let outlines_id = document.new_object_id();
+        let item = |document: &mut Document, title: &str, dest: Object| {
+            document.add_object(dictionary! { "Title" => Object::string_literal(title), "Dest" => dest })
+        };
+        let to_page = |page: ObjectId| Object::Array(vec![page.into(), "Fit".into()]);
+        let first = item(&mut document, "First", to_page(pages[0]));
+        let second = item(&mut document, "Second", Object::string_literal("second"));
+        let section = item(&mut document, "Section", to_page(pages[2]));
+        outline::link_outline_items(&mut document, &mut dictionary! {}, &[first, second]);
+        let mut parent = document.get_dictionary(second).unwrap().clone();
+        outline::link_outline_items(&mut document, &mut parent, &[section]);
+        parent.set("Parent", outlines_id);
+        document.objects.insert(second, Object::Dictionary(parent));
+        let mut outlines = dictionary! { "Type" => "Outlines" };
+        outline::link_outline_items(&mut document, &mut outlines, &[first, second]);
+        document.objects.insert(outlines_id, Object::Dictionary(outlines));
+        let catalog = document.catalog_mut().unwrap();
+        catalog.set("Outlines", outlines_id);
+        catalog.set("Dests", dictionary! { "second" => to_page(pages[1]), "third" => to_page(pages[2]) });
+
+        PageEditor::delete_pages(&mut document, &[0, 1]).unwrap();
+
+        // The item for the second page stays as the parent of the section
+        assert_eq!(outline::outline_items(&document, outlines_id), [second]);
+        let parent = document.get_dictionary(second).unwrap();
+        assert!(!parent.has(b"Dest"));
+        assert_eq!(outline::outline_items(&document, second), [section]);
+        assert!(!document.objects.contains_key(&first));
+
+        let destinations = named_destinations(&document, document.catalog().unwrap());
+        assert_eq!(destinations.keys().collect::<Vec<_>>(), [&b"third".to_vec()]);
+    }
+
+    #[test]
+    fn deleting_pages_unlinks_their_article_beads() {
+        let mut document = page_form_document(3);
+        let pages: Vec<ObjectId> = document.get_pages().into_values().collect();
+        let thread_id = document.add_object(dictionary! { "Type" => "Thread" });
+        let beads: Vec<ObjectId> = pages
+            .iter()
+            .map(|page_id| document.add_object(dictionary! { "Type" => "Bead", "P" => *page_id }))
+            .collect();
+        for (index, bead_id) in beads.iter().enumerate() {
+            let bead = document.get_dictionary_mut(*bead_id).unwrap();
+            bead.set("N", beads[(index + 1) % 3]);
+            bead.set("V", beads[(index + 2) % 3]);
+        }
+        document.get_dictionary_mut(beads[0]).unwrap().set("T", thread_id);
+        document.get_dictionary_mut(thread_id).unwrap().set("F", beads[0]);
+        document.catalog_mut().unwrap().set("Threads", vec![thread_id.into()]);
+
+        PageEditor::delete_pages(&mut document, &[0]).unwrap();
+
+        let thread = document.get_dictionary(thread_id).unwrap();
+        assert_eq!(thread.get(b"F").unwrap().as_reference().unwrap(), beads[1]);
+        let bead = |id: ObjectId, key: &[u8]| document.get_dictionary(id).unwrap().get(key).unwrap().as_reference().unwrap();
+        assert_eq!((bead(beads[1], b"N"), bead(beads[1], b"V")), (beads[2], beads[2]));
+        assert_eq!(bead(beads[1], b"T"), thread_id);
+        assert!(!document.objects.contains_key(&beads[0]));
+    }
+}
@@ -0,0 +1,261 @@
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+use std::collections::HashSet;
+use crate::pdf::{PdfError, Result};
+use super::merge::MergedForm;
+use super::{inherit_page_attributes, renumber_objects, retain_pages, selected_page_ids, DroppedPages};
+use super::{MergeOptions, PdfSplitter};
+
+/// Rearranges, rotates, removes and adds the pages of a loaded document
+pub struct PageEditor;
+
+impl PageEditor {
+    /// Move pages, by zero-based index, so they follow each other in the
+    /// order given, starting at position `to` among the pages once moved
+    pub fn move_pages(document: &mut Document, pages: &[u32], to: u32) -> Result<()> {
+        let moving = selected_page_ids(document, pages)?;
+        let moving_ids: HashSet<ObjectId> = moving.iter().copied().collect();
+        let mut order: Vec<ObjectId> = document
+            .get_pages()
+            .into_values()
+            .filter(|page_id| !moving_ids.contains(page_id))
+            .collect();
+
+        let to = to as usize;
+        if to > order.len() {
+            return Err(PdfError::InvalidPage(to as u32));
+        }
+        order.splice(to..to, moving);
+        retain_pages(document, &order, DroppedPages::Prune)
+    }
+
+    /// Rotate pages clockwise by a multiple of 90 degrees, which may be negative
+    pub fn rotate_pages(document: &mut Document, pages: &[u32], degrees: i64) -> Result<()> {
+        if degrees % 90 != 0 {
+            return Err(PdfError::UnsupportedOperation(format!(
+                "Pages can only be rotated by multiples of 90 degrees, not {}",
+                degrees
+            )));
+        }
+        for page_id in selected_page_ids(document, pages)? {
+            inherit_page_attributes(document, page_id)?;
+            let page = document.get_dictionary_mut(page_id)?;
+            let rotation = page.get(b"Rotate").and_then(|obj| obj.as_i64()).unwrap_or(0);
+            page.set("Rotate", (rotation + degrees).rem_euclid(360));
+        }
+        Ok(())
+    }
+
+    /// Delete pages, by zero-based index
+    ///
+    /// Widgets on the pages go with them, and so do the fields left without
+    /// a widget, along with outline items and links leading to the pages.
+    /// The structure tree and article threads lose only the pages' entries,
+    /// and the pages left keep their labels.
+    pub fn delete_pages(document: &mut Document, pages: &[u32]) -> Result<()> {
+        let deleted: HashSet<ObjectId> = selected_page_ids(document, pages)?.into_iter().collect();
+        let remaining: Vec<ObjectId> = document
+            .get_pages()
+            .into_values()
+            .filter(|page_id| !deleted.contains(page_id))
+            .collect();
+        if remaining.is_empty() {
+            return Err(PdfError::UnsupportedOperation("A document needs at least one page".to_string()));
+        }
+        retain_pages(document, &remaining, DroppedPages::Prune)
+    }
+
+    /// Insert blank pages of the given size in points, so the first of them
+    /// ends up at zero-based position `at`
+    pub fn insert_blank_pages(document: &mut Document, at: u32, count: u32, width: f64, height: f64) -> Result<()> {
+        if count == 0 {
+            return Err(PdfError::InvalidPageRange("No pages to insert".to_string()));
+        }
+        if !(width > 0.0 && height > 0.0) {
+            return Err(PdfError::UnsupportedOperation(format!(
+                "Invalid page size {} x {}",
+                width, height
+            )));
+        }
+
+        // Checked before adding any objects, so a failure leaves nothing behind
+        let order = Self::page_order(document, at)?;
+        let blank_pages: Vec<ObjectId> = (0..count)
+            .map(|_| {
+                document.add_object(dictionary! {
+                    "Type" => "Page",
+                    "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+                    "Resources" => dictionary! {},
+                })
+            })
+            .collect();
+        Self::insert_at(document, order, at, blank_pages)
+    }
+
+    /// Insert pages copied from another document, either the given ones by
+    /// zero-based index or all of them, so the first ends up at zero-based
+    /// position `at`
+    ///
+    /// Form fields with widgets on the copied pages come along. Those whose
+    /// names the document already uses are renamed as when merging, since
+    /// fields of the same name share one value.
+    pub fn insert_pages(document: &mut Document, at: u32, source: &Document, pages: Option<&[u32]>) -> Result<()> {
+        let order = Self::page_order(document, at)?;
+        let pages = pages
+            .map(<[u32]>::to_vec)
+            .unwrap_or_else(|| (0..source.get_pages().len() as u32).collect());
+
+        let mut copied = PdfSplitter::extract_document(source, &pages)?;
+        renumber_objects(&mut copied, document.max_id + 1);
+        let copied_pages: Vec<ObjectId> = copied.get_pages().into_values().collect();
+        let copied_form = copied
+            .catalog()?
+            .get(b"AcroForm")
+            .and_then(|obj| copied.dereference(obj))
+            .and_then(|(_, obj)| obj.as_dict())
+            .ok()
+            .cloned();
+
+        document.objects.append(&mut copied.objects);
+        document.max_id = copied.max_id;
+        if let Some(copied_form) = copied_form {
+            Self::combine_forms(document, &copied_form)?;
+        }
+        Self::insert_at(document, order, at, copied_pages)
+    }
+
+    /// The current page order, once `at` is checked to be a place to insert pages
+    fn page_order(document: &Document, at: u32) -> Result<Vec<ObjectId>> {
+        let order: Vec<ObjectId> = document.get_pages().into_values().collect();
+        if at as usize > order.len() {
+            return Err(PdfError::InvalidPage(at));
+        }
+        Ok(order)
+    }
+
+    /// Put pages into the page order at zero-based position `at` and make
+    /// that the document's page tree
+    fn insert_at(document: &mut Document, mut order: Vec<ObjectId>, at: u32, pages: Vec<ObjectId>) -> Result<()> {
+        let at = at as usize;
+        order.splice(at..at, pages);
+        retain_pages(document, &order, DroppedPages::Prune)
+    }
+
+    /// Add the fields of a copied AcroForm, whose objects are already in the
+    /// document, to the document's own AcroForm
+    fn combine_forms(document: &mut Document, copied_form: &Dictionary) -> Result<()> {
+        let (acro_form_id, acro_form) = match document
+            .catalog()?
+            .get(b"AcroForm")
+            .and_then(|obj| document.dereference(obj))
+        {
+            Ok((id, Object::Dictionary(acro_form))) => (id, acro_form.clone()),
+            _ => (None, Dictionary::new()),
+        };
+
+        let options = MergeOptions::default();
+        let mut form = MergedForm::default();
+        form.add(document, 0, &acro_form, &options)?;
+        form.add(document, 1, copied_form, &options)?;
+        let Some(combined) = form.finish() else {
+            return Ok(());
+        };
+
+        // Entries the merged form does not know about, such as `/CO`, stay
+        let mut acro_form = acro_form;
+        for (key, value) in combined.iter() {
+            if key.as_slice() != b"DR" {
+                acro_form.set(key.clone(), value.clone());
+            }
+        }
+        let mut resources = acro_form
+            .get(b"DR")
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_dict())
+            .cloned()
+            .unwrap_or_default();
+        if let Ok(fonts) = combined.get(b"DR").and_then(|obj| obj.as_dict()).and_then(|dr| dr.get(b"Font")) {
+            resources.set("Font", fonts.clone());
+        }
+        acro_form.set("DR", resources);
+
+        match acro_form_id {
+            Some(acro_form_id) => {
+                document.objects.insert(acro_form_id, Object::Dictionary(acro_form));
+            }
+            None => {
+                let acro_form_id = document.add_object(acro_form);
+                document.catalog_mut()?.set("AcroForm", acro_form_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::form::{FieldKind, FormFieldTree};
+    use crate::pdf::testing::{blank_document, field, form_document};
+
+    fn page_ids(document: &Document) -> Vec<ObjectId> {
+        document.get_pages().into_values().collect()
+    }
+
+    #[test]
+    fn moved_pages_keep_the_order_given() {
+        let mut document = blank_document(4);
+        let pages = page_ids(&document);
+
+        PageEditor::move_pages(&mut document, &[3, 0], 1).unwrap();
+        assert_eq!(page_ids(&document), [pages[1], pages[3], pages[0], pages[2]]);
+
+        PageEditor::move_pages(&mut document, &[0], 3).unwrap();
+        assert_eq!(page_ids(&document), [pages[3], pages[0], pages[2], pages[1]]);
+
+        assert!(matches!(PageEditor::move_pages(&mut document, &[0], 4), Err(PdfError::InvalidPage(4))));
+        assert!(matches!(PageEditor::move_pages(&mut document, &[1, 1], 0), Err(PdfError::InvalidPageRange(_))));
+    }
+
+    #[test]
+    fn rotation_adds_to_an_inherited_rotate() {
+        let mut document = blank_document(2);
+        let pages = page_ids(&document);
+        let pages_id = document.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        document.get_dictionary_mut(pages_id).unwrap().set("Rotate", 90);
+
+        PageEditor::rotate_pages(&mut document, &[0], 90).unwrap();
+        PageEditor::rotate_pages(&mut document, &[1], -180).unwrap();
+
+        let rotation = |document: &Document, page_id| {
+            document.get_dictionary(page_id).unwrap().get(b"Rotate").unwrap().as_i64().unwrap()
+        };
+        assert_eq!(rotation(&document, pages[0]), 180);
+        assert_eq!(rotation(&document, pages[1]), 270);
+        assert!(matches!(
+            PageEditor::rotate_pages(&mut document, &[0], 45),
+            Err(PdfError::UnsupportedOperation(_))
+        ));
+        assert_eq!(rotation(&document, pages[0]), 180);
+    }
+
+    #[test]
+    fn inserted_fields_are_renamed_when_their_names_are_taken() {
+        let mut document = form_document(2, vec![field("name", FieldKind::Text, 0, &[])]);
+        let source = form_document(2, vec![field("name", FieldKind::Text, 0, &[]), field("email", FieldKind::Text, 1, &[])]);
+        let pages = page_ids(&document);
+
+        PageEditor::insert_pages(&mut document, 1, &source, Some(&[0])).unwrap();
+
+        let order = page_ids(&document);
+        assert_eq!(order.len(), 3);
+        assert_eq!((order[0], order[2]), (pages[0], pages[1]));
+
+        // Only the fields on the inserted page come along
+        let tree = FormFieldTree::from_document(&document);
+        let mut names: Vec<&str> = tree.terminals().iter().map(|field| field.full_name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["name", "name_2"]);
+        assert_eq!(tree.find("name").unwrap().widgets[0].page, Some(0));
+        assert_eq!(tree.find("name_2").unwrap().widgets[0].page, Some(1));
+    }
+}
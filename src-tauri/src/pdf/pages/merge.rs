@@ -0,0 +1,722 @@
+use lopdf::content::Content;
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, StringFormat};
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use crate::pdf::{self, decode_text_string, encode_text_string, PdfError, Result};
+use super::outline::{destination_name, link_outline_items, named_destinations, outline_items, MAX_OUTLINE_DEPTH};
+use crate::pdf::objects::prune_unreachable;
+use super::{inherit_page_attributes, renumber_objects, MAX_FIELD_DEPTH};
+
+/// Options for merging documents
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MergeOptions {
+    /// Appended to a field name that is already taken by an earlier document
+    ///
+    /// `{n}` stands for a counter starting at 2 and `{doc}` for the one-based
+    /// position of the document in the merge. Without `{n}`, a counter is
+    /// added after the suffix when the suffix alone is not enough.
+    pub field_suffix: String,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            field_suffix: "_{n}".to_string(),
+        }
+    }
+}
+
+/// A form field renamed because an earlier document had a field of the same name
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RenamedField {
+    /// Zero-based position of the document in the merge
+    pub document: usize,
+    pub from: String,
+    pub to: String,
+}
+
+/// Combines documents into one
+pub struct PdfMerger;
+
+impl PdfMerger {
+    /// Merge PDF files in the given order and save the result
+    ///
+    /// Returns the form fields that were renamed to keep them apart.
+    pub fn merge_files<P: AsRef<Path>, Q: AsRef<Path>>(
+        paths: &[P],
+        output_path: Q,
+        options: &MergeOptions,
+    ) -> Result<Vec<RenamedField>> {
+        let sources = paths
+            .iter()
+            .map(|path| {
+                let name = path
+                    .as_ref()
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_else(|| path.as_ref().to_string_lossy().to_string());
+                Ok((name, Document::load(path.as_ref())?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let (mut document, renamed) = Self::merge_documents(sources, options)?;
+        pdf::write_document(&mut document, output_path)?;
+        Ok(renamed)
+    }
+
+    /// Merge named documents in the given order
+    ///
+    /// The pages of each document follow those of the one before. Their form
+    /// fields are combined into a single AcroForm, renaming top-level fields
+    /// whose names an earlier document already uses, as fields of the same
+    /// name share one value. Each document gets an outline entry under its
+    /// name, holding its own outline. Named destinations in outlines and
+    /// links are replaced with the explicit destinations they stand for, as
+    /// the documents' name trees are left behind. The document information
+    /// comes from the first document.
+    pub fn merge_documents(
+        sources: Vec<(String, Document)>,
+        options: &MergeOptions,
+    ) -> Result<(Document, Vec<RenamedField>)> {
+        if sources.is_empty() {
+            return Err(PdfError::UnsupportedOperation("No documents to merge".to_string()));
+        }
+        if let Some((name, _)) = sources.iter().find(|(_, document)| document.is_encrypted()) {
+            return Err(PdfError::UnsupportedOperation(format!("{} is encrypted", name)));
+        }
+
+        let version = sources
+            .iter()
+            .map(|(_, document)| document.version.clone())
+            .max()
+            .unwrap_or_else(|| "1.7".to_string());
+        let mut merged = Document::with_version(version);
+        let pages_id = merged.new_object_id();
+        let outlines_id = merged.new_object_id();
+
+        let mut kids = Vec::new();
+        let mut form = MergedForm::default();
+        let mut entries = Vec::new();
+        let mut info = None;
+
+        for (index, (name, mut document)) in sources.into_iter().enumerate() {
+            renumber_objects(&mut document, merged.max_id + 1);
+
+            let page_ids: Vec<ObjectId> = document.get_pages().into_values().collect();
+            for page_id in &page_ids {
+                inherit_page_attributes(&mut document, *page_id)?;
+                document.get_dictionary_mut(*page_id)?.set("Parent", pages_id);
+            }
+
+            // The document's own catalog and page tree are left behind and pruned
+            let catalog = document.catalog()?.clone();
+            let destinations = named_destinations(&document, &catalog);
+            let acro_form = catalog
+                .get(b"AcroForm")
+                .and_then(|obj| document.dereference(obj))
+                .and_then(|(_, obj)| obj.as_dict())
+                .ok()
+                .cloned();
+            let outline_items = catalog
+                .get(b"Outlines")
+                .and_then(|obj| obj.as_reference())
+                .map(|outlines_id| outline_items(&document, outlines_id))
+                .unwrap_or_default();
+            if index == 0 {
+                info = document.trailer.get(b"Info").ok().cloned();
+            }
+
+            merged.objects.append(&mut document.objects);
+            merged.max_id = document.max_id;
+            resolve_link_destinations(&mut merged, &page_ids, &destinations);
+
+            if let Some(acro_form) = acro_form {
+                form.add(&mut merged, index, &acro_form, options)?;
+            }
+
+            let entry_id = merged.new_object_id();
+            let mut entry = dictionary! {
+                "Title" => encode_text_string(&name),
+                "Parent" => outlines_id,
+            };
+            if let Some(first_page) = page_ids.first() {
+                entry.set("Dest", vec![(*first_page).into(), "Fit".into()]);
+            }
+            adopt_outline_items(&mut merged, &mut entry, entry_id, &outline_items, &destinations);
+            merged.objects.insert(entry_id, Object::Dictionary(entry));
+            entries.push(entry_id);
+
+            kids.extend(page_ids);
+        }
+
+        let page_count = kids.len() as i64;
+        merged.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids.into_iter().map(Object::Reference).collect::<Vec<_>>(),
+                "Count" => page_count,
+            }),
+        );
+
+        let mut outlines = dictionary! { "Type" => "Outlines" };
+        link_outline_items(&mut merged, &mut outlines, &entries);
+        merged.objects.insert(outlines_id, Object::Dictionary(outlines));
+
+        let mut catalog = dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "Outlines" => outlines_id,
+        };
+        if let Some(acro_form) = form.finish() {
+            catalog.set("AcroForm", merged.add_object(acro_form));
+        }
+        let catalog_id = merged.add_object(catalog);
+        merged.trailer.set("Root", catalog_id);
+        if let Some(info) = info {
+            merged.trailer.set("Info", info);
+        }
+
+        prune_unreachable(&mut merged);
+        Ok((merged, form.renamed))
+    }
+}
+
+/// The AcroForm of a merged document, built up one document at a time
+#[derive(Default)]
+pub(super) struct MergedForm {
+    fields: Vec<Object>,
+    /// Calculation order of every document, one after the other
+    calculation_order: Vec<Object>,
+    /// Names of the top-level fields of earlier documents
+    taken: HashSet<String>,
+    /// Font resources of every document, renamed where names clash
+    fonts: Dictionary,
+    default_appearance: Option<Object>,
+    need_appearances: bool,
+    renamed: Vec<RenamedField>,
+}
+
+impl MergedForm {
+    /// Add the fields of one document, whose objects are already in `merged`
+    ///
+    /// Font resources named like those of an earlier document but standing
+    /// for another font are renamed, and the `/DA` strings of the document
+    /// follow the new names.
+    pub(super) fn add(&mut self, merged: &mut Document, index: usize, acro_form: &Dictionary, options: &MergeOptions) -> Result<()> {
+        let field_ids: Vec<ObjectId> = acro_form
+            .get(b"Fields")
+            .and_then(|obj| merged.dereference(obj))
+            .and_then(|(_, obj)| obj.as_array())
+            .map(|fields| fields.iter().filter_map(|f| f.as_reference().ok()).collect())
+            .unwrap_or_default();
+
+        let fonts = acro_form
+            .get(b"DR")
+            .and_then(|obj| merged.dereference(obj))
+            .and_then(|(_, obj)| obj.as_dict())
+            .and_then(|resources| resources.get(b"Font"))
+            .and_then(|obj| merged.dereference(obj))
+            .and_then(|(_, obj)| obj.as_dict())
+            .cloned()
+            .unwrap_or_default();
+        let font_renames = self.add_fonts(merged, &fonts);
+
+        let default_appearance = acro_form
+            .get(b"DA")
+            .ok()
+            .map(|da| rename_da_fonts(da, &font_renames).unwrap_or_else(|| da.clone()));
+        if !font_renames.is_empty() {
+            for field_id in &field_ids {
+                rename_subtree_da_fonts(merged, *field_id, &font_renames, 0);
+            }
+        }
+
+        // A rename must not clash with the document's own names either
+        let own: HashSet<String> = field_ids
+            .iter()
+            .filter_map(|id| merged.get_dictionary(*id).ok())
+            .filter_map(|field| field.get(b"T").and_then(|obj| obj.as_str()).map(decode_text_string).ok())
+            .collect();
+
+        let mut names = Vec::new();
+        for field_id in field_ids {
+            let Ok(field) = merged.get_dictionary_mut(field_id) else {
+                continue;
+            };
+
+            // Fields relying on the form-wide appearance keep their own
+            if let Some(default_appearance) = &default_appearance {
+                if index > 0 && !field.has(b"DA") {
+                    field.set("DA", default_appearance.clone());
+                }
+            }
+
+            if let Ok(name) = field.get(b"T").and_then(|obj| obj.as_str()).map(decode_text_string) {
+                let name = if self.taken.contains(&name) {
+                    let unique = self.unique_name(&name, index, &options.field_suffix, &own);
+                    field.set("T", encode_text_string(&unique));
+                    self.renamed.push(RenamedField {
+                        document: index,
+                        from: name,
+                        to: unique.clone(),
+                    });
+                    unique
+                } else {
+                    name
+                };
+                names.push(name);
+            }
+            self.fields.push(Object::Reference(field_id));
+        }
+        self.taken.extend(names);
+
+        if let Ok(calculation_order) = acro_form
+            .get(b"CO")
+            .and_then(|obj| merged.dereference(obj))
+            .and_then(|(_, obj)| obj.as_array())
+        {
+            self.calculation_order.extend(calculation_order.iter().filter(|obj| obj.as_reference().is_ok()).cloned());
+        }
+
+        if self.default_appearance.is_none() {
+            self.default_appearance = default_appearance;
+        }
+        self.need_appearances |= acro_form
+            .get(b"NeedAppearances")
+            .and_then(|obj| obj.as_bool())
+            .unwrap_or(false);
+        Ok(())
+    }
+
+    /// Add a document's font resources, returning the ones renamed because
+    /// an earlier document uses their name for another font
+    fn add_fonts(&mut self, merged: &Document, fonts: &Dictionary) -> HashMap<Vec<u8>, Vec<u8>> {
+        let mut renames = HashMap::new();
+        for (name, font) in fonts.iter() {
+            match self.fonts.get(name) {
+                Err(_) => self.fonts.set(name.clone(), font.clone()),
+                Ok(existing) if same_font(merged, existing, font) => {}
+                Ok(_) => {
+                    let mut n = 2;
+                    let unique = loop {
+                        let candidate = [name.as_slice(), format!("_{}", n).as_bytes()].concat();
+                        if !self.fonts.has(&candidate) && !fonts.has(&candidate) {
+                            break candidate;
+                        }
+                        n += 1;
+                    };
+                    self.fonts.set(unique.clone(), font.clone());
+                    renames.insert(name.clone(), unique);
+                }
+            }
+        }
+        renames
+    }
+
+    /// The first name made from `name` and the suffix pattern that neither an
+    /// earlier document nor the document being added uses
+    fn unique_name(&self, name: &str, index: usize, pattern: &str, own: &HashSet<String>) -> String {
+        let suffix = pattern.replace("{doc}", &(index + 1).to_string());
+        let mut n = 2;
+        loop {
+            let candidate = if suffix.contains("{n}") {
+                format!("{}{}", name, suffix.replace("{n}", &n.to_string()))
+            } else if n == 2 {
+                format!("{}{}", name, suffix)
+            } else {
+                format!("{}{}{}", name, suffix, n - 1)
+            };
+            if !self.taken.contains(&candidate) && !own.contains(&candidate) && !candidate.is_empty() {
+                return candidate;
+            }
+            n += 1;
+        }
+    }
+
+    /// The combined AcroForm dictionary, if any document had fields
+    pub(super) fn finish(&self) -> Option<Dictionary> {
+        if self.fields.is_empty() {
+            return None;
+        }
+        let mut acro_form = dictionary! {
+            "Fields" => self.fields.clone(),
+            "DR" => dictionary! { "Font" => self.fonts.clone() },
+        };
+        if let Some(default_appearance) = &self.default_appearance {
+            acro_form.set("DA", default_appearance.clone());
+        }
+        if !self.calculation_order.is_empty() {
+            acro_form.set("CO", self.calculation_order.clone());
+        }
+        if self.need_appearances {
+            acro_form.set("NeedAppearances", true);
+        }
+        Some(acro_form)
+    }
+}
+
+/// Whether two font resources stand for the same font: the same object, or
+/// the same standard font that neither embeds
+fn same_font(document: &Document, a: &Object, b: &Object) -> bool {
+    if let (Ok(a), Ok(b)) = (a.as_reference(), b.as_reference()) {
+        if a == b {
+            return true;
+        }
+    }
+
+    let font = |obj: &Object| {
+        document
+            .dereference(obj)
+            .and_then(|(_, obj)| obj.as_dict())
+            .ok()
+            .filter(|font| !font.has(b"FontDescriptor"))
+            .map(|font| {
+                let entry = |key: &[u8]| font.get(key).and_then(|obj| obj.as_name()).ok().map(<[u8]>::to_vec);
+                (entry(b"Subtype"), entry(b"BaseFont"), entry(b"Encoding"))
+            })
+    };
+    matches!((font(a), font(b)), (Some(a), Some(b)) if a == b && a.1.is_some())
+}
+
+/// A `/DA` string with its font resource renamed, or `None` if it needs no change
+fn rename_da_fonts(da: &Object, renames: &HashMap<Vec<u8>, Vec<u8>>) -> Option<Object> {
+    let mut content = Content::decode(da.as_str().ok()?).ok()?;
+    let mut changed = false;
+    for operation in &mut content.operations {
+        if operation.operator != "Tf" {
+            continue;
+        }
+        if let Some(Object::Name(name)) = operation.operands.first_mut() {
+            if let Some(renamed) = renames.get(name) {
+                *name = renamed.clone();
+                changed = true;
+            }
+        }
+    }
+    if !changed {
+        return None;
+    }
+    Some(Object::String(content.encode().ok()?, StringFormat::Literal))
+}
+
+/// Rename fonts in the `/DA` strings of a field, its kids and its widgets
+fn rename_subtree_da_fonts(document: &mut Document, field_id: ObjectId, renames: &HashMap<Vec<u8>, Vec<u8>>, depth: usize) {
+    if depth > MAX_FIELD_DEPTH {
+        return;
+    }
+    let Ok(field) = document.get_dictionary_mut(field_id) else {
+        return;
+    };
+    if let Some(da) = field.get(b"DA").ok().and_then(|da| rename_da_fonts(da, renames)) {
+        field.set("DA", da);
+    }
+
+    let kids: Vec<ObjectId> = field
+        .get(b"Kids")
+        .and_then(|obj| obj.as_array())
+        .map(|kids| kids.iter().filter_map(|kid| kid.as_reference().ok()).collect())
+        .unwrap_or_default();
+    for kid_id in kids {
+        rename_subtree_da_fonts(document, kid_id, renames, depth + 1);
+    }
+}
+
+/// Hang outline items from a new parent, linked in order, and resolve their
+/// named destinations, which would otherwise point into the name tree of a
+/// document that is no longer there
+fn adopt_outline_items(
+    document: &mut Document,
+    parent: &mut Dictionary,
+    parent_id: ObjectId,
+    items: &[ObjectId],
+    destinations: &HashMap<Vec<u8>, Object>,
+) {
+    for item_id in items {
+        if let Ok(item) = document.get_dictionary_mut(*item_id) {
+            item.set("Parent", parent_id);
+        }
+        resolve_destinations(document, *item_id, destinations, 0);
+    }
+    link_outline_items(document, parent, items);
+}
+
+/// Replace named destinations in an outline item and its descendants with
+/// the explicit destinations they stand for
+fn resolve_destinations(
+    document: &mut Document,
+    item_id: ObjectId,
+    destinations: &HashMap<Vec<u8>, Object>,
+    depth: usize,
+) {
+    if depth > MAX_OUTLINE_DEPTH {
+        return;
+    }
+    resolve_destination(document, item_id, destinations);
+
+    let children = outline_items(document, item_id);
+    for child_id in children {
+        resolve_destinations(document, child_id, destinations, depth + 1);
+    }
+}
+
+/// Replace named destinations in the link annotations of pages with the
+/// explicit destinations they stand for
+fn resolve_link_destinations(document: &mut Document, page_ids: &[ObjectId], destinations: &HashMap<Vec<u8>, Object>) {
+    for page_id in page_ids {
+        let annot_ids: Vec<ObjectId> = document
+            .get_dictionary(*page_id)
+            .and_then(|page| page.get(b"Annots"))
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_array())
+            .map(|annots| annots.iter().filter_map(|annot| annot.as_reference().ok()).collect())
+            .unwrap_or_default();
+        for annot_id in annot_ids {
+            let is_link = document
+                .get_dictionary(annot_id)
+                .and_then(|annot| annot.get(b"Subtype"))
+                .and_then(|obj| obj.as_name())
+                .is_ok_and(|subtype| subtype == b"Link");
+            if is_link {
+                resolve_destination(document, annot_id, destinations);
+            }
+        }
+    }
+}
+
+/// Replace the named destination of an outline item or link annotation,
+/// given as its `/Dest` or through a GoTo action, with the explicit one
+fn resolve_destination(document: &mut Document, owner_id: ObjectId, destinations: &HashMap<Vec<u8>, Object>) {
+    let Ok(owner) = document.get_dictionary_mut(owner_id) else {
+        return;
+    };
+
+    if let Some(destination) = owner.get(b"Dest").ok().and_then(destination_name) {
+        match destinations.get(&destination) {
+            Some(explicit) => owner.set("Dest", explicit.clone()),
+            None => {
+                owner.remove(b"Dest");
+            }
+        }
+    }
+    let action = match owner.get_mut(b"A") {
+        Ok(Object::Dictionary(action)) => action,
+        Ok(Object::Reference(action_id)) => {
+            let action_id = *action_id;
+            match document.get_dictionary_mut(action_id) {
+                Ok(action) => action,
+                Err(_) => return,
+            }
+        }
+        _ => return,
+    };
+    let is_go_to = action.get(b"S").and_then(|obj| obj.as_name()).is_ok_and(|s| s == b"GoTo");
+    if let Some(destination) = action.get(b"D").ok().and_then(destination_name).filter(|_| is_go_to) {
+        if let Some(explicit) = destinations.get(&destination) {
+            action.set("D", explicit.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::testing::blank_document;
+    use crate::pdf::FormFieldTree;
+
+    /// A one-page document whose text fields use `/Helv`, standing for `base_font`
+    fn font_form_document(names: &[&str], base_font: &str) -> Document {
+        let mut document = blank_document(1);
+        let fields: Vec<Object> = names
+            .iter()
+            .map(|name| {
+                document
+                    .add_object(dictionary! {
+                        "FT" => "Tx",
+                        "T" => encode_text_string(name),
+                        "DA" => Object::string_literal("/Helv 12 Tf 0 g"),
+                    })
+                    .into()
+            })
+            .collect();
+        let font_id = document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => base_font,
+        });
+        let acro_form_id = document.add_object(dictionary! {
+            "Fields" => fields,
+            "DR" => dictionary! { "Font" => dictionary! { "Helv" => font_id } },
+        });
+        document.catalog_mut().unwrap().set("AcroForm", acro_form_id);
+        document
+    }
+
+    /// A two-page document whose outline item and link on the first page
+    /// lead to the second page through the named destination "chapter"
+    fn outlined_document(title: &str) -> Document {
+        let mut document = blank_document(2);
+        let pages: Vec<ObjectId> = document.get_pages().into_values().collect();
+        let outlines_id = document.new_object_id();
+        let item_id = document.add_object(dictionary! {
+            "Title" => encode_text_string(title),
+            "Parent" => outlines_id,
+            "Dest" => Object::string_literal("chapter"),
+        });
+        document.objects.insert(outlines_id, Object::Dictionary(dictionary! {
+            "Type" => "Outlines",
+            "First" => item_id,
+            "Last" => item_id,
+            "Count" => 1,
+        }));
+        let action_id = document.add_object(dictionary! { "S" => "GoTo", "D" => Object::string_literal("chapter") });
+        let link_id = document.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+            "A" => action_id,
+        });
+        document.get_dictionary_mut(pages[0]).unwrap().set("Annots", vec![link_id.into()]);
+
+        let catalog = document.catalog_mut().unwrap();
+        catalog.set("Outlines", outlines_id);
+        catalog.set("Dests", dictionary! { "chapter" => vec![pages[1].into(), "Fit".into()] });
+        document
+    }
+
+    fn merge(sources: Vec<Document>) -> (Document, Vec<RenamedField>) {
+        let sources = sources
+            .into_iter()
+            .enumerate()
+            .map(|(index, document)| (format!("doc{}", index + 1), document))
+            .collect();
+        PdfMerger::merge_documents(sources, &MergeOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn renamed_fields_avoid_the_names_of_their_own_document() {
+        let (merged, renamed) = merge(vec![
+            font_form_document(&["a"], "Helvetica"),
+            font_form_document(&["a", "a_2"], "Helvetica"),
+        ]);
+
+        assert_eq!(renamed.len(), 1);
+        assert_eq!((renamed[0].from.as_str(), renamed[0].to.as_str()), ("a", "a_3"));
+
+        let tree = FormFieldTree::from_document(&merged);
+        let mut names: Vec<&str> = tree.terminals().iter().map(|field| field.full_name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["a", "a_2", "a_3"]);
+    }
+
+    #[test]
+    fn clashing_font_resources_are_renamed_in_the_default_appearance() {
+        let (merged, _) = merge(vec![
+            font_form_document(&["first"], "Helvetica"),
+            font_form_document(&["second"], "Times-Roman"),
+            font_form_document(&["third"], "Helvetica"),
+        ]);
+
+        let acro_form = merged
+            .catalog()
+            .and_then(|catalog| catalog.get(b"AcroForm"))
+            .and_then(|obj| merged.dereference(obj))
+            .and_then(|(_, obj)| obj.as_dict())
+            .unwrap();
+        let fonts = acro_form
+            .get(b"DR")
+            .and_then(|obj| obj.as_dict())
+            .and_then(|resources| resources.get(b"Font"))
+            .and_then(|obj| obj.as_dict())
+            .unwrap();
+        let base_font = |name: &[u8]| {
+            let font = fonts.get(name).and_then(|obj| merged.dereference(obj)).unwrap().1;
+            font.as_dict().unwrap().get(b"BaseFont").unwrap().as_name().unwrap().to_vec()
+        };
+        assert_eq!(fonts.len(), 2);
+        assert_eq!(base_font(b"Helv"), b"Helvetica");
+        assert_eq!(base_font(b"Helv_2"), b"Times-Roman");
+
+        let tree = FormFieldTree::from_document(&merged);
+        let font_of = |name: &str| {
+            let da = tree.find(name).unwrap().default_appearance.clone().unwrap();
+            crate::pdf::appearance::DefaultAppearance::parse(da.as_bytes()).font_name
+        };
+        assert_eq!(font_of("first"), b"Helv");
+        assert_eq!(font_of("second"), b"Helv_2");
+        assert_eq!(font_of("third"), b"Helv");
+    }
+
+    #[test]
+    fn each_document_gets_an_outline_entry_with_resolved_destinations() {
+        let (merged, _) = merge(vec![outlined_document("First chapter"), outlined_document("Second chapter")]);
+        let pages: Vec<ObjectId> = merged.get_pages().into_values().collect();
+        assert_eq!(pages.len(), 4);
+
+        let catalog = merged.catalog().unwrap();
+        assert!(!catalog.has(b"Dests"));
+        let outlines_id = catalog.get(b"Outlines").unwrap().as_reference().unwrap();
+        let entries = outline_items(&merged, outlines_id);
+        assert_eq!(entries.len(), 2);
+
+        let title = |item_id: ObjectId| {
+            decode_text_string(merged.get_dictionary(item_id).unwrap().get(b"Title").unwrap().as_str().unwrap())
+        };
+        let dest_page = |destination: &Object| destination.as_array().unwrap()[0].as_reference().unwrap();
+        for (index, entry_id) in entries.into_iter().enumerate() {
+            let first_page = pages[index * 2];
+            let chapter_page = pages[index * 2 + 1];
+            let entry = merged.get_dictionary(entry_id).unwrap();
+            assert_eq!(title(entry_id), format!("doc{}", index + 1));
+            assert_eq!(dest_page(entry.get(b"Dest").unwrap()), first_page);
+
+            let items = outline_items(&merged, entry_id);
+            assert_eq!(items.len(), 1);
+            assert_eq!(title(items[0]), ["First chapter", "Second chapter"][index]);
+            let item = merged.get_dictionary(items[0]).unwrap();
+            assert_eq!(item.get(b"Parent").unwrap().as_reference().unwrap(), entry_id);
+            assert_eq!(dest_page(item.get(b"Dest").unwrap()), chapter_page);
+
+            let annots = merged.get_dictionary(first_page).unwrap().get(b"Annots").unwrap().as_array().unwrap();
+            let link = merged.get_dictionary(annots[0].as_reference().unwrap()).unwrap();
+            let action = merged.dereference(link.get(b"A").unwrap()).unwrap().1.as_dict().unwrap();
+            assert_eq!(dest_page(action.get(b"D").unwrap()), chapter_page);
+        }
+    }
+
+    #[test]
+    fn calculation_orders_are_concatenated() {
+        let with_calculation_order = |mut document: Document| {
+            let acro_form_id = document.catalog().unwrap().get(b"AcroForm").unwrap().as_reference().unwrap();
+            let acro_form = document.get_dictionary_mut(acro_form_id).unwrap();
+            let mut fields = acro_form.get(b"Fields").unwrap().as_array().unwrap().clone();
+            fields.reverse();
+            acro_form.set("CO", fields);
+            document
+        };
+        let (merged, _) = merge(vec![
+            with_calculation_order(font_form_document(&["a", "b"], "Helvetica")),
+            font_form_document(&["c"], "Helvetica"),
+            with_calculation_order(font_form_document(&["d", "e"], "Helvetica")),
+        ]);
+
+        let acro_form = merged
+            .catalog()
+            .and_then(|catalog| catalog.get(b"AcroForm"))
+            .and_then(|obj| merged.dereference(obj))
+            .and_then(|(_, obj)| obj.as_dict())
+            .unwrap();
+        let names: Vec<String> = acro_form
+            .get(b"CO")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|obj| merged.get_dictionary(obj.as_reference().unwrap()).unwrap())
+            .map(|field| decode_text_string(field.get(b"T").unwrap().as_str().unwrap()))
+            .collect();
+        assert_eq!(names, ["b", "a", "e", "d"]);
+    }
+}
@@ -0,0 +1,278 @@
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+use std::collections::{HashMap, HashSet};
+use crate::pdf::Result;
+
+/// Deepest outline walked before giving up on a malformed document
+pub(super) const MAX_OUTLINE_DEPTH: usize = 32;
+
+/// The top-level items of an outline, in order
+pub(super) fn outline_items(document: &Document, outlines_id: ObjectId) -> Vec<ObjectId> {
+    let mut items = Vec::new();
+    let mut seen = HashSet::new();
+    let mut next = document
+        .get_dictionary(outlines_id)
+        .and_then(|outlines| outlines.get(b"First"))
+        .and_then(|obj| obj.as_reference())
+        .ok();
+
+    while let Some(item_id) = next {
+        if !seen.insert(item_id) {
+            break;
+        }
+        items.push(item_id);
+        next = document
+            .get_dictionary(item_id)
+            .and_then(|item| item.get(b"Next"))
+            .and_then(|obj| obj.as_reference())
+            .ok();
+    }
+    items
+}
+
+/// Chain outline items under a parent and set the parent's open count
+pub(super) fn link_outline_items(document: &mut Document, parent: &mut Dictionary, items: &[ObjectId]) {
+    let (Some(first), Some(last)) = (items.first(), items.last()) else {
+        return;
+    };
+    parent.set("First", *first);
+    parent.set("Last", *last);
+
+    let mut count = items.len() as i64;
+    for (index, item_id) in items.iter().enumerate() {
+        let Ok(item) = document.get_dictionary_mut(*item_id) else {
+            continue;
+        };
+        item.remove(b"Prev");
+        item.remove(b"Next");
+        if index > 0 {
+            item.set("Prev", items[index - 1]);
+        }
+        if let Some(next) = items.get(index + 1) {
+            item.set("Next", *next);
+        }
+        // Open items show their descendants too
+        count += item.get(b"Count").and_then(|obj| obj.as_i64()).unwrap_or(0).max(0);
+    }
+    parent.set("Count", count);
+}
+
+/// Remove the outline items below a node that lead to dropped pages
+///
+/// Items that still have items of their own are kept without their
+/// destination, so the rest of the outline keeps its shape.
+pub(super) fn prune_outline_items(
+    document: &mut Document,
+    parent_id: ObjectId,
+    dropped: &HashSet<ObjectId>,
+    destinations: &HashMap<Vec<u8>, Object>,
+    depth: usize,
+) {
+    let mut kept = Vec::new();
+    for item_id in outline_items(document, parent_id) {
+        if depth < MAX_OUTLINE_DEPTH {
+            prune_outline_items(document, item_id, dropped, destinations, depth + 1);
+        }
+        let Ok(item) = document.get_dictionary(item_id) else {
+            continue;
+        };
+        if target_page(document, item, destinations).is_some_and(|page| dropped.contains(&page)) {
+            if !item.has(b"First") {
+                continue;
+            }
+            if let Ok(item) = document.get_dictionary_mut(item_id) {
+                item.remove(b"Dest");
+                item.remove(b"A");
+            }
+        }
+        kept.push(item_id);
+    }
+
+    let Ok(mut parent) = document.get_dictionary(parent_id).cloned() else {
+        return;
+    };
+    let closed = parent.get(b"Count").and_then(|obj| obj.as_i64()).is_ok_and(|count| count < 0);
+    parent.remove(b"First");
+    parent.remove(b"Last");
+    parent.remove(b"Count");
+    link_outline_items(document, &mut parent, &kept);
+    if closed {
+        if let Ok(count) = parent.get(b"Count").and_then(|obj| obj.as_i64()) {
+            parent.set("Count", -count);
+        }
+    }
+    document.objects.insert(parent_id, Object::Dictionary(parent));
+}
+
+/// The page an outline item or link annotation leads to, through either its
+/// `/Dest` or a GoTo action
+pub(super) fn target_page(
+    document: &Document,
+    item: &Dictionary,
+    destinations: &HashMap<Vec<u8>, Object>,
+) -> Option<ObjectId> {
+    if let Ok(destination) = item.get(b"Dest") {
+        return destination_page(document, destination, destinations);
+    }
+    let (_, action) = document.dereference(item.get(b"A").ok()?).ok()?;
+    action_page(document, action, destinations)
+}
+
+/// The page a GoTo action leads to
+pub(super) fn action_page(
+    document: &Document,
+    action: &Object,
+    destinations: &HashMap<Vec<u8>, Object>,
+) -> Option<ObjectId> {
+    let action = action.as_dict().ok()?;
+    if action.get(b"S").and_then(|obj| obj.as_name()).ok()? != b"GoTo" {
+        return None;
+    }
+    destination_page(document, action.get(b"D").ok()?, destinations)
+}
+
+/// The page a destination leads to, looking up named destinations
+pub(super) fn destination_page(
+    document: &Document,
+    destination: &Object,
+    destinations: &HashMap<Vec<u8>, Object>,
+) -> Option<ObjectId> {
+    let explicit = match destination_name(destination) {
+        Some(name) => destinations.get(&name)?.clone(),
+        None => explicit_destination(document, destination)?,
+    };
+    explicit.as_array().ok()?.first()?.as_reference().ok()
+}
+
+/// Replace the named destinations of a document with those that do not lead
+/// to dropped pages, kept as a single `/Dests` name tree
+pub(super) fn retain_named_destinations(
+    document: &mut Document,
+    dropped: &HashSet<ObjectId>,
+    destinations: &HashMap<Vec<u8>, Object>,
+) -> Result<()> {
+    let mut kept: Vec<(&Vec<u8>, &Object)> = destinations
+        .iter()
+        .filter(|(_, destination)| {
+            let page = destination
+                .as_array()
+                .ok()
+                .and_then(|destination| destination.first())
+                .and_then(|page| page.as_reference().ok());
+            !page.is_some_and(|page| dropped.contains(&page))
+        })
+        .collect();
+    // Name trees are sorted by name
+    kept.sort_unstable_by_key(|(name, _)| *name);
+    let tree = (!kept.is_empty()).then(|| {
+        let names = kept
+            .into_iter()
+            .flat_map(|(name, destination)| {
+                [Object::String(name.clone(), lopdf::StringFormat::Literal), destination.clone()]
+            })
+            .collect::<Vec<_>>();
+        dictionary! { "Names" => names }
+    });
+
+    let names_id = document
+        .catalog()?
+        .get(b"Names")
+        .and_then(|obj| obj.as_reference())
+        .ok();
+    let catalog = document.catalog_mut()?;
+    catalog.remove(b"Dests");
+    let names = match names_id {
+        Some(names_id) => document.get_dictionary_mut(names_id)?,
+        None => match catalog.get_mut(b"Names") {
+            Ok(Object::Dictionary(names)) => names,
+            _ => {
+                if let Some(tree) = tree {
+                    catalog.set("Names", dictionary! { "Dests" => tree });
+                }
+                return Ok(());
+            }
+        },
+    };
+    match tree {
+        Some(tree) => names.set("Dests", tree),
+        None => {
+            names.remove(b"Dests");
+        }
+    }
+    Ok(())
+}
+
+/// The name of a named destination, which may be a name or a string
+pub(super) fn destination_name(destination: &Object) -> Option<Vec<u8>> {
+    match destination {
+        Object::Name(name) | Object::String(name, _) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+/// Every named destination of a document, from both the catalog's `/Dests`
+/// dictionary and the `/Dests` name tree, as explicit destination arrays
+pub(super) fn named_destinations(document: &Document, catalog: &Dictionary) -> HashMap<Vec<u8>, Object> {
+    let mut destinations = HashMap::new();
+
+    if let Ok(dests) = catalog
+        .get(b"Dests")
+        .and_then(|obj| document.dereference(obj))
+        .and_then(|(_, obj)| obj.as_dict())
+    {
+        for (name, destination) in dests {
+            if let Some(explicit) = explicit_destination(document, destination) {
+                destinations.insert(name.clone(), explicit);
+            }
+        }
+    }
+
+    if let Ok(tree) = catalog
+        .get(b"Names")
+        .and_then(|obj| document.dereference(obj))
+        .and_then(|(_, obj)| obj.as_dict())
+        .and_then(|names| names.get(b"Dests"))
+        .and_then(|obj| document.dereference(obj))
+        .and_then(|(_, obj)| obj.as_dict())
+    {
+        collect_name_tree(document, tree, &mut destinations, 0);
+    }
+    destinations
+}
+
+fn collect_name_tree(
+    document: &Document,
+    node: &Dictionary,
+    destinations: &mut HashMap<Vec<u8>, Object>,
+    depth: usize,
+) {
+    if depth > MAX_OUTLINE_DEPTH {
+        return;
+    }
+    if let Ok(names) = node.get(b"Names").and_then(|obj| obj.as_array()) {
+        for pair in names.chunks_exact(2) {
+            if let (Ok(name), Some(explicit)) = (pair[0].as_str(), explicit_destination(document, &pair[1])) {
+                destinations.insert(name.to_vec(), explicit);
+            }
+        }
+    }
+    if let Ok(kids) = node.get(b"Kids").and_then(|obj| obj.as_array()) {
+        for kid in kids {
+            if let Ok(kid) = kid.as_reference().and_then(|id| document.get_dictionary(id)) {
+                collect_name_tree(document, kid, destinations, depth + 1);
+            }
+        }
+    }
+}
+
+/// A destination array, given either directly or as the `/D` of a dictionary
+fn explicit_destination(document: &Document, destination: &Object) -> Option<Object> {
+    let (_, destination) = document.dereference(destination).ok()?;
+    match destination {
+        Object::Array(_) => Some(destination.clone()),
+        Object::Dictionary(dict) => {
+            let (_, inner) = document.dereference(dict.get(b"D").ok()?).ok()?;
+            matches!(inner, Object::Array(_)).then(|| inner.clone())
+        }
+        _ => None,
+    }
+}
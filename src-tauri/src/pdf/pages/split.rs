@@ -0,0 +1,201 @@
+use lopdf::Document;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::pdf::{self, PdfError, Result};
+use super::{renumber_objects, retain_pages, selected_page_ids, DroppedPages};
+
+/// Splits documents into parts and extracts pages from them
+pub struct PdfSplitter;
+
+impl PdfSplitter {
+    /// Split a PDF file into one part per page range, saved in a directory
+    ///
+    /// Ranges are one-based and separated by commas, as in `1-3,5,8-`. Parts
+    /// are named after the file and their pages, such as `report-1-3.pdf`,
+    /// and handed to `write` along with their path to be saved. Returns the
+    /// paths of the parts in order.
+    pub fn split_by_ranges<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        ranges: &str,
+        output_dir: Q,
+        write: impl FnMut(&Path, &mut Document) -> Result<()>,
+    ) -> Result<Vec<PathBuf>> {
+        let document = Document::load(path.as_ref())?;
+        let parts = Self::parse_ranges(ranges, document.get_pages().len() as u32)?;
+        Self::write_parts(path.as_ref(), &document, &parts, output_dir.as_ref(), write)
+    }
+
+    /// Split a PDF file into parts of a fixed number of pages, saved in a
+    /// directory like those of [`PdfSplitter::split_by_ranges`]; the last
+    /// part holds whatever pages are left
+    pub fn split_every<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        pages_per_part: u32,
+        output_dir: Q,
+        write: impl FnMut(&Path, &mut Document) -> Result<()>,
+    ) -> Result<Vec<PathBuf>> {
+        if pages_per_part == 0 {
+            return Err(PdfError::InvalidPageRange("Parts need at least one page".to_string()));
+        }
+        let document = Document::load(path.as_ref())?;
+        let pages: Vec<u32> = (0..document.get_pages().len() as u32).collect();
+        let parts: Vec<Vec<u32>> = pages
+            .chunks(pages_per_part as usize)
+            .map(|part| part.to_vec())
+            .collect();
+        Self::write_parts(path.as_ref(), &document, &parts, output_dir.as_ref(), write)
+    }
+
+    /// Save selected pages of a PDF file as a new document
+    ///
+    /// Pages are given as ranges like those of [`PdfSplitter::split_by_ranges`]
+    /// and end up in the order given.
+    pub fn extract_pages<P: AsRef<Path>, Q: AsRef<Path>>(path: P, pages: &str, output_path: Q) -> Result<()> {
+        let document = Document::load(path.as_ref())?;
+        let pages = Self::parse_ranges(pages, document.get_pages().len() as u32)?.concat();
+        let mut extracted = Self::extract_document(&document, &pages)?;
+        pdf::write_document(&mut extracted, output_path)
+    }
+
+    /// A copy of a document holding only the given pages, by zero-based
+    /// index, in the given order
+    ///
+    /// Form fields with widgets on the pages come along; everything only the
+    /// other pages used is left out.
+    pub fn extract_document(document: &Document, pages: &[u32]) -> Result<Document> {
+        if document.is_encrypted() {
+            return Err(PdfError::UnsupportedOperation("Document is encrypted".to_string()));
+        }
+
+        let page_ids = selected_page_ids(document, pages)?;
+        let mut extracted = document.clone();
+        retain_pages(&mut extracted, &page_ids, DroppedPages::Strip)?;
+        renumber_objects(&mut extracted, 1);
+        Ok(extracted)
+    }
+
+    /// Parse one-based page ranges such as `1-3,5,8-` into zero-based page
+    /// indexes, one list per range
+    ///
+    /// A range may leave out its first or last page to start at the first
+    /// page of the document or run to its end.
+    pub fn parse_ranges(ranges: &str, page_count: u32) -> Result<Vec<Vec<u32>>> {
+        let parse_page = |text: &str, default: u32| {
+            let text = text.trim();
+            if text.is_empty() {
+                return Ok(default);
+            }
+            let page: u32 = text
+                .parse()
+                .map_err(|_| PdfError::InvalidPageRange(format!("{} is not a page number", text)))?;
+            if page == 0 || page > page_count {
+                return Err(PdfError::InvalidPageRange(format!(
+                    "Page {} is not in the document, which has {} pages",
+                    page, page_count
+                )));
+            }
+            Ok(page)
+        };
+
+        let mut parts = Vec::new();
+        for range in ranges.split(',').map(str::trim) {
+            let (first, last) = match range.split_once('-') {
+                Some((first, last)) => (parse_page(first, 1)?, parse_page(last, page_count)?),
+                None if range.is_empty() => {
+                    return Err(PdfError::InvalidPageRange(format!("Empty range in \"{}\"", ranges)));
+                }
+                None => {
+                    let page = parse_page(range, 0)?;
+                    (page, page)
+                }
+            };
+            if first > last {
+                return Err(PdfError::InvalidPageRange(format!("{} runs backwards", range)));
+            }
+            parts.push((first - 1..last).collect());
+        }
+        Ok(parts)
+    }
+
+    /// Save each part of a document in a directory, named after the file and
+    /// the pages of the part
+    fn write_parts(
+        path: &Path,
+        document: &Document,
+        parts: &[Vec<u32>],
+        output_dir: &Path,
+        mut write: impl FnMut(&Path, &mut Document) -> Result<()>,
+    ) -> Result<Vec<PathBuf>> {
+        fs::create_dir_all(output_dir)?;
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "document".to_string());
+
+        let mut names = HashSet::new();
+        let mut output_paths = Vec::with_capacity(parts.len());
+        for pages in parts {
+            let mut part = Self::extract_document(document, pages)?;
+
+            let (first, last) = (pages[0] + 1, pages[pages.len() - 1] + 1);
+            let base = if first == last {
+                format!("{}-{}", stem, first)
+            } else {
+                format!("{}-{}-{}", stem, first, last)
+            };
+            // The same range given twice still gets a file of its own
+            let mut name = base.clone();
+            let mut n = 2;
+            while !names.insert(name.clone()) {
+                name = format!("{} ({})", base, n);
+                n += 1;
+            }
+
+            let output_path = output_dir.join(format!("{}.pdf", name));
+            write(&output_path, &mut part)?;
+            output_paths.push(output_path);
+        }
+        Ok(output_paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(ranges: &str) -> Result<Vec<Vec<u32>>> {
+        PdfSplitter::parse_ranges(ranges, 10)
+    }
+
+    #[test]
+    fn ranges_are_one_based_and_inclusive() {
+        assert_eq!(parse("1-3, 5 ,7-7").unwrap(), vec![vec![0, 1, 2], vec![4], vec![6]]);
+    }
+
+    #[test]
+    fn open_ranges_run_to_either_end() {
+        assert_eq!(parse("8-").unwrap(), vec![vec![7, 8, 9]]);
+        assert_eq!(parse("-3").unwrap(), vec![vec![0, 1, 2]]);
+        assert_eq!(parse("-").unwrap(), vec![(0..10).collect::<Vec<u32>>()]);
+    }
+
+    #[test]
+    fn backwards_ranges_are_rejected() {
+        assert!(matches!(parse("5-2"), Err(PdfError::InvalidPageRange(_))));
+    }
+
+    #[test]
+    fn blank_ranges_are_rejected() {
+        for ranges in ["", " ", "1,,3", "2,"] {
+            assert!(matches!(parse(ranges), Err(PdfError::InvalidPageRange(_))), "{:?}", ranges);
+        }
+    }
+
+    #[test]
+    fn pages_outside_the_document_are_rejected() {
+        for ranges in ["0", "0-2", "11", "9-11", "11-", "x", "1-y"] {
+            assert!(matches!(parse(ranges), Err(PdfError::InvalidPageRange(_))), "{:?}", ranges);
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+use std::collections::HashSet;
+use crate::pdf::Result;
+
+/// Deepest structure or number tree walked before giving up on a malformed document
+const MAX_STRUCTURE_DEPTH: usize = 64;
+
+/// Most beads followed around an article thread
+const MAX_THREAD_BEADS: usize = 100_000;
+
+/// Remove the structure elements, marked-content references and parent
+/// tree entries that belong to dropped pages
+pub(super) fn prune_structure_tree(document: &mut Document, dropped: &HashSet<ObjectId>) -> Result<()> {
+    let Some((root_id, mut root)) = catalog_dictionary(document, b"StructTreeRoot")? else {
+        return Ok(());
+    };
+
+    if let Ok(kids) = root.get(b"K").cloned() {
+        match retain_struct_kids(document, &kids, None, dropped, 0) {
+            Some(kids) => root.set("K", kids),
+            None => {
+                root.remove(b"K");
+            }
+        }
+    }
+
+    // The parent tree maps the pages' /StructParents and the annotations'
+    // /StructParent to their structure elements
+    let mut keys = HashSet::new();
+    for page_id in dropped {
+        let Ok(page) = document.get_dictionary(*page_id) else {
+            continue;
+        };
+        if let Ok(key) = page.get(b"StructParents").and_then(|obj| obj.as_i64()) {
+            keys.insert(key);
+        }
+        let annotations = page
+            .get(b"Annots")
+            .and_then(|obj| document.dereference(obj))
+            .and_then(|(_, obj)| obj.as_array())
+            .map(|annotations| annotations.to_vec())
+            .unwrap_or_default();
+        for annotation in annotations {
+            let key = document
+                .dereference(&annotation)
+                .and_then(|(_, obj)| obj.as_dict())
+                .and_then(|annotation| annotation.get(b"StructParent"))
+                .and_then(|obj| obj.as_i64());
+            if let Ok(key) = key {
+                keys.insert(key);
+            }
+        }
+    }
+    if let Ok(parent_tree) = root.get_mut(b"ParentTree") {
+        match parent_tree {
+            Object::Reference(id) => {
+                let id = *id;
+                remove_number_tree_keys(document, id, &keys, 0);
+            }
+            Object::Dictionary(node) => remove_number_keys(document, node, &keys, 0),
+            _ => {}
+        }
+    }
+
+    store_catalog_dictionary(document, b"StructTreeRoot", root_id, root)
+}
+
+/// Relabel the pages kept from `old_order`, now in the order of `kept`, as
+/// they were labelled before
+///
+/// The label ranges are rebuilt so each page keeps its label even as the
+/// pages around it go.
+pub(super) fn renumber_page_labels(document: &mut Document, old_order: &[ObjectId], kept: &[ObjectId]) -> Result<()> {
+    let Some((_, labels)) = catalog_dictionary(document, b"PageLabels")? else {
+        return Ok(());
+    };
+    let mut ranges = Vec::new();
+    collect_number_tree(document, &labels, &mut ranges, 0);
+    let mut ranges: Vec<(i64, Dictionary)> = ranges
+        .into_iter()
+        .filter_map(|(start, value)| {
+            let range = document.dereference(&value).and_then(|(_, obj)| obj.as_dict()).ok()?.clone();
+            Some((start, range))
+        })
+        .collect();
+    ranges.sort_by_key(|(start, _)| *start);
+
+    let mut nums = Vec::new();
+    let mut previous: Option<(usize, i64)> = None;
+    for (index, page_id) in kept.iter().enumerate() {
+        let Some(old_index) = old_order.iter().position(|id| id == page_id) else {
+            continue;
+        };
+        let old_index = old_index as i64;
+        let Some(range_index) = ranges.iter().rposition(|(start, _)| *start <= old_index) else {
+            continue;
+        };
+        let (start, range) = &ranges[range_index];
+        let first = range.get(b"St").and_then(|obj| obj.as_i64()).unwrap_or(1);
+        let number = first + old_index - start;
+
+        // A page that does not continue the previous one starts a range of its own
+        if previous != Some((range_index, number - 1)) {
+            let mut range = range.clone();
+            if number == 1 {
+                range.remove(b"St");
+            } else {
+                range.set("St", number);
+            }
+            nums.push(Object::Integer(index as i64));
+            nums.push(Object::Dictionary(range));
+        }
+        previous = Some((range_index, number));
+    }
+
+    let catalog = document.catalog_mut()?;
+    if nums.is_empty() {
+        catalog.remove(b"PageLabels");
+    } else {
+        catalog.set("PageLabels", dictionary! { "Nums" => nums });
+    }
+    Ok(())
+}
+
+/// Unlink the beads of article threads that sit on dropped pages, removing
+/// threads left without beads
+pub(super) fn prune_threads(document: &mut Document, dropped: &HashSet<ObjectId>) -> Result<()> {
+    let Ok(threads) = document
+        .catalog()?
+        .get(b"Threads")
+        .and_then(|obj| document.dereference(obj))
+        .and_then(|(_, obj)| obj.as_array())
+        .cloned()
+    else {
+        return Ok(());
+    };
+
+    let mut remaining = Vec::new();
+    for thread in threads {
+        let Ok(thread_id) = thread.as_reference() else {
+            continue;
+        };
+        let Ok(first) = document
+            .get_dictionary(thread_id)
+            .and_then(|thread| thread.get(b"F"))
+            .and_then(|obj| obj.as_reference())
+        else {
+            continue;
+        };
+
+        let mut beads = Vec::new();
+        let mut next = Some(first);
+        while let Some(bead_id) = next.filter(|id| beads.len() < MAX_THREAD_BEADS && !beads.contains(id)) {
+            beads.push(bead_id);
+            next = document
+                .get_dictionary(bead_id)
+                .and_then(|bead| bead.get(b"N"))
+                .and_then(|obj| obj.as_reference())
+                .ok()
+                .filter(|id| *id != first);
+        }
+        let beads: Vec<ObjectId> = beads
+            .into_iter()
+            .filter(|bead_id| {
+                document
+                    .get_dictionary(*bead_id)
+                    .and_then(|bead| bead.get(b"P"))
+                    .and_then(|obj| obj.as_reference())
+                    .is_ok_and(|page_id| !dropped.contains(&page_id))
+            })
+            .collect();
+        let Some(first) = beads.first().copied() else {
+            continue;
+        };
+
+        // The beads form a ring, with the first one pointing back at the thread
+        for (index, bead_id) in beads.iter().enumerate() {
+            let bead = document.get_dictionary_mut(*bead_id)?;
+            bead.set("N", beads[(index + 1) % beads.len()]);
+            bead.set("V", beads[(index + beads.len() - 1) % beads.len()]);
+            if index == 0 {
+                bead.set("T", thread_id);
+            } else {
+                bead.remove(b"T");
+            }
+        }
+        document.get_dictionary_mut(thread_id)?.set("F", first);
+        remaining.push(Object::Reference(thread_id));
+    }
+
+    let catalog = document.catalog_mut()?;
+    if remaining.is_empty() {
+        catalog.remove(b"Threads");
+    } else {
+        catalog.set("Threads", remaining);
+    }
+    Ok(())
+}
+
+/// The kids of a structure element, or of the structure tree root, that do
+/// not belong to dropped pages, or `None` if none are left
+///
+/// `page` is the page the kids' marked content is on unless they say otherwise.
+fn retain_struct_kids(
+    document: &mut Document,
+    kids: &Object,
+    page: Option<ObjectId>,
+    dropped: &HashSet<ObjectId>,
+    depth: usize,
+) -> Option<Object> {
+    if depth > MAX_STRUCTURE_DEPTH {
+        return Some(kids.clone());
+    }
+    let on_dropped_page = |page: Option<ObjectId>| page.is_some_and(|page| dropped.contains(&page));
+
+    let items = match kids {
+        Object::Array(items) => items.clone(),
+        kid => vec![kid.clone()],
+    };
+    let mut kept = Vec::new();
+    for mut kid in items {
+        let keep = match &mut kid {
+            // Marked content on the element's own page
+            Object::Integer(_) => !on_dropped_page(page),
+            Object::Reference(id) => match document.get_dictionary(*id).ok().cloned() {
+                Some(element) if is_struct_element(&element) => retain_struct_element(document, *id, element, dropped, depth),
+                Some(reference) => !on_dropped_page(struct_page(&reference).or(page)),
+                None => true,
+            },
+            Object::Dictionary(element) if is_struct_element(element) => {
+                prune_struct_element(document, element, dropped, depth)
+            }
+            // Direct marked-content and object references
+            Object::Dictionary(reference) => !on_dropped_page(struct_page(reference).or(page)),
+            _ => true,
+        };
+        if keep {
+            kept.push(kid);
+        }
+    }
+
+    match (kids, kept.len()) {
+        (_, 0) => None,
+        (Object::Array(_), _) => Some(Object::Array(kept)),
+        _ => kept.pop(),
+    }
+}
+
+/// Prune the kids of a structure element held in its own object, returning
+/// whether the element stays
+fn retain_struct_element(
+    document: &mut Document,
+    element_id: ObjectId,
+    mut element: Dictionary,
+    dropped: &HashSet<ObjectId>,
+    depth: usize,
+) -> bool {
+    let keep = prune_struct_element(document, &mut element, dropped, depth);
+    if keep {
+        document.objects.insert(element_id, Object::Dictionary(element));
+    }
+    keep
+}
+
+/// Prune a structure element's kids, returning whether the element stays
+fn prune_struct_element(document: &mut Document, element: &mut Dictionary, dropped: &HashSet<ObjectId>, depth: usize) -> bool {
+    let page = struct_page(element);
+    let Ok(kids) = element.get(b"K").cloned() else {
+        // An element without content stays unless it names a dropped page
+        return !page.is_some_and(|page| dropped.contains(&page));
+    };
+
+    match retain_struct_kids(document, &kids, page, dropped, depth + 1) {
+        Some(kids) => {
+            element.set("K", kids);
+            true
+        }
+        None => false,
+    }
+}
+
+fn is_struct_element(dictionary: &Dictionary) -> bool {
+    dictionary.has(b"S")
+        && !dictionary
+            .get(b"Type")
+            .and_then(|obj| obj.as_name())
+            .is_ok_and(|kind| kind == b"MCR" || kind == b"OBJR")
+}
+
+fn struct_page(dictionary: &Dictionary) -> Option<ObjectId> {
+    dictionary.get(b"Pg").and_then(|obj| obj.as_reference()).ok()
+}
+
+/// Remove entries from a number tree held in its own object
+fn remove_number_tree_keys(document: &mut Document, node_id: ObjectId, keys: &HashSet<i64>, depth: usize) {
+    let Ok(mut node) = document.get_dictionary(node_id).cloned() else {
+        return;
+    };
+    remove_number_keys(document, &mut node, keys, depth);
+    document.objects.insert(node_id, Object::Dictionary(node));
+}
+
+/// Remove entries from a number tree node and the nodes below it
+///
+/// `/Limits` are left as they are; they still bound the keys that remain.
+fn remove_number_keys(document: &mut Document, node: &mut Dictionary, keys: &HashSet<i64>, depth: usize) {
+    if depth > MAX_STRUCTURE_DEPTH {
+        return;
+    }
+    if let Ok(Object::Array(nums)) = node.get_mut(b"Nums") {
+        let entries: Vec<Object> = nums
+            .chunks(2)
+            .filter(|pair| !pair[0].as_i64().is_ok_and(|key| keys.contains(&key)))
+            .flatten()
+            .cloned()
+            .collect();
+        *nums = entries;
+    }
+    let kids: Vec<ObjectId> = node
+        .get(b"Kids")
+        .and_then(|obj| obj.as_array())
+        .map(|kids| kids.iter().filter_map(|kid| kid.as_reference().ok()).collect())
+        .unwrap_or_default();
+    for kid_id in kids {
+        remove_number_tree_keys(document, kid_id, keys, depth + 1);
+    }
+}
+
+/// Every entry of a number tree, in no particular order
+fn collect_number_tree(document: &Document, node: &Dictionary, entries: &mut Vec<(i64, Object)>, depth: usize) {
+    if depth > MAX_STRUCTURE_DEPTH {
+        return;
+    }
+    if let Ok(nums) = node.get(b"Nums").and_then(|obj| obj.as_array()) {
+        for pair in nums.chunks(2) {
+            if let [key, value] = pair {
+                if let Ok(key) = key.as_i64() {
+                    entries.push((key, value.clone()));
+                }
+            }
+        }
+    }
+    let kids = node.get(b"Kids").and_then(|obj| obj.as_array()).cloned().unwrap_or_default();
+    for kid in kids {
+        if let Ok(kid) = document.dereference(&kid).and_then(|(_, obj)| obj.as_dict()) {
+            collect_number_tree(document, kid, entries, depth + 1);
+        }
+    }
+}
+
+/// A catalog entry that is a dictionary, along with its object id if it is
+/// held in an object of its own
+fn catalog_dictionary(document: &Document, key: &[u8]) -> Result<Option<(Option<ObjectId>, Dictionary)>> {
+    Ok(document
+        .catalog()?
+        .get(key)
+        .and_then(|obj| document.dereference(obj))
+        .ok()
+        .and_then(|(id, obj)| Some((id, obj.as_dict().ok()?.clone()))))
+}
+
+/// Store a catalog dictionary back where [`catalog_dictionary`] found it
+fn store_catalog_dictionary(document: &mut Document, key: &[u8], id: Option<ObjectId>, dictionary: Dictionary) -> Result<()> {
+    match id {
+        Some(id) => {
+            document.objects.insert(id, Object::Dictionary(dictionary));
+        }
+        None => document.catalog_mut()?.set(key.to_vec(), dictionary),
+    }
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::HashMap;
+use crate::pdf::encode_text_string;
+use crate::pdf::form::{ChoiceOption, FieldFlags, FieldKind, FormFieldGenerator, FormFieldTree, PdfFormField};
+
+/// A document of empty US Letter pages
+pub(crate) fn blank_document(pages: usize) -> Document {
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+
+    let mut kids = Vec::new();
+    for _ in 0..pages {
+        let content_id = document.add_object(Stream::new(Dictionary::new(), Vec::new()));
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Contents" => content_id,
+        });
+        kids.push(Object::Reference(page_id));
+    }
+
+    document.objects.insert(pages_id, Object::Dictionary(dictionary! {
+        "Type" => "Pages",
+        "Kids" => kids,
+        "Count" => pages as i64,
+    }));
+    let catalog_id = document.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    document.trailer.set("Root", catalog_id);
+    document
+}
+
+/// A blank document holding the given fields
+pub(crate) fn form_document(pages: usize, fields: Vec<PdfFormField>) -> Document {
+    let mut document = blank_document(pages);
+    FormFieldGenerator::add_fields_to_document(&mut document, fields).unwrap();
+    document
+}
+
+/// A field with a single widget near the top of a page
+pub(crate) fn field(name: &str, field_type: FieldKind, page: u32, options: &[&str]) -> PdfFormField {
+    PdfFormField {
+        id: name.to_string(),
+        name: name.to_string(),
+        field_type,
+        value: None,
+        rect: [100.0, 600.0, 300.0, 620.0],
+        page,
+        flags: FieldFlags::for_new_field(),
+        options: options.iter().map(|option| ChoiceOption::new(*option)).collect(),
+        properties: HashMap::new(),
+        widgets: Vec::new(),
+    }
+}
+
+/// Move a top-level field under a new parent field, returning the parent's id
+pub(crate) fn nest_field(document: &mut Document, parent: &str, name: &str) -> ObjectId {
+    let field_id = FormFieldTree::from_document(document).find(name).unwrap().object_id;
+    let parent_id = document.add_object(dictionary! {
+        "T" => encode_text_string(parent),
+        "Kids" => vec![field_id.into()],
+    });
+    document.get_dictionary_mut(field_id).unwrap().set("Parent", parent_id);
+
+    let acro_form_id = document.catalog().unwrap().get(b"AcroForm").unwrap().as_reference().unwrap();
+    let fields = document.get_dictionary_mut(acro_form_id).unwrap().get_mut(b"Fields").unwrap().as_array_mut().unwrap();
+    for entry in fields.iter_mut() {
+        if entry.as_reference().is_ok_and(|id| id == field_id) {
+            *entry = Object::Reference(parent_id);
+        }
+    }
+    parent_id
+}
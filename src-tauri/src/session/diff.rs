@@ -0,0 +1,166 @@
+use lopdf::Document;
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet};
+use crate::pdf::{self, FieldRef, FormFieldTree, PdfDocument, PdfFieldValue, PdfParser};
+use super::DocumentEdit;
+
+/// A field whose value differs between an open document and its file on disk
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FieldDifference {
+    pub name: String,
+    /// Value in the open document, `None` if the field is missing or empty there
+    pub ours: Option<PdfFieldValue>,
+    /// Value in the file on disk, `None` if the field is missing or empty there
+    pub theirs: Option<PdfFieldValue>,
+    /// Whether one of the unsaved edits touched the field
+    pub edited: bool,
+}
+
+/// How an open document compares with the current version of its file on disk
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DocumentDiff {
+    pub path: String,
+    /// Descriptions of the unsaved edits, oldest first
+    pub pending_edits: Vec<String>,
+    /// Fields whose values differ, by fully-qualified name
+    pub fields: Vec<FieldDifference>,
+    pub our_page_count: u32,
+    pub their_page_count: u32,
+    /// Whether the document information differs
+    pub metadata_changed: bool,
+    /// Whether the unsaved edits apply cleanly to the file on disk, so
+    /// replaying them merges both sets of changes
+    pub replayable: bool,
+}
+
+impl DocumentDiff {
+    /// Compare the structure of an open document and its unsaved edits
+    /// with another version of its file
+    ///
+    /// `pending` is `None` when the edits cannot be replayed, as for sessions
+    /// whose edits were made before the file was last saved.
+    pub fn between(ours: &PdfDocument, pending: Option<&[DocumentEdit]>, theirs: &Document) -> pdf::Result<Self> {
+        let path = ours.path.clone();
+        let their_info = PdfParser::parse_document(path.clone(), theirs)?;
+        let ours_form = &ours.form;
+        let theirs_form = &their_info.form;
+        let edited = pending
+            .map(|edits| edited_fields(edits, &[ours_form, theirs_form]))
+            .unwrap_or_default();
+
+        let our_values = field_values(ours_form);
+        let their_values = field_values(theirs_form);
+        let names: BTreeSet<&String> = our_values.keys().chain(their_values.keys()).collect();
+        let fields = names
+            .into_iter()
+            .filter_map(|name| {
+                let ours = our_values.get(name).cloned().flatten();
+                let theirs = their_values.get(name).cloned().flatten();
+                (ours != theirs).then(|| FieldDifference {
+                    name: name.clone(),
+                    ours,
+                    theirs,
+                    edited: edited.contains(name),
+                })
+            })
+            .collect();
+
+        let metadata_changed = serde_json::to_value(&ours.metadata).ok()
+            != serde_json::to_value(&their_info.metadata).ok();
+
+        let replayable = pending.is_some_and(|edits| {
+            let mut merged = theirs.clone();
+            edits.iter().all(|edit| edit.apply(&mut merged).is_ok())
+        });
+
+        Ok(DocumentDiff {
+            path,
+            pending_edits: pending
+                .unwrap_or_default()
+                .iter()
+                .map(DocumentEdit::describe)
+                .collect(),
+            fields,
+            our_page_count: ours.page_count,
+            their_page_count: their_info.page_count,
+            metadata_changed,
+            replayable,
+        })
+    }
+}
+
+/// Values of the fields that hold one, by fully-qualified name
+fn field_values(form: &FormFieldTree) -> BTreeMap<String, Option<PdfFieldValue>> {
+    form.terminals()
+        .into_iter()
+        .filter(|field| field.is_exported())
+        .map(|field| (field.full_name.clone(), field.value.clone()))
+        .collect()
+}
+
+/// Names of the fields a list of edits touches, looking up fields referred
+/// to by object id, their own or that of one of their widgets, in each of
+/// the given forms in turn
+fn edited_fields(edits: &[DocumentEdit], forms: &[&FormFieldTree]) -> BTreeSet<String> {
+    let name_of = |field: &FieldRef| match field {
+        FieldRef::Name(name) => Some(name.clone()),
+        FieldRef::ObjectId(id) => forms
+            .iter()
+            .find_map(|form| form.find_by_id(*id))
+            .map(|node| node.full_name.clone()),
+    };
+    // Flattening removes the fields, so only the other forms still list them
+    let all_fields = || forms.iter().flat_map(|form| field_values(form).into_keys()).collect::<Vec<_>>();
+
+    let mut names = BTreeSet::new();
+    for edit in edits {
+        match edit {
+            DocumentEdit::AddFields { fields } => names.extend(fields.iter().map(|field| field.name.clone())),
+            DocumentEdit::FillForm { values } | DocumentEdit::ImportFormData { values, .. } => {
+                names.extend(values.keys().cloned())
+            }
+            DocumentEdit::FlattenForm { field_names: Some(field_names) } => names.extend(field_names.iter().cloned()),
+            DocumentEdit::FlattenForm { field_names: None } => names.extend(all_fields()),
+            DocumentEdit::UpdateField { field, .. } | DocumentEdit::DeleteField { field } => {
+                names.extend(name_of(field))
+            }
+            DocumentEdit::GenerateAppearances
+            | DocumentEdit::UpdateMetadata { .. }
+            | DocumentEdit::MovePages { .. }
+            | DocumentEdit::RotatePages { .. }
+            | DocumentEdit::DeletePages { .. }
+            | DocumentEdit::InsertBlankPages { .. }
+            | DocumentEdit::InsertPages { .. } => {}
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::testing::{field, form_document};
+    use crate::pdf::form::FieldKind;
+    use crate::pdf::FieldUpdate;
+
+    #[test]
+    fn fields_edited_through_a_widget_are_marked_edited() {
+        let document = form_document(1, vec![
+            field("name", FieldKind::Text, 0, &[]),
+            field("choice", FieldKind::Radio, 0, &["Yes", "No"]),
+        ]);
+        let form = FormFieldTree::from_document(&document);
+        let update = |id| DocumentEdit::UpdateField {
+            field: FieldRef::ObjectId(id),
+            update: FieldUpdate { rect: Some([0.0, 0.0, 10.0, 10.0]), ..Default::default() },
+        };
+        let radio_widget = form.find("choice").unwrap().widgets[1].object_id;
+        let text_field = form.find("name").unwrap().object_id;
+
+        let edited = edited_fields(&[update(radio_widget)], &[&form]);
+        assert_eq!(edited.into_iter().collect::<Vec<_>>(), ["choice"]);
+
+        let edited = edited_fields(&[update(text_field)], &[&form]);
+        assert_eq!(edited.into_iter().collect::<Vec<_>>(), ["name"]);
+    }
+}
@@ -0,0 +1,707 @@
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::pdf::{self, FieldRef, FieldUpdate, MetadataUpdate, PdfFieldValue, PdfFormField, PdfError};
+use crate::pdf::{FormEditor, FormFieldGenerator, FormFiller, FormFlattener, PageEditor, PdfParser};
+use crate::pdf::objects::{collect_references, reachable_objects};
+
+/// Number of most recent edits that keep the object changes needed to undo them
+pub const MAX_UNDO_DEPTH: usize = 100;
+
+/// A mutation of an open document, recorded in its journal
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum DocumentEdit {
+    AddFields { fields: Vec<PdfFormField> },
+    FillForm { values: HashMap<String, PdfFieldValue> },
+    FlattenForm { field_names: Option<Vec<String>> },
+    UpdateField { field: FieldRef, update: FieldUpdate },
+    DeleteField { field: FieldRef },
+    GenerateAppearances,
+    UpdateMetadata { metadata: MetadataUpdate },
+    /// Field values read from an exchange file such as XFDF
+    ImportFormData { source: String, values: HashMap<String, PdfFieldValue> },
+    /// Pages by zero-based index, moved to start at position `to`
+    MovePages { pages: Vec<u32>, to: u32 },
+    RotatePages { pages: Vec<u32>, degrees: i64 },
+    DeletePages { pages: Vec<u32> },
+    InsertBlankPages { at: u32, count: u32, width: f64, height: f64 },
+    /// Pages copied from the PDF file at `source`, all of them unless listed
+    InsertPages { at: u32, source: String, pages: Option<Vec<u32>> },
+}
+
+impl DocumentEdit {
+    /// Apply the edit to a loaded document
+    pub fn apply(&self, document: &mut Document) -> pdf::Result<()> {
+        match self {
+            DocumentEdit::AddFields { fields } => FormFieldGenerator::add_fields_to_document(document, fields.clone()),
+            DocumentEdit::FillForm { values } => FormFiller::fill_document(document, values),
+            DocumentEdit::FlattenForm { field_names } => {
+                FormFlattener::flatten_document(document, field_names.as_deref())
+            }
+            DocumentEdit::UpdateField { field, update } => FormEditor::update_in_document(document, field, update),
+            DocumentEdit::DeleteField { field } => FormEditor::delete_from_document(document, field),
+            DocumentEdit::GenerateAppearances => FormFieldGenerator::generate_appearances_in_document(document),
+            DocumentEdit::UpdateMetadata { metadata } => PdfParser::update_metadata(document, metadata),
+            DocumentEdit::ImportFormData { values, .. } => FormFiller::fill_document(document, values),
+            DocumentEdit::MovePages { pages, to } => PageEditor::move_pages(document, pages, *to),
+            DocumentEdit::RotatePages { pages, degrees } => PageEditor::rotate_pages(document, pages, *degrees),
+            DocumentEdit::DeletePages { pages } => PageEditor::delete_pages(document, pages),
+            DocumentEdit::InsertBlankPages { at, count, width, height } => {
+                PageEditor::insert_blank_pages(document, *at, *count, *width, *height)
+            }
+            DocumentEdit::InsertPages { at, source, pages } => {
+                PageEditor::insert_pages(document, *at, &Document::load(source)?, pages.as_deref())
+            }
+        }
+    }
+
+    /// Objects the edit may change or remove
+    ///
+    /// Objects the edit creates are left out, as they get ids above the
+    /// document's current `max_id`.
+    fn footprint(&self, document: &Document) -> BTreeSet<ObjectId> {
+        match self {
+            DocumentEdit::UpdateMetadata { .. } => document
+                .trailer
+                .get(b"Info")
+                .and_then(|obj| obj.as_reference())
+                .into_iter()
+                .collect(),
+            DocumentEdit::AddFields { .. }
+            | DocumentEdit::DeleteField { .. }
+            | DocumentEdit::FillForm { .. }
+            | DocumentEdit::FlattenForm { .. }
+            | DocumentEdit::UpdateField { .. }
+            | DocumentEdit::GenerateAppearances
+            | DocumentEdit::ImportFormData { .. } => form_footprint(document),
+            DocumentEdit::RotatePages { pages, .. } => rotation_footprint(document, pages),
+            DocumentEdit::DeletePages { pages } => page_footprint(document, pages),
+            DocumentEdit::MovePages { .. }
+            | DocumentEdit::InsertBlankPages { .. }
+            | DocumentEdit::InsertPages { .. } => page_footprint(document, &[]),
+        }
+    }
+
+    /// A short human-readable summary for the history list
+    pub fn describe(&self) -> String {
+        let field_name = |field: &FieldRef| match field {
+            FieldRef::Name(name) => format!("'{}'", name),
+            FieldRef::ObjectId(id) => format!("{} {} R", id.0, id.1),
+        };
+
+        match self {
+            DocumentEdit::AddFields { fields } => format!("Add {} field(s)", fields.len()),
+            DocumentEdit::FillForm { values } => format!("Fill {} field(s)", values.len()),
+            DocumentEdit::FlattenForm { field_names: None } => "Flatten form".to_string(),
+            DocumentEdit::FlattenForm { field_names: Some(names) } => format!("Flatten {} field(s)", names.len()),
+            DocumentEdit::UpdateField { field, .. } => format!("Update field {}", field_name(field)),
+            DocumentEdit::DeleteField { field } => format!("Delete field {}", field_name(field)),
+            DocumentEdit::GenerateAppearances => "Generate appearances".to_string(),
+            DocumentEdit::UpdateMetadata { .. } => "Update metadata".to_string(),
+            DocumentEdit::ImportFormData { source, values } => {
+                format!("Import {} field(s) from {}", values.len(), source)
+            }
+            DocumentEdit::MovePages { pages, to } => format!("Move {} page(s) to page {}", pages.len(), to + 1),
+            DocumentEdit::RotatePages { pages, degrees } => format!("Rotate {} page(s) by {}°", pages.len(), degrees),
+            DocumentEdit::DeletePages { pages } => format!("Delete {} page(s)", pages.len()),
+            DocumentEdit::InsertBlankPages { count, .. } => format!("Insert {} blank page(s)", count),
+            DocumentEdit::InsertPages { source, pages, .. } => match pages {
+                Some(pages) => format!("Insert {} page(s) from {}", pages.len(), source),
+                None => format!("Insert pages from {}", source),
+            },
+        }
+    }
+}
+
+/// An entry of the history list shown to the user
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    pub index: usize,
+    pub description: String,
+    pub edit: DocumentEdit,
+    /// Seconds since the Unix epoch when the edit was made
+    pub timestamp: u64,
+    /// Whether the edit is currently applied, as opposed to undone
+    pub applied: bool,
+    /// Whether the edit can still be undone or redone in memory
+    pub reversible: bool,
+}
+
+/// Objects by id, with `None` for ids that do not exist
+type ObjectStates = Vec<(ObjectId, Option<Object>)>;
+
+/// State of the objects and trailer touched by an edit
+#[derive(Clone)]
+struct Snapshot {
+    objects: ObjectStates,
+    trailer: Dictionary,
+    max_id: u32,
+}
+
+impl Snapshot {
+    fn restore(&self, document: &mut Document) {
+        for (id, object) in &self.objects {
+            match object {
+                Some(object) => {
+                    document.objects.insert(*id, object.clone());
+                }
+                None => {
+                    document.objects.remove(id);
+                }
+            }
+        }
+        document.trailer = self.trailer.clone();
+        document.max_id = self.max_id;
+    }
+}
+
+struct JournalEntry {
+    edit: DocumentEdit,
+    timestamp: u64,
+    /// Object states before and after the edit, dropped beyond the undo depth
+    changes: Option<(Snapshot, Snapshot)>,
+}
+
+/// Reversible record of the edits made to an open document
+///
+/// Each edit keeps the before and after state of the objects it changed, so
+/// undo and redo swap those objects back in without reparsing. Only the
+/// objects an edit may touch are copied beforehand, which leaves out the
+/// content and resources of the pages an edit keeps. The edits
+/// themselves are kept as [`DocumentEdit`] values so the journal can also be
+/// replayed against the file on disk.
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+    /// Number of entries currently applied
+    cursor: usize,
+    /// Number of entries contained in the file on disk, unknown once the
+    /// journal no longer leads back to that file
+    saved: Option<usize>,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Journal {
+            entries: Vec::new(),
+            cursor: 0,
+            saved: Some(0),
+        }
+    }
+}
+
+impl Journal {
+    /// An empty journal for a document that differs from its file on disk
+    /// in ways the journal does not record
+    pub fn detached() -> Self {
+        Journal {
+            saved: None,
+            ..Self::default()
+        }
+    }
+
+    /// Apply an edit and record it, discarding any undone edits
+    ///
+    /// A failed edit leaves the document as it was and is not recorded.
+    pub fn record(&mut self, document: &mut Document, edit: DocumentEdit) -> pdf::Result<()> {
+        let trailer = document.trailer.clone();
+        let max_id = document.max_id;
+        let footprint = edit.footprint(document);
+        let objects = footprint
+            .iter()
+            .filter_map(|id| Some((*id, document.objects.get(id)?.clone())))
+            .collect();
+
+        let result = edit.apply(document);
+
+        // Whatever the edit may have changed, plus the objects it created
+        let ids: BTreeSet<ObjectId> = footprint
+            .into_iter()
+            .chain(document.objects.range((max_id + 1, 0)..).map(|(id, _)| *id))
+            .collect();
+        let (before, after) = diff_objects(objects, &document.objects, ids);
+
+        if let Err(err) = result {
+            Snapshot { objects: before, trailer, max_id }.restore(document);
+            return Err(err);
+        }
+
+        let changes = (
+            Snapshot { objects: before, trailer, max_id },
+            Snapshot {
+                objects: after,
+                trailer: document.trailer.clone(),
+                max_id: document.max_id,
+            },
+        );
+
+        self.entries.truncate(self.cursor);
+        if self.saved.is_some_and(|saved| saved > self.cursor) {
+            // The file on disk holds edits that no longer exist in the journal
+            self.saved = None;
+        }
+        self.entries.push(JournalEntry {
+            edit,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0),
+            changes: Some(changes),
+        });
+        self.cursor = self.entries.len();
+
+        // Only the most recent edits stay reversible
+        let irreversible = self.entries.len().saturating_sub(MAX_UNDO_DEPTH);
+        for entry in &mut self.entries[..irreversible] {
+            entry.changes = None;
+        }
+
+        Ok(())
+    }
+
+    /// Revert the most recent applied edit, returning whether there was one
+    pub fn undo(&mut self, document: &mut Document) -> pdf::Result<bool> {
+        if self.cursor == 0 {
+            return Ok(false);
+        }
+        let entry = &self.entries[self.cursor - 1];
+        let (before, _) = entry.changes.as_ref().ok_or_else(|| {
+            PdfError::UnsupportedOperation(format!(
+                "'{}' is too far back in the history to undo",
+                entry.edit.describe()
+            ))
+        })?;
+
+        before.restore(document);
+        self.cursor -= 1;
+        Ok(true)
+    }
+
+    /// Reapply the most recently undone edit, returning whether there was one
+    pub fn redo(&mut self, document: &mut Document) -> pdf::Result<bool> {
+        let Some(entry) = self.entries.get(self.cursor) else {
+            return Ok(false);
+        };
+        let (_, after) = entry.changes.as_ref().ok_or_else(|| {
+            PdfError::UnsupportedOperation(format!(
+                "'{}' is too far back in the history to redo",
+                entry.edit.describe()
+            ))
+        })?;
+
+        after.restore(document);
+        self.cursor += 1;
+        Ok(true)
+    }
+
+    /// Every recorded edit, oldest first
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| HistoryEntry {
+                index,
+                description: entry.edit.describe(),
+                edit: entry.edit.clone(),
+                timestamp: entry.timestamp,
+                applied: index < self.cursor,
+                reversible: entry.changes.is_some(),
+            })
+            .collect()
+    }
+
+    /// Whether there are applied edits that are not in the file on disk
+    pub fn is_dirty(&self) -> bool {
+        self.saved != Some(self.cursor)
+    }
+
+    /// Note that the document as it is now has been written to disk
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.cursor);
+    }
+
+    /// The applied edits that are missing from the file on disk
+    ///
+    /// Fails when edits contained in that file have since been undone, as
+    /// those cannot be taken back out of it by replaying, or when the
+    /// journal never led back to that file.
+    pub fn pending_edits(&self) -> pdf::Result<Vec<DocumentEdit>> {
+        let saved = self.saved.filter(|saved| *saved <= self.cursor).ok_or_else(|| {
+            PdfError::UnsupportedOperation(
+                "The file on disk no longer matches the edit history; save the document instead of replaying"
+                    .to_string(),
+            )
+        })?;
+        Ok(self.entries[saved..self.cursor]
+            .iter()
+            .map(|entry| entry.edit.clone())
+            .collect())
+    }
+
+    /// Estimate the memory held by the recorded object states
+    pub fn size(&self) -> usize {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.changes.as_ref())
+            .flat_map(|(before, after)| before.objects.iter().chain(&after.objects))
+            .filter_map(|(_, object)| object.as_ref())
+            .map(super::object_size)
+            .sum()
+    }
+}
+
+/// Split the given objects that differ between two states into their old and new versions
+fn diff_objects(
+    mut before: BTreeMap<ObjectId, Object>,
+    after: &BTreeMap<ObjectId, Object>,
+    ids: BTreeSet<ObjectId>,
+) -> (ObjectStates, ObjectStates) {
+    let mut old = Vec::new();
+    let mut new = Vec::new();
+
+    for id in ids {
+        let changed = match (before.get(&id), after.get(&id)) {
+            (Some(a), Some(b)) => !same_object(a, b),
+            _ => true,
+        };
+        if changed {
+            old.push((id, before.remove(&id)));
+            new.push((id, after.get(&id).cloned()));
+        }
+    }
+
+    (old, new)
+}
+
+/// Objects a form edit may change or remove
+///
+/// That is the catalog, the AcroForm and everything it refers to short of
+/// the pages, and each page with the `/Annots`, `/Resources` and
+/// `/XObject` objects that widgets are added to or flattened into.
+fn form_footprint(document: &Document) -> BTreeSet<ObjectId> {
+    let mut ids = BTreeSet::new();
+    let page_ids: BTreeSet<ObjectId> = document.get_pages().into_values().collect();
+
+    if let Ok(root_id) = document.trailer.get(b"Root").and_then(|obj| obj.as_reference()) {
+        ids.insert(root_id);
+    }
+
+    let mut pending = Vec::new();
+    if let Ok(acro_form) = document.catalog().and_then(|catalog| catalog.get(b"AcroForm")) {
+        collect_references(acro_form, &mut pending);
+    }
+    collect_short_of_pages(document, pending, &page_ids, &mut ids);
+
+    for page_id in page_ids {
+        ids.insert(page_id);
+        let Ok(page) = document.get_dictionary(page_id) else {
+            continue;
+        };
+        if let Ok(annots_id) = page.get(b"Annots").and_then(|obj| obj.as_reference()) {
+            ids.insert(annots_id);
+        }
+
+        // Resources may be inherited from the page tree
+        let mut node = Some(page);
+        let resources = loop {
+            let Some(current) = node else {
+                break None;
+            };
+            if let Ok(resources) = current.get(b"Resources") {
+                break Some(resources);
+            }
+            node = current
+                .get(b"Parent")
+                .and_then(|obj| obj.as_reference())
+                .and_then(|id| document.get_dictionary(id))
+                .ok();
+        };
+        if let Some(resources) = resources {
+            if let Ok(resources_id) = resources.as_reference() {
+                ids.insert(resources_id);
+            }
+            let xobjects = document
+                .dereference(resources)
+                .and_then(|(_, obj)| obj.as_dict())
+                .and_then(|dict| dict.get(b"XObject"))
+                .and_then(|obj| obj.as_reference());
+            if let Ok(xobjects_id) = xobjects {
+                ids.insert(xobjects_id);
+            }
+        }
+    }
+
+    ids
+}
+
+/// Objects rotating pages may change: the pages, by zero-based index, and
+/// the page tree nodes they inherit attributes from
+fn rotation_footprint(document: &Document, pages: &[u32]) -> BTreeSet<ObjectId> {
+    let page_ids = document.get_pages();
+    let mut ids = BTreeSet::new();
+
+    for page in pages {
+        let mut next = page_ids.get(&(page + 1)).copied();
+        while let Some(id) = next {
+            if !ids.insert(id) {
+                break;
+            }
+            next = document
+                .get_dictionary(id)
+                .and_then(|node| node.get(b"Parent"))
+                .and_then(|obj| obj.as_reference())
+                .ok();
+        }
+    }
+    ids
+}
+
+/// Objects a page edit may change or remove, deleting the pages given by
+/// zero-based index
+///
+/// That is the catalog and everything it refers to short of the pages kept,
+/// which takes in the page tree, the AcroForm, the outline, the name trees
+/// and the deleted pages with all of their objects, then each kept page with
+/// its annotations. Objects nothing refers to are included as well, as the
+/// edit prunes them.
+fn page_footprint(document: &Document, deleted: &[u32]) -> BTreeSet<ObjectId> {
+    let pages = document.get_pages();
+    let deleted: BTreeSet<ObjectId> = deleted.iter().filter_map(|page| pages.get(&(page + 1)).copied()).collect();
+    let kept: BTreeSet<ObjectId> = pages.into_values().filter(|page_id| !deleted.contains(page_id)).collect();
+    let mut ids = BTreeSet::new();
+
+    let root = document.trailer.get(b"Root").and_then(|obj| obj.as_reference());
+    collect_short_of_pages(document, root.into_iter().collect(), &kept, &mut ids);
+    for page_id in &kept {
+        let mut pending = Vec::new();
+        if let Ok(annots) = document.get_dictionary(*page_id).and_then(|page| page.get(b"Annots")) {
+            collect_references(annots, &mut pending);
+        }
+        collect_short_of_pages(document, pending, &kept, &mut ids);
+    }
+
+    let reachable = reachable_objects(document);
+    ids.extend(document.objects.keys().filter(|id| !reachable.contains(id)));
+    ids
+}
+
+/// Add the objects reachable from `pending` to `ids`, taking in but not
+/// looking into the given pages
+fn collect_short_of_pages(
+    document: &Document,
+    mut pending: Vec<ObjectId>,
+    page_ids: &BTreeSet<ObjectId>,
+    ids: &mut BTreeSet<ObjectId>,
+) {
+    while let Some(id) = pending.pop() {
+        if !ids.insert(id) || page_ids.contains(&id) {
+            continue;
+        }
+        match document.objects.get(&id) {
+            Some(Object::Dictionary(dict)) => collect_dictionary_references(dict, &mut pending),
+            Some(Object::Stream(stream)) => collect_dictionary_references(&stream.dict, &mut pending),
+            Some(object) => collect_references(object, &mut pending),
+            None => {}
+        }
+    }
+}
+
+/// References held by a dictionary, except a widget's `/P` back to its page
+fn collect_dictionary_references(dict: &Dictionary, references: &mut Vec<ObjectId>) {
+    for (key, value) in dict.iter() {
+        if key != b"P" {
+            collect_references(value, references);
+        }
+    }
+}
+
+/// Structural equality, which lopdf objects do not implement themselves
+fn same_object(a: &Object, b: &Object) -> bool {
+    match (a, b) {
+        (Object::Null, Object::Null) => true,
+        (Object::Boolean(a), Object::Boolean(b)) => a == b,
+        (Object::Integer(a), Object::Integer(b)) => a == b,
+        (Object::Real(a), Object::Real(b)) => a.to_bits() == b.to_bits(),
+        (Object::Name(a), Object::Name(b)) => a == b,
+        (Object::String(a, _), Object::String(b, _)) => a == b,
+        (Object::Array(a), Object::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_object(a, b))
+        }
+        (Object::Dictionary(a), Object::Dictionary(b)) => same_dictionary(a, b),
+        (Object::Stream(a), Object::Stream(b)) => same_dictionary(&a.dict, &b.dict) && a.content == b.content,
+        (Object::Reference(a), Object::Reference(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn same_dictionary(a: &Dictionary, b: &Dictionary) -> bool {
+    a.len() == b.len()
+        && a.iter().all(|(key, value)| b.get(key).is_ok_and(|other| same_object(value, other)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::form::FieldKind;
+    use crate::pdf::testing::{blank_document, field};
+    use lopdf::{dictionary, Stream};
+
+    /// A two-page document whose pages share an indirect `/Resources` and
+    /// carry some content
+    fn document() -> Document {
+        let mut document = blank_document(2);
+        let resources_id = document.add_object(dictionary! {});
+        for page_id in document.get_pages().into_values() {
+            let content_id = document.add_object(Stream::new(Dictionary::new(), b"0 0 m 10 10 l S".to_vec()));
+            let page = document.get_dictionary_mut(page_id).unwrap();
+            page.set("Resources", resources_id);
+            page.set("Contents", content_id);
+        }
+        document
+    }
+
+    fn same_objects(a: &Document, b: &Document) -> bool {
+        a.objects.len() == b.objects.len()
+            && a.objects
+                .iter()
+                .all(|(id, object)| b.objects.get(id).is_some_and(|other| same_object(object, other)))
+    }
+
+    #[test]
+    fn undoing_form_edits_restores_every_object() {
+        let mut document = document();
+        let mut journal = Journal::default();
+        let mut states = vec![document.clone()];
+
+        let edits = [
+            DocumentEdit::AddFields {
+                fields: vec![field("name", FieldKind::Text, 0, &[]), field("agree", FieldKind::Checkbox, 1, &[])],
+            },
+            DocumentEdit::FillForm {
+                values: HashMap::from([
+                    ("name".to_string(), PdfFieldValue::Single("Ada".to_string())),
+                    ("agree".to_string(), PdfFieldValue::Single("true".to_string())),
+                ]),
+            },
+            DocumentEdit::UpdateField {
+                field: FieldRef::Name("name".to_string()),
+                update: FieldUpdate { page: Some(1), ..FieldUpdate::default() },
+            },
+            DocumentEdit::DeleteField { field: FieldRef::Name("agree".to_string()) },
+            DocumentEdit::FlattenForm { field_names: None },
+        ];
+        for edit in edits {
+            journal.record(&mut document, edit).unwrap();
+            states.push(document.clone());
+        }
+
+        for state in states.iter().rev().skip(1) {
+            assert!(journal.undo(&mut document).unwrap());
+            assert!(same_objects(&document, state));
+        }
+        for state in states.iter().skip(1) {
+            assert!(journal.redo(&mut document).unwrap());
+            assert!(same_objects(&document, state));
+        }
+    }
+
+    #[test]
+    fn form_edits_leave_page_content_out_of_the_snapshot() {
+        let mut document = document();
+        let content_ids: Vec<ObjectId> = document
+            .get_pages()
+            .into_values()
+            .flat_map(|page_id| document.get_page_contents(page_id))
+            .collect();
+
+        let mut journal = Journal::default();
+        journal
+            .record(&mut document, DocumentEdit::AddFields { fields: vec![field("name", FieldKind::Text, 0, &[])] })
+            .unwrap();
+
+        let (before, after) = journal.entries[0].changes.as_ref().unwrap();
+        assert!(before
+            .objects
+            .iter()
+            .chain(&after.objects)
+            .all(|(id, _)| !content_ids.contains(id)));
+    }
+
+    #[test]
+    fn failed_edits_leave_the_document_as_it_was() {
+        let mut document = document();
+        let original = document.clone();
+        let mut journal = Journal::default();
+
+        let edit = DocumentEdit::AddFields { fields: vec![field("name", FieldKind::Text, 5, &[])] };
+        assert!(journal.record(&mut document, edit).is_err());
+        assert!(same_objects(&document, &original));
+        assert_eq!(document.max_id, original.max_id);
+        assert!(journal.history().is_empty());
+    }
+
+    #[test]
+    fn undoing_page_edits_restores_every_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.pdf");
+        let mut copied = document();
+        FormFieldGenerator::add_fields_to_document(&mut copied, vec![field("name", FieldKind::Text, 0, &[])]).unwrap();
+        copied.save(&source).unwrap();
+
+        let mut document = document();
+        let fields = vec![field("name", FieldKind::Text, 0, &[]), field("agree", FieldKind::Checkbox, 1, &[])];
+        FormFieldGenerator::add_fields_to_document(&mut document, fields).unwrap();
+        let mut journal = Journal::default();
+        let mut states = vec![document.clone()];
+
+        let edits = [
+            DocumentEdit::InsertBlankPages { at: 1, count: 2, width: 200.0, height: 300.0 },
+            DocumentEdit::InsertPages { at: 0, source: source.to_string_lossy().to_string(), pages: None },
+            DocumentEdit::MovePages { pages: vec![4, 0], to: 1 },
+            DocumentEdit::RotatePages { pages: vec![0, 2], degrees: -90 },
+            DocumentEdit::DeletePages { pages: vec![1, 3, 5] },
+        ];
+        for edit in edits {
+            journal.record(&mut document, edit).unwrap();
+            states.push(document.clone());
+        }
+
+        for state in states.iter().rev().skip(1) {
+            assert!(journal.undo(&mut document).unwrap());
+            assert!(same_objects(&document, state));
+        }
+        for state in states.iter().skip(1) {
+            assert!(journal.redo(&mut document).unwrap());
+            assert!(same_objects(&document, state));
+        }
+    }
+
+    #[test]
+    fn page_edits_leave_the_content_of_kept_pages_out_of_the_snapshot() {
+        let mut document = document();
+        let pages: Vec<ObjectId> = document.get_pages().into_values().collect();
+        let content_of = |document: &Document, page_id| document.get_page_contents(page_id)[0];
+        let kept_content = content_of(&document, pages[0]);
+        let deleted_content = content_of(&document, pages[1]);
+        let pages_id = document.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+
+        assert_eq!(
+            DocumentEdit::RotatePages { pages: vec![1], degrees: 90 }.footprint(&document),
+            BTreeSet::from([pages[1], pages_id])
+        );
+
+        let footprint = DocumentEdit::DeletePages { pages: vec![1] }.footprint(&document);
+        assert!(footprint.contains(&deleted_content));
+        assert!(!footprint.contains(&kept_content));
+
+        let mut journal = Journal::default();
+        journal.record(&mut document, DocumentEdit::MovePages { pages: vec![1], to: 0 }).unwrap();
+        let (before, after) = journal.entries[0].changes.as_ref().unwrap();
+        assert!(before
+            .objects
+            .iter()
+            .chain(&after.objects)
+            .all(|(id, _)| *id != kept_content && *id != deleted_content));
+    }
+}
@@ -0,0 +1,446 @@
+use lopdf::{Document, Object};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+use thiserror::Error;
+use crate::fs::FsError;
+use crate::pdf::{self, PdfDocument, PdfError, PdfParser};
+
+mod diff;
+mod journal;
+mod recovery;
+pub use diff::{DocumentDiff, FieldDifference};
+pub use journal::{DocumentEdit, HistoryEntry, Journal, MAX_UNDO_DEPTH};
+pub use recovery::{RecoverableSession, RecoveryStore, AUTOSAVE_INTERVAL};
+
+/// Memory the open documents may use before idle ones are evicted
+pub const DEFAULT_MEMORY_LIMIT: usize = 512 * 1024 * 1024;
+
+/// Rough in-memory cost of an object beyond its stream content
+const OBJECT_OVERHEAD: usize = 128;
+
+/// Error types for document sessions
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("No open document with session id {0}; it may have been closed or evicted")]
+    NotFound(String),
+
+    #[error("No recoverable session with id {0}")]
+    RecoveryNotFound(String),
+
+    #[error("Failed to keep a working copy: {0}")]
+    Recovery(String),
+
+    #[error(transparent)]
+    Pdf(#[from] PdfError),
+
+    #[error(transparent)]
+    Fs(#[from] FsError),
+}
+
+impl From<lopdf::Error> for SessionError {
+    fn from(err: lopdf::Error) -> Self {
+        SessionError::Pdf(err.into())
+    }
+}
+
+/// Result type for session operations
+pub type Result<T> = std::result::Result<T, SessionError>;
+
+/// A document opened through `open_document`, as returned to the frontend
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentSessionInfo {
+    pub session_id: String,
+    pub document: PdfDocument,
+}
+
+/// A loaded document along with its parsed structure and edit journal
+pub struct DocumentSession {
+    pub path: PathBuf,
+    pub document: Document,
+    /// Structure of the document as of the last edit
+    pub info: PdfDocument,
+    pub journal: Journal,
+    size: usize,
+    last_used: Instant,
+    /// Counts the changes to the document, to tell whether the working copy is current
+    revision: u64,
+    autosaved_revision: u64,
+}
+
+impl DocumentSession {
+    fn new(path: PathBuf, document: Document) -> Result<Self> {
+        let info = PdfParser::parse_document(path.to_string_lossy().to_string(), &document)?;
+        let size = estimate_size(&document);
+
+        Ok(DocumentSession {
+            path,
+            document,
+            info,
+            journal: Journal::default(),
+            size,
+            last_used: Instant::now(),
+            revision: 0,
+            autosaved_revision: 0,
+        })
+    }
+
+    /// Whether there are edits that have not been saved
+    pub fn is_modified(&self) -> bool {
+        self.journal.is_dirty()
+    }
+
+    /// Re-read the document structure after an edit
+    fn refresh(&mut self) -> Result<()> {
+        self.info = PdfParser::parse_document(self.path.to_string_lossy().to_string(), &self.document)?;
+        self.size = estimate_size(&self.document) + self.journal.size();
+        Ok(())
+    }
+}
+
+/// A document to autosave with its path and the descriptions of its unsaved edits
+type WorkingCopy = (PathBuf, Document, Vec<String>);
+
+struct SessionCache {
+    sessions: HashMap<String, DocumentSession>,
+    memory_limit: usize,
+    next_id: u64,
+    /// Sessions evicted since [`SessionStore::take_evicted`] was last called
+    evicted: Vec<String>,
+}
+
+/// Open documents shared between commands, managed as Tauri state
+///
+/// Documents are parsed once by `open` and then queried and edited in memory
+/// until they are closed. When the estimated size of all open documents
+/// exceeds the memory limit, the least recently used sessions without unsaved
+/// edits are evicted.
+pub struct SessionStore {
+    cache: Mutex<SessionCache>,
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEMORY_LIMIT)
+    }
+}
+
+impl SessionStore {
+    /// Create an empty store with the given memory limit in bytes
+    pub fn new(memory_limit: usize) -> Self {
+        SessionStore {
+            cache: Mutex::new(SessionCache {
+                sessions: HashMap::new(),
+                memory_limit,
+                next_id: 1,
+                evicted: Vec::new(),
+            }),
+        }
+    }
+
+    /// Load a document and start a session for it
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<DocumentSessionInfo> {
+        let document = Document::load(path.as_ref())?;
+        let session = DocumentSession::new(path.as_ref().to_path_buf(), document)?;
+
+        let mut cache = self.lock();
+        let session_id = Self::next_session_id(&mut cache);
+        Ok(Self::insert(&mut cache, session_id, session))
+    }
+
+    /// Reopen a session left behind by an earlier run from its working copy
+    ///
+    /// The session edits the document it was editing before, and counts as
+    /// modified until it is saved. Its earlier edits cannot be undone.
+    pub fn recover(&self, recovery: &RecoveryStore, id: &str) -> Result<DocumentSessionInfo> {
+        // Check the working copy loads before taking it over, so a broken
+        // one stays where the next run can still find it
+        let (record, document) = recovery.load(id)?;
+        let mut session = DocumentSession::new(PathBuf::from(record.path), document)?;
+        session.journal = Journal::detached();
+
+        let session_id = Self::next_session_id(&mut self.lock());
+        recovery.adopt(id, &session_id)?;
+        Ok(Self::insert(&mut self.lock(), session_id, session))
+    }
+
+    /// Write working copies of the documents changed since the last autosave
+    ///
+    /// Documents whose edits have all been saved or undone lose their working
+    /// copy instead. Failures are logged rather than returned, as autosaving
+    /// runs in the background.
+    pub fn autosave(&self, recovery: &RecoveryStore) {
+        let changed: Vec<(String, u64, Option<WorkingCopy>)> = {
+            let cache = self.lock();
+            cache
+                .sessions
+                .iter()
+                .filter(|(_, session)| session.revision != session.autosaved_revision)
+                .map(|(id, session)| {
+                    // Copy the document so it is written without holding the lock
+                    let copy = session.is_modified().then(|| {
+                        let edits = session
+                            .journal
+                            .history()
+                            .into_iter()
+                            .filter(|entry| entry.applied)
+                            .map(|entry| entry.description)
+                            .collect();
+                        (session.path.clone(), session.document.clone(), edits)
+                    });
+                    (id.clone(), session.revision, copy)
+                })
+                .collect()
+        };
+
+        for (session_id, revision, copy) in changed {
+            match copy {
+                Some((path, mut document, edits)) => {
+                    if let Err(err) = recovery.write(&session_id, &path, &mut document, edits) {
+                        log::warn!("Failed to autosave {}: {}", path.display(), err);
+                        continue;
+                    }
+                }
+                None => recovery.remove(&session_id),
+            }
+
+            match self.lock().sessions.get_mut(&session_id) {
+                Some(session) if session.revision == revision => session.autosaved_revision = revision,
+                Some(_) => {}
+                // Closed while being written, so its edits were meant to be discarded
+                None => recovery.remove(&session_id),
+            }
+        }
+    }
+
+    /// End a session, discarding any unsaved edits
+    pub fn close(&self, session_id: &str) -> Result<()> {
+        self.lock()
+            .sessions
+            .remove(session_id)
+            .map(|_| ())
+            .ok_or_else(|| SessionError::NotFound(session_id.to_string()))
+    }
+
+    /// Run a query against an open document
+    pub fn with_session<T>(
+        &self,
+        session_id: &str,
+        query: impl FnOnce(&DocumentSession) -> crate::pdf::Result<T>,
+    ) -> Result<T> {
+        let mut cache = self.lock();
+        let session = Self::touch(&mut cache, session_id)?;
+        Ok(query(session)?)
+    }
+
+    /// Apply an edit to an open document, recording it in the journal
+    ///
+    /// Returns the refreshed document structure.
+    pub fn apply(&self, session_id: &str, edit: DocumentEdit) -> Result<PdfDocument> {
+        self.update(session_id, |session| session.journal.record(&mut session.document, edit).map(|_| true))
+    }
+
+    /// Revert the most recent edit of an open document
+    pub fn undo(&self, session_id: &str) -> Result<PdfDocument> {
+        self.update(session_id, |session| session.journal.undo(&mut session.document))
+    }
+
+    /// Reapply the most recently undone edit of an open document
+    pub fn redo(&self, session_id: &str) -> Result<PdfDocument> {
+        self.update(session_id, |session| session.journal.redo(&mut session.document))
+    }
+
+    /// List the edits recorded for an open document, oldest first
+    pub fn history(&self, session_id: &str) -> Result<Vec<HistoryEntry>> {
+        self.with_session(session_id, |session| Ok(session.journal.history()))
+    }
+
+    /// Replay the unsaved edits of an open document against its file on disk
+    /// and save the result, leaving the session itself untouched
+    pub fn replay(&self, session_id: &str, output_path: &Path) -> Result<()> {
+        let (path, edits) = self.with_session(session_id, |session| {
+            Ok((session.path.clone(), session.journal.pending_edits()?))
+        })?;
+
+        let mut document = Document::load(&path)?;
+        for edit in &edits {
+            edit.apply(&mut document)?;
+        }
+        pdf::write_document(&mut document, output_path)?;
+        Ok(())
+    }
+
+    /// Compare an open document with its file as it is on disk now
+    ///
+    /// The comparison, which replays the unsaved edits, works on copies so
+    /// other sessions are not held up meanwhile.
+    pub fn diff_with_disk(&self, session_id: &str) -> Result<DocumentDiff> {
+        let (path, ours, pending) = self.with_session(session_id, |session| {
+            // Edits made before the file was last saved cannot be told apart
+            // from changes on disk, so those sessions have nothing to replay
+            let pending = session.journal.pending_edits().ok();
+            Ok((session.path.clone(), session.info.clone(), pending))
+        })?;
+        let theirs = Document::load(&path)?;
+        Ok(DocumentDiff::between(&ours, pending.as_deref(), &theirs)?)
+    }
+
+    /// Change an open document, refreshing its structure if anything changed
+    fn update(
+        &self,
+        session_id: &str,
+        change: impl FnOnce(&mut DocumentSession) -> crate::pdf::Result<bool>,
+    ) -> Result<PdfDocument> {
+        let mut cache = self.lock();
+        let session = Self::touch(&mut cache, session_id)?;
+        if change(session)? {
+            session.revision += 1;
+            session.refresh()?;
+        }
+        let info = session.info.clone();
+
+        Self::evict(&mut cache, session_id);
+        Ok(info)
+    }
+
+    /// Save an open document, to its own path unless another one is given
+    ///
+    /// Saving to another path makes that the session's path from then on.
+    /// The document is written from a copy so other sessions are not held
+    /// up, and edits made while it is written keep it modified.
+    pub fn save(&self, session_id: &str, output_path: Option<PathBuf>) -> Result<()> {
+        let (path, revision, mut document) = {
+            let mut cache = self.lock();
+            let session = Self::touch(&mut cache, session_id)?;
+            let path = output_path.unwrap_or_else(|| session.path.clone());
+            (path, session.revision, session.document.clone())
+        };
+
+        pdf::write_document(&mut document, &path)?;
+
+        let mut cache = self.lock();
+        // Closed while being written, so there is nothing left to update
+        let Some(session) = cache.sessions.get_mut(session_id) else {
+            return Ok(());
+        };
+        session.info.path = path.to_string_lossy().to_string();
+        session.path = path;
+        if session.revision == revision {
+            session.journal.mark_saved();
+            session.revision += 1;
+        }
+        Ok(())
+    }
+
+    /// Change the memory limit, evicting sessions if the new limit is exceeded
+    pub fn set_memory_limit(&self, memory_limit: usize) {
+        let mut cache = self.lock();
+        cache.memory_limit = memory_limit;
+        Self::evict(&mut cache, "");
+    }
+
+    /// The sessions evicted since this was last called, so whatever is kept
+    /// for them outside the store can go too
+    pub fn take_evicted(&self) -> Vec<String> {
+        std::mem::take(&mut self.lock().evicted)
+    }
+
+    fn next_session_id(cache: &mut SessionCache) -> String {
+        let session_id = format!("doc-{}", cache.next_id);
+        cache.next_id += 1;
+        session_id
+    }
+
+    fn insert(cache: &mut SessionCache, session_id: String, session: DocumentSession) -> DocumentSessionInfo {
+        let info = session.info.clone();
+        cache.sessions.insert(session_id.clone(), session);
+        Self::evict(cache, &session_id);
+
+        DocumentSessionInfo {
+            session_id,
+            document: info,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SessionCache> {
+        // A panic mid-edit leaves at worst one half-edited document behind
+        self.cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn touch<'a>(cache: &'a mut SessionCache, session_id: &str) -> Result<&'a mut DocumentSession> {
+        let session = cache
+            .sessions
+            .get_mut(session_id)
+            .ok_or_else(|| SessionError::NotFound(session_id.to_string()))?;
+        session.last_used = Instant::now();
+        Ok(session)
+    }
+
+    /// Drop least recently used sessions until the open documents fit the limit
+    ///
+    /// The session in use and sessions with unsaved edits are never evicted,
+    /// so the limit may still be exceeded afterwards.
+    fn evict(cache: &mut SessionCache, keep: &str) {
+        let mut total: usize = cache.sessions.values().map(|session| session.size).sum();
+        if total <= cache.memory_limit {
+            return;
+        }
+
+        let mut candidates: Vec<(Instant, String, usize)> = cache
+            .sessions
+            .iter()
+            .filter(|(id, session)| id.as_str() != keep && !session.is_modified())
+            .map(|(id, session)| (session.last_used, id.clone(), session.size))
+            .collect();
+        candidates.sort();
+
+        for (_, id, size) in candidates {
+            if total <= cache.memory_limit {
+                break;
+            }
+            log::info!("Evicting document session {} to free {} bytes", id, size);
+            cache.sessions.remove(&id);
+            cache.evicted.push(id);
+            total -= size;
+        }
+    }
+}
+
+/// Estimate the memory held by a loaded document
+fn estimate_size(document: &Document) -> usize {
+    document.objects.values().map(object_size).sum()
+}
+
+/// Estimate the memory held by a single object
+fn object_size(object: &Object) -> usize {
+    match object {
+        Object::Stream(stream) => stream.content.len() + OBJECT_OVERHEAD,
+        _ => OBJECT_OVERHEAD,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::testing::blank_document;
+
+    #[test]
+    fn evicted_sessions_are_reported_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blank.pdf");
+        blank_document(1).save(&path).unwrap();
+
+        let store = SessionStore::new(usize::MAX);
+        let first = store.open(&path).unwrap().session_id;
+        let second = store.open(&path).unwrap().session_id;
+        assert!(store.take_evicted().is_empty());
+
+        store.with_session(&second, |_| Ok(())).unwrap();
+        store.set_memory_limit(1);
+        assert_eq!(store.take_evicted(), [first.clone(), second]);
+        assert!(store.take_evicted().is_empty());
+        assert!(matches!(store.close(&first), Err(SessionError::NotFound(_))));
+    }
+}
@@ -0,0 +1,242 @@
+use lopdf::Document;
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::fs::FileSystem;
+use crate::pdf;
+use super::{Result, SessionError};
+
+/// How often documents with unsaved edits are written to the recovery directory
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Working copy of the document within a session's recovery directory
+const WORKING_COPY: &str = "document.pdf";
+/// Description of the session within its recovery directory
+const SESSION_RECORD: &str = "session.json";
+
+/// A session with unsaved edits left behind by an earlier run of the app
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecoverableSession {
+    /// Identifies the session among those in the recovery directory
+    #[serde(default)]
+    pub id: String,
+    /// The document the session was editing
+    pub path: String,
+    pub name: String,
+    /// Seconds since the Unix epoch when the working copy was written
+    pub saved_at: u64,
+    /// Descriptions of the edits made since the document was last saved, oldest first
+    pub edits: Vec<String>,
+}
+
+/// Working copies of documents with unsaved edits, kept so the edits
+/// survive the app being killed
+///
+/// Each session gets a directory holding the document as edited and a
+/// record of where it came from. Directories are named after the run of the
+/// app that wrote them, so a run only offers to recover the ones it finds
+/// from earlier runs.
+pub struct RecoveryStore {
+    root: PathBuf,
+    run_id: String,
+}
+
+impl RecoveryStore {
+    /// Create a store keeping working copies under `root`
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        let run_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+
+        RecoveryStore {
+            root: root.into(),
+            run_id: format!("{}-{}", run_id, std::process::id()),
+        }
+    }
+
+    /// Write the working copy of an open document
+    pub fn write(&self, session_id: &str, path: &Path, document: &mut Document, edits: Vec<String>) -> Result<()> {
+        let dir = self.session_dir(session_id);
+        fs::create_dir_all(&dir).map_err(|e| SessionError::Recovery(format!("{}: {}", dir.display(), e)))?;
+
+        let record = RecoverableSession {
+            id: String::new(),
+            path: path.to_string_lossy().to_string(),
+            name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.to_string_lossy().to_string()),
+            saved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            edits,
+        };
+        let record = serde_json::to_vec_pretty(&record).map_err(|e| SessionError::Recovery(e.to_string()))?;
+
+        // The record goes last, so a directory without one holds no complete copy
+        pdf::write_document(document, dir.join(WORKING_COPY))?;
+        FileSystem::write_file(dir.join(SESSION_RECORD), &record)?;
+        Ok(())
+    }
+
+    /// Forget the working copy of an open document, once its edits are saved or discarded
+    pub fn remove(&self, session_id: &str) {
+        let dir = self.session_dir(session_id);
+        if dir.exists() {
+            if let Err(err) = fs::remove_dir_all(&dir) {
+                log::warn!("Failed to remove working copy {}: {}", dir.display(), err);
+            }
+        }
+    }
+
+    /// List the sessions left behind by earlier runs, most recently written first
+    pub fn list(&self) -> Vec<RecoverableSession> {
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return Vec::new();
+        };
+
+        let mut sessions: Vec<RecoverableSession> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let id = entry.file_name().to_string_lossy().to_string();
+                if id.starts_with(&self.run_prefix()) {
+                    return None;
+                }
+                self.read_record(&id).ok()
+            })
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.saved_at));
+        sessions
+    }
+
+    /// Read the record and working copy of a session left behind by an earlier run
+    pub fn load(&self, id: &str) -> Result<(RecoverableSession, Document)> {
+        let record = self.read_record(id)?;
+        let document = Document::load(self.root.join(id).join(WORKING_COPY))?;
+        Ok((record, document))
+    }
+
+    /// Take over a session left behind by an earlier run as an open session
+    /// of this run, so its working copy is kept up to date from now on
+    pub fn adopt(&self, id: &str, session_id: &str) -> Result<()> {
+        self.read_record(id)?;
+        let dir = self.session_dir(session_id);
+        fs::rename(self.root.join(id), &dir)
+            .map_err(|e| SessionError::Recovery(format!("{}: {}", dir.display(), e)))
+    }
+
+    /// Throw away a session left behind by an earlier run
+    pub fn discard(&self, id: &str) -> Result<()> {
+        self.read_record(id)?;
+        let dir = self.root.join(id);
+        fs::remove_dir_all(&dir).map_err(|e| SessionError::Recovery(format!("{}: {}", dir.display(), e)))
+    }
+
+    fn read_record(&self, id: &str) -> Result<RecoverableSession> {
+        // Ids name directories directly under the root and nothing else
+        let is_name = Path::new(id).file_name().is_some_and(|name| name == id);
+        let data = fs::read(self.root.join(id).join(SESSION_RECORD))
+            .ok()
+            .filter(|_| is_name && !id.starts_with(&self.run_prefix()))
+            .ok_or_else(|| SessionError::RecoveryNotFound(id.to_string()))?;
+
+        let record: RecoverableSession =
+            serde_json::from_slice(&data).map_err(|e| SessionError::Recovery(format!("{}: {}", id, e)))?;
+        Ok(RecoverableSession {
+            id: id.to_string(),
+            ..record
+        })
+    }
+
+    fn run_prefix(&self) -> String {
+        format!("{}-", self.run_id)
+    }
+
+    fn session_dir(&self, session_id: &str) -> PathBuf {
+        self.root.join(format!("{}{}", self.run_prefix(), session_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::testing::blank_document;
+
+    fn store(root: &Path, run_id: &str) -> RecoveryStore {
+        RecoveryStore {
+            root: root.to_path_buf(),
+            run_id: run_id.to_string(),
+        }
+    }
+
+    fn ids(store: &RecoveryStore) -> Vec<String> {
+        store.list().into_iter().map(|session| session.id).collect()
+    }
+
+    #[test]
+    fn later_runs_adopt_or_discard_working_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let earlier = store(dir.path(), "earlier");
+        let document_path = dir.path().join("form.pdf");
+        earlier.write("kept", &document_path, &mut blank_document(2), vec!["Filled 1 field".to_string()]).unwrap();
+        earlier.write("dropped", &document_path, &mut blank_document(1), Vec::new()).unwrap();
+
+        let later = store(dir.path(), "later");
+        let mut found = ids(&later);
+        found.sort();
+        assert_eq!(found, vec!["earlier-dropped", "earlier-kept"]);
+
+        let (record, document) = later.load("earlier-kept").unwrap();
+        assert_eq!(record.path, document_path.to_string_lossy());
+        assert_eq!(record.edits, vec!["Filled 1 field"]);
+        assert_eq!(document.get_pages().len(), 2);
+
+        later.adopt("earlier-kept", "session").unwrap();
+        later.discard("earlier-dropped").unwrap();
+        assert!(ids(&later).is_empty());
+        assert!(dir.path().join("later-session").join(WORKING_COPY).is_file());
+        assert!(!dir.path().join("earlier-dropped").exists());
+        assert_eq!(ids(&store(dir.path(), "latest")), vec!["later-session"]);
+    }
+
+    #[test]
+    fn the_current_run_does_not_offer_its_own_working_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let current = store(dir.path(), "current");
+        current.write("session", &dir.path().join("form.pdf"), &mut blank_document(1), Vec::new()).unwrap();
+
+        assert!(ids(&current).is_empty());
+        assert!(matches!(current.load("current-session"), Err(SessionError::RecoveryNotFound(_))));
+        assert!(matches!(current.discard("current-session"), Err(SessionError::RecoveryNotFound(_))));
+        assert!(dir.path().join("current-session").exists());
+    }
+
+    #[test]
+    fn ids_outside_the_root_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("recovery");
+        // A complete working copy next to the root, where a crafted id could point
+        store(dir.path(), "earlier").write("x", &dir.path().join("form.pdf"), &mut blank_document(1), Vec::new()).unwrap();
+        let later = store(&root, "later");
+
+        for id in ["../earlier-x", "..", ".", "", "a/b", "/tmp"] {
+            assert!(matches!(later.load(id), Err(SessionError::RecoveryNotFound(_))), "{}", id);
+            assert!(matches!(later.discard(id), Err(SessionError::RecoveryNotFound(_))), "{}", id);
+        }
+        assert!(dir.path().join("earlier-x").join(SESSION_RECORD).is_file());
+    }
+
+    #[test]
+    fn unreadable_working_copies_are_not_adopted() {
+        let dir = tempfile::tempdir().unwrap();
+        store(dir.path(), "earlier").write("session", &dir.path().join("form.pdf"), &mut blank_document(1), Vec::new()).unwrap();
+        fs::write(dir.path().join("earlier-session").join(WORKING_COPY), b"not a pdf").unwrap();
+
+        let later = store(dir.path(), "later");
+        assert!(later.load("earlier-session").is_err());
+        assert_eq!(ids(&later), vec!["earlier-session"]);
+    }
+}
@@ -9,8 +9,6 @@ interface PdfState {
   activeTool: 'select' | 'text' | 'checkbox' | 'radio';
   isLoading: boolean;
   error: string | null;
-  undoStack: PdfDocument[];
-  redoStack: PdfDocument[];
 }
 
 type PdfAction =
@@ -19,10 +17,8 @@ type PdfAction =
   | { type: 'SELECT_FIELD'; payload: FormField | null }
   | { type: 'SET_ACTIVE_TOOL'; payload: PdfState['activeTool'] }
   | { type: 'SET_LOADING'; payload: boolean }
-  | { type: 'SET_ERROR'; payload: string | null }
-  | { type: 'PUSH_UNDO'; payload: PdfDocument }
-  | { type: 'UNDO' }
-  | { type: 'REDO' };
+  | { type: 'SET_FIELDS'; payload: FormField[] }
+  | { type: 'SET_ERROR'; payload: string | null };
 
 const initialState: PdfState = {
   currentDocument: null,
@@ -30,9 +26,7 @@ const initialState: PdfState = {
   selectedField: null,
   activeTool: 'select',
   isLoading: false,
-  error: null,
-  undoStack: [],
-  redoStack: []
+  error: null
 };
 
 const pdfReducer = (state: PdfState, action: PdfAction): PdfState => {
@@ -47,32 +41,15 @@ const pdfReducer = (state: PdfState, action: PdfAction): PdfState => {
       return { ...state, activeTool: action.payload };
     case 'SET_LOADING':
       return { ...state, isLoading: action.payload };
-    case 'SET_ERROR':
-      return { ...state, error: action.payload };
-    case 'PUSH_UNDO':
-      return {
-        ...state,
-        undoStack: [...state.undoStack, action.payload],
-        redoStack: []
-      };
-    case 'UNDO':
-      if (state.undoStack.length === 0) return state;
-      const prevDoc = state.undoStack[state.undoStack.length - 1];
-      return {
-        ...state,
-        currentDocument: prevDoc,
-        undoStack: state.undoStack.slice(0, -1),
-        redoStack: state.currentDocument ? [...state.redoStack, state.currentDocument] : state.redoStack
-      };
-    case 'REDO':
-      if (state.redoStack.length === 0) return state;
-      const nextDoc = state.redoStack[state.redoStack.length - 1];
+    case 'SET_FIELDS':
+      if (!state.currentDocument) return state;
       return {
         ...state,
-        currentDocument: nextDoc,
-        redoStack: state.redoStack.slice(0, -1),
-        undoStack: state.currentDocument ? [...state.undoStack, state.currentDocument] : state.undoStack
+        currentDocument: { ...state.currentDocument, formFields: action.payload },
+        error: null
       };
+    case 'SET_ERROR':
+      return { ...state, error: action.payload };
     default:
       return state;
   }
@@ -81,13 +58,13 @@ const pdfReducer = (state: PdfState, action: PdfAction): PdfState => {
 interface PdfContextType extends PdfState {
   openPdf: () => Promise<void>;
   savePdf: () => Promise<void>;
-  addFormField: (field: Omit<FormField, 'name'>) => Promise<void>;
+  addFormField: (field: Omit<FormField, 'name' | 'widget_id'>) => Promise<void>;
   updateFormField: (field: FormField) => Promise<void>;
   deleteFormField: (fieldName: string) => Promise<void>;
   setZoom: (zoom: number) => void;
   setActiveTool: (tool: PdfState['activeTool']) => void;
-  undo: () => void;
-  redo: () => void;
+  undo: () => Promise<void>;
+  redo: () => Promise<void>;
 }
 
 const PdfContext = createContext<PdfContextType | null>(null);
@@ -99,19 +76,22 @@ export const PdfProvider: React.FC<{ children: React.ReactNode }> = ({ children
     try {
       dispatch({ type: 'SET_LOADING', payload: true });
       const document = await PdfService.openPdf();
+      if (state.currentDocument) {
+        await PdfService.closePdf(state.currentDocument.sessionId);
+      }
       dispatch({ type: 'SET_DOCUMENT', payload: document });
     } catch (error) {
       dispatch({ type: 'SET_ERROR', payload: error instanceof Error ? error.message : 'Failed to open PDF' });
     } finally {
       dispatch({ type: 'SET_LOADING', payload: false });
     }
-  }, []);
+  }, [state.currentDocument]);
 
   const savePdf = useCallback(async () => {
     if (!state.currentDocument) return;
     try {
       dispatch({ type: 'SET_LOADING', payload: true });
-      await PdfService.savePdf(state.currentDocument.path, state.currentDocument.formFields);
+      await PdfService.savePdf(state.currentDocument.sessionId);
     } catch (error) {
       dispatch({ type: 'SET_ERROR', payload: error instanceof Error ? error.message : 'Failed to save PDF' });
     } finally {
@@ -119,17 +99,16 @@ export const PdfProvider: React.FC<{ children: React.ReactNode }> = ({ children
     }
   }, [state.currentDocument]);
 
-  const addFormField = useCallback(async (field: Omit<FormField, 'name'>) => {
+  const addFormField = useCallback(async (field: Omit<FormField, 'name' | 'widget_id'>) => {
     if (!state.currentDocument) return;
     try {
       dispatch({ type: 'SET_LOADING', payload: true });
-      const newField = await PdfService.addFormField(state.currentDocument.path, field);
-      const updatedDoc = {
-        ...state.currentDocument,
-        formFields: [...state.currentDocument.formFields, newField]
-      };
-      dispatch({ type: 'PUSH_UNDO', payload: state.currentDocument });
-      dispatch({ type: 'SET_DOCUMENT', payload: updatedDoc });
+      const formFields = await PdfService.addFormField(
+        state.currentDocument.sessionId,
+        field,
+        state.currentDocument.formFields
+      );
+      dispatch({ type: 'SET_FIELDS', payload: formFields });
     } catch (error) {
       dispatch({ type: 'SET_ERROR', payload: error instanceof Error ? error.message : 'Failed to add form field' });
     } finally {
@@ -141,15 +120,8 @@ export const PdfProvider: React.FC<{ children: React.ReactNode }> = ({ children
     if (!state.currentDocument) return;
     try {
       dispatch({ type: 'SET_LOADING', payload: true });
-      await PdfService.updateFormField(state.currentDocument.path, field);
-      const updatedDoc = {
-        ...state.currentDocument,
-        formFields: state.currentDocument.formFields.map(f =>
-          f.name === field.name ? field : f
-        )
-      };
-      dispatch({ type: 'PUSH_UNDO', payload: state.currentDocument });
-      dispatch({ type: 'SET_DOCUMENT', payload: updatedDoc });
+      const formFields = await PdfService.updateFormField(state.currentDocument.sessionId, field);
+      dispatch({ type: 'SET_FIELDS', payload: formFields });
     } catch (error) {
       dispatch({ type: 'SET_ERROR', payload: error instanceof Error ? error.message : 'Failed to update form field' });
     } finally {
@@ -161,13 +133,8 @@ export const PdfProvider: React.FC<{ children: React.ReactNode }> = ({ children
     if (!state.currentDocument) return;
     try {
       dispatch({ type: 'SET_LOADING', payload: true });
-      await PdfService.deleteFormField(state.currentDocument.path, fieldName);
-      const updatedDoc = {
-        ...state.currentDocument,
-        formFields: state.currentDocument.formFields.filter(f => f.name !== fieldName)
-      };
-      dispatch({ type: 'PUSH_UNDO', payload: state.currentDocument });
-      dispatch({ type: 'SET_DOCUMENT', payload: updatedDoc });
+      const formFields = await PdfService.deleteFormField(state.currentDocument.sessionId, fieldName);
+      dispatch({ type: 'SET_FIELDS', payload: formFields });
     } catch (error) {
       dispatch({ type: 'SET_ERROR', payload: error instanceof Error ? error.message : 'Failed to delete form field' });
     } finally {
@@ -183,13 +150,26 @@ export const PdfProvider: React.FC<{ children: React.ReactNode }> = ({ children
     dispatch({ type: 'SET_ACTIVE_TOOL', payload: tool });
   }, []);
 
-  const undo = useCallback(() => {
-    dispatch({ type: 'UNDO' });
-  }, []);
+  // Undo and redo go through the backend journal of the open session
+  const undo = useCallback(async () => {
+    if (!state.currentDocument) return;
+    try {
+      const document = await PdfService.undo(state.currentDocument.sessionId);
+      dispatch({ type: 'SET_DOCUMENT', payload: document });
+    } catch (error) {
+      dispatch({ type: 'SET_ERROR', payload: error instanceof Error ? error.message : 'Nothing to undo' });
+    }
+  }, [state.currentDocument]);
 
-  const redo = useCallback(() => {
-    dispatch({ type: 'REDO' });
-  }, []);
+  const redo = useCallback(async () => {
+    if (!state.currentDocument) return;
+    try {
+      const document = await PdfService.redo(state.currentDocument.sessionId);
+      dispatch({ type: 'SET_DOCUMENT', payload: document });
+    } catch (error) {
+      dispatch({ type: 'SET_ERROR', payload: error instanceof Error ? error.message : 'Nothing to redo' });
+    }
+  }, [state.currentDocument]);
 
   return (
     <PdfContext.Provider
@@ -64,7 +64,7 @@ export class PdfFormFieldGenerator {
     const field: RustPdfFormField = {
       id: element.id,
       name: element.name || element.id,
-      field_type: '',
+      field_type: 'text',
       value: undefined,
       rect: [
         element.x,
@@ -89,12 +89,13 @@ export class PdfFormFieldGenerator {
         break;
         
       case FormElementType.Dropdown:
-        field.field_type = 'dropdown';
+        field.field_type = 'combo';
         field.value = element.properties?.selectedOption || '';
         
-        // Add options as a comma-separated string
+        // Options are sent as a list so values may contain commas
         if (element.properties?.options) {
-          field.properties.options = element.properties.options.join(',');
+          field.options = element.properties.options;
+          delete field.properties.options;
         }
         break;
         
@@ -133,7 +134,7 @@ export class PdfFormFieldGenerator {
     const typeCount: Record<string, number> = {
       text: 0,
       checkbox: 0,
-      dropdown: 0,
+      combo: 0,
       signature: 0
     };
     
@@ -152,7 +153,7 @@ export class PdfFormFieldGenerator {
           case 'checkbox':
             field.name = `Checkbox${typeCount[type]}`;
             break;
-          case 'dropdown':
+          case 'combo':
             field.name = `Dropdown${typeCount[type]}`;
             break;
           case 'signature':
@@ -1,4 +1,5 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
 
 // Types that match our Rust structs
 export interface PdfPage {
@@ -24,6 +25,52 @@ export interface PdfDocument {
   page_count: number;
   pages: PdfPage[];
   metadata: PdfMetadata;
+  form: FormFieldTree;
+}
+
+// Value of a field, or the selected options of a multi-select list box
+export type PdfFieldValue = string | string[];
+
+// A widget annotation through which a field is displayed
+export interface PdfFieldWidget {
+  object_id: [number, number];
+  page?: number;
+  rect: [number, number, number, number];
+  appearance_state?: string;
+}
+
+// A node of the form field hierarchy with inherited attributes resolved
+export interface PdfFieldNode {
+  object_id: [number, number];
+  name: string;
+  full_name: string;
+  kind?: FieldKind;
+  flags: FieldFlags;
+  value?: PdfFieldValue;
+  default_value?: PdfFieldValue;
+  default_appearance?: string;
+  quadding: number;
+  options: ChoiceOption[];
+  widgets: PdfFieldWidget[];
+  children: PdfFieldNode[];
+}
+
+export interface FormFieldTree {
+  fields: PdfFieldNode[];
+}
+
+// Identifies an existing field by fully-qualified name or by field/widget object id
+export type FieldRef = { name: string } | { object_id: [number, number] };
+
+// Changes to apply to an existing field; omitted entries are left alone
+export interface FieldUpdate {
+  name?: string;
+  rect?: [number, number, number, number];
+  page?: number;
+  flags?: FieldFlags;
+  default_value?: string;
+  default_appearance?: string;
+  quadding?: number;
 }
 
 export interface FileInfo {
@@ -31,6 +78,129 @@ export interface FileInfo {
   name: string;
   size: number;
   last_modified?: string;
+  content_hash: string;
+}
+
+export interface FileFingerprint {
+  // Milliseconds since the Unix epoch
+  modified: number | null;
+  size: number;
+  content_hash: string;
+}
+
+// Payload of the document-changed event
+export interface FileChange {
+  session_id: string;
+  path: string;
+  // Null when the file was removed
+  fingerprint: FileFingerprint | null;
+}
+
+export interface FieldDifference {
+  name: string;
+  ours: PdfFieldValue | null;
+  theirs: PdfFieldValue | null;
+  edited: boolean;
+}
+
+export interface DocumentDiff {
+  path: string;
+  pending_edits: string[];
+  fields: FieldDifference[];
+  our_page_count: number;
+  their_page_count: number;
+  metadata_changed: boolean;
+  replayable: boolean;
+}
+
+export interface RecentFile {
+  path: string;
+  name: string;
+  last_opened: number;
+  last_page?: number;
+  pinned: boolean;
+}
+
+export interface DocumentSessionInfo {
+  session_id: string;
+  document: PdfDocument;
+}
+
+export interface RecoverableSession {
+  id: string;
+  path: string;
+  name: string;
+  // Seconds since the Unix epoch when the working copy was written
+  saved_at: number;
+  edits: string[];
+}
+
+export interface MetadataUpdate {
+  title?: string;
+  author?: string;
+  subject?: string;
+  keywords?: string;
+  creator?: string;
+  producer?: string;
+}
+
+// A recorded mutation of an open document, matching the Rust DocumentEdit enum
+export type DocumentEdit =
+  | { op: 'add_fields'; fields: PdfFormField[] }
+  | { op: 'fill_form'; values: Record<string, PdfFieldValue> }
+  | { op: 'flatten_form'; field_names?: string[] }
+  | { op: 'update_field'; field: FieldRef; update: FieldUpdate }
+  | { op: 'delete_field'; field: FieldRef }
+  | { op: 'generate_appearances' }
+  | { op: 'update_metadata'; metadata: MetadataUpdate }
+  | { op: 'import_form_data'; source: string; values: Record<string, PdfFieldValue> }
+  | { op: 'move_pages'; pages: number[]; to: number }
+  | { op: 'rotate_pages'; pages: number[]; degrees: number }
+  | { op: 'delete_pages'; pages: number[] }
+  | { op: 'insert_blank_pages'; at: number; count: number; width: number; height: number }
+  | { op: 'insert_pages'; at: number; source: string; pages?: number[] };
+
+export interface HistoryEntry {
+  index: number;
+  description: string;
+  edit: DocumentEdit;
+  timestamp: number;
+  applied: boolean;
+  reversible: boolean;
+}
+
+export interface SaveOptions {
+  create_backup: boolean;
+  overwrite: boolean;
+  // Save even if another program changed the file since it was opened
+  force?: boolean;
+}
+
+export interface BackupInfo {
+  id: string;
+  document_path: string;
+  path: string;
+  // Milliseconds since the Unix epoch
+  created: number;
+  size: number;
+}
+
+export interface BackupPolicy {
+  max_count: number;
+  max_age_days: number | null;
+  max_total_bytes: number | null;
+}
+
+export interface MergeOptions {
+  // Appended to colliding field names; {n} is a counter from 2, {doc} the document's position
+  field_suffix: string;
+}
+
+export interface RenamedField {
+  // Zero-based position of the document in the merge
+  document: number;
+  from: string;
+  to: string;
 }
 
 export interface CommandError {
@@ -38,15 +208,63 @@ export interface CommandError {
   message: string;
 }
 
+// Kind of a form field, matching the Rust FieldKind enum
+export type FieldKind =
+  | 'text'
+  | 'checkbox'
+  | 'radio'
+  | 'combo'
+  | 'list'
+  | 'push_button'
+  | 'signature';
+
+// Decoded /Ff field flags; only those that apply to the field's kind are used
+// no_toggle_to_off and radios_in_unison default to true when left out
+export interface FieldFlags {
+  read_only?: boolean;
+  required?: boolean;
+  no_export?: boolean;
+  multiline?: boolean;
+  password?: boolean;
+  file_select?: boolean;
+  do_not_spell_check?: boolean;
+  do_not_scroll?: boolean;
+  comb?: boolean;
+  rich_text?: boolean;
+  no_toggle_to_off?: boolean;
+  radios_in_unison?: boolean;
+  edit?: boolean;
+  sort?: boolean;
+  multi_select?: boolean;
+  commit_on_sel_change?: boolean;
+}
+
+// An option of a choice field or radio group
+export interface ChoiceOption {
+  export_value: string;
+  display_value: string;
+}
+
 // PDF Form Field interface
 export interface PdfFormField {
   id: string;
   name: string;
-  field_type: string;
+  field_type: FieldKind;
   value?: string;
   rect: [number, number, number, number];
   page: number;
+  flags?: FieldFlags;
+  // Plain strings are used as both export and display value
+  options?: (string | ChoiceOption)[];
   properties: Record<string, string>;
+  widgets?: PdfFormWidget[];
+}
+
+// A single widget of a form field, e.g. one option of a radio group
+export interface PdfFormWidget {
+  rect: [number, number, number, number];
+  page: number;
+  export_value?: string;
 }
 
 // Wrapper functions for Tauri commands
@@ -68,6 +286,187 @@ export async function parsePdf(path: string): Promise<PdfDocument> {
   return invoke<PdfDocument>('parse_pdf', { path });
 }
 
+/**
+ * Load a PDF file once and keep it open for later queries and edits
+ * @param path Path to the PDF file
+ * @returns Session id and PDF document structure
+ */
+export async function openDocument(path: string): Promise<DocumentSessionInfo> {
+  return invoke<DocumentSessionInfo>('open_document', { path });
+}
+
+/**
+ * Close an open document, discarding any unsaved edits
+ * @param sessionId Session id returned by openDocument
+ */
+export async function closeDocument(sessionId: string): Promise<void> {
+  return invoke<void>('close_document', { sessionId });
+}
+
+/**
+ * List the documents with unsaved edits left behind when the app last quit
+ * @returns Recoverable sessions, most recently autosaved first
+ */
+export async function listRecoverableSessions(): Promise<RecoverableSession[]> {
+  return invoke<RecoverableSession[]>('list_recoverable_sessions');
+}
+
+/**
+ * Reopen a document left with unsaved edits, as it was when last autosaved
+ * @param id Id of the recoverable session
+ * @returns The new session, which counts as modified until saved
+ */
+export async function recoverSession(id: string): Promise<DocumentSessionInfo> {
+  return invoke<DocumentSessionInfo>('recover_session', { id });
+}
+
+/**
+ * Throw away the unsaved edits of a document left behind when the app last quit
+ * @param id Id of the recoverable session
+ */
+export async function discardRecoverableSession(id: string): Promise<void> {
+  return invoke<void>('discard_recoverable_session', { id });
+}
+
+/**
+ * Save an open document, backing up the file it replaces
+ *
+ * Fails with MODIFIED_EXTERNALLY if another program changed the document's
+ * file since it was opened, unless options.force is set.
+ * @param sessionId Session id returned by openDocument
+ * @param outputPath Path to save to instead of the document's own path
+ * @param options Whether to back up, overwrite another file and save over external changes
+ */
+export async function saveDocument(sessionId: string, outputPath?: string, options?: SaveOptions): Promise<void> {
+  return invoke<void>('save_document', { sessionId, outputPath, options });
+}
+
+/**
+ * Listen for open documents being changed on disk by another program
+ * @param handler Called with each change
+ * @returns Function that stops listening
+ */
+export async function onDocumentChanged(handler: (change: FileChange) => void): Promise<UnlistenFn> {
+  return listen<FileChange>('document-changed', (event) => handler(event.payload));
+}
+
+/**
+ * Compare an open document with its file as it is on disk now
+ * @param sessionId Session id returned by openDocument
+ * @returns Differing fields, the unsaved edits and whether they can be replayed onto the file
+ */
+export async function diffDocumentWithDisk(sessionId: string): Promise<DocumentDiff> {
+  return invoke<DocumentDiff>('diff_document_with_disk', { sessionId });
+}
+
+/**
+ * Change how much memory open documents may use before idle ones are evicted
+ * @param limitBytes Memory limit in bytes
+ */
+export async function setSessionMemoryLimit(limitBytes: number): Promise<void> {
+  return invoke<void>('set_session_memory_limit', { limitBytes });
+}
+
+/**
+ * List recently opened files, pinned ones first, dropping files that no longer exist
+ * @returns The recent files
+ */
+export async function getRecentFiles(): Promise<RecentFile[]> {
+  return invoke<RecentFile[]>('get_recent_files');
+}
+
+/**
+ * Pin a recent file so it stays listed however long ago it was opened
+ * @param path Path of the recent file
+ * @returns The updated recent files
+ */
+export async function pinRecentFile(path: string): Promise<RecentFile[]> {
+  return invoke<RecentFile[]>('pin_recent_file', { path });
+}
+
+/**
+ * Unpin a recent file, letting it age out of the list again
+ * @param path Path of the recent file
+ * @returns The updated recent files
+ */
+export async function unpinRecentFile(path: string): Promise<RecentFile[]> {
+  return invoke<RecentFile[]>('unpin_recent_file', { path });
+}
+
+/**
+ * Remember the page last viewed in a recent file
+ * @param path Path of the recent file
+ * @param page Zero-based page index
+ * @returns The updated recent files
+ */
+export async function setRecentFilePage(path: string, page: number): Promise<RecentFile[]> {
+  return invoke<RecentFile[]>('set_recent_file_page', { path, page });
+}
+
+/**
+ * Forget the recent files
+ * @param keepPinned Whether pinned files stay in the list
+ * @returns The updated recent files
+ */
+export async function clearRecentFiles(keepPinned = true): Promise<RecentFile[]> {
+  return invoke<RecentFile[]>('clear_recent_files', { keepPinned });
+}
+
+/**
+ * Get the dimensions and rotation of a page
+ * @param sessionId Session id returned by openDocument
+ * @param page Zero-based page index
+ * @returns Page information
+ */
+export async function getPageInfo(sessionId: string, page: number): Promise<PdfPage> {
+  return invoke<PdfPage>('get_page_info', { sessionId, page });
+}
+
+/**
+ * Get the number of pages in an open document
+ * @param sessionId Session id returned by openDocument
+ * @returns Page count
+ */
+export async function getTotalPages(sessionId: string): Promise<number> {
+  return invoke<number>('get_total_pages', { sessionId });
+}
+
+/**
+ * Get the form field hierarchy of an open document
+ * @param sessionId Session id returned by openDocument
+ * @returns Field tree with inherited attributes resolved
+ */
+export async function getFormFields(sessionId: string): Promise<FormFieldTree> {
+  return invoke<FormFieldTree>('get_form_fields', { sessionId });
+}
+
+/**
+ * Extract the text of a page
+ * @param sessionId Session id returned by openDocument
+ * @param page Zero-based page index
+ * @returns Text content of the page
+ */
+export async function extractText(sessionId: string, page: number): Promise<string> {
+  return invoke<string>('extract_text', { sessionId, page });
+}
+
+/**
+ * Map a point on the displayed page back to unrotated page space
+ * @param sessionId Session id returned by openDocument
+ * @param page Zero-based page index
+ * @param x Horizontal position in displayed page space
+ * @param y Vertical position in displayed page space
+ * @returns The point in unrotated page space
+ */
+export async function transformCoordinates(
+  sessionId: string,
+  page: number,
+  x: number,
+  y: number
+): Promise<[number, number]> {
+  return invoke<[number, number]>('transform_coordinates', { sessionId, page, x, y });
+}
+
 /**
  * Save a PDF file using the system dialog
  * @param defaultPath Optional default path for the save dialog
@@ -79,6 +478,9 @@ export async function savePdfDialog(defaultPath?: string): Promise<string | unde
 
 /**
  * Save PDF data to a file
+ *
+ * Fails with MODIFIED_EXTERNALLY if the file is open in a session and another
+ * program changed it since.
  * @param path Path to save the file
  * @param data Binary data to save
  */
@@ -86,6 +488,19 @@ export async function savePdfFile(path: string, data: Uint8Array): Promise<void>
   return invoke<void>('save_pdf_file', { path, data: Array.from(data) });
 }
 
+/**
+ * Save PDF data to a file, optionally keeping a backup of the file it replaces
+ *
+ * Fails if the file is open in a session and another program changed it
+ * since, unless options.force is set.
+ * @param path Path to save the file
+ * @param data Binary data to save
+ * @param options Whether to back up and overwrite an existing file
+ */
+export async function savePdf(path: string, data: Uint8Array, options: SaveOptions): Promise<void> {
+  return invoke<void>('save_pdf', { path, data: Array.from(data), options });
+}
+
 /**
  * Get file information
  * @param path Path to the file
@@ -96,12 +511,56 @@ export async function getFileInfo(path: string): Promise<FileInfo> {
 }
 
 /**
- * Create a backup of a file
+ * Back up a file as it is on disk now
+ * @param path Path to the file
+ * @returns The new backup
+ */
+export async function createFileBackup(path: string): Promise<BackupInfo> {
+  return invoke<BackupInfo>('create_file_backup', { path });
+}
+
+/**
+ * List the backups of a file
  * @param path Path to the file
- * @returns Path to the backup file
+ * @returns Backups, newest first
  */
-export async function createFileBackup(path: string): Promise<string> {
-  return invoke<string>('create_file_backup', { path });
+export async function listBackups(path: string): Promise<BackupInfo[]> {
+  return invoke<BackupInfo[]>('list_backups', { path });
+}
+
+/**
+ * Replace a file with one of its backups; reopen the file to see the restored version
+ * @param path Path to the file
+ * @param backupId Id of the backup to restore
+ * @returns The backup made of the file before it was replaced, if it existed
+ */
+export async function restoreBackup(path: string, backupId: string): Promise<BackupInfo | null> {
+  return invoke<BackupInfo | null>('restore_backup', { path, backupId });
+}
+
+/**
+ * Remove one backup of a file
+ * @param path Path to the file
+ * @param backupId Id of the backup to remove
+ */
+export async function deleteBackup(path: string, backupId: string): Promise<void> {
+  return invoke<void>('delete_backup', { path, backupId });
+}
+
+/**
+ * Get the retention policy for backups
+ * @returns Current policy
+ */
+export async function getBackupPolicy(): Promise<BackupPolicy> {
+  return invoke<BackupPolicy>('get_backup_policy');
+}
+
+/**
+ * Change the retention policy for backups, removing backups it no longer allows
+ * @param policy New policy
+ */
+export async function setBackupPolicy(policy: BackupPolicy): Promise<void> {
+  return invoke<void>('set_backup_policy', { policy });
 }
 
 /**
@@ -124,6 +583,9 @@ export async function readFileBase64(path: string): Promise<string> {
 
 /**
  * Write base64 data to a file
+ *
+ * Fails with MODIFIED_EXTERNALLY if the file is open in a session and another
+ * program changed it since.
  * @param path Path to write to
  * @param data Base64 encoded data
  */
@@ -149,6 +611,338 @@ export async function addFormFieldsToPdf(
   });
 }
 
+/**
+ * Create a new form PDF from a layout definition
+ * @param layoutPath Path to a JSON layout, or YAML for `.yaml` and `.yml` files
+ * @param outputPath Path to save the new PDF
+ */
+export async function createFormFromLayout(layoutPath: string, outputPath: string): Promise<void> {
+  return invoke<void>('create_form_from_layout', { layoutPath, outputPath });
+}
+
+/**
+ * Fill existing form fields by fully-qualified name
+ * @param pdfPath Path to the PDF file
+ * @param values Map of fully-qualified field names to values
+ * @param outputPath Path to save the filled PDF
+ */
+export async function fillForm(
+  pdfPath: string,
+  values: Record<string, PdfFieldValue>,
+  outputPath: string
+): Promise<void> {
+  return invoke<void>('fill_form', { pdfPath, values, outputPath });
+}
+
+/**
+ * Flatten form fields into the page content so they can no longer be edited
+ * @param pdfPath Path to the PDF file
+ * @param fieldNames Fully-qualified names to flatten, or undefined for all fields
+ * @param outputPath Path to save the flattened PDF
+ */
+export async function flattenForm(
+  pdfPath: string,
+  fieldNames: string[] | undefined,
+  outputPath: string
+): Promise<void> {
+  return invoke<void>('flatten_form', { pdfPath, fieldNames, outputPath });
+}
+
+/**
+ * Rename, move or reconfigure an existing form field
+ * @param pdfPath Path to the PDF file
+ * @param field Field to update, by name or object id
+ * @param update Changes to apply
+ * @param outputPath Path to save the modified PDF
+ */
+export async function updateFormField(
+  pdfPath: string,
+  field: FieldRef,
+  update: FieldUpdate,
+  outputPath: string
+): Promise<void> {
+  return invoke<void>('update_form_field', { pdfPath, field, update, outputPath });
+}
+
+/**
+ * Delete an existing form field along with its kids and widgets
+ * @param pdfPath Path to the PDF file
+ * @param field Field to delete, by name or object id
+ * @param outputPath Path to save the modified PDF
+ */
+export async function deleteFormField(
+  pdfPath: string,
+  field: FieldRef,
+  outputPath: string
+): Promise<void> {
+  return invoke<void>('delete_form_field', { pdfPath, field, outputPath });
+}
+
+/**
+ * Add form fields to an open document
+ * @param sessionId Session id returned by openDocument
+ * @param fields Form fields to add
+ * @returns The updated field tree
+ */
+export async function addDocumentFormFields(
+  sessionId: string,
+  fields: PdfFormField[]
+): Promise<FormFieldTree> {
+  return invoke<FormFieldTree>('add_document_form_fields', { sessionId, fields });
+}
+
+/**
+ * Fill form fields of an open document by fully-qualified name
+ * @param sessionId Session id returned by openDocument
+ * @param values Field values keyed by fully-qualified name
+ * @returns The updated field tree
+ */
+export async function fillDocumentForm(
+  sessionId: string,
+  values: Record<string, PdfFieldValue>
+): Promise<FormFieldTree> {
+  return invoke<FormFieldTree>('fill_document_form', { sessionId, values });
+}
+
+/**
+ * Flatten form fields of an open document
+ * @param sessionId Session id returned by openDocument
+ * @param fieldNames Fully-qualified names to flatten, or all fields when omitted
+ * @returns The updated field tree
+ */
+export async function flattenDocumentForm(
+  sessionId: string,
+  fieldNames?: string[]
+): Promise<FormFieldTree> {
+  return invoke<FormFieldTree>('flatten_document_form', { sessionId, fieldNames });
+}
+
+/**
+ * Rename, move or reconfigure a form field of an open document
+ * @param sessionId Session id returned by openDocument
+ * @param field Fully-qualified name or object id of the field or one of its widgets
+ * @param update Changes to apply
+ * @returns The updated field tree
+ */
+export async function updateDocumentFormField(
+  sessionId: string,
+  field: FieldRef,
+  update: FieldUpdate
+): Promise<FormFieldTree> {
+  return invoke<FormFieldTree>('update_document_form_field', { sessionId, field, update });
+}
+
+/**
+ * Delete a form field of an open document along with its kids and widgets
+ * @param sessionId Session id returned by openDocument
+ * @param field Fully-qualified name or object id of the field
+ * @returns The updated field tree
+ */
+export async function deleteDocumentFormField(sessionId: string, field: FieldRef): Promise<FormFieldTree> {
+  return invoke<FormFieldTree>('delete_document_form_field', { sessionId, field });
+}
+
+/**
+ * Generate appearance streams for the form fields of an open document
+ * @param sessionId Session id returned by openDocument
+ */
+export async function generateDocumentAppearanceStreams(sessionId: string): Promise<void> {
+  return invoke<void>('generate_document_appearance_streams', { sessionId });
+}
+
+/**
+ * Change the title, author and other metadata of an open document
+ * @param sessionId Session id returned by openDocument
+ * @param metadata Entries to change; empty strings remove an entry
+ * @returns The updated PDF document structure
+ */
+export async function updateDocumentMetadata(
+  sessionId: string,
+  metadata: MetadataUpdate
+): Promise<PdfDocument> {
+  return invoke<PdfDocument>('update_document_metadata', { sessionId, metadata });
+}
+
+/**
+ * Move pages of an open document
+ * @param sessionId Session id returned by openDocument
+ * @param pages Zero-based indexes of the pages to move, in the order wanted
+ * @param to Zero-based position of the first moved page once moved
+ * @returns The updated PDF document structure
+ */
+export async function moveDocumentPages(sessionId: string, pages: number[], to: number): Promise<PdfDocument> {
+  return invoke<PdfDocument>('move_document_pages', { sessionId, pages, to });
+}
+
+/**
+ * Rotate pages of an open document clockwise
+ * @param sessionId Session id returned by openDocument
+ * @param pages Zero-based indexes of the pages to rotate
+ * @param degrees A multiple of 90, negative to rotate counterclockwise
+ * @returns The updated PDF document structure
+ */
+export async function rotateDocumentPages(
+  sessionId: string,
+  pages: number[],
+  degrees: number
+): Promise<PdfDocument> {
+  return invoke<PdfDocument>('rotate_document_pages', { sessionId, pages, degrees });
+}
+
+/**
+ * Delete pages of an open document along with their form field widgets
+ * @param sessionId Session id returned by openDocument
+ * @param pages Zero-based indexes of the pages to delete
+ * @returns The updated PDF document structure
+ */
+export async function deleteDocumentPages(sessionId: string, pages: number[]): Promise<PdfDocument> {
+  return invoke<PdfDocument>('delete_document_pages', { sessionId, pages });
+}
+
+/**
+ * Insert blank pages into an open document
+ * @param sessionId Session id returned by openDocument
+ * @param at Zero-based position of the first new page
+ * @param count Number of pages to insert
+ * @param width Page width in points
+ * @param height Page height in points
+ * @returns The updated PDF document structure
+ */
+export async function insertBlankPages(
+  sessionId: string,
+  at: number,
+  count: number,
+  width: number,
+  height: number
+): Promise<PdfDocument> {
+  return invoke<PdfDocument>('insert_blank_pages', { sessionId, at, count, width, height });
+}
+
+/**
+ * Insert pages copied from another PDF file into an open document
+ * @param sessionId Session id returned by openDocument
+ * @param at Zero-based position of the first new page
+ * @param sourcePath Path to the PDF to copy pages from
+ * @param pages Zero-based indexes of the pages to copy; all of them if omitted
+ * @returns The updated PDF document structure
+ */
+export async function insertDocumentPages(
+  sessionId: string,
+  at: number,
+  sourcePath: string,
+  pages?: number[]
+): Promise<PdfDocument> {
+  return invoke<PdfDocument>('insert_document_pages', { sessionId, at, sourcePath, pages });
+}
+
+/**
+ * Revert the most recent edit of an open document
+ * @param sessionId Session id returned by openDocument
+ * @returns The updated PDF document structure
+ */
+export async function undo(sessionId: string): Promise<PdfDocument> {
+  return invoke<PdfDocument>('undo', { sessionId });
+}
+
+/**
+ * Reapply the most recently undone edit of an open document
+ * @param sessionId Session id returned by openDocument
+ * @returns The updated PDF document structure
+ */
+export async function redo(sessionId: string): Promise<PdfDocument> {
+  return invoke<PdfDocument>('redo', { sessionId });
+}
+
+/**
+ * List the edits made to an open document
+ * @param sessionId Session id returned by openDocument
+ * @returns Recorded edits, oldest first
+ */
+export async function history(sessionId: string): Promise<HistoryEntry[]> {
+  return invoke<HistoryEntry[]>('history', { sessionId });
+}
+
+/**
+ * Replay the unsaved edits of an open document against its file on disk
+ * @param sessionId Session id returned by openDocument
+ * @param outputPath Path to save the replayed document
+ */
+export async function replayJournal(sessionId: string, outputPath: string): Promise<void> {
+  return invoke<void>('replay_journal', { sessionId, outputPath });
+}
+
+/**
+ * Apply the field values of an XFDF file to an open document
+ * @param sessionId Session id returned by openDocument
+ * @param xfdfPath Path to the XFDF file
+ * @returns The updated field tree
+ */
+export async function importXfdf(sessionId: string, xfdfPath: string): Promise<FormFieldTree> {
+  return invoke<FormFieldTree>('import_xfdf', { sessionId, xfdfPath });
+}
+
+/**
+ * Apply the field values of an FDF file to an open document
+ * @param sessionId Session id returned by openDocument
+ * @param fdfPath Path to the FDF file
+ * @returns The updated field tree
+ */
+export async function importFdf(sessionId: string, fdfPath: string): Promise<FormFieldTree> {
+  return invoke<FormFieldTree>('import_fdf', { sessionId, fdfPath });
+}
+
+/**
+ * Apply the field values of a JSON data file to an open document
+ * @param sessionId Session id returned by openDocument
+ * @param jsonPath Path to a JSON object keyed by fully-qualified field name
+ * @returns The updated field tree
+ */
+export async function importJson(sessionId: string, jsonPath: string): Promise<FormFieldTree> {
+  return invoke<FormFieldTree>('import_json', { sessionId, jsonPath });
+}
+
+/**
+ * Describe the form data of an open document as a JSON Schema
+ * @param sessionId Session id returned by openDocument
+ * @returns A JSON Schema whose properties are the fully-qualified field names
+ */
+export async function getFormSchema(sessionId: string): Promise<Record<string, unknown>> {
+  return invoke<Record<string, unknown>>('get_form_schema', { sessionId });
+}
+
+/**
+ * Export the values of every terminal form field of an open document
+ * @param sessionId Session id returned by openDocument
+ * @param format One of 'json', 'csv', 'xfdf' or 'fdf'
+ * @returns The exported data, base64 encoded for the binary 'fdf' format
+ */
+export async function exportFormData(
+  sessionId: string,
+  format: 'json' | 'csv' | 'xfdf' | 'fdf'
+): Promise<string> {
+  return invoke<string>('export_form_data', { sessionId, format });
+}
+
+/**
+ * Fill a copy of a template PDF for every record of a CSV file
+ * @param templatePath Path to the template PDF
+ * @param csvPath Path to a CSV file whose header names fields by fully-qualified name
+ * @param outputDir Directory to write the filled PDFs to
+ * @param filenamePattern File name with `{row}` and `{field name}` placeholders; `.pdf` is added if left out
+ * @returns Paths of the filled PDFs in record order
+ *
+ * Fails with MODIFIED_EXTERNALLY if an output file is open in a session and
+ * another program changed it since.
+ */
+export async function batchFillCsv(
+  templatePath: string,
+  csvPath: string,
+  outputDir: string,
+  filenamePattern: string
+): Promise<string[]> {
+  return invoke<string[]>('batch_fill_csv', { templatePath, csvPath, outputDir, filenamePattern });
+}
+
 /**
  * Generate appearance streams for form fields in a PDF
  * @param pdfPath Path to the PDF file
@@ -162,4 +956,61 @@ export async function generateAppearanceStreams(
     pdf_path: pdfPath,
     output_path: outputPath,
   });
-} 
\ No newline at end of file
+}
+
+/**
+ * Merge PDF files in the given order into a new file
+ * @param paths Paths of the PDFs to merge
+ * @param outputPath Path to save the merged PDF
+ * @param options How to rename form fields whose names are already taken
+ * @returns Form fields that were renamed
+ */
+export async function mergePdfs(
+  paths: string[],
+  outputPath: string,
+  options?: MergeOptions
+): Promise<RenamedField[]> {
+  return invoke<RenamedField[]>('merge_pdfs', { paths, outputPath, options });
+}
+
+/**
+ * Split a PDF file into one part per page range
+ * @param path Path to the PDF file
+ * @param ranges One-based page ranges such as "1-3,5,8-"
+ * @param outputDir Directory to save the parts in
+ * @returns Paths of the parts
+ *
+ * Fails with MODIFIED_EXTERNALLY if the file of a part is open in a session
+ * and another program changed it since.
+ */
+export async function splitPdf(path: string, ranges: string, outputDir: string): Promise<string[]> {
+  return invoke<string[]>('split_pdf', { path, ranges, outputDir });
+}
+
+/**
+ * Split a PDF file into parts of a fixed number of pages
+ * @param path Path to the PDF file
+ * @param pagesPerPart Number of pages in each part; the last part may have fewer
+ * @param outputDir Directory to save the parts in
+ * @returns Paths of the parts
+ *
+ * Fails with MODIFIED_EXTERNALLY if the file of a part is open in a session
+ * and another program changed it since.
+ */
+export async function splitPdfEvery(
+  path: string,
+  pagesPerPart: number,
+  outputDir: string
+): Promise<string[]> {
+  return invoke<string[]>('split_pdf_every', { path, pagesPerPart, outputDir });
+}
+
+/**
+ * Save selected pages of a PDF file as a new file
+ * @param path Path to the PDF file
+ * @param pages One-based page ranges such as "1-3,5", in the order wanted
+ * @param outputPath Path to save the new PDF
+ */
+export async function extractPages(path: string, pages: string, outputPath: string): Promise<void> {
+  return invoke<void>('extract_pages', { path, pages, outputPath });
+}
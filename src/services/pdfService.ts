@@ -1,18 +1,39 @@
-import { invoke } from '@tauri-apps/api/core';
-import { open, save } from '@tauri-apps/plugin-dialog'; 
+import { open, save } from '@tauri-apps/plugin-dialog';
+import {
+  addDocumentFormFields,
+  closeDocument,
+  deleteDocumentFormField,
+  fillDocumentForm,
+  getFormFields,
+  openDocument,
+  redo,
+  saveDocument,
+  undo,
+  updateDocumentFormField,
+  type FieldKind,
+  type FormFieldTree,
+  type PdfDocument as SessionDocument,
+  type PdfFieldNode
+} from '../lib/commands';
 
 export interface FormField {
+  // Fully-qualified field name
   name: string;
-  field_type: string;
+  field_type: FieldKind;
   value: string | null;
+  // Position of the first widget in PDF user space
   x: number;
   y: number;
   width: number;
   height: number;
   page: number;
+  // Object id of that widget, which moving or resizing it addresses
+  widget_id: [number, number];
 }
 
 export interface PdfDocument {
+  // Session id of the document held open by the backend
+  sessionId: string;
   path: string;
   pages: number;
   formFields: FormField[];
@@ -32,15 +53,24 @@ export class PdfService {
         throw new Error('No file selected');
       }
 
-      const result = await invoke('open_pdf', { path: filePath });
-      return result as PdfDocument;
+      const { session_id, document } = await openDocument(filePath);
+      return toPdfDocument(session_id, document);
     } catch (error) {
       console.error('Error opening PDF:', error);
       throw error;
     }
   }
 
-  static async savePdf(path: string, formFields: FormField[]): Promise<void> {
+  static async closePdf(sessionId: string): Promise<void> {
+    try {
+      await closeDocument(sessionId);
+    } catch (error) {
+      console.error('Error closing PDF:', error);
+      throw error;
+    }
+  }
+
+  static async savePdf(sessionId: string): Promise<void> {
     try {
       const savePath = await save({
         filters: [{
@@ -53,61 +83,132 @@ export class PdfService {
         throw new Error('No save location selected');
       }
 
-      await invoke('save_pdf', {
-        sourcePath: path,
-        targetPath: savePath,
-        formFields
-      });
+      // The dialog has already asked before replacing an existing file
+      await saveDocument(sessionId, savePath, { create_backup: true, overwrite: true });
     } catch (error) {
       console.error('Error saving PDF:', error);
       throw error;
     }
   }
 
-  static async addFormField(path: string, field: Omit<FormField, 'name'>): Promise<FormField> {
+  static async addFormField(
+    sessionId: string,
+    field: Omit<FormField, 'name' | 'widget_id'>,
+    existing: FormField[]
+  ): Promise<FormField[]> {
     try {
-      const result = await invoke('add_form_field', {
-        path,
-        field
-      });
-      return result as FormField;
+      const name = uniqueFieldName(field.field_type, existing);
+      const tree = await addDocumentFormFields(sessionId, [{
+        id: name,
+        name,
+        field_type: field.field_type,
+        value: field.value ?? undefined,
+        rect: [field.x, field.y, field.x + field.width, field.y + field.height],
+        page: field.page,
+        properties: {}
+      }]);
+      return flattenFields(tree);
     } catch (error) {
       console.error('Error adding form field:', error);
       throw error;
     }
   }
 
-  static async updateFormField(path: string, field: FormField): Promise<void> {
+  static async updateFormField(sessionId: string, field: FormField): Promise<FormField[]> {
     try {
-      await invoke('update_form_field', {
-        path,
-        field
+      // A field may have several widgets, so the one shown is addressed by id
+      let tree = await updateDocumentFormField(sessionId, { object_id: field.widget_id }, {
+        rect: [field.x, field.y, field.x + field.width, field.y + field.height],
+        page: field.page
       });
+      if (field.value !== null) {
+        tree = await fillDocumentForm(sessionId, { [field.name]: field.value });
+      }
+      return flattenFields(tree);
     } catch (error) {
       console.error('Error updating form field:', error);
       throw error;
     }
   }
 
-  static async deleteFormField(path: string, fieldName: string): Promise<void> {
+  static async deleteFormField(sessionId: string, fieldName: string): Promise<FormField[]> {
     try {
-      await invoke('delete_form_field', {
-        path,
-        fieldName
-      });
+      const tree = await deleteDocumentFormField(sessionId, { name: fieldName });
+      return flattenFields(tree);
     } catch (error) {
       console.error('Error deleting form field:', error);
       throw error;
     }
   }
 
-  static async extractFormFields(path: string): Promise<FormField[]> {
+  static async extractFormFields(sessionId: string): Promise<FormField[]> {
     try {
-      const result = await invoke('extract_form_fields', { path });
-      return result as FormField[];
+      return flattenFields(await getFormFields(sessionId));
     } catch (error) {
       console.error('Error extracting form fields:', error);
       throw error;
     }
   }
-}
\ No newline at end of file
+
+  static async undo(sessionId: string): Promise<PdfDocument> {
+    try {
+      return toPdfDocument(sessionId, await undo(sessionId));
+    } catch (error) {
+      console.error('Error undoing edit:', error);
+      throw error;
+    }
+  }
+
+  static async redo(sessionId: string): Promise<PdfDocument> {
+    try {
+      return toPdfDocument(sessionId, await redo(sessionId));
+    } catch (error) {
+      console.error('Error redoing edit:', error);
+      throw error;
+    }
+  }
+}
+
+function toPdfDocument(sessionId: string, document: SessionDocument): PdfDocument {
+  return {
+    sessionId,
+    path: document.path,
+    pages: document.page_count,
+    formFields: flattenFields(document.form)
+  };
+}
+
+// Terminal fields of the tree, placed by their first widget
+function flattenFields(tree: FormFieldTree): FormField[] {
+  const fields: FormField[] = [];
+  const visit = (node: PdfFieldNode) => {
+    node.children.forEach(visit);
+
+    const widget = node.widgets[0];
+    if (!node.kind || !widget) return;
+
+    const [x1, y1, x2, y2] = widget.rect;
+    fields.push({
+      name: node.full_name,
+      field_type: node.kind,
+      value: Array.isArray(node.value) ? node.value.join(', ') : node.value ?? null,
+      x: Math.min(x1, x2),
+      y: Math.min(y1, y2),
+      width: Math.abs(x2 - x1),
+      height: Math.abs(y2 - y1),
+      page: widget.page ?? 0,
+      widget_id: widget.object_id
+    });
+  };
+  tree.fields.forEach(visit);
+  return fields;
+}
+
+function uniqueFieldName(kind: FieldKind, existing: FormField[]): string {
+  const taken = new Set(existing.map(field => field.name));
+  let index = existing.length + 1;
+  while (taken.has(`${kind}_${index}`)) {
+    index++;
+  }
+  return `${kind}_${index}`;
+}